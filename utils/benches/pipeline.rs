@@ -0,0 +1,153 @@
+//! Lint and build on generated catalogs (`cargo bench --bench pipeline`)
+//!
+//! Every download is a local resource so nothing goes over the network, what's measured is the
+//! pipeline itself (and how it scales with the number of fonts).
+
+use std::{path::Path, time::Duration};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use semver::Version;
+use serde_json::json;
+use url::Url;
+use utils::{
+    build::{build, BuildContext},
+    cache::HttpCache,
+    cancel::Cancellation,
+    fetch::Fetcher,
+    lint::{lint, IdScheme, LintContext, LintMode, LintRules},
+    lock::Lockfile,
+    metrics::Timings,
+    types::Source,
+};
+use uuid::Uuid;
+
+/// Catalog sizes (in fonts)
+const SIZES: [usize; 2] = [1_000, 10_000];
+/// Fonts share the downloads like the real catalogs do (several fonts per archive)
+const DOWNLOADS: usize = 100;
+/// Fonts per group
+const GROUP_SIZE: usize = 50;
+
+/// Writes the downloads into `directory` and returns a catalog of `fonts` fonts using them
+fn catalog(directory: &Path, fonts: usize) -> Source {
+    for download in 0..DOWNLOADS {
+        std::fs::write(
+            directory.join(format!("download-{}.exe", download)),
+            format!("not an archive {}", download).repeat(256),
+        )
+        .expect("Failed to write a download");
+    }
+
+    let publisher = Uuid::new_v4();
+    let ids: Vec<Uuid> = (0..fonts).map(|_| Uuid::new_v4()).collect();
+
+    let source = json!({
+        "publishers": [{
+            "id": publisher,
+            "name": "Benchmark Foundry",
+        }],
+        "groups": ids
+            .chunks(GROUP_SIZE)
+            .enumerate()
+            .map(|(index, chunk)| json!({
+                "id": Uuid::new_v4(),
+                "name": format!("group {:05}", index),
+                "fonts": chunk,
+            }))
+            .collect::<Vec<_>>(),
+        "fonts": ids
+            .iter()
+            .enumerate()
+            .map(|(index, id)| json!({
+                "id": id,
+                "name": format!("Font {:05}", index),
+                "shortName": format!("font-{:05}", index),
+                "publisher": publisher,
+                "categories": ["sans-serif"],
+                "installations": [{
+                    "type": "cabextract",
+                    "download": format!("./download-{}.exe", index % DOWNLOADS),
+                    "files": [{
+                        "file": format!("font{:05}.ttf", index),
+                        "registryName": format!("Font {:05} (TrueType)", index),
+                    }],
+                }],
+            }))
+            .collect::<Vec<_>>(),
+    });
+
+    serde_json::from_value(source).expect("The generated catalog is invalid")
+}
+
+fn pipeline(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("Failed to start the runtime");
+    let fetcher = Fetcher::new(Duration::ZERO).expect("Failed to create the fetcher");
+    let rules = LintRules::default();
+    let base_url = Url::parse("https://example.com/").unwrap();
+
+    let mut group = c.benchmark_group("pipeline");
+    group.sample_size(10);
+
+    for size in SIZES {
+        let directory = std::env::temp_dir().join(format!("winefonts-bench-{}", size));
+        std::fs::create_dir_all(&directory).expect("Failed to create the bench directory");
+        let source = catalog(&directory, size);
+
+        group.bench_with_input(BenchmarkId::new("lint", size), &source, |b, source| {
+            b.to_async(&runtime).iter(|| async {
+                let cache = HttpCache::load(None).await;
+                let ctx = LintContext {
+                    fetcher: &fetcher,
+                    cache: &cache,
+                    rules: &rules,
+                    changed_fonts: None,
+                    published: None,
+                };
+                lint(
+                    source,
+                    directory.clone(),
+                    LintMode::Check,
+                    IdScheme::Random,
+                    &ctx,
+                )
+                .await
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("build", size), &source, |b, source| {
+            b.to_async(&runtime).iter(|| async {
+                let mut cache = HttpCache::load(None).await;
+                let mut timings = Timings::default();
+                let cancellation = Cancellation::default();
+                let mut lock = Lockfile::default();
+                let mut ctx = BuildContext {
+                    fetcher: &fetcher,
+                    cache: &mut cache,
+                    scanner: None,
+                    timings: &mut timings,
+                    cancellation: &cancellation,
+                    lock: &mut lock,
+                    externals: None,
+                };
+                let built = build(
+                    Version::new(1, 0, 0),
+                    source,
+                    base_url.clone(),
+                    directory.clone(),
+                    vec![],
+                    &mut ctx,
+                )
+                .await;
+                assert!(built.is_ok(), "The generated catalog failed to build");
+                built
+            })
+        });
+
+        let _ = std::fs::remove_dir_all(&directory);
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, pipeline);
+criterion_main!(benches);
@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use utils::types::Compiled;
+
+// Version files are read back from the bucket and from upstream databases
+fuzz_target!(|data: &[u8]| {
+    if let Ok(compiled) = serde_json::from_slice::<Compiled>(data) {
+        let _ = serde_json::to_vec(&compiled);
+    }
+});
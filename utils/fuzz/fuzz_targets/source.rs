@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use utils::{strict, types::Source};
+
+// Catalogs are parsed strictly (unknown fields are reported with their path)
+fuzz_target!(|data: &[u8]| {
+    if let Ok(contents) = std::str::from_utf8(data) {
+        let _ = strict::from_str::<Source>(contents);
+    }
+});
@@ -0,0 +1,295 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519},
+};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_APPROVAL_FILE: &str = "approval.json";
+/// The trusted keys of a repository (next to the first config), publishing from it always needs
+/// an approval once they're committed
+pub const DEFAULT_APPROVAL_KEYS_FILE: &str = "approval-keys.txt";
+
+pub enum ApprovalError {
+    /// Git couldn't tell the commit of a config (Path, Error)
+    Git(PathBuf, String),
+    /// A config has uncommitted changes, the approval wouldn't cover them (Path)
+    Dirty(PathBuf),
+    /// The configs are in different commits (Commit, Commit)
+    CommitsDiffer(String, String),
+    /// A key couldn't be read or generated (Error)
+    Key(String),
+    /// A trusted keys line couldn't be parsed (Line number, Line)
+    KeysLine(usize, String),
+    /// The approval was made for another commit (Approved commit, Commit)
+    CommitMismatch(String, String),
+    /// The approver isn't in the trusted keys (Approver)
+    UnknownApprover(String),
+    /// The signature doesn't match the approver's key (Approver)
+    BadSignature(String),
+    /// The publisher approved their own release (Approver)
+    SelfApproved(String),
+    /// The publisher's key isn't in the trusted keys
+    UnknownPublisher,
+    /// The approval was made for another bundle, or for the configs instead of a bundle (or the
+    /// other way around)
+    BundleMismatch,
+}
+
+impl Display for ApprovalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalError::Git(path, error) => write!(
+                f,
+                "Failed to get the git commit of {}: {}",
+                path.display(),
+                error
+            ),
+            ApprovalError::Dirty(path) => write!(
+                f,
+                "{} has uncommitted changes, commit them to have them approved",
+                path.display()
+            ),
+            ApprovalError::CommitsDiffer(a, b) => {
+                write!(f, "The configs are in different commits ({} and {})", a, b)
+            }
+            ApprovalError::Key(error) => write!(f, "Invalid key: {}", error),
+            ApprovalError::KeysLine(number, line) => {
+                write!(f, "Invalid trusted key on line {}: {}", number, line)
+            }
+            ApprovalError::CommitMismatch(approved, commit) => write!(
+                f,
+                "The approval is for commit {} but the configs are at {}",
+                approved, commit
+            ),
+            ApprovalError::UnknownApprover(approver) => {
+                write!(f, "{} isn't a trusted approver", approver)
+            }
+            ApprovalError::BadSignature(approver) => {
+                write!(f, "The signature of {} doesn't match their key", approver)
+            }
+            ApprovalError::SelfApproved(approver) => write!(
+                f,
+                "{} approved their own release, it needs a second maintainer",
+                approver
+            ),
+            ApprovalError::UnknownPublisher => {
+                write!(f, "The publisher key isn't one of the trusted keys")
+            }
+            ApprovalError::BundleMismatch => write!(
+                f,
+                "The approval isn't for what is being published (approve the bundle with --bundle)"
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A maintainer's sign-off on the configs at a commit
+pub struct Approval {
+    /// Git commit of the configs
+    pub commit: String,
+    /// Name of the approver in the trusted keys
+    pub approver: String,
+    /// Hash of the vendored bundle that was approved (none when the configs were)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bundle: Option<String>,
+    /// Ed25519 signature of the commit and the bundle hash (base64)
+    pub signature: String,
+}
+
+/// What is signed
+fn message(commit: &str, bundle: Option<&str>) -> Vec<u8> {
+    match bundle {
+        Some(bundle) => format!("winefonts approval {} bundle {}", commit, bundle).into_bytes(),
+        None => format!("winefonts approval {}", commit).into_bytes(),
+    }
+}
+
+fn git(directory: &Path, args: &[&str]) -> Result<String, ApprovalError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .args(args)
+        .output()
+        .map_err(|e| ApprovalError::Git(directory.to_path_buf(), e.to_string()))?;
+
+    if !output.status.success() {
+        return Err(ApprovalError::Git(
+            directory.to_path_buf(),
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether a file is committed at HEAD (outside of a repository nothing is)
+pub fn is_committed(path: &Path) -> bool {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+
+    git(
+        directory,
+        &[
+            "cat-file",
+            "-e",
+            &format!("HEAD:./{}", file_name.to_string_lossy()),
+        ],
+    )
+    .is_ok()
+}
+
+/// The git commit the configs are at (they have to be committed)
+pub fn commit_of(configs: &[PathBuf]) -> Result<String, ApprovalError> {
+    let mut commit: Option<String> = None;
+
+    for config in configs {
+        let directory = match config.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = config.file_name().unwrap_or(config.as_os_str());
+
+        let head = git(directory, &["rev-parse", "HEAD"])?;
+        let status = git(
+            directory,
+            &["status", "--porcelain", "--", &file_name.to_string_lossy()],
+        )?;
+        if !status.is_empty() {
+            return Err(ApprovalError::Dirty(config.clone()));
+        }
+
+        match &commit {
+            Some(commit) if commit != &head => {
+                return Err(ApprovalError::CommitsDiffer(commit.clone(), head))
+            }
+            _ => commit = Some(head),
+        }
+    }
+
+    commit.ok_or_else(|| ApprovalError::Git(PathBuf::from("."), "no configs".to_string()))
+}
+
+/// Generates a signing key (base64 PKCS#8) and its public key (base64)
+pub fn generate_key() -> Result<(String, String), ApprovalError> {
+    let document = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new())
+        .map_err(|_| ApprovalError::Key("failed to generate a key".to_string()))?;
+    let pair = Ed25519KeyPair::from_pkcs8(document.as_ref())
+        .map_err(|e| ApprovalError::Key(e.to_string()))?;
+
+    Ok((
+        STANDARD.encode(document.as_ref()),
+        STANDARD.encode(pair.public_key().as_ref()),
+    ))
+}
+
+fn key_pair(key: &str) -> Result<Ed25519KeyPair, ApprovalError> {
+    let document = STANDARD
+        .decode(key.trim())
+        .map_err(|e| ApprovalError::Key(e.to_string()))?;
+
+    Ed25519KeyPair::from_pkcs8(&document).map_err(|e| ApprovalError::Key(e.to_string()))
+}
+
+/// The public key of a key made by `generate_key`
+pub fn public_key(key: &str) -> Result<Vec<u8>, ApprovalError> {
+    Ok(key_pair(key)?.public_key().as_ref().to_vec())
+}
+
+/// Signs the commit (and the hash of the bundle made from it) with a key made by `generate_key`
+pub fn sign(
+    key: &str,
+    approver: String,
+    commit: String,
+    bundle: Option<String>,
+) -> Result<Approval, ApprovalError> {
+    let pair = key_pair(key)?;
+
+    Ok(Approval {
+        signature: STANDARD.encode(pair.sign(&message(&commit, bundle.as_deref())).as_ref()),
+        commit,
+        approver,
+        bundle,
+    })
+}
+
+/// Maintainers allowed to approve, one `<name> <public key>` per line (`#` starts a comment)
+pub struct TrustedKeys {
+    keys: Vec<(String, Vec<u8>)>,
+}
+
+impl TrustedKeys {
+    pub fn parse(data: &str) -> Result<TrustedKeys, ApprovalError> {
+        let mut keys = vec![];
+
+        for (index, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let invalid = || ApprovalError::KeysLine(index + 1, line.to_string());
+            let (name, key) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let key = STANDARD.decode(key.trim()).map_err(|_| invalid())?;
+            keys.push((name.to_string(), key));
+        }
+
+        Ok(TrustedKeys { keys })
+    }
+
+    /// Checks the approval covers the commit and was made by a trusted maintainer other than
+    /// the publisher, who is known by their key (`public_key` of the key they publish with), not
+    /// by a name they could choose
+    pub fn verify(
+        &self,
+        approval: &Approval,
+        commit: &str,
+        bundle: Option<&str>,
+        publisher_key: &[u8],
+    ) -> Result<(), ApprovalError> {
+        if approval.commit != commit {
+            return Err(ApprovalError::CommitMismatch(
+                approval.commit.clone(),
+                commit.to_string(),
+            ));
+        }
+        if approval.bundle.as_deref() != bundle {
+            return Err(ApprovalError::BundleMismatch);
+        }
+
+        let publisher = match self.keys.iter().find(|(_, key)| key == publisher_key) {
+            Some((name, _)) => name,
+            None => return Err(ApprovalError::UnknownPublisher),
+        };
+        let key = match self
+            .keys
+            .iter()
+            .find(|(name, _)| name == &approval.approver)
+        {
+            Some((_, key)) => key,
+            None => return Err(ApprovalError::UnknownApprover(approval.approver.clone())),
+        };
+        // The same key listed under another name is still the publisher
+        if &approval.approver == publisher || key == publisher_key {
+            return Err(ApprovalError::SelfApproved(approval.approver.clone()));
+        }
+        let signature = STANDARD
+            .decode(&approval.signature)
+            .map_err(|_| ApprovalError::BadSignature(approval.approver.clone()))?;
+
+        UnparsedPublicKey::new(&ED25519, key)
+            .verify(&message(commit, bundle), &signature)
+            .map_err(|_| ApprovalError::BadSignature(approval.approver.clone()))
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::{Cursor, Read, Write},
+};
+
+use cab::{CabinetBuilder, CompressionType};
+use serde::{Deserialize, Serialize};
+
+use crate::{families::font_faces, types::FontFace};
+
+pub enum ArchiveError {
+    /// No cabinet could be found in the file
+    NotACabinet,
+    /// The archive doesn't contain the file (name)
+    MissingFile(String),
+    /// Reading or writing the archive failed (error)
+    Io(String),
+}
+
+impl Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::NotACabinet => write!(f, "No cabinet found"),
+            ArchiveError::MissingFile(name) => write!(f, "Missing file in archive ({})", name),
+            ArchiveError::Io(error) => write!(f, "Archive error ({})", error),
+        }
+    }
+}
+
+/// Finds the start of a cabinet (plain .cab files and self-extracting .exe files)
+fn find_cabinet(data: &[u8]) -> Option<usize> {
+    // MSCF, 4 reserved zero bytes, ... version 1.3 at offset 24
+    (0..data.len().saturating_sub(26)).find(|&i| {
+        &data[i..i + 4] == b"MSCF"
+            && data[i + 4..i + 8] == [0, 0, 0, 0]
+            && data[i + 24] == 3
+            && data[i + 25] == 1
+    })
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A file inside an archive
+pub struct ArchiveFile {
+    /// The uncompressed size
+    pub size: u64,
+    /// The sha256 of the extracted file
+    pub hash: String,
+    /// The faces (empty if it isn't a font), missing in entries cached before they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub faces: Option<Vec<FontFace>>,
+}
+
+/// A cabinet archive (what `cabextract` extracts)
+pub struct Archive {
+    cabinet: cab::Cabinet<Cursor<Vec<u8>>>,
+}
+
+impl Archive {
+    pub fn open(data: &[u8]) -> Result<Archive, ArchiveError> {
+        let offset = find_cabinet(data).ok_or(ArchiveError::NotACabinet)?;
+
+        match cab::Cabinet::new(Cursor::new(data[offset..].to_vec())) {
+            Ok(cabinet) => Ok(Archive { cabinet }),
+            Err(e) => Err(ArchiveError::Io(e.to_string())),
+        }
+    }
+
+    /// The names of all files in the archive
+    pub fn file_names(&self) -> Vec<String> {
+        self.cabinet
+            .folder_entries()
+            .flat_map(|folder| folder.file_entries())
+            .map(|file| file.name().to_string())
+            .collect()
+    }
+
+    /// Extracts every file in the archive to record its size and hash (by name)
+    pub fn files(&mut self) -> Result<BTreeMap<String, ArchiveFile>, ArchiveError> {
+        let mut files = BTreeMap::new();
+
+        for name in self.file_names() {
+            let data = self.read(&name)?;
+            files.insert(
+                name,
+                ArchiveFile {
+                    size: data.len() as u64,
+                    hash: sha256::digest(&data),
+                    faces: Some(font_faces(&data)),
+                },
+            );
+        }
+
+        Ok(files)
+    }
+
+    /// The real name of a file (names are matched case-insensitively, like `cabextract -L`)
+    fn real_name(&self, name: &str) -> Result<String, ArchiveError> {
+        self.file_names()
+            .into_iter()
+            .find(|file| file.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ArchiveError::MissingFile(name.to_string()))
+    }
+
+    /// Reads a file from the archive
+    pub fn read(&mut self, name: &str) -> Result<Vec<u8>, ArchiveError> {
+        let real_name = self.real_name(name)?;
+
+        let mut data = vec![];
+        match self.cabinet.read_file(&real_name) {
+            Ok(mut reader) => match reader.read_to_end(&mut data) {
+                Ok(_) => Ok(data),
+                Err(e) => Err(ArchiveError::Io(e.to_string())),
+            },
+            Err(e) => Err(ArchiveError::Io(e.to_string())),
+        }
+    }
+}
+
+/// Packs files (name, data) into a new cabinet
+pub fn build_cabinet(files: &[(String, Vec<u8>)]) -> Result<Vec<u8>, ArchiveError> {
+    let mut builder = CabinetBuilder::new();
+    let folder = builder.add_folder(CompressionType::MsZip);
+    for (name, _) in files {
+        folder.add_file(name.clone());
+    }
+
+    let io_error = |e: std::io::Error| ArchiveError::Io(e.to_string());
+
+    let mut writer = builder.build(Cursor::new(vec![])).map_err(io_error)?;
+    let mut index = 0;
+    while let Some(mut file) = writer.next_file().map_err(io_error)? {
+        file.write_all(&files[index].1).map_err(io_error)?;
+        index += 1;
+    }
+
+    Ok(writer.finish().map_err(io_error)?.into_inner())
+}
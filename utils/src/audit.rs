@@ -0,0 +1,114 @@
+use s3::{error::S3Error, Bucket};
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::utils::now;
+
+/// One json record per line, oldest first
+pub const AUDIT_LOG_PATH: &str = "audit-log.jsonl";
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Operation {
+    Update,
+    Gc,
+    Repair,
+    Promote,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A change made to the bucket
+pub struct AuditRecord {
+    pub operation: Operation,
+    /// Who ran it (`WINEFONTS_ACTOR`, `GITHUB_ACTOR` or the user name)
+    pub actor: String,
+    /// Unix time (in seconds)
+    pub timestamp: u64,
+    /// Version of the tool that made the change
+    pub tool_version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<Version>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<Uuid>,
+    /// Fonts added or removed (by an update)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed_fonts: Option<usize>,
+    /// What was changed, for humans
+    pub summary: String,
+}
+
+/// Who is making the change
+pub fn actor() -> String {
+    ["WINEFONTS_ACTOR", "GITHUB_ACTOR", "USER", "USERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|value| !value.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+impl AuditRecord {
+    pub fn new(operation: Operation, summary: String) -> AuditRecord {
+        AuditRecord {
+            operation,
+            actor: actor(),
+            timestamp: now(),
+            tool_version: env!("CARGO_PKG_VERSION").to_string(),
+            version: None,
+            version_id: None,
+            changed_fonts: None,
+            summary,
+        }
+    }
+
+    pub fn with_version(mut self, version: Version, id: Uuid) -> AuditRecord {
+        self.version = Some(version);
+        self.version_id = Some(id);
+        self
+    }
+
+    pub fn with_changed_fonts(mut self, changed_fonts: usize) -> AuditRecord {
+        self.changed_fonts = Some(changed_fonts);
+        self
+    }
+}
+
+/// Appends a record to the audit log (S3 can't append, so the log is read and written back)
+///
+/// The change is already made when it's recorded, failing only loses the record.
+pub async fn append(s3: &Bucket, record: &AuditRecord) {
+    let mut log = match s3.get_object(AUDIT_LOG_PATH).await {
+        Ok(data) => data.to_vec(),
+        // The first record
+        Err(S3Error::Http(404, _)) => vec![],
+        // Don't overwrite a log that couldn't be read
+        Err(e) => {
+            warn!(
+                "Failed to read {}: {}, the change isn't recorded",
+                AUDIT_LOG_PATH, e
+            );
+            return;
+        }
+    };
+
+    let line = match serde_json::to_vec(record) {
+        Ok(line) => line,
+        Err(e) => {
+            warn!("Failed to serialize the audit record: {}", e);
+            return;
+        }
+    };
+    if !log.is_empty() && !log.ends_with(b"\n") {
+        log.push(b'\n');
+    }
+    log.extend(line);
+    log.push(b'\n');
+
+    match s3
+        .put_object_with_content_type(AUDIT_LOG_PATH, &log, "application/x-ndjson")
+        .await
+    {
+        Ok(_) => info!("Recorded the change in {}", AUDIT_LOG_PATH),
+        Err(e) => warn!("Failed to write {}: {}", AUDIT_LOG_PATH, e),
+    }
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{types::Compiled, utils::format_size};
+
+/// The version of the shields.io endpoint schema
+const SCHEMA_VERSION: u32 = 1;
+
+const COLOR: &str = "blue";
+/// The version badge of a pre-release
+const PRERELEASE_COLOR: &str = "orange";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A shields.io endpoint badge, shown with `https://img.shields.io/endpoint?url=<badge url>`
+pub struct Badge {
+    pub schema_version: u32,
+    pub label: String,
+    pub message: String,
+    pub color: String,
+}
+
+impl Badge {
+    fn new(label: &str, message: String, color: &str) -> Badge {
+        Badge {
+            schema_version: SCHEMA_VERSION,
+            label: label.to_string(),
+            message,
+            color: color.to_string(),
+        }
+    }
+}
+
+/// The badges of a database by name: how many fonts it has, the size of its downloads and its
+/// version
+pub fn badges(file: &Compiled) -> Vec<(&'static str, Badge)> {
+    let size = file
+        .downloads
+        .iter()
+        .map(|download| download.file_size)
+        .sum();
+    let color = match file.version.pre.is_empty() {
+        true => COLOR,
+        false => PRERELEASE_COLOR,
+    };
+
+    vec![
+        (
+            "fonts",
+            Badge::new("fonts", file.fonts.len().to_string(), COLOR),
+        ),
+        ("size", Badge::new("size", format_size(size), COLOR)),
+        (
+            "version",
+            Badge::new("database", format!("v{}", file.version), color),
+        ),
+    ]
+}
@@ -0,0 +1,44 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+};
+
+use futures::stream::{self, StreamExt};
+
+/// How many prefixes are worked on at once by default
+pub const DEFAULT_JOBS: usize = 4;
+
+/// The outcome of an operation on one prefix
+pub struct PrefixResult<T, E> {
+    pub prefix: PathBuf,
+    pub result: Result<T, E>,
+}
+
+/// Runs the operation on every prefix (e.g. the prefixes of a Lutris library), at most `jobs`
+/// at once, the results are in the order of the prefixes
+///
+/// One prefix failing doesn't stop the others. Downloads the prefixes share are only fetched
+/// once, see `ArtifactCache::lock`.
+pub async fn for_each_prefix<'a, T, E, F, Fut>(
+    prefixes: &'a [PathBuf],
+    jobs: usize,
+    operation: F,
+) -> Vec<PrefixResult<T, E>>
+where
+    F: Fn(&'a Path) -> Fut,
+    Fut: Future<Output = Result<T, E>> + 'a,
+{
+    stream::iter(prefixes)
+        .map(|prefix| {
+            let result = operation(prefix);
+            async move {
+                PrefixResult {
+                    prefix: prefix.clone(),
+                    result: result.await,
+                }
+            }
+        })
+        .buffered(jobs.max(1))
+        .collect()
+        .await
+}
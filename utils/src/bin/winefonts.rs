@@ -0,0 +1,1039 @@
+#[macro_use]
+extern crate tracing;
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use clap::{Parser, Subcommand, ValueEnum};
+use semver::Version;
+use url::Url;
+use uuid::Uuid;
+
+#[cfg(feature = "windows-native")]
+use utils::native;
+use utils::{
+    batch::{self, PrefixResult},
+    client::{check_for_update, DatabaseUpdate, DownloadManager, DEFAULT_DATABASE_URL},
+    export, fetch, host, install,
+    logging::{self, LogFormat},
+    policy::{self, InstallTarget},
+    preflight::{check_space, required_space},
+    profile::{Profile, DEFAULT_PROFILE_FILE},
+    store::{default_cache_dir, parse_size, ArtifactCache, EvictionPolicy},
+    system::{host_font_directories, missing_fonts, PresentFamilies},
+    tui,
+    types::{Compiled, CompiledApplication, CompiledFont},
+    utils::{format_size, now},
+    wine,
+};
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    /// Bottles dependency manifests (a directory)
+    Bottles,
+    /// A Lutris installer (a file)
+    Lutris,
+    /// A lookup of the fonts Steam games need by app id, for Proton helpers (a json file,
+    /// the references select applications)
+    SteamCompat,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// Installs fonts (or groups) into a prefix
+    Install {
+        #[clap(long = "prefix", env = "WINEPREFIX", required = true)]
+        /// The wine prefix (repeat it for several, they're worked on concurrently)
+        prefixes: Vec<PathBuf>,
+
+        #[clap(long, default_value_t = batch::DEFAULT_JOBS)]
+        /// How many prefixes are worked on at once
+        jobs: usize,
+
+        #[clap(long, env = "WINEFONTS_DATABASE_URL", default_value = DEFAULT_DATABASE_URL)]
+        /// The versions.json of the database
+        database_url: Url,
+
+        #[clap(required = true)]
+        /// Font, group or application names (or uuids, or `steam:<app id>`)
+        fonts: Vec<String>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_SIZE", value_parser = parse_cache_size)]
+        /// Evict the least recently used downloads until the cache fits (e.g. `500M`, `2G`)
+        cache_max_size: Option<u64>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_AGE")]
+        /// Evict downloads that weren't used for this many days
+        cache_max_age: Option<u64>,
+
+        #[clap(long, env = "WINE", default_value = "wine")]
+        /// The wine binary used to register the fonts
+        wine: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_WINE_VERSION", value_parser = parse_wine_version)]
+        /// The wine version fonts have to work with (defaults to the version of the wine binary)
+        wine_version: Option<Version>,
+
+        #[clap(long)]
+        /// Install fonts even if the database says they don't work with the wine version
+        ignore_wine_version: bool,
+
+        #[clap(long)]
+        /// Install fonts even if the host or prefix already has them
+        reinstall: bool,
+
+        #[clap(long)]
+        /// Request every mirror at once and download from the fastest
+        race: bool,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    #[cfg(feature = "windows-native")]
+    /// Installs fonts (or groups) into Windows itself (`C:\Windows\Fonts`), needs an elevated
+    /// shell
+    InstallNative {
+        #[clap(long, env = "WINEFONTS_DATABASE_URL", default_value = DEFAULT_DATABASE_URL)]
+        /// The versions.json of the database
+        database_url: Url,
+
+        #[clap(required = true)]
+        /// Font, group or application names (or uuids, or `steam:<app id>`)
+        fonts: Vec<String>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Install fonts even if Windows already has them
+        reinstall: bool,
+
+        #[clap(long)]
+        /// Request every mirror at once and download from the fastest
+        race: bool,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Exports fonts (or groups) as manifests other wine managers can install
+    Export {
+        #[clap(value_enum)]
+        format: ExportFormat,
+
+        #[clap(long, env = "WINEFONTS_DATABASE_URL", default_value = DEFAULT_DATABASE_URL)]
+        /// The versions.json of the database
+        database_url: Url,
+
+        #[clap(short, long)]
+        /// Where to write the export (a directory for bottles, a file for lutris)
+        output: PathBuf,
+
+        /// Font, group or application names (or uuids, or `steam:<app id>`), every font (every
+        /// application for steam-compat) if empty
+        fonts: Vec<String>,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Browses the database and installs the picked fonts interactively
+    Tui {
+        #[clap(long, env = "WINEPREFIX")]
+        /// The wine prefix
+        prefix: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_DATABASE_URL", default_value = DEFAULT_DATABASE_URL)]
+        /// The versions.json of the database
+        database_url: Url,
+
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_SIZE", value_parser = parse_cache_size)]
+        /// Evict the least recently used downloads until the cache fits (e.g. `500M`, `2G`)
+        cache_max_size: Option<u64>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_AGE")]
+        /// Evict downloads that weren't used for this many days
+        cache_max_age: Option<u64>,
+
+        #[clap(long, env = "WINE", default_value = "wine")]
+        /// The wine binary used to register the fonts
+        wine: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_WINE_VERSION", value_parser = parse_wine_version)]
+        /// The wine version fonts have to work with (defaults to the version of the wine binary)
+        wine_version: Option<Version>,
+
+        #[clap(long)]
+        /// List fonts even if the database says they don't work with the wine version
+        ignore_wine_version: bool,
+
+        #[clap(long)]
+        /// Request every mirror at once and download from the fastest
+        race: bool,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Installs and uninstalls fonts until the prefix has exactly the given fonts (or groups)
+    Sync {
+        #[clap(long = "prefix", env = "WINEPREFIX", required = true)]
+        /// The wine prefix (repeat it for several, they're worked on concurrently)
+        prefixes: Vec<PathBuf>,
+
+        #[clap(long, default_value_t = batch::DEFAULT_JOBS)]
+        /// How many prefixes are worked on at once
+        jobs: usize,
+
+        #[clap(long, env = "WINEFONTS_DATABASE_URL")]
+        /// The versions.json of the database (defaults to the profile's, then the official one)
+        database_url: Option<Url>,
+
+        /// Font, group or application names (or uuids, or `steam:<app id>`), every installed
+        /// font is uninstalled if empty
+        fonts: Vec<String>,
+
+        #[clap(long, num_args = 0..=1, default_missing_value = DEFAULT_PROFILE_FILE, conflicts_with = "fonts")]
+        /// Take the fonts and options from a profile (`winefonts-profile.toml` if no path is
+        /// given), the flags win over its options
+        profile: Option<PathBuf>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_SIZE", value_parser = parse_cache_size)]
+        /// Evict the least recently used downloads until the cache fits (e.g. `500M`, `2G`)
+        cache_max_size: Option<u64>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_MAX_AGE")]
+        /// Evict downloads that weren't used for this many days
+        cache_max_age: Option<u64>,
+
+        #[clap(long, env = "WINE", default_value = "wine")]
+        /// The wine binary used to register the fonts
+        wine: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_WINE_VERSION", value_parser = parse_wine_version)]
+        /// The wine version fonts have to work with (defaults to the version of the wine binary)
+        wine_version: Option<Version>,
+
+        #[clap(long)]
+        /// Keep fonts even if the database says they don't work with the wine version
+        ignore_wine_version: bool,
+
+        #[clap(long)]
+        /// Only print what would change
+        dry_run: bool,
+
+        #[clap(long)]
+        /// Request every mirror at once and download from the fastest
+        race: bool,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Installs fonts for the host itself (its native apps), not only for wine
+    Host {
+        #[command(subcommand)]
+        action: HostCommands,
+    },
+    /// Shows or clears the download cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum HostCommands {
+    /// Installs fonts (or groups) into `~/.local/share/fonts` (refreshing fontconfig's cache), or
+    /// `~/Library/Fonts` on macOS
+    Install {
+        #[clap(long, env = "WINEFONTS_DATABASE_URL", default_value = DEFAULT_DATABASE_URL)]
+        /// The versions.json of the database
+        database_url: Url,
+
+        #[clap(required = true)]
+        /// Font, group or application names (or uuids, or `steam:<app id>`)
+        fonts: Vec<String>,
+
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Install fonts even if the host already has them
+        reinstall: bool,
+
+        #[clap(long)]
+        /// Request every mirror at once and download from the fastest
+        race: bool,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Uninstalls fonts installed for the host
+    Uninstall {
+        #[clap(required = true)]
+        /// Font names (or uuids)
+        fonts: Vec<String>,
+    },
+    /// Lists the fonts installed for the host
+    List,
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Shows how many downloads are cached and how much space they take
+    Stats {
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+    },
+    /// Removes every cached download
+    Clear {
+        #[clap(long, env = "WINEFONTS_CACHE_DIR")]
+        /// Where downloads are kept (defaults to the user cache directory)
+        cache_dir: Option<PathBuf>,
+    },
+}
+
+fn parse_cache_size(size: &str) -> Result<u64, String> {
+    parse_size(size).ok_or_else(|| format!("\"{}\" isn't a size", size))
+}
+
+/// Applies the cache limits (if there are any)
+async fn evict(cache: &ArtifactCache, max_size: Option<u64>, max_age: Option<u64>) {
+    let policy = EvictionPolicy {
+        max_size,
+        max_age: max_age.map(|days| Duration::from_secs(days * 24 * 60 * 60)),
+    };
+    if policy.is_unbounded() {
+        return;
+    }
+
+    let evicted = cache.evict(&policy).await;
+    if !evicted.is_empty() {
+        info!(
+            "Evicted {} downloads from the cache ({} left)",
+            evicted.len(),
+            format_size(cache.stats().size)
+        );
+    }
+}
+
+fn parse_wine_version(version: &str) -> Result<Version, String> {
+    wine::parse_version(version).ok_or_else(|| format!("\"{}\" isn't a wine version", version))
+}
+
+#[derive(Parser)]
+#[command(
+    author = "WineFonts Team",
+    about = "Installs fonts from the WineFonts database into wine prefixes"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    /// How logs are written
+    log_format: LogFormat,
+}
+
+fn fetcher_from_interval(request_interval: u64) -> fetch::Fetcher {
+    match fetch::Fetcher::new(Duration::from_millis(request_interval)) {
+        Ok(fetcher) => fetcher,
+        Err(error) => {
+            error!("Failed to create http client: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exits if the license of any of the fonts doesn't allow the install target (before anything
+/// is downloaded)
+fn exit_on_violations(fonts: &[&CompiledFont], target: InstallTarget) {
+    let violations = policy::check(fonts, target);
+    for violation in &violations {
+        error!("{}", violation);
+    }
+    if !violations.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// Resolves font, group and application references (they can overlap), exits on unknown
+/// references
+fn select_fonts<'a>(compiled: &'a Compiled, references: &[String]) -> Vec<&'a CompiledFont> {
+    let mut selected: Vec<&CompiledFont> = vec![];
+    let mut seen = HashSet::new();
+
+    for reference in references {
+        match compiled.select(reference) {
+            Some(found) => selected.extend(found.into_iter().filter(|f| seen.insert(f.id))),
+            None => {
+                error!("Unknown font, group or application: {}", reference);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    selected
+}
+
+/// Resolves application references, exits on unknown references
+fn select_applications<'a>(
+    compiled: &'a Compiled,
+    references: &[String],
+) -> Vec<&'a CompiledApplication> {
+    let mut selected: Vec<&CompiledApplication> = vec![];
+
+    for reference in references {
+        match compiled.find_application(reference) {
+            Some(found) if !selected.iter().any(|a| a.id == found.id) => selected.push(found),
+            Some(_) => {}
+            None => {
+                error!("Unknown application: {}", reference);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    selected
+}
+
+/// Whether a font works with the wine version, logs the fonts that don't
+fn supports_wine(font: &CompiledFont, version: &Version) -> bool {
+    let compatibility = match &font.wine {
+        Some(compatibility) if !compatibility.supports(version) => compatibility,
+        _ => return true,
+    };
+
+    match &compatibility.reason {
+        Some(reason) => warn!(
+            "Skipping {}, it doesn't work with wine {}: {}",
+            font.name, version, reason
+        ),
+        None => warn!(
+            "Skipping {}, it doesn't work with wine {}",
+            font.name, version
+        ),
+    }
+    false
+}
+
+/// The wine version fonts are filtered by (none if it's ignored or can't be detected)
+async fn wine_version(wine: &Path, version: Option<Version>, ignore: bool) -> Option<Version> {
+    if ignore {
+        return None;
+    }
+    if version.is_some() {
+        return version;
+    }
+
+    let detected = wine::detect(wine).await;
+    match &detected {
+        Some(version) => info!("Using wine {}", version),
+        None => warn!(
+            "Failed to get the version of {}, fonts aren't checked against it (pass --wine-version)",
+            wine.display()
+        ),
+    }
+    detected
+}
+
+/// Writes an exported file, exits on failure
+async fn write_export(path: &PathBuf, data: String) {
+    match tokio::fs::write(path, data).await {
+        Ok(_) => info!("Wrote {}", path.display()),
+        Err(error) => {
+            error!("Failed to write {}: {}", path.display(), error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The latest database (the cached one while it's still the latest), exits on failure
+async fn database(fetcher: &fetch::Fetcher, database_url: &Url, cache_dir: &Path) -> Compiled {
+    match check_for_update(fetcher, database_url, cache_dir).await {
+        Ok(DatabaseUpdate::UpToDate(compiled)) => {
+            info!("Using database {}", compiled.version);
+            compiled
+        }
+        Ok(DatabaseUpdate::Updated(compiled)) => {
+            info!("Updated to database {}", compiled.version);
+            compiled
+        }
+        Err(error) => {
+            error!("Failed to fetch the database: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// What the operations on the prefixes share
+struct Target<'a> {
+    compiled: &'a Compiled,
+    wine: &'a Path,
+    cache_dir: &'a Path,
+    cache: &'a ArtifactCache,
+    manager: &'a DownloadManager<'a>,
+}
+
+/// Installs the fonts the prefix (or the host) doesn't have yet, returns how many were installed
+async fn install_prefix(
+    prefix: &Path,
+    target: &Target<'_>,
+    host: &PresentFamilies,
+    fonts: &[&CompiledFont],
+    reinstall: bool,
+) -> Result<usize, String> {
+    let fonts = missing_fonts(fonts.to_vec(), &host.with_prefix(prefix), reinstall);
+    if fonts.is_empty() {
+        return Ok(0);
+    }
+
+    let requirement = required_space(target.compiled, &fonts).map_err(|e| e.to_string())?;
+    check_space(target.cache_dir, prefix, requirement).map_err(|e| e.to_string())?;
+
+    let mut installed = vec![];
+    for font in &fonts {
+        let entries =
+            install::install_font(prefix, target.compiled, font, target.manager, target.cache)
+                .await
+                .map_err(|e| format!("Failed to install {}: {}", font.name, e))?;
+        info!("Installed {} into {}", font.name, prefix.display());
+        installed.extend(entries);
+    }
+    install::register(prefix, target.wine, &installed)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    Ok(fonts.len())
+}
+
+/// Installs and uninstalls fonts until the prefix has exactly the desired fonts, returns what
+/// changed (or would have)
+async fn sync_prefix<'a>(
+    prefix: &Path,
+    target: &Target<'a>,
+    desired: &[&'a CompiledFont],
+    dry_run: bool,
+) -> Result<install::SyncPlan<'a>, String> {
+    let manifest = install::InstallManifest::load(prefix)
+        .await
+        .map_err(|e| e.to_string())?;
+    let plan = install::plan_sync(&manifest, target.compiled, desired);
+    for font in &plan.install {
+        info!("{}: install {}", prefix.display(), font.name);
+    }
+    for font in &plan.update {
+        info!("{}: update {}", prefix.display(), font.name);
+    }
+    for (_, name) in &plan.uninstall {
+        info!("{}: uninstall {}", prefix.display(), name);
+    }
+    if plan.is_empty() || dry_run {
+        return Ok(plan);
+    }
+
+    let fetched: Vec<&CompiledFont> = plan.install.iter().chain(&plan.update).copied().collect();
+    let requirement = required_space(target.compiled, &fetched).map_err(|e| e.to_string())?;
+    check_space(target.cache_dir, prefix, requirement).map_err(|e| e.to_string())?;
+
+    // Updated fonts are uninstalled first, their files or registry names may be gone
+    let mut removed = vec![];
+    let outdated = plan.update.iter().map(|font| (font.id, &font.name));
+    let unwanted = plan.uninstall.iter().map(|(id, name)| (*id, name));
+    for (id, name) in outdated.chain(unwanted) {
+        let entries = install::uninstall_font(prefix, id)
+            .await
+            .map_err(|e| format!("Failed to uninstall {}: {}", name, e))?;
+        removed.extend(entries);
+    }
+    if !removed.is_empty() {
+        install::unregister(prefix, target.wine, &removed)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut installed = vec![];
+    for font in &fetched {
+        let entries =
+            install::install_font(prefix, target.compiled, font, target.manager, target.cache)
+                .await
+                .map_err(|e| format!("Failed to install {}: {}", font.name, e))?;
+        installed.extend(entries);
+    }
+    if !installed.is_empty() {
+        install::register(prefix, target.wine, &installed)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+
+    Ok(plan)
+}
+
+#[tokio::main]
+async fn main() {
+    // CLI parser
+    let parser = Cli::parse();
+
+    // Init the logger (the level defaults to info unless RUST_LOG is set, logs would draw over
+    // the tui)
+    let level = match parser.command {
+        Commands::Tui { .. } => "off",
+        _ => "info",
+    };
+    logging::init(parser.log_format, level);
+
+    match parser.command {
+        Commands::Install {
+            prefixes,
+            jobs,
+            database_url,
+            fonts,
+            cache_dir,
+            cache_max_size,
+            cache_max_age,
+            wine,
+            wine_version: version,
+            ignore_wine_version,
+            reinstall,
+            race,
+            request_interval,
+        } => {
+            let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+            let cache = ArtifactCache::open(cache_dir.clone()).await;
+            let fetcher = fetcher_from_interval(request_interval);
+            let compiled = database(&fetcher, &database_url, &cache_dir).await;
+
+            let mut selected = select_fonts(&compiled, &fonts);
+            if let Some(version) = wine_version(&wine, version, ignore_wine_version).await {
+                selected.retain(|font| supports_wine(font, &version));
+            }
+
+            let manager = DownloadManager::new(&fetcher, None).race(race);
+            let target = Target {
+                compiled: &compiled,
+                wine: &wine,
+                cache_dir: &cache_dir,
+                cache: &cache,
+                manager: &manager,
+            };
+
+            let host = PresentFamilies::scan(&host_font_directories());
+            let results = batch::for_each_prefix(&prefixes, jobs, |prefix| {
+                install_prefix(prefix, &target, &host, &selected, reinstall)
+            })
+            .await;
+            evict(&cache, cache_max_size, cache_max_age).await;
+
+            let mut failed = false;
+            for PrefixResult { prefix, result } in results {
+                match result {
+                    Ok(0) => info!("{}: nothing to install", prefix.display()),
+                    Ok(count) => info!("{}: installed {} fonts", prefix.display(), count),
+                    Err(error) => {
+                        error!("{}: {}", prefix.display(), error);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        #[cfg(feature = "windows-native")]
+        Commands::InstallNative {
+            database_url,
+            fonts,
+            cache_dir,
+            reinstall,
+            race,
+            request_interval,
+        } => {
+            let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+            let cache = ArtifactCache::open(cache_dir.clone()).await;
+            let fetcher = fetcher_from_interval(request_interval);
+            let compiled = database(&fetcher, &database_url, &cache_dir).await;
+
+            let present = PresentFamilies::scan(&[native::fonts_directory()]);
+            let selected = missing_fonts(select_fonts(&compiled, &fonts), &present, reinstall);
+            exit_on_violations(&selected, InstallTarget::Windows);
+            if selected.is_empty() {
+                info!("Nothing to install");
+                return;
+            }
+
+            let manager = DownloadManager::new(&fetcher, None).race(race);
+            let mut installed = vec![];
+            for font in &selected {
+                match native::install_font(&compiled, font, &manager, &cache).await {
+                    Ok(entries) => {
+                        info!("Installed {}", font.name);
+                        installed.extend(entries);
+                    }
+                    Err(error) => {
+                        error!("Failed to install {}: {}", font.name, error);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            if let Err(error) = native::register(&installed).await {
+                error!("{}", error);
+                std::process::exit(1);
+            }
+
+            info!("Installed {} fonts", selected.len());
+        }
+        Commands::Export {
+            format,
+            database_url,
+            output,
+            fonts,
+            request_interval,
+        } => {
+            let fetcher = fetcher_from_interval(request_interval);
+            let compiled = database(&fetcher, &database_url, &default_cache_dir()).await;
+            let selected = || match fonts.is_empty() {
+                true => compiled.fonts.iter().collect(),
+                false => select_fonts(&compiled, &fonts),
+            };
+
+            match format {
+                ExportFormat::Bottles => {
+                    let files = match export::bottles(&compiled, &selected()) {
+                        Ok(files) => files,
+                        Err(error) => {
+                            error!("{}", error);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    if let Err(error) = tokio::fs::create_dir_all(&output).await {
+                        error!("Failed to create {}: {}", output.display(), error);
+                        std::process::exit(1);
+                    }
+                    for (name, data) in files {
+                        write_export(&output.join(name), data).await;
+                    }
+                }
+                ExportFormat::Lutris => match export::lutris(&compiled, &selected()) {
+                    Ok(data) => write_export(&output, data).await,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                },
+                ExportFormat::SteamCompat => {
+                    let applications = match fonts.is_empty() {
+                        true => compiled.applications.iter().collect(),
+                        false => select_applications(&compiled, &fonts),
+                    };
+                    for application in &applications {
+                        if application.steam_app_id.is_none() && !fonts.is_empty() {
+                            warn!("Skipping {}, it has no Steam app id", application.name);
+                        }
+                    }
+                    match export::steam_compat(&compiled, &applications) {
+                        Ok(data) => write_export(&output, data).await,
+                        Err(error) => {
+                            error!("{}", error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+        }
+        Commands::Tui {
+            prefix,
+            database_url,
+            cache_dir,
+            cache_max_size,
+            cache_max_age,
+            wine,
+            wine_version: version,
+            ignore_wine_version,
+            race,
+            request_interval,
+        } => {
+            let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+            let cache = ArtifactCache::open(cache_dir.clone()).await;
+            let fetcher = fetcher_from_interval(request_interval);
+            let mut compiled = database(&fetcher, &database_url, &cache_dir).await;
+            if let Some(version) = wine_version(&wine, version, ignore_wine_version).await {
+                compiled.fonts.retain(|font| supports_wine(font, &version));
+                let listed: HashSet<_> = compiled.fonts.iter().map(|font| font.id).collect();
+                for group in &mut compiled.groups {
+                    group.fonts.retain(|id| listed.contains(id));
+                }
+                for application in &mut compiled.applications {
+                    application.fonts.retain(|id| listed.contains(id));
+                }
+            }
+            let present = PresentFamilies::scan_host_and_prefix(&prefix);
+            let manager = DownloadManager::new(&fetcher, None).race(race);
+
+            let target = tui::InstallTarget {
+                prefix: &prefix,
+                cache: &cache,
+                wine: &wine,
+                manager: &manager,
+            };
+
+            let mut terminal = ratatui::init();
+            let result = tui::run(&mut terminal, &compiled, &present, &target).await;
+            ratatui::restore();
+            evict(&cache, cache_max_size, cache_max_age).await;
+
+            match result {
+                Ok(0) => {}
+                Ok(count) => println!("Installed {} fonts", count),
+                Err(error) => {
+                    error!("Terminal error: {}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Commands::Sync {
+            prefixes,
+            jobs,
+            database_url,
+            fonts,
+            cache_dir,
+            cache_max_size,
+            cache_max_age,
+            wine,
+            wine_version: version,
+            ignore_wine_version,
+            profile: profile_path,
+            dry_run,
+            race,
+            request_interval,
+        } => {
+            let profile = profile_path.as_ref().map(|path| match Profile::load(path) {
+                Ok(profile) => profile,
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            });
+            let options = profile
+                .as_ref()
+                .map(|profile| profile.options.clone())
+                .unwrap_or_default();
+            let database_url = database_url
+                .or(options.database_url)
+                .unwrap_or_else(|| Url::parse(DEFAULT_DATABASE_URL).unwrap());
+            let version = version.or_else(|| profile.as_ref()?.wine_version());
+            let ignore_wine_version = ignore_wine_version || options.ignore_wine_version;
+            let race = race || options.race;
+
+            let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+            let cache = ArtifactCache::open(cache_dir.clone()).await;
+            let fetcher = fetcher_from_interval(request_interval);
+            let compiled = database(&fetcher, &database_url, &cache_dir).await;
+
+            let mut desired = match (&profile, &profile_path) {
+                (Some(profile), Some(path)) => match profile.select(path, &compiled) {
+                    Ok(desired) => desired,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                },
+                _ => select_fonts(&compiled, &fonts),
+            };
+            if let Some(version) = wine_version(&wine, version, ignore_wine_version).await {
+                desired.retain(|font| supports_wine(font, &version));
+            }
+
+            let manager = DownloadManager::new(&fetcher, None).race(race);
+            let target = Target {
+                compiled: &compiled,
+                wine: &wine,
+                cache_dir: &cache_dir,
+                cache: &cache,
+                manager: &manager,
+            };
+
+            let results = batch::for_each_prefix(&prefixes, jobs, |prefix| {
+                sync_prefix(prefix, &target, &desired, dry_run)
+            })
+            .await;
+            evict(&cache, cache_max_size, cache_max_age).await;
+
+            let mut failed = false;
+            for PrefixResult { prefix, result } in results {
+                match result {
+                    Ok(plan) if plan.is_empty() => {
+                        info!("{}: in sync ({} fonts)", prefix.display(), plan.unchanged)
+                    }
+                    Ok(plan) if dry_run => info!(
+                        "{}: would install {}, update {} and uninstall {} fonts",
+                        prefix.display(),
+                        plan.install.len(),
+                        plan.update.len(),
+                        plan.uninstall.len()
+                    ),
+                    Ok(plan) => info!(
+                        "{}: synced ({} installed, {} updated, {} uninstalled)",
+                        prefix.display(),
+                        plan.install.len(),
+                        plan.update.len(),
+                        plan.uninstall.len()
+                    ),
+                    Err(error) => {
+                        error!("{}: {}", prefix.display(), error);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Host { action } => match action {
+            HostCommands::Install {
+                database_url,
+                fonts,
+                cache_dir,
+                reinstall,
+                race,
+                request_interval,
+            } => {
+                let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+                let cache = ArtifactCache::open(cache_dir.clone()).await;
+                let fetcher = fetcher_from_interval(request_interval);
+                let compiled = database(&fetcher, &database_url, &cache_dir).await;
+
+                let present = PresentFamilies::scan(&host_font_directories());
+                let selected = missing_fonts(select_fonts(&compiled, &fonts), &present, reinstall);
+                exit_on_violations(&selected, InstallTarget::Host);
+                if selected.is_empty() {
+                    info!("Nothing to install");
+                    return;
+                }
+
+                let manager = DownloadManager::new(&fetcher, None).race(race);
+                for font in &selected {
+                    match host::install_font(&compiled, font, &manager, &cache).await {
+                        Ok(_) => info!("Installed {}", font.name),
+                        Err(error) => {
+                            error!("Failed to install {}: {}", font.name, error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                // The fonts are there, fontconfig picks them up on its own eventually
+                if let Err(error) = host::refresh().await {
+                    warn!("{}", error);
+                }
+                info!(
+                    "Installed {} fonts into {}",
+                    selected.len(),
+                    host::fonts_directory().display()
+                );
+            }
+            HostCommands::Uninstall { fonts } => {
+                let manifest = match host::manifest().await {
+                    Ok(manifest) => manifest,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                };
+
+                let mut ids = vec![];
+                for reference in &fonts {
+                    let uuid = Uuid::parse_str(reference).ok();
+                    match manifest.fonts.iter().find(|installed| {
+                        Some(installed.id) == uuid || installed.name.eq_ignore_ascii_case(reference)
+                    }) {
+                        Some(installed) if !ids.contains(&installed.id) => ids.push(installed.id),
+                        Some(_) => {}
+                        None => {
+                            error!("{} isn't installed for the host", reference);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                for id in ids {
+                    match host::uninstall_font(id).await {
+                        Ok(removed) => {
+                            if let Some(installed) = removed.first() {
+                                info!("Uninstalled {}", installed.name);
+                            }
+                        }
+                        Err(error) => {
+                            error!("{}", error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+
+                if let Err(error) = host::refresh().await {
+                    warn!("{}", error);
+                }
+            }
+            HostCommands::List => {
+                let manifest = match host::manifest().await {
+                    Ok(manifest) => manifest,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                };
+
+                let mut seen = HashSet::new();
+                for installed in &manifest.fonts {
+                    if seen.insert(installed.id) {
+                        println!(
+                            "{} ({}, {})",
+                            installed.name, installed.id, installed.version
+                        );
+                    }
+                }
+            }
+        },
+        Commands::Cache { action } => match action {
+            CacheCommands::Stats { cache_dir } => {
+                let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+                let stats = ArtifactCache::open(cache_dir.clone()).await.stats();
+
+                println!("Directory: {}", cache_dir.display());
+                println!("Downloads: {}", stats.downloads);
+                println!("Size: {}", format_size(stats.size));
+                if let Some(last_used) = stats.least_recently_used {
+                    let age = now().saturating_sub(last_used);
+                    println!("Least recently used: {} days ago", age / (24 * 60 * 60));
+                }
+            }
+            CacheCommands::Clear { cache_dir } => {
+                let cache_dir = cache_dir.unwrap_or_else(default_cache_dir);
+                let freed = ArtifactCache::open(cache_dir).await.clear().await;
+                info!("Cleared the cache ({} freed)", format_size(freed));
+            }
+        },
+    }
+}
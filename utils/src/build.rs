@@ -1,15 +1,37 @@
-use std::{collections::HashMap, fmt::Display, path::PathBuf};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    time::Instant,
+};
 
+use memmap2::Mmap;
+use reqwest::{header::HeaderMap, StatusCode};
 use semver::Version;
+use tracing::Instrument;
 use url::Url;
 use uuid::Uuid;
 
 use crate::{
+    archive::{Archive, ArchiveFile},
+    cache::{CacheEntry, HttpCache},
+    cancel::Cancellation,
+    exit,
+    families::font_faces,
+    fetch::{FetchError, Fetcher},
+    lock::{describe, LockedDownload, Lockfile},
+    metrics::{Timings, TransferDirection},
+    report::{MergedDownload, PrunedDownloads},
+    resolve::{resolve, ResolvedDownload},
+    scan::Scanner,
+    subset::repack_archive,
     types::{
-        CabextractInstalationCompiled, Compiled, CompiledDownloads, CompiledInstalationType,
-        Source, SourceDownload, SourceInstalationType, SourceUUID,
+        is_plain_file_name, CabextractInstalationCompiled, CabextractInstalationExtractData,
+        Compiled, CompiledApplication, CompiledDownloads, CompiledFamily, CompiledIndexes,
+        CompiledInstalationType, CompiledPublisher, DownloadAccess, FontFace, Source,
+        SourceDownload, SourceInstalationType, SourceUUID, SubsetOptions, Woff2InstalationCompiled,
     },
-    utils::{generate_url, DownloadsList, UploadableDownloadInfo},
+    utils::{generate_url, DownloadsList, UploadableData, UploadableDownloadInfo},
 };
 
 pub enum BuildError {
@@ -19,11 +41,46 @@ pub enum BuildError {
     /// A font is missing (name)
     MissingFont(String),
 
+    /// A publisher is missing (reference)
+    MissingPublisher(String),
+
     /// The download failed (URL, error)
     DownloadFailed(Url, String),
 
     /// File not found (path, error)
     FileError(PathBuf, String),
+
+    /// A download couldn't be resolved (error)
+    ResolveFailed(String),
+
+    /// A derived download couldn't be generated (error)
+    DeriveFailed(String),
+
+    /// The scanner rejected or failed on an artifact (error)
+    ScanFailed(String),
+
+    /// A download id isn't in the built downloads, a build bug (referenced by, id)
+    DanglingDownload(String, Uuid),
+
+    /// A download doesn't match its lockfile entry anymore (Source, Locked hash, Hash)
+    LockMismatch(String, String, String),
+
+    /// A file name isn't a single plain path component (font, file)
+    UnsafeFileName(String, String),
+
+    /// The user interrupted the build
+    Cancelled,
+}
+
+impl BuildError {
+    /// The exit code the error is reported with (see `exit`)
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            BuildError::DownloadFailed(_, _) | BuildError::ResolveFailed(_) => exit::NETWORK,
+            BuildError::FileError(_, _) => exit::IO,
+            _ => exit::LINT_ERRORS,
+        }
+    }
 }
 
 impl Display for BuildError {
@@ -33,40 +90,551 @@ impl Display for BuildError {
                 write!(f, "Unexpected empty uuid (name: {})", name)
             }
             BuildError::MissingFont(name) => write!(f, "Missing font (name: {})", name),
+            BuildError::MissingPublisher(reference) => {
+                write!(f, "Missing publisher (reference: {})", reference)
+            }
             BuildError::DownloadFailed(url, error) => {
                 write!(f, "Download failed (url: {}, error: {})", url, error)
             }
             BuildError::FileError(path, error) => {
                 write!(f, "File error (path: {}, error: {})", path.display(), error)
             }
+            BuildError::ResolveFailed(error) => write!(f, "Resolve failed ({})", error),
+            BuildError::DeriveFailed(error) => write!(f, "Derive failed ({})", error),
+            BuildError::ScanFailed(error) => write!(f, "Scan failed ({})", error),
+            BuildError::DanglingDownload(referenced_by, id) => write!(
+                f,
+                "Dangling download (referenced by: {}, id: {})",
+                referenced_by, id
+            ),
+            BuildError::LockMismatch(source, locked, hash) => write!(
+                f,
+                "Lock mismatch (source: {}, locked: {}, hash: {}), refresh the lockfile if the change is expected",
+                source, locked, hash
+            ),
+            BuildError::UnsafeFileName(font, file) => {
+                write!(f, "Unsafe file name (font: {}, file: {})", font, file)
+            }
+            BuildError::Cancelled => write!(f, "Cancelled"),
+        }
+    }
+}
+
+/// How a derived download is generated
+enum DerivationKind {
+    /// Split and/or subset the files of a cabinet into a new cabinet
+    Repack(Vec<CabextractInstalationExtractData>, Option<SubsetOptions>),
+    /// Convert a WOFF2 font to a TTF
+    Woff2,
+}
+
+impl DerivationKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            DerivationKind::Repack(_, _) => "cab",
+            DerivationKind::Woff2 => "ttf",
+        }
+    }
+
+    fn derive(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        match self {
+            DerivationKind::Repack(files, subset) => repack_archive(
+                data,
+                files,
+                subset
+                    .as_ref()
+                    .map(|subset| subset.unicode_ranges.as_slice()),
+            )
+            .map_err(|e| e.to_string()),
+            DerivationKind::Woff2 => wuff::decompress_woff2(data).map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// A download generated from another one at build time
+struct Derivation {
+    /// The temp uuid of the installation's download
+    uuid: Uuid,
+    /// The temp uuid of the original download
+    parent: Uuid,
+    download: SourceDownload,
+    kind: DerivationKind,
+}
+
+/// Points every installation using the download `from` to `to`
+pub fn replace_download(built: &mut Compiled, from: Uuid, to: Uuid) {
+    for font in &mut built.fonts {
+        for installation in &mut font.installations {
+            if installation.download() == from {
+                installation.set_download(to);
+            }
         }
     }
 }
 
+/// The files in a download (empty if it isn't a cabinet)
+fn archive_files(data: &[u8]) -> BTreeMap<String, ArchiveFile> {
+    Archive::open(data)
+        .and_then(|mut archive| archive.files())
+        .unwrap_or_default()
+}
+
+/// Records the installed size and file hashes of the cabextract installations using a
+/// download (temp uuid), and the faces of their files
+fn record_installed_files(
+    built: &mut Compiled,
+    download: Uuid,
+    files: &BTreeMap<String, ArchiveFile>,
+    faces: &mut Vec<(Uuid, String, FontFace)>,
+) {
+    for font in &mut built.fonts {
+        for installation in &mut font.installations {
+            if installation.download() != download {
+                continue;
+            }
+
+            if let CompiledInstalationType::Cabextract(data) = installation {
+                let found: Vec<(&String, Option<&ArchiveFile>)> = data
+                    .files
+                    .iter()
+                    .map(|file| {
+                        let archived = files
+                            .iter()
+                            .find(|(name, _)| name.eq_ignore_ascii_case(&file.file))
+                            .map(|(_, archived)| archived);
+                        (&file.file, archived)
+                    })
+                    .collect();
+
+                // Unknown if any of the files isn't in the archive
+                data.installed_size = found
+                    .iter()
+                    .map(|(_, archived)| archived.map(|archived| archived.size))
+                    .sum();
+                data.file_hashes = found
+                    .iter()
+                    .filter_map(|(name, archived)| {
+                        archived.map(|archived| (name.to_string(), archived.hash.clone()))
+                    })
+                    .collect();
+
+                for (name, archived) in found {
+                    for face in archived
+                        .iter()
+                        .flat_map(|archived| archived.faces.iter().flatten())
+                    {
+                        faces.push((font.id, name.to_string(), face.clone()));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Merges identical downloads (same content and access) and drops the ones nothing installs or
+/// derives from, published ids are kept over new ones
+pub fn prune_downloads(built: &mut Compiled, downloadables: &DownloadsList) -> PrunedDownloads {
+    let mut pruned = PrunedDownloads::default();
+    let published = |id: Uuid| downloadables.iter().any(|d| d.id == id);
+
+    // Merge
+    let mut kept: Vec<CompiledDownloads> = vec![];
+    for download in std::mem::take(&mut built.downloads) {
+        let same = kept
+            .iter_mut()
+            .find(|k| k.hash == download.hash && k.access == download.access);
+        let same = match same {
+            Some(same) => same,
+            None => {
+                kept.push(download);
+                continue;
+            }
+        };
+
+        if same.id == download.id {
+            pruned.duplicated.push(download.id);
+            continue;
+        }
+
+        let (into, merged) = match !published(same.id) && published(download.id) {
+            true => {
+                for earlier in &mut pruned.merged {
+                    if earlier.into == same.id {
+                        earlier.into = download.id;
+                    }
+                }
+                (download.id, std::mem::replace(same, download))
+            }
+            false => (same.id, download),
+        };
+        for mirror in merged.mirrors {
+            if !same.mirrors.contains(&mirror) {
+                same.mirrors.push(mirror);
+            }
+        }
+        pruned.merged.push(MergedDownload {
+            id: merged.id,
+            into,
+        });
+    }
+    built.downloads = kept;
+
+    for merged in &pruned.merged {
+        replace_download(built, merged.id, merged.into);
+        for download in &mut built.downloads {
+            if download.derived_from == Some(merged.id) {
+                download.derived_from = Some(merged.into);
+            }
+        }
+    }
+
+    // Drop (until only parents of kept downloads are left)
+    loop {
+        let used = |id: Uuid| {
+            built
+                .fonts
+                .iter()
+                .flat_map(|font| &font.installations)
+                .any(|installation| installation.download() == id)
+                || built.downloads.iter().any(|d| d.derived_from == Some(id))
+        };
+        let unused: Vec<Uuid> = built
+            .downloads
+            .iter()
+            .map(|d| d.id)
+            .filter(|id| !used(*id))
+            .collect();
+        if unused.is_empty() {
+            break;
+        }
+
+        built.downloads.retain(|d| !unused.contains(&d.id));
+        pruned.dropped.extend(unused);
+    }
+
+    pruned
+}
+
+/// Checks every download id the database references was built (no temp uuid is left)
+fn check_downloads(built: &Compiled) -> Result<(), BuildError> {
+    let exists = |id: Uuid| built.downloads.iter().any(|d| d.id == id);
+
+    for font in &built.fonts {
+        for installation in &font.installations {
+            if !exists(installation.download()) {
+                return Err(BuildError::DanglingDownload(
+                    format!("font: {}", font.name),
+                    installation.download(),
+                ));
+            }
+        }
+    }
+
+    for download in &built.downloads {
+        if let Some(parent) = download.derived_from {
+            if !exists(parent) {
+                return Err(BuildError::DanglingDownload(
+                    format!("download: {}", download.id),
+                    parent,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads a download into memory
+async fn load_download(
+    download: &ResolvedDownload,
+    base_path: &Path,
+    fetcher: &Fetcher,
+) -> Result<Vec<u8>, BuildError> {
+    match download {
+        ResolvedDownload::External(url) => {
+            let data = match fetcher.get(url).await {
+                Ok(data) => data,
+                Err(e) => return Err(BuildError::DownloadFailed(url.clone(), e.to_string())),
+            };
+
+            if data.status() != StatusCode::OK {
+                return Err(BuildError::DownloadFailed(
+                    url.clone(),
+                    format!("Status code: {}", data.status()),
+                ));
+            }
+
+            match fetcher.read_body(data).await {
+                Ok(data) => Ok(data),
+                Err(e) => Err(BuildError::DownloadFailed(url.clone(), e.to_string())),
+            }
+        }
+        ResolvedDownload::Local(path) => match std::fs::read(base_path.join(path)) {
+            Ok(data) => Ok(data),
+            Err(e) => Err(BuildError::FileError(path.clone(), e.to_string())),
+        },
+    }
+}
+
+/// Maps a local resource into memory, so large font packs are paged in as they're hashed instead
+/// of being read whole
+fn map_file(path: &Path) -> std::io::Result<Mmap> {
+    let file = std::fs::File::open(path)?;
+    // SAFETY: the resources aren't expected to change during a build, a file truncated while
+    // mapped fails the build (SIGBUS) rather than publishing a torn artifact
+    unsafe { Mmap::map(&file) }
+}
+
+/// The network, cache and hooks a build uses
+pub struct BuildContext<'a> {
+    pub fetcher: &'a Fetcher,
+    pub cache: &'a mut HttpCache,
+    /// Every artifact is run through the scanner before it's published
+    pub scanner: Option<&'a Scanner>,
+    /// Where the download and hash time goes
+    pub timings: &'a mut Timings,
+    /// Checked before every download
+    pub cancellation: &'a Cancellation,
+    /// Locked urls are used instead of resolving again, every download is recorded in it
+    pub lock: &'a mut Lockfile,
+    /// Offline the external resources are read from here (named by their locked hash, see
+    /// `VendorManifest::externals`)
+    pub externals: Option<&'a Path>,
+}
+
+/// Resolves a download, using the url the lockfile has for it (if any)
+async fn resolve_locked(
+    download: &SourceDownload,
+    ctx: &BuildContext<'_>,
+) -> Result<ResolvedDownload, BuildError> {
+    if let Some(url) = ctx
+        .lock
+        .find(download)
+        .and_then(|locked| locked.url.clone())
+    {
+        return Ok(ResolvedDownload::External(url));
+    }
+
+    resolve(download, ctx.fetcher)
+        .await
+        .map_err(|e| BuildError::ResolveFailed(e.to_string()))
+}
+
+/// What hashing an external resource found
+struct HashedExternal {
+    hash: String,
+    size: u64,
+    files: BTreeMap<String, ArchiveFile>,
+    /// The bytes, if they were kept
+    bytes: Option<Vec<u8>>,
+    /// Where the url redirected to (none without redirects)
+    redirected_to: Option<Url>,
+}
+
+/// Runs the scanner (if any) on an artifact
+fn scan(scanner: Option<&Scanner>, name: &str, data: &[u8]) -> Result<(), BuildError> {
+    match scanner {
+        Some(scanner) => scanner
+            .scan(name, data)
+            .map_err(|e| BuildError::ScanFailed(e.to_string())),
+        None => Ok(()),
+    }
+}
+
+/// The bytes of a locked external resource from the bundle (offline builds can't download it)
+async fn read_external(url: &Url, ctx: &BuildContext<'_>) -> Result<Vec<u8>, BuildError> {
+    let offline =
+        || BuildError::DownloadFailed(url.clone(), FetchError::Offline(url.clone()).to_string());
+
+    let locked = ctx
+        .lock
+        .downloads
+        .iter()
+        .find(|locked| locked.url.as_ref() == Some(url))
+        .ok_or_else(offline)?;
+    // The hash comes from the lockfile, it can't point anywhere else
+    if !locked.hash.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(offline());
+    }
+    let path = ctx.externals.ok_or_else(offline)?.join(&locked.hash);
+
+    info!("Offline, reading {} from {}", url, path.display());
+    tokio::fs::read(&path)
+        .await
+        .map_err(|e| BuildError::DownloadFailed(url.clone(), format!("{}: {}", path.display(), e)))
+}
+
+/// Downloads and hashes an external resource, unless the cache says it hasn't changed
+///
+/// With a scanner the cache is bypassed, every artifact has to be scanned. With `keep` it's
+/// bypassed too and the bytes are returned, so derived downloads don't fetch them again.
+async fn hash_external(
+    url: &Url,
+    keep: bool,
+    ctx: &mut BuildContext<'_>,
+) -> Result<HashedExternal, BuildError> {
+    // Entries without file sizes or faces are downloaded again to record them
+    let cached = match (ctx.scanner, keep) {
+        (Some(_), _) | (_, true) => None,
+        (None, false) => ctx
+            .cache
+            .get(url)
+            .filter(|cached| {
+                cached
+                    .files
+                    .as_ref()
+                    .is_some_and(|files| files.values().all(|file| file.faces.is_some()))
+            })
+            .cloned(),
+    };
+    // Offline the bytes come from the bundle and are hashed like downloads, the cache isn't
+    // trusted for them
+    if ctx.fetcher.is_offline() {
+        let bytes = read_external(url, ctx).await?;
+
+        scan(ctx.scanner, url.path(), &bytes)?;
+
+        let started = Instant::now();
+        let hash = sha256::digest(bytes.as_slice());
+        let files = archive_files(&bytes);
+        ctx.timings.add_phase("hash", started.elapsed());
+
+        // The redirect can't be followed offline, the locked one is kept
+        let redirected_to = ctx
+            .lock
+            .downloads
+            .iter()
+            .find(|locked| locked.url.as_ref() == Some(url))
+            .and_then(|locked| locked.redirected_to.clone());
+
+        return Ok(HashedExternal {
+            hash,
+            size: bytes.len() as u64,
+            files,
+            bytes: keep.then_some(bytes),
+            redirected_to,
+        });
+    }
+
+    let headers = match &cached {
+        Some(cached) => cached.conditional_headers(),
+        None => HeaderMap::new(),
+    };
+
+    let started = Instant::now();
+    // The redirects are followed by hand so the final url can be locked
+    let (chain, data) = match ctx.fetcher.get_traced(url, headers).await {
+        Ok(traced) => traced,
+        Err(e) => return Err(BuildError::DownloadFailed(url.clone(), e.to_string())),
+    };
+    let redirected_to = chain.last().filter(|last| *last != url).cloned();
+
+    if let (StatusCode::NOT_MODIFIED, Some(cached)) = (data.status(), &cached) {
+        ctx.timings.add_phase("download", started.elapsed());
+        info!("Unchanged upstream, skipping download: {}", url);
+        return Ok(HashedExternal {
+            hash: cached.hash.clone(),
+            size: cached.file_size,
+            files: cached.files.clone().unwrap_or_default(),
+            bytes: None,
+            redirected_to,
+        });
+    }
+
+    if data.status() != StatusCode::OK {
+        return Err(BuildError::DownloadFailed(
+            url.clone(),
+            format!("Status code: {}", data.status()),
+        ));
+    }
+
+    let headers = data.headers().clone();
+    let bytes = match ctx.fetcher.read_body(data).await {
+        Ok(data) => data,
+        Err(e) => return Err(BuildError::DownloadFailed(url.clone(), e.to_string())),
+    };
+    ctx.timings.add_phase("download", started.elapsed());
+    ctx.timings.add_transfer(
+        TransferDirection::Download,
+        url.as_str(),
+        bytes.len() as u64,
+        started.elapsed(),
+    );
+
+    scan(ctx.scanner, url.path(), &bytes)?;
+
+    let started = Instant::now();
+    let hash = sha256::digest(bytes.as_slice());
+    let size = bytes.len() as u64;
+    let files = archive_files(&bytes);
+    ctx.timings.add_phase("hash", started.elapsed());
+    ctx.cache.insert(
+        url.clone(),
+        CacheEntry::from_headers(&headers, hash.clone(), size, files.clone()),
+    );
+
+    Ok(HashedExternal {
+        hash,
+        size,
+        files,
+        bytes: keep.then_some(bytes),
+        redirected_to,
+    })
+}
+
 pub async fn build(
     version: Version,
     source: &Source,
     base_url: Url,
     base_path: PathBuf,
     downloadables: DownloadsList,
+    ctx: &mut BuildContext<'_>,
 ) -> Result<(Vec<UploadableDownloadInfo>, Compiled), BuildError> {
     let mut built = Compiled {
         version,
+        publishers: vec![],
         groups: vec![],
+        applications: vec![],
         fonts: vec![],
         downloads: vec![],
+        families: vec![],
+        indexes: CompiledIndexes::default(),
     };
 
+    // Font id, installed file, face
+    let mut faces: Vec<(Uuid, String, FontFace)> = vec![];
+
+    for publisher in &source.publishers {
+        built.publishers.push(CompiledPublisher {
+            id: match publisher.id {
+                SourceUUID::Uuid(uuid) => uuid,
+                SourceUUID::Null => {
+                    return Err(BuildError::UnexpectedEmptyUuid(format!(
+                        "publisher: {}",
+                        publisher.name
+                    )))
+                }
+            },
+            name: publisher.name.clone(),
+            url: publisher.url.clone(),
+        });
+    }
+
     let mut new_downloads: Vec<UploadableDownloadInfo> = vec![];
 
     let mut check_download: HashMap<SourceDownload, Vec<Uuid>> = HashMap::new();
 
+    // A download is signed if any installation using it is
+    let mut download_access: HashMap<SourceDownload, DownloadAccess> = HashMap::new();
+
+    let mut derivations: Vec<Derivation> = vec![];
+
     for group in &source.groups {
         let mut fonts: Vec<Uuid> = vec![];
 
         // Loop through the fonts
         for font_item in &group.fonts {
-            let font = source.fonts.iter().find(|f| &f.name == font_item);
+            let font = source.find_font(font_item);
 
             // Check if the font exists
             if let Some(uuid) = font {
@@ -80,7 +648,7 @@ pub async fn build(
                     }
                 });
             } else {
-                return Err(BuildError::MissingFont(font_item.clone()));
+                return Err(BuildError::MissingFont(font_item.to_string()));
             }
         }
 
@@ -96,6 +664,40 @@ pub async fn build(
             },
             name: group.name.clone(),
             fonts,
+            namespace: source.namespace.clone(),
+        });
+    }
+
+    for application in &source.applications {
+        let mut fonts: Vec<Uuid> = vec![];
+
+        for font_item in &application.fonts {
+            match source.find_font(font_item).map(|font| font.id) {
+                Some(SourceUUID::Uuid(uuid)) => fonts.push(uuid),
+                Some(SourceUUID::Null) => {
+                    return Err(BuildError::UnexpectedEmptyUuid(format!(
+                        "application -> font: {}",
+                        font_item
+                    )))
+                }
+                None => return Err(BuildError::MissingFont(font_item.to_string())),
+            }
+        }
+
+        built.applications.push(CompiledApplication {
+            id: match application.id {
+                SourceUUID::Uuid(uuid) => uuid,
+                SourceUUID::Null => {
+                    return Err(BuildError::UnexpectedEmptyUuid(format!(
+                        "application: {}",
+                        application.name
+                    )))
+                }
+            },
+            name: application.name.clone(),
+            steam_app_id: application.steam_app_id,
+            fonts,
+            namespace: source.namespace.clone(),
         });
     }
 
@@ -111,21 +713,59 @@ pub async fn build(
             }
         };
 
+        // Clients join the names onto the fonts directory
+        if let Some(file) = font
+            .installations
+            .iter()
+            .flat_map(|installation| installation.installed_files())
+            .find(|file| !is_plain_file_name(&file.file))
+        {
+            return Err(BuildError::UnsafeFileName(font.name.clone(), file.file));
+        }
+
         let mut installations: Vec<CompiledInstalationType> = vec![];
 
         for installation in &font.installations {
             // Insert a temp random uuid for the download
             let download_uuid = Uuid::new_v4();
 
-            let download = match installation {
-                SourceInstalationType::Cabextract(data) => &data.download,
+            let download = installation.download();
+
+            // Derived downloads are generated from the original, which is kept as their parent
+            let kind = match installation {
+                SourceInstalationType::Cabextract(data) => {
+                    let split = data.files.iter().any(|file| file.split.is_some());
+                    (split || data.subset.is_some())
+                        .then(|| DerivationKind::Repack(data.files.clone(), data.subset.clone()))
+                }
+                SourceInstalationType::Woff2(_) => Some(DerivationKind::Woff2),
+            };
+            let parent_uuid = match kind {
+                Some(kind) => {
+                    let parent = Uuid::new_v4();
+                    derivations.push(Derivation {
+                        uuid: download_uuid,
+                        parent,
+                        download: download.clone(),
+                        kind,
+                    });
+
+                    parent
+                }
+                None => download_uuid,
             };
 
+            if installation.access() == DownloadAccess::Signed {
+                download_access.insert(download.clone(), DownloadAccess::Signed);
+            } else {
+                download_access.entry(download.clone()).or_default();
+            }
+
             // Push the download
-            if let Some(downloads) = check_download.get_mut(&download) {
-                downloads.push(download_uuid);
+            if let Some(downloads) = check_download.get_mut(download) {
+                downloads.push(parent_uuid);
             } else {
-                check_download.insert(download.clone(), vec![download_uuid]);
+                check_download.insert(download.clone(), vec![parent_uuid]);
             }
 
             // Push the installation
@@ -133,60 +773,148 @@ pub async fn build(
                 SourceInstalationType::Cabextract(data) => {
                     CompiledInstalationType::Cabextract(CabextractInstalationCompiled {
                         download: download_uuid,
-                        files: data.files.clone(),
+                        // Split collections are installed as their faces
+                        files: data
+                            .files
+                            .iter()
+                            .flat_map(|file| file.installed_files())
+                            .collect(),
+                        subset: data.subset.clone(),
+                        installed_size: None,
+                        file_hashes: BTreeMap::new(),
+                        hosts: data.hosts.clone(),
+                    })
+                }
+                SourceInstalationType::Woff2(data) => {
+                    CompiledInstalationType::Woff2(Woff2InstalationCompiled {
+                        download: download_uuid,
+                        file: data.file.clone(),
+                        registry_name: data.registry_name.clone(),
+                        installed_size: None,
+                        file_hashes: BTreeMap::new(),
+                        hosts: data.hosts.clone(),
                     })
                 }
             });
         }
 
+        // Export the conflicts as uuids
+        let mut conflicts_with: Vec<Uuid> = vec![];
+        for reference in &font.conflicts_with {
+            match source.find_font(reference).map(|other| other.id) {
+                Some(SourceUUID::Uuid(uuid)) => conflicts_with.push(uuid),
+                Some(SourceUUID::Null) => {
+                    return Err(BuildError::UnexpectedEmptyUuid(format!(
+                        "font -> conflicts with: {}",
+                        reference
+                    )))
+                }
+                None => return Err(BuildError::MissingFont(reference.to_string())),
+            }
+        }
+
+        let publisher = match source.find_publisher(&font.publisher) {
+            Some(publisher) => publisher,
+            None => return Err(BuildError::MissingPublisher(font.publisher.to_string())),
+        };
+
         built.fonts.push(crate::types::CompiledFont {
             id,
             name: font.name.clone(),
             short_name: font.short_name.clone(),
-            publisher: font.publisher.clone(),
+            previous_names: font.previous_names.clone(),
+            publisher: publisher.name.clone(),
+            publisher_id: publisher.id.uuid(),
             categories: font.categories.clone(),
+            tags: font.tags.clone(),
+            license: font.license.clone(),
             installations,
+            namespace: source.namespace.clone(),
+            conflicts_with,
+            wine: font.wine.clone(),
         });
     }
 
+    // The final download ids (temp uuid -> id)
+    let mut final_ids: HashMap<Uuid, Uuid> = HashMap::new();
+
     // Add the downloads
-    for (download, uuids) in check_download {
-        let bytes = match download {
-            SourceDownload::ExternalResource(ref url) => match reqwest::get(url.clone()).await {
-                Ok(data) => {
-                    if data.status() != 200 {
-                        return Err(BuildError::DownloadFailed(
-                            url.clone(),
-                            format!("Status code: {}", data.status()),
-                        ));
-                    }
+    let sources: Vec<SourceDownload> = check_download.keys().cloned().collect();
+    // The bytes of external resources that are derived from, kept so they're only fetched once
+    let parents: HashSet<SourceDownload> = derivations.iter().map(|d| d.download.clone()).collect();
+    let mut parent_bytes: HashMap<SourceDownload, Vec<u8>> = HashMap::new();
+    for (source, uuids) in check_download {
+        if ctx.cancellation.is_cancelled() {
+            return Err(BuildError::Cancelled);
+        }
+        let access = download_access[&source];
 
-                    match data.bytes().await {
-                        Ok(data) => data.as_ref().to_vec(),
-                        Err(e) => {
-                            return Err(BuildError::DownloadFailed(url.clone(), e.to_string()))
-                        }
+        let span = info_span!("artifact", source = ?source);
+        let (download, hash, size, files, redirected_to) = async {
+            let download = resolve_locked(&source, ctx).await?;
+
+            let (hash, size, files, redirected_to) = match download {
+                ResolvedDownload::External(ref url) => {
+                    let hashed = hash_external(url, parents.contains(&source), ctx).await?;
+                    if let Some(bytes) = hashed.bytes {
+                        parent_bytes.insert(source.clone(), bytes);
                     }
+
+                    (hashed.hash, hashed.size, hashed.files, hashed.redirected_to)
                 }
-                Err(e) => return Err(BuildError::DownloadFailed(url.clone(), e.to_string())),
-            },
-            SourceDownload::LocalResource(ref path) => {
-                let joined = base_path.join(&path);
+                ResolvedDownload::Local(ref path) => {
+                    let joined = base_path.join(path);
 
-                let data = match std::fs::read(joined) {
-                    Ok(data) => data,
-                    Err(e) => return Err(BuildError::FileError(path.clone(), e.to_string())),
-                };
+                    let data = match map_file(&joined) {
+                        Ok(data) => data,
+                        Err(e) => return Err(BuildError::FileError(path.clone(), e.to_string())),
+                    };
 
-                data.as_slice().to_vec()
-            }
-        };
+                    scan(ctx.scanner, &path.to_string_lossy(), &data)?;
+
+                    let started = Instant::now();
+                    let hashed = (
+                        sha256::digest(&data[..]),
+                        data.len() as u64,
+                        archive_files(&data),
+                        None,
+                    );
+                    ctx.timings.add_phase("hash", started.elapsed());
 
-        let hash = sha256::digest(&bytes);
-        let size = bytes.len() as u64;
+                    hashed
+                }
+            };
+
+            Ok((download, hash, size, files, redirected_to))
+        }
+        .instrument(span)
+        .await?;
+
+        // The content of a locked download can't change
+        if let Some(locked) = ctx.lock.find(&source) {
+            if !locked.hash.eq_ignore_ascii_case(&hash) {
+                return Err(BuildError::LockMismatch(
+                    describe(&source),
+                    locked.hash.clone(),
+                    hash,
+                ));
+            }
+        }
+        ctx.lock.record(LockedDownload {
+            source,
+            url: match &download {
+                ResolvedDownload::External(url) => Some(url.clone()),
+                ResolvedDownload::Local(_) => None,
+            },
+            redirected_to,
+            hash: hash.clone(),
+            file_size: size,
+        });
 
-        // Check if the download already exists
-        let existing = downloadables.iter().find(|d| d.hash == hash);
+        // Check if the download already exists (with the same access)
+        let existing = downloadables
+            .iter()
+            .find(|d| d.hash == hash && d.access == access);
 
         let id = match existing {
             Some(existing) => {
@@ -195,6 +923,10 @@ pub async fn build(
                     file_size: size,
                     hash,
                     download_url: existing.download_url.clone(),
+                    derived_from: None,
+                    access,
+                    mirrors: existing.mirrors.clone(),
+                    metalink: None,
                 });
 
                 existing.id
@@ -203,8 +935,13 @@ pub async fn build(
                 let id = Uuid::new_v4();
 
                 let url = match download {
-                    SourceDownload::ExternalResource(ref url) => url.clone(),
-                    SourceDownload::LocalResource(ref path) => generate_url(&base_url, &path, &id),
+                    ResolvedDownload::External(ref url) => url.clone(),
+                    ResolvedDownload::Local(ref path) => generate_url(
+                        &base_url,
+                        path.extension().and_then(|e| e.to_str()),
+                        &id,
+                        access,
+                    ),
                 };
 
                 built.downloads.push(CompiledDownloads {
@@ -212,14 +949,20 @@ pub async fn build(
                     file_size: size,
                     hash: hash.clone(),
                     download_url: url.clone(),
+                    derived_from: None,
+                    access,
+                    mirrors: vec![],
+                    metalink: None,
                 });
 
-                if let SourceDownload::LocalResource(ref path) = download {
+                if let ResolvedDownload::Local(ref path) = download {
                     new_downloads.push(UploadableDownloadInfo {
                         uuid: id,
-                        file_path: path.to_owned(),
+                        data: UploadableData::File(path.to_owned()),
                         hash,
                         file_size: size,
+                        access,
+                        mirrors: vec![],
                     });
                 }
 
@@ -229,19 +972,162 @@ pub async fn build(
 
         // Replace the uuids
         for uuid in uuids {
-            for font in &mut built.fonts {
-                for installation in &mut font.installations {
-                    match installation {
-                        CompiledInstalationType::Cabextract(data) => {
-                            if data.download == uuid {
-                                data.download = id;
+            record_installed_files(&mut built, uuid, &files, &mut faces);
+            final_ids.insert(uuid, id);
+            replace_download(&mut built, uuid, id);
+        }
+    }
+
+    // Sources the catalog dropped aren't locked anymore
+    ctx.lock.retain_sources(&sources);
+
+    // Add the derived downloads
+    for derivation in derivations {
+        if ctx.cancellation.is_cancelled() {
+            return Err(BuildError::Cancelled);
+        }
+        let parent = final_ids[&derivation.parent];
+        // Derived downloads are as private as their parent
+        let access = download_access[&derivation.download];
+
+        let span = info_span!("derived_artifact", source = ?derivation.download);
+        let derived = async {
+            let download = resolve_locked(&derivation.download, ctx).await?;
+            let loaded;
+            let data: &[u8] = match (&download, parent_bytes.get(&derivation.download)) {
+                // Already fetched (or read from the bundle) when the parent was hashed
+                (ResolvedDownload::External(_), Some(bytes)) => bytes,
+                (ResolvedDownload::External(url), None) if ctx.fetcher.is_offline() => {
+                    loaded = read_external(url, ctx).await?;
+                    &loaded
+                }
+                _ => {
+                    let started = Instant::now();
+                    loaded = load_download(&download, &base_path, ctx.fetcher).await?;
+                    if let ResolvedDownload::External(url) = &download {
+                        ctx.timings.add_phase("download", started.elapsed());
+                        ctx.timings.add_transfer(
+                            TransferDirection::Download,
+                            url.as_str(),
+                            loaded.len() as u64,
+                            started.elapsed(),
+                        );
+                    }
+                    &loaded
+                }
+            };
+
+            let started = Instant::now();
+            let derived = derivation
+                .kind
+                .derive(data)
+                .map_err(BuildError::DeriveFailed);
+            ctx.timings.add_phase("derive", started.elapsed());
+
+            derived
+        }
+        .instrument(span)
+        .await?;
+        let extension = derivation.kind.extension();
+
+        scan(
+            ctx.scanner,
+            &format!("{}.{}", derivation.uuid, extension),
+            &derived,
+        )?;
+
+        let hash = sha256::digest(&derived);
+        let size = derived.len() as u64;
+
+        match derivation.kind {
+            DerivationKind::Repack(_, _) => record_installed_files(
+                &mut built,
+                derivation.uuid,
+                &archive_files(&derived),
+                &mut faces,
+            ),
+            // The converted font is the installed file
+            DerivationKind::Woff2 => {
+                for font in &mut built.fonts {
+                    for installation in &mut font.installations {
+                        if let CompiledInstalationType::Woff2(data) = installation {
+                            if data.download == derivation.uuid {
+                                data.installed_size = Some(size);
+                                data.file_hashes =
+                                    BTreeMap::from([(data.file.clone(), hash.clone())]);
+                                for face in font_faces(&derived) {
+                                    faces.push((font.id, data.file.clone(), face));
+                                }
                             }
                         }
                     }
                 }
             }
         }
+
+        // Check if the download already exists (in this build or a previous one)
+        let existing = match built
+            .downloads
+            .iter()
+            .find(|d| d.hash == hash && d.access == access)
+        {
+            Some(existing) => Some((existing.id, None)),
+            None => downloadables
+                .iter()
+                .find(|d| d.hash == hash && d.access == access)
+                .map(|d| (d.id, Some(d))),
+        };
+
+        let id = match existing {
+            Some((id, None)) => id,
+            Some((id, Some(existing))) => {
+                built.downloads.push(CompiledDownloads {
+                    id,
+                    file_size: size,
+                    hash,
+                    download_url: existing.download_url.clone(),
+                    derived_from: Some(parent),
+                    access,
+                    mirrors: existing.mirrors.clone(),
+                    metalink: None,
+                });
+
+                id
+            }
+            None => {
+                let id = Uuid::new_v4();
+
+                built.downloads.push(CompiledDownloads {
+                    id,
+                    file_size: size,
+                    hash: hash.clone(),
+                    download_url: generate_url(&base_url, Some(extension), &id, access),
+                    derived_from: Some(parent),
+                    access,
+                    mirrors: vec![],
+                    metalink: None,
+                });
+
+                new_downloads.push(UploadableDownloadInfo {
+                    uuid: id,
+                    data: UploadableData::Generated(derived, extension.to_string()),
+                    hash,
+                    file_size: size,
+                    access,
+                    mirrors: vec![],
+                });
+
+                id
+            }
+        };
+
+        replace_download(&mut built, derivation.uuid, id);
     }
 
+    built.families = CompiledFamily::from_faces(faces);
+    built.indexes = CompiledIndexes::from_fonts(&built.fonts);
+
+    check_downloads(&built)?;
+
     Ok((new_downloads, built))
 }
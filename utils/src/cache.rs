@@ -0,0 +1,135 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use url::Url;
+
+use crate::archive::ArchiveFile;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// What we know about an external resource from the last time we downloaded it
+pub struct CacheEntry {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub hash: String,
+    pub file_size: u64,
+    /// The files in the archive (empty if it isn't one), missing in entries written before
+    /// they were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files: Option<BTreeMap<String, ArchiveFile>>,
+}
+
+impl CacheEntry {
+    /// Creates an entry from the response headers the bytes were downloaded with
+    pub fn from_headers(
+        headers: &HeaderMap,
+        hash: String,
+        file_size: u64,
+        files: BTreeMap<String, ArchiveFile>,
+    ) -> CacheEntry {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|value: &HeaderValue| value.to_str().ok())
+                .map(|value| value.to_string())
+        };
+
+        CacheEntry {
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            hash,
+            file_size,
+            files: Some(files),
+        }
+    }
+
+    /// Whether the server gave us anything to revalidate with
+    pub fn has_validators(&self) -> bool {
+        self.etag.is_some() || self.last_modified.is_some()
+    }
+
+    /// The If-None-Match/If-Modified-Since headers for a conditional request
+    pub fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Some(value) = self
+            .etag
+            .as_ref()
+            .and_then(|etag| HeaderValue::from_str(etag).ok())
+        {
+            headers.insert(header::IF_NONE_MATCH, value);
+        }
+
+        if let Some(value) = self
+            .last_modified
+            .as_ref()
+            .and_then(|date| HeaderValue::from_str(date).ok())
+        {
+            headers.insert(header::IF_MODIFIED_SINCE, value);
+        }
+
+        headers
+    }
+}
+
+/// A cache of validators for external resources, persisted as json between runs
+pub struct HttpCache {
+    path: Option<PathBuf>,
+    entries: BTreeMap<Url, CacheEntry>,
+}
+
+impl HttpCache {
+    /// Loads the cache (a missing or broken file results in an empty cache)
+    pub async fn load(path: Option<PathBuf>) -> HttpCache {
+        let entries = match &path {
+            Some(path) => match fs::read_to_string(path).await {
+                Ok(data) => match serde_json::from_str(&data) {
+                    Ok(entries) => entries,
+                    Err(error) => {
+                        warn!("Failed to parse cache: {}... Using empty cache", error);
+                        BTreeMap::new()
+                    }
+                },
+                Err(_) => BTreeMap::new(),
+            },
+            None => BTreeMap::new(),
+        };
+
+        HttpCache { path, entries }
+    }
+
+    pub fn get(&self, url: &Url) -> Option<&CacheEntry> {
+        self.entries.get(url)
+    }
+
+    pub fn insert(&mut self, url: Url, entry: CacheEntry) {
+        if entry.has_validators() {
+            self.entries.insert(url, entry);
+        } else {
+            self.entries.remove(&url);
+        }
+    }
+
+    /// Writes the cache back to disk (if it has a path)
+    pub async fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        let data = match serde_json::to_vec_pretty(&self.entries) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!("Failed to serialize cache: {}", error);
+                return;
+            }
+        };
+
+        match fs::write(path, data).await {
+            Ok(_) => info!("Wrote cache to {}", path.display()),
+            Err(error) => warn!("Failed to write cache: {}", error),
+        }
+    }
+}
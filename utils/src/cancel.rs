@@ -0,0 +1,38 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+#[derive(Debug, Clone, Default)]
+/// Set once the user asks to stop (Ctrl-C), long steps check it between units of work so they
+/// never stop halfway through a transfer or an index write
+pub struct Cancellation {
+    requested: Arc<AtomicBool>,
+}
+
+impl Cancellation {
+    /// Cancels on the first Ctrl-C and exits right away on the second
+    pub fn on_ctrl_c() -> Cancellation {
+        let cancellation = Cancellation::default();
+        let requested = cancellation.requested.clone();
+
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_err() {
+                return;
+            }
+            warn!("Interrupted, stopping after the current step (press Ctrl-C again to abort)");
+            requested.store(true, Ordering::SeqCst);
+
+            if tokio::signal::ctrl_c().await.is_ok() {
+                error!("Aborted");
+                std::process::exit(130);
+            }
+        });
+
+        cancellation
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
@@ -0,0 +1,304 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde_json::{Map, Value};
+
+use crate::{
+    collation::collate,
+    types::{Source, SourceUUID},
+};
+
+/// A source file, several catalogs are linted and built into one database
+pub struct Catalog {
+    pub path: PathBuf,
+    pub source: Source,
+}
+
+/// Whether the catalogs can be merged (they have to share the namespace)
+pub fn same_namespace(catalogs: &[Catalog]) -> bool {
+    catalogs
+        .windows(2)
+        .all(|pair| pair[0].source.namespace == pair[1].source.namespace)
+}
+
+/// Merges the catalogs into one source (sorted, identical publishers are kept once)
+pub fn merge(catalogs: &[Catalog]) -> Source {
+    if let [catalog] = catalogs {
+        return catalog.source.clone();
+    }
+
+    let mut merged = Source {
+        namespace: catalogs.first().and_then(|c| c.source.namespace.clone()),
+        publishers: vec![],
+        groups: vec![],
+        applications: vec![],
+        fonts: vec![],
+        lint: Default::default(),
+        // Split keeps the notes of each catalog
+        notes: None,
+    };
+
+    for catalog in catalogs {
+        for publisher in &catalog.source.publishers {
+            if !merged.publishers.contains(publisher) {
+                merged.publishers.push(publisher.clone());
+            }
+        }
+        merged.groups.extend(catalog.source.groups.iter().cloned());
+        merged
+            .applications
+            .extend(catalog.source.applications.iter().cloned());
+        merged.fonts.extend(catalog.source.fonts.iter().cloned());
+        merged.lint.extend(&catalog.source.lint);
+    }
+
+    merged.publishers.sort_by(|a, b| collate(&a.name, &b.name));
+    merged.groups.sort_by(|a, b| collate(&a.name, &b.name));
+    merged
+        .applications
+        .sort_by(|a, b| collate(&a.name, &b.name));
+    merged.fonts.sort_by(|a, b| collate(&a.name, &b.name));
+
+    merged
+}
+
+pub enum SplitError {
+    /// A fixed entry doesn't come from any of the catalogs (Kind, Name)
+    Unassigned(&'static str, String),
+}
+
+impl Display for SplitError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SplitError::Unassigned(kind, name) => write!(
+                f,
+                "The {} \"{}\" doesn't belong to any catalog anymore, nothing was written",
+                kind, name
+            ),
+        }
+    }
+}
+
+/// Whether a fixed entry is an entry of a catalog, by id (entries that had no id yet by name, or
+/// a name they had before)
+fn same_entry(
+    original: (&SourceUUID, &str),
+    fixed: (&SourceUUID, &str),
+    previous: &[String],
+) -> bool {
+    match original.0 {
+        SourceUUID::Uuid(_) => original.0 == fixed.0,
+        SourceUUID::Null => original.1 == fixed.1 || previous.iter().any(|name| name == original.1),
+    }
+}
+
+/// Splits a merged (and fixed) source back into its catalogs, entries are matched by id and every
+/// entry has to land in a catalog
+pub fn split(merged: &Source, catalogs: &[Catalog]) -> Result<Vec<Source>, SplitError> {
+    if let [_] = catalogs {
+        return Ok(vec![merged.clone()]);
+    }
+
+    let split: Vec<Source> = catalogs
+        .iter()
+        .map(|catalog| {
+            let source = &catalog.source;
+
+            let fonts: Vec<_> = merged
+                .fonts
+                .iter()
+                .filter(|font| {
+                    source.fonts.iter().any(|f| {
+                        same_entry(
+                            (&f.id, &f.name),
+                            (&font.id, &font.name),
+                            &font.previous_names,
+                        )
+                    })
+                })
+                .cloned()
+                .collect();
+
+            // Keep the catalog's publishers and the ones created for its fonts
+            let publishers = merged
+                .publishers
+                .iter()
+                .filter(|publisher| {
+                    let fixed = (&publisher.id, publisher.name.as_str());
+                    let original = source
+                        .publishers
+                        .iter()
+                        .any(|p| same_entry((&p.id, &p.name), fixed, &[]));
+                    let created = catalogs.iter().all(|catalog| {
+                        catalog
+                            .source
+                            .publishers
+                            .iter()
+                            .all(|p| !same_entry((&p.id, &p.name), fixed, &[]))
+                    }) && fonts.iter().any(|font| font.publisher.matches(publisher));
+
+                    original || created
+                })
+                .cloned()
+                .collect();
+
+            Source {
+                namespace: source.namespace.clone(),
+                publishers,
+                groups: merged
+                    .groups
+                    .iter()
+                    .filter(|group| {
+                        source
+                            .groups
+                            .iter()
+                            .any(|g| same_entry((&g.id, &g.name), (&group.id, &group.name), &[]))
+                    })
+                    .cloned()
+                    .collect(),
+                applications: merged
+                    .applications
+                    .iter()
+                    .filter(|application| {
+                        source.applications.iter().any(|a| {
+                            same_entry((&a.id, &a.name), (&application.id, &application.name), &[])
+                        })
+                    })
+                    .cloned()
+                    .collect(),
+                fonts,
+                lint: source.lint.clone(),
+                notes: source.notes.clone(),
+            }
+        })
+        .collect();
+
+    // Writing the catalogs back would drop anything that didn't land in one
+    if let Some(font) = merged
+        .fonts
+        .iter()
+        .find(|font| !split.iter().any(|s| s.fonts.contains(font)))
+    {
+        return Err(SplitError::Unassigned("font", font.name.clone()));
+    }
+    if let Some(publisher) = merged
+        .publishers
+        .iter()
+        .find(|publisher| !split.iter().any(|s| s.publishers.contains(publisher)))
+    {
+        return Err(SplitError::Unassigned("publisher", publisher.name.clone()));
+    }
+    if let Some(group) = merged
+        .groups
+        .iter()
+        .find(|group| !split.iter().any(|s| s.groups.contains(group)))
+    {
+        return Err(SplitError::Unassigned("group", group.name.clone()));
+    }
+    if let Some(application) = merged
+        .applications
+        .iter()
+        .find(|application| !split.iter().any(|s| s.applications.contains(application)))
+    {
+        return Err(SplitError::Unassigned(
+            "application",
+            application.name.clone(),
+        ));
+    }
+
+    Ok(split)
+}
+
+/// Reads a catalog as it was at a git revision (`None` if it didn't exist yet)
+pub fn at_revision(path: &Path, revision: &str) -> Result<Option<Source>, String> {
+    let directory = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let file_name = path.file_name().unwrap_or(path.as_os_str());
+
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(directory)
+        .arg("show")
+        .arg(format!("{}:./{}", revision, file_name.to_string_lossy()))
+        .output()
+        .map_err(|e| e.to_string())?;
+
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr).trim().to_string();
+        // The revision exists but the file doesn't
+        if error.contains("does not exist in") || error.contains("exists on disk, but not in") {
+            return Ok(None);
+        }
+        return Err(error);
+    }
+
+    serde_json::from_slice(&output.stdout)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+
+/// Names of the fonts that are new or changed since the old source
+pub fn changed_fonts(old: &Source, new: &Source) -> HashSet<String> {
+    new.fonts
+        .iter()
+        .filter(|font| !old.fonts.contains(font))
+        .map(|font| font.name.clone())
+        .collect()
+}
+
+/// The element of the original list a rewritten one came from (by id, then name, then position)
+fn original_element<'a>(original: &'a [Value], element: &Value, index: usize) -> Option<&'a Value> {
+    for key in ["id", "name"] {
+        let found = element.get(key).and_then(|value| {
+            original
+                .iter()
+                .find(|candidate| candidate.get(key) == Some(value))
+        });
+        if found.is_some() {
+            return found;
+        }
+    }
+
+    original.get(index)
+}
+
+/// Orders the keys of a rewritten source like they were in the original file (new keys go
+/// last), so `lint --fix` only changes what it fixes
+pub fn reorder_like(original: &Value, value: Value) -> Value {
+    match (original, value) {
+        (Value::Object(original), Value::Object(mut map)) => {
+            let mut ordered = Map::new();
+            for (key, original) in original {
+                // Notes written as `x-comment` keep their place
+                let key = match key.as_str() {
+                    "x-comment" => "notes",
+                    key => key,
+                };
+                if let Some(value) = map.shift_remove(key) {
+                    ordered.insert(key.to_string(), reorder_like(original, value));
+                }
+            }
+            ordered.extend(map);
+
+            Value::Object(ordered)
+        }
+        (Value::Array(original), Value::Array(list)) => Value::Array(
+            list.into_iter()
+                .enumerate()
+                .map(
+                    |(index, element)| match original_element(original, &element, index) {
+                        Some(original) => reorder_like(original, element),
+                        None => element,
+                    },
+                )
+                .collect(),
+        ),
+        (_, value) => value,
+    }
+}
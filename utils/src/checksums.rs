@@ -0,0 +1,56 @@
+/// Known-good sha256 hashes of the classic Microsoft core fonts releases (file name, hash)
+pub const KNOWN_MS_CORE_FONTS: &[(&str, &str)] = &[
+    (
+        "andale32.exe",
+        "0524fe42951adc3a7eb870e32f0920313c71f170c859b5f770d82b4ee111e970",
+    ),
+    (
+        "arial32.exe",
+        "85297a4d146e9c87ac6f74822734bdee5f4b2a722d7eaa584b7f2cbf76f478f6",
+    ),
+    (
+        "arialb32.exe",
+        "a425f0ffb6a1a5ede5b979ed6177f4f4f4fdef6ae7c302a7b7720ef332fec0a8",
+    ),
+    (
+        "comic32.exe",
+        "9c6df3feefde26d4e41d4a4fe5db2a89f9123a772594d7f59afd062625cd204e",
+    ),
+    (
+        "courie32.exe",
+        "bb511d861655dde879ae552eb86b134d6fae67cb58502e6ff73ec5d9151f3384",
+    ),
+    (
+        "georgi32.exe",
+        "2c2c7dcda6606ea5cf08918fb7cd3f3359e9e84338dc690013f20cd42e930301",
+    ),
+    (
+        "impact32.exe",
+        "6061ef3b7401d9642f5dfdb5f2b376aa14663f6275e60a51207ad4facf2fccfb",
+    ),
+    (
+        "times32.exe",
+        "db56595ec6ef5d3de5c24994f001f03b2a13e37cee27bc25c58f6f43e8f807ab",
+    ),
+    (
+        "trebuc32.exe",
+        "5a690d9bb8510be1b8b4fe49f1f2319651fe51bbe54775ddddd8ef0bd07fdac9",
+    ),
+    (
+        "verdan32.exe",
+        "c1cb61255e363166794e47664e2f21af8e3a26cb6346eb8d2ae2fa85dd5aad96",
+    ),
+    (
+        "webdin32.exe",
+        "64595b5abc1080fba8610c5c34fab5863408e806aafe84653ca8575bed17d75a",
+    ),
+];
+
+/// The known-good hashes for a file name (empty if we don't track the file)
+pub fn known_hashes(file_name: &str) -> Vec<&'static str> {
+    KNOWN_MS_CORE_FONTS
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(file_name))
+        .map(|(_, hash)| *hash)
+        .collect()
+}
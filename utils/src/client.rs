@@ -0,0 +1,673 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    Response, StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{fs, io::AsyncWriteExt, task::JoinSet};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    fetch::Fetcher,
+    types::{Compiled, CompiledDownloads, DownloadAccess},
+    utils::VersionInfo,
+};
+
+/// The official database, packagers can embed another one by setting
+/// `WINEFONTS_DEFAULT_DATABASE_URL` at build time
+pub const DEFAULT_DATABASE_URL: &str = match option_env!("WINEFONTS_DEFAULT_DATABASE_URL") {
+    Some(url) => url,
+    None => "https://winefonts.bashed.sh/versions.json",
+};
+
+/// Turns the (private) url of a signed download into one the client can fetch
+///
+/// Implemented by whoever hosts the licensed fonts (e.g. an S3 presigner behind a login).
+pub trait UrlSigner {
+    fn sign(&self, download: &CompiledDownloads) -> Result<Url, String>;
+}
+
+pub enum ClientError {
+    /// The download is signed but no signer was given (Id)
+    NoSigner(Uuid),
+    /// The signer refused the download (Id, Error)
+    SignFailed(Uuid, String),
+    /// The download couldn't be fetched (Url, Error)
+    FetchFailed(Url, String),
+    /// The downloaded file doesn't match the database (Id, Expected hash, Hash)
+    HashMismatch(Uuid, String, String),
+    /// The downloaded file has the wrong size (Id, Expected size, Size)
+    SizeMismatch(Uuid, u64, u64),
+    /// A file couldn't be read or written (Path, Error)
+    Io(PathBuf, String),
+    /// No url of the download worked (Id, Errors)
+    AllUrlsFailed(Uuid, Vec<String>),
+    /// A database file couldn't be parsed (Url, Error)
+    InvalidDatabase(Url, String),
+    /// The versions file lists no version (Url)
+    NoVersions(Url),
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientError::NoSigner(id) => {
+                write!(f, "The download {} is signed but no signer is set", id)
+            }
+            ClientError::SignFailed(id, error) => {
+                write!(f, "Failed to sign the download {}: {}", id, error)
+            }
+            ClientError::FetchFailed(url, error) => {
+                write!(f, "Failed to fetch {}: {}", url, error)
+            }
+            ClientError::HashMismatch(id, expected, hash) => write!(
+                f,
+                "The download {} has the hash {} (expected {})",
+                id, hash, expected
+            ),
+            ClientError::SizeMismatch(id, expected, size) => write!(
+                f,
+                "The download {} is {} bytes (expected {})",
+                id, size, expected
+            ),
+            ClientError::Io(path, error) => {
+                write!(f, "File error (path: {}, error: {})", path.display(), error)
+            }
+            ClientError::AllUrlsFailed(id, errors) => write!(
+                f,
+                "Every url of the download {} failed ({})",
+                id,
+                errors.join("; ")
+            ),
+            ClientError::InvalidDatabase(url, error) => {
+                write!(f, "Failed to parse {}: {}", url, error)
+            }
+            ClientError::NoVersions(url) => write!(f, "{} lists no version", url),
+        }
+    }
+}
+
+/// The url to fetch a download from, signed if the download is private
+pub fn download_url(
+    download: &CompiledDownloads,
+    signer: Option<&dyn UrlSigner>,
+) -> Result<Url, ClientError> {
+    match download.access {
+        DownloadAccess::Public => Ok(download.download_url.clone()),
+        DownloadAccess::Signed => match signer {
+            Some(signer) => signer
+                .sign(download)
+                .map_err(|e| ClientError::SignFailed(download.id, e)),
+            None => Err(ClientError::NoSigner(download.id)),
+        },
+    }
+}
+
+/// Fetches a download and checks it against the database
+pub async fn fetch_download(
+    fetcher: &Fetcher,
+    download: &CompiledDownloads,
+    signer: Option<&dyn UrlSigner>,
+) -> Result<Vec<u8>, ClientError> {
+    let url = download_url(download, signer)?;
+    let data = fetch_bytes(fetcher, &url).await?;
+
+    if data.len() as u64 != download.file_size {
+        return Err(ClientError::SizeMismatch(
+            download.id,
+            download.file_size,
+            data.len() as u64,
+        ));
+    }
+
+    let hash = sha256::digest(&data);
+    if !hash.eq_ignore_ascii_case(&download.hash) {
+        return Err(ClientError::HashMismatch(
+            download.id,
+            download.hash.clone(),
+            hash,
+        ));
+    }
+
+    Ok(data)
+}
+
+/// Fetches a url into memory (non-2xx answers are errors)
+async fn fetch_bytes(fetcher: &Fetcher, url: &Url) -> Result<Vec<u8>, ClientError> {
+    let fetch_error = |error: String| ClientError::FetchFailed(url.clone(), error);
+
+    let response = fetcher
+        .get(url)
+        .await
+        .map_err(|e| fetch_error(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(fetch_error(format!("Status code: {}", response.status())));
+    }
+
+    fetcher
+        .read_body(response)
+        .await
+        .map_err(|e| fetch_error(e.to_string()))
+}
+
+/// The newest version listed in a versions.json
+fn latest_version(versions_url: &Url, data: &[u8]) -> Result<VersionInfo, ClientError> {
+    let versions: Vec<VersionInfo> = serde_json::from_slice(data)
+        .map_err(|e| ClientError::InvalidDatabase(versions_url.clone(), e.to_string()))?;
+
+    versions
+        .into_iter()
+        .max_by(|a, b| a.version.cmp(&b.version))
+        .ok_or_else(|| ClientError::NoVersions(versions_url.clone()))
+}
+
+/// Fetches a version file (from its url or a mirror, checked against the listed hash)
+async fn fetch_version(fetcher: &Fetcher, latest: &VersionInfo) -> Result<Vec<u8>, ClientError> {
+    let mut errors = vec![];
+    let urls = std::iter::once(&latest.download_url).chain(
+        latest
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.scheme() == "http" || mirror.scheme() == "https"),
+    );
+    for url in urls {
+        let data = match fetch_bytes(fetcher, url).await {
+            Ok(data) => data,
+            Err(e) => {
+                warn!("{}", e);
+                errors.push(e.to_string());
+                continue;
+            }
+        };
+
+        let hash = sha256::digest(&data);
+        if !hash.eq_ignore_ascii_case(&latest.hash) {
+            let e = ClientError::HashMismatch(latest.id, latest.hash.clone(), hash);
+            warn!("{}", e);
+            errors.push(e.to_string());
+            continue;
+        }
+
+        return Ok(data);
+    }
+
+    Err(ClientError::AllUrlsFailed(latest.id, errors))
+}
+
+/// Fetches the latest database listed in a versions.json (from its url or a mirror, checked
+/// against the listed hash)
+pub async fn fetch_database(
+    fetcher: &Fetcher,
+    versions_url: &Url,
+) -> Result<Compiled, ClientError> {
+    let data = fetch_bytes(fetcher, versions_url).await?;
+    let latest = latest_version(versions_url, &data)?;
+
+    let data = fetch_version(fetcher, &latest).await?;
+    serde_json::from_slice(&data)
+        .map_err(|e| ClientError::InvalidDatabase(latest.download_url.clone(), e.to_string()))
+}
+
+/// The database `check_for_update` keeps in the cache directory
+pub const DATABASE_FILE: &str = "database.json";
+
+/// What `check_for_update` knows about the cached database (kept next to it)
+pub const DATABASE_METADATA_FILE: &str = "database.meta.json";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// Where the cached database came from and the validators of that versions.json
+pub struct DatabaseMetadata {
+    pub versions_url: Url,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_modified: Option<String>,
+    /// The hash of the cached database (as listed in the versions.json)
+    pub hash: String,
+}
+
+impl DatabaseMetadata {
+    fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Some(value) = self
+            .etag
+            .as_ref()
+            .and_then(|etag| HeaderValue::from_str(etag).ok())
+        {
+            headers.insert(header::IF_NONE_MATCH, value);
+        }
+
+        if let Some(value) = self
+            .last_modified
+            .as_ref()
+            .and_then(|date| HeaderValue::from_str(date).ok())
+        {
+            headers.insert(header::IF_MODIFIED_SINCE, value);
+        }
+
+        headers
+    }
+}
+
+/// The outcome of `check_for_update`
+pub enum DatabaseUpdate {
+    /// The cached database is the latest (the versions.json is unchanged or still lists it)
+    UpToDate(Compiled),
+    /// A newer database was fetched (and cached)
+    Updated(Compiled),
+}
+
+impl DatabaseUpdate {
+    pub fn into_database(self) -> Compiled {
+        match self {
+            DatabaseUpdate::UpToDate(compiled) | DatabaseUpdate::Updated(compiled) => compiled,
+        }
+    }
+}
+
+/// The cached database of the versions.json (if it's intact)
+async fn cached_database(
+    cache_dir: &Path,
+    versions_url: &Url,
+) -> Option<(DatabaseMetadata, Vec<u8>)> {
+    let metadata = fs::read(cache_dir.join(DATABASE_METADATA_FILE))
+        .await
+        .ok()?;
+    let metadata: DatabaseMetadata = serde_json::from_slice(&metadata).ok()?;
+    if &metadata.versions_url != versions_url {
+        return None;
+    }
+
+    let data = fs::read(cache_dir.join(DATABASE_FILE)).await.ok()?;
+    sha256::digest(&data)
+        .eq_ignore_ascii_case(&metadata.hash)
+        .then_some((metadata, data))
+}
+
+/// Caches a database (the metadata is written last, a partial write is never trusted)
+async fn cache_database(
+    cache_dir: &Path,
+    metadata: &DatabaseMetadata,
+    data: Option<&[u8]>,
+) -> Result<(), ClientError> {
+    let io_error = |path: &Path, e: std::io::Error| ClientError::Io(path.to_owned(), e.to_string());
+
+    fs::create_dir_all(cache_dir)
+        .await
+        .map_err(|e| io_error(cache_dir, e))?;
+    if let Some(data) = data {
+        let path = cache_dir.join(DATABASE_FILE);
+        fs::write(&path, data)
+            .await
+            .map_err(|e| io_error(&path, e))?;
+    }
+
+    let path = cache_dir.join(DATABASE_METADATA_FILE);
+    let metadata = serde_json::to_vec_pretty(metadata)
+        .map_err(|e| ClientError::Io(path.clone(), e.to_string()))?;
+    fs::write(&path, metadata)
+        .await
+        .map_err(|e| io_error(&path, e))
+}
+
+/// Fetches the latest database unless the cached one is still the latest, the versions.json is
+/// requested conditionally (ETag/Last-Modified) so polling it is cheap
+pub async fn check_for_update(
+    fetcher: &Fetcher,
+    versions_url: &Url,
+    cache_dir: &Path,
+) -> Result<DatabaseUpdate, ClientError> {
+    let fetch_error = |error: String| ClientError::FetchFailed(versions_url.clone(), error);
+    let parse = |data: &[u8]| {
+        serde_json::from_slice::<Compiled>(data)
+            .map_err(|e| ClientError::InvalidDatabase(versions_url.clone(), e.to_string()))
+    };
+
+    let cached = cached_database(cache_dir, versions_url).await;
+    let headers = match &cached {
+        Some((metadata, _)) => metadata.conditional_headers(),
+        None => HeaderMap::new(),
+    };
+
+    let response = fetcher
+        .get_with_headers(versions_url, headers)
+        .await
+        .map_err(|e| fetch_error(e.to_string()))?;
+    if let (StatusCode::NOT_MODIFIED, Some((_, data))) = (response.status(), &cached) {
+        return parse(data).map(DatabaseUpdate::UpToDate);
+    }
+    if !response.status().is_success() {
+        return Err(fetch_error(format!("Status code: {}", response.status())));
+    }
+
+    let header = |name: header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+    };
+    let (etag, last_modified) = (header(header::ETAG), header(header::LAST_MODIFIED));
+    let data = fetcher
+        .read_body(response)
+        .await
+        .map_err(|e| fetch_error(e.to_string()))?;
+    let latest = latest_version(versions_url, &data)?;
+
+    let metadata = DatabaseMetadata {
+        versions_url: versions_url.clone(),
+        etag,
+        last_modified,
+        hash: latest.hash.clone(),
+    };
+    let (update, data) = match cached {
+        Some((cached, data)) if cached.hash.eq_ignore_ascii_case(&latest.hash) => (false, data),
+        _ => (true, fetch_version(fetcher, &latest).await?),
+    };
+    let compiled = parse(&data)?;
+
+    // A database that can't be cached is still good to use
+    if let Err(error) = cache_database(cache_dir, &metadata, update.then_some(&data[..])).await {
+        warn!("Failed to cache the database: {}", error);
+    }
+
+    Ok(match update {
+        true => DatabaseUpdate::Updated(compiled),
+        false => DatabaseUpdate::UpToDate(compiled),
+    })
+}
+
+/// Every url a download can be fetched from, in order of preference
+///
+/// Only http(s) mirrors are used, the rest (e.g. `ipfs://`) need a dedicated client.
+pub fn candidate_urls(
+    download: &CompiledDownloads,
+    signer: Option<&dyn UrlSigner>,
+) -> Result<Vec<Url>, ClientError> {
+    let mut urls = vec![download_url(download, signer)?];
+    urls.extend(
+        download
+            .mirrors
+            .iter()
+            .filter(|mirror| mirror.scheme() == "http" || mirror.scheme() == "https")
+            .cloned(),
+    );
+
+    Ok(urls)
+}
+
+/// Where an unfinished download is kept until it is verified
+fn partial_path(path: &Path) -> PathBuf {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".part");
+    PathBuf::from(partial)
+}
+
+/// Checks a downloaded file against the database
+fn verify_file(path: &Path, download: &CompiledDownloads) -> Result<(), ClientError> {
+    let size = match std::fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return Err(ClientError::Io(path.to_path_buf(), e.to_string())),
+    };
+    if size != download.file_size {
+        return Err(ClientError::SizeMismatch(
+            download.id,
+            download.file_size,
+            size,
+        ));
+    }
+
+    let hash = match sha256::try_digest(path) {
+        Ok(hash) => hash,
+        Err(e) => return Err(ClientError::Io(path.to_path_buf(), e.to_string())),
+    };
+    if !hash.eq_ignore_ascii_case(&download.hash) {
+        return Err(ClientError::HashMismatch(
+            download.id,
+            download.hash.clone(),
+            hash,
+        ));
+    }
+
+    Ok(())
+}
+
+/// The first byte of a `Content-Range` (e.g. `bytes 100-199/200`)
+fn range_start(range: &str) -> Option<u64> {
+    range
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Downloads artifacts to disk: resumes partial files, verifies them and falls back to the
+/// mirrors when a url fails
+pub struct DownloadManager<'a> {
+    fetcher: &'a Fetcher,
+    signer: Option<&'a dyn UrlSigner>,
+    race: bool,
+}
+
+impl<'a> DownloadManager<'a> {
+    pub fn new(fetcher: &'a Fetcher, signer: Option<&'a dyn UrlSigner>) -> DownloadManager<'a> {
+        DownloadManager {
+            fetcher,
+            signer,
+            race: false,
+        }
+    }
+
+    /// Request every url at once and download from the first to answer, instead of trying
+    /// them in order
+    pub fn race(mut self, race: bool) -> DownloadManager<'a> {
+        self.race = race;
+        self
+    }
+
+    /// Downloads to `path` (through `<path>.part`), the file is only moved there once verified
+    pub async fn download_to(
+        &self,
+        download: &CompiledDownloads,
+        path: &Path,
+    ) -> Result<(), ClientError> {
+        let partial = partial_path(path);
+        let mut urls = candidate_urls(download, self.signer)?;
+        let mut errors = vec![];
+
+        // A previous run may have finished the download but not verified it
+        if let Ok(metadata) = fs::metadata(&partial).await {
+            if metadata.len() >= download.file_size {
+                if verify_file(&partial, download).is_ok() {
+                    return self.finish(&partial, path).await;
+                }
+                self.discard(&partial).await?;
+            }
+        }
+
+        // Start with the fastest url, the others are the fallback
+        let mut first = None;
+        if self.race && urls.len() > 1 {
+            match self.fastest(&urls, &partial).await {
+                Some((index, response)) => first = Some((urls.remove(index), response)),
+                None => errors.push("no url answered the race".to_string()),
+            }
+        }
+
+        let mut attempts = vec![];
+        if let Some((url, response)) = first {
+            attempts.push((url, Some(response)));
+        }
+        attempts.extend(urls.into_iter().map(|url| (url, None)));
+
+        for (url, response) in attempts {
+            let response = match response {
+                Some(response) => Ok(response),
+                None => self.request(&url, &partial).await,
+            };
+
+            let result = match response {
+                Ok(response) => self.write(response, &partial, download).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                warn!("Failed to download {}: {}", url, e);
+                errors.push(e.to_string());
+                // Keep the partial file, the next url can resume it (unless it's too large)
+                if matches!(e, ClientError::SizeMismatch(..)) {
+                    self.discard(&partial).await?;
+                }
+                continue;
+            }
+
+            match verify_file(&partial, download) {
+                Ok(()) => return self.finish(&partial, path).await,
+                Err(e) => {
+                    warn!("Failed to download {}: {}", url, e);
+                    errors.push(e.to_string());
+                    self.discard(&partial).await?;
+                }
+            }
+        }
+
+        Err(ClientError::AllUrlsFailed(download.id, errors))
+    }
+
+    /// The headers to resume the partial file
+    async fn resume_headers(partial: &Path) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+
+        if let Ok(metadata) = fs::metadata(partial).await {
+            if metadata.len() > 0 {
+                if let Ok(range) = HeaderValue::from_str(&format!("bytes={}-", metadata.len())) {
+                    headers.insert(header::RANGE, range);
+                }
+            }
+        }
+
+        headers
+    }
+
+    async fn request(&self, url: &Url, partial: &Path) -> Result<Response, ClientError> {
+        let headers = DownloadManager::resume_headers(partial).await;
+
+        let response = self
+            .fetcher
+            .get_with_headers(url, headers)
+            .await
+            .map_err(|e| ClientError::FetchFailed(url.clone(), e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(ClientError::FetchFailed(
+                url.clone(),
+                format!("Status code: {}", response.status()),
+            ));
+        }
+
+        Ok(response)
+    }
+
+    /// Requests every url and returns the first successful response (and its index)
+    async fn fastest(&self, urls: &[Url], partial: &Path) -> Option<(usize, Response)> {
+        let headers = DownloadManager::resume_headers(partial).await;
+
+        let mut requests = JoinSet::new();
+        for (index, url) in urls.iter().enumerate() {
+            let fetcher = self.fetcher.clone();
+            let url = url.clone();
+            let headers = headers.clone();
+
+            requests.spawn(async move { (index, fetcher.get_with_headers(&url, headers).await) });
+        }
+
+        while let Some(joined) = requests.join_next().await {
+            if let Ok((index, Ok(response))) = joined {
+                if response.status().is_success() {
+                    requests.abort_all();
+                    return Some((index, response));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Streams the response into the partial file (appending if the server resumed it), giving
+    /// up once it's larger than the download
+    async fn write(
+        &self,
+        mut response: Response,
+        partial: &Path,
+        download: &CompiledDownloads,
+    ) -> Result<(), ClientError> {
+        let io_error = |e: std::io::Error| ClientError::Io(partial.to_path_buf(), e.to_string());
+        let url = response.url().clone();
+
+        let mut options = fs::OpenOptions::new();
+        let mut written = match response.status() {
+            StatusCode::PARTIAL_CONTENT => {
+                // The server has to resume where the partial file ends
+                let length = fs::metadata(partial).await.map(|m| m.len()).unwrap_or(0);
+                let start = response
+                    .headers()
+                    .get(header::CONTENT_RANGE)
+                    .and_then(|range| range.to_str().ok())
+                    .and_then(range_start);
+                if start != Some(length) {
+                    return Err(ClientError::FetchFailed(
+                        url,
+                        format!("Resumed at {:?} instead of {}", start, length),
+                    ));
+                }
+
+                options.append(true).create(true);
+                length
+            }
+            _ => {
+                options.write(true).create(true).truncate(true);
+                0
+            }
+        };
+        let mut file = options.open(partial).await.map_err(io_error)?;
+
+        loop {
+            match response.chunk().await {
+                Ok(Some(chunk)) => {
+                    written += chunk.len() as u64;
+                    if written > download.file_size {
+                        return Err(ClientError::SizeMismatch(
+                            download.id,
+                            download.file_size,
+                            written,
+                        ));
+                    }
+                    file.write_all(&chunk).await.map_err(io_error)?
+                }
+                Ok(None) => break,
+                Err(e) => return Err(ClientError::FetchFailed(url, e.to_string())),
+            }
+        }
+
+        file.flush().await.map_err(io_error)
+    }
+
+    async fn finish(&self, partial: &Path, path: &Path) -> Result<(), ClientError> {
+        fs::rename(partial, path)
+            .await
+            .map_err(|e| ClientError::Io(path.to_path_buf(), e.to_string()))
+    }
+
+    async fn discard(&self, partial: &Path) -> Result<(), ClientError> {
+        fs::remove_file(partial)
+            .await
+            .map_err(|e| ClientError::Io(partial.to_path_buf(), e.to_string()))
+    }
+}
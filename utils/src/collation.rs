@@ -0,0 +1,27 @@
+use std::cmp::Ordering;
+
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// The text without accents and case (e.g. `Élan` -> `elan`)
+fn base(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The order of names in the catalogs: case-insensitive and accents after the plain letter
+/// (`arial` < `Arial Black` < `Élan` < `Eras`)
+///
+/// Ties are broken by the accents, then the case (uppercase first), so only equal names are
+/// equal and the order doesn't depend on the editor that sorted them.
+pub fn collate(a: &str, b: &str) -> Ordering {
+    base(a)
+        .cmp(&base(b))
+        .then_with(|| {
+            a.nfd()
+                .flat_map(char::to_lowercase)
+                .cmp(b.nfd().flat_map(char::to_lowercase))
+        })
+        .then_with(|| a.cmp(b))
+}
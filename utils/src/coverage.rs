@@ -0,0 +1,179 @@
+use serde::Serialize;
+
+use crate::{
+    families::{family_name, is_wine_family},
+    types::Source,
+};
+
+/// The families an application expects to find
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub families: &'static [&'static str],
+}
+
+/// The requirement profiles we ship
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "core-web",
+        description: "The Core fonts for the Web (what most Windows software assumes)",
+        families: &[
+            "Andale Mono",
+            "Arial",
+            "Arial Black",
+            "Comic Sans MS",
+            "Courier New",
+            "Georgia",
+            "Impact",
+            "Times New Roman",
+            "Trebuchet MS",
+            "Verdana",
+            "Webdings",
+        ],
+    },
+    Profile {
+        name: "office-2016",
+        description: "Microsoft Office 2016 (documents and the ribbon)",
+        families: &[
+            "Arial",
+            "Arial Black",
+            "Arial Narrow",
+            "Book Antiqua",
+            "Bookman Old Style",
+            "Calibri",
+            "Calibri Light",
+            "Cambria",
+            "Cambria Math",
+            "Candara",
+            "Century Gothic",
+            "Comic Sans MS",
+            "Consolas",
+            "Constantia",
+            "Corbel",
+            "Courier New",
+            "Garamond",
+            "Georgia",
+            "Gill Sans MT",
+            "Impact",
+            "Lucida Console",
+            "Lucida Sans Unicode",
+            "Microsoft Sans Serif",
+            "Palatino Linotype",
+            "Segoe UI",
+            "Symbol",
+            "Tahoma",
+            "Times New Roman",
+            "Trebuchet MS",
+            "Verdana",
+            "Webdings",
+            "Wingdings",
+        ],
+    },
+    Profile {
+        name: "adobe-cc",
+        description: "Adobe Creative Cloud (the UI and the default document fonts)",
+        families: &[
+            "Adobe Clean",
+            "Arial",
+            "Kozuka Gothic Pr6N",
+            "Kozuka Mincho Pr6N",
+            "Minion Pro",
+            "Myriad Pro",
+            "Segoe UI",
+            "Source Code Pro",
+            "Source Sans Pro",
+            "Source Serif Pro",
+            "Tahoma",
+            "Times New Roman",
+        ],
+    },
+];
+
+/// A profile by name
+pub fn find_profile(name: &str) -> Option<&'static Profile> {
+    PROFILES
+        .iter()
+        .find(|profile| profile.name.eq_ignore_ascii_case(name))
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "status")]
+/// Where a required family comes from
+pub enum FamilyCoverage {
+    /// Fonts of the catalog install it (Fonts)
+    Catalog {
+        fonts: Vec<String>,
+    },
+    /// Wine installs it into every prefix
+    Wine,
+    Missing,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequiredFamily {
+    pub family: String,
+    #[serde(flatten)]
+    pub coverage: FamilyCoverage,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// How much of a profile the catalog satisfies
+pub struct ProfileCoverage {
+    pub profile: String,
+    pub description: String,
+    pub families: Vec<RequiredFamily>,
+}
+
+impl ProfileCoverage {
+    /// Families neither the catalog nor Wine provide
+    pub fn missing(&self) -> impl Iterator<Item = &RequiredFamily> {
+        self.families
+            .iter()
+            .filter(|family| family.coverage == FamilyCoverage::Missing)
+    }
+
+    /// Satisfied families (catalog or Wine)
+    pub fn satisfied(&self) -> usize {
+        self.families.len() - self.missing().count()
+    }
+}
+
+/// Checks which of a profile's families the catalog (or Wine) provides
+pub fn coverage(source: &Source, profile: &Profile) -> ProfileCoverage {
+    let families = profile
+        .families
+        .iter()
+        .map(|family| {
+            let fonts: Vec<String> = source
+                .fonts
+                .iter()
+                .filter(|font| {
+                    font.installations
+                        .iter()
+                        .flat_map(|installation| installation.installed_files())
+                        .any(|file| family_name(&file.registry_name).eq_ignore_ascii_case(family))
+                })
+                .map(|font| font.name.clone())
+                .collect();
+
+            let coverage = match (fonts.is_empty(), is_wine_family(family)) {
+                (false, _) => FamilyCoverage::Catalog { fonts },
+                (true, true) => FamilyCoverage::Wine,
+                (true, false) => FamilyCoverage::Missing,
+            };
+
+            RequiredFamily {
+                family: family.to_string(),
+                coverage,
+            }
+        })
+        .collect();
+
+    ProfileCoverage {
+        profile: profile.name.to_string(),
+        description: profile.description.to_string(),
+        families,
+    }
+}
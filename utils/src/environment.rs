@@ -0,0 +1,245 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use s3::{error::S3Error, Bucket};
+use semver::Version;
+use serde::de::DeserializeOwned;
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    metalink::metalink,
+    types::Compiled,
+    utils::{
+        generate_metalink_url, generate_versions_url, upload_downloadables_to_s3,
+        upload_metalink_to_s3, upload_version_to_s3, upload_versions_to_s3, Downloadable,
+        DownloadsList, VersionInfo, VERSIONS_FILE_PATH,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+/// Where a database is published
+pub enum Environment {
+    #[default]
+    /// What clients use by default
+    Production,
+    /// New databases soak here (nightly clients) before they're promoted, it has its own bucket
+    /// and base url
+    Staging,
+}
+
+/// A bucket and the url it's served from
+pub struct Target {
+    pub bucket: Bucket,
+    pub base_url: Url,
+}
+
+pub enum PromoteError {
+    /// An index couldn't be read (File name, Error)
+    UnreadableIndex(String, String),
+    /// The version isn't in staging (Version)
+    UnknownVersion(Version),
+    /// Production already has the version (Version)
+    AlreadyPromoted(Version),
+    /// The version file couldn't be read (Id, Error)
+    UnreadableVersion(Uuid, String),
+    /// The version file doesn't match versions.json (Id, Expected hash, Hash)
+    VersionHashMismatch(Uuid, String, String),
+    /// A download couldn't be copied (Id, Error)
+    Download(Uuid, String),
+    /// A download doesn't match the version (Id, Expected hash, Hash)
+    DownloadHashMismatch(Uuid, String, String),
+}
+
+impl Display for PromoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromoteError::UnreadableIndex(name, error) => {
+                write!(f, "Failed to read {}: {}", name, error)
+            }
+            PromoteError::UnknownVersion(version) => {
+                write!(f, "Staging has no version {}", version)
+            }
+            PromoteError::AlreadyPromoted(version) => {
+                write!(f, "Production already has version {}", version)
+            }
+            PromoteError::UnreadableVersion(id, error) => {
+                write!(f, "Failed to read the version file {}: {}", id, error)
+            }
+            PromoteError::VersionHashMismatch(id, expected, hash) => write!(
+                f,
+                "The version file {} has the hash {} but is listed with {}",
+                id, hash, expected
+            ),
+            PromoteError::Download(id, error) => {
+                write!(f, "Failed to copy the download {}: {}", id, error)
+            }
+            PromoteError::DownloadHashMismatch(id, expected, hash) => write!(
+                f,
+                "The download {} has the hash {} but the version expects {}",
+                id, hash, expected
+            ),
+        }
+    }
+}
+
+/// What a promotion published
+pub struct Promoted {
+    pub id: Uuid,
+    pub version_url: Url,
+    pub copied_downloads: usize,
+}
+
+/// The key of an object served from `base_url` (`None` for other urls)
+fn object_key(base_url: &Url, url: &Url) -> Option<String> {
+    let relative = url.as_str().strip_prefix(base_url.as_str())?;
+
+    urlencoding::decode(relative)
+        .ok()
+        .map(|key| key.into_owned())
+}
+
+/// Reads an index of a bucket, it's only empty when the bucket doesn't have it yet (anything else
+/// is an error, the index is written back)
+async fn read_index<T: DeserializeOwned>(
+    bucket: &Bucket,
+    name: &str,
+) -> Result<Vec<T>, PromoteError> {
+    match bucket.get_object(format!("/{}", name)).await {
+        Ok(data) => serde_json::from_slice(data.as_slice())
+            .map_err(|e| PromoteError::UnreadableIndex(name.to_string(), e.to_string())),
+        Err(S3Error::Http(404, _)) => Ok(vec![]),
+        Err(e) => Err(PromoteError::UnreadableIndex(
+            name.to_string(),
+            e.to_string(),
+        )),
+    }
+}
+
+/// Copies a version (and the downloads it has in staging) from staging to production
+///
+/// Nothing is written until the version file and every download match their hashes.
+/// Downloads keep their ids, their urls and metalinks are moved to production.
+pub async fn promote(
+    staging: &Target,
+    production: &Target,
+    version: &Version,
+) -> Result<Promoted, PromoteError> {
+    let staging_versions: Vec<VersionInfo> = read_index(&staging.bucket, "versions.json").await?;
+    let info = match staging_versions.iter().find(|v| &v.version == version) {
+        Some(info) => info,
+        None => return Err(PromoteError::UnknownVersion(version.clone())),
+    };
+
+    let mut versions: Vec<VersionInfo> = read_index(&production.bucket, "versions.json").await?;
+    if versions.iter().any(|v| &v.version == version) {
+        return Err(PromoteError::AlreadyPromoted(version.clone()));
+    }
+
+    // Validate the version file
+    let path = format!("{}/{}.json", VERSIONS_FILE_PATH, info.id);
+    let data = match staging.bucket.get_object(&path).await {
+        Ok(data) => data.to_vec(),
+        Err(e) => return Err(PromoteError::UnreadableVersion(info.id, e.to_string())),
+    };
+    let hash = sha256::digest(data.as_slice());
+    if !hash.eq_ignore_ascii_case(&info.hash) {
+        return Err(PromoteError::VersionHashMismatch(
+            info.id,
+            info.hash.clone(),
+            hash,
+        ));
+    }
+    let mut compiled: Compiled = match serde_json::from_slice(&data) {
+        Ok(compiled) => compiled,
+        Err(e) => return Err(PromoteError::UnreadableVersion(info.id, e.to_string())),
+    };
+
+    // Validate the downloads staging serves (external ones stay where they are)
+    let mut copies = vec![];
+    for download in &compiled.downloads {
+        let key = match object_key(&staging.base_url, &download.download_url) {
+            Some(key) => key,
+            None => continue,
+        };
+
+        let data = match staging.bucket.get_object(&key).await {
+            Ok(data) => data.to_vec(),
+            Err(e) => return Err(PromoteError::Download(download.id, e.to_string())),
+        };
+        let hash = sha256::digest(data.as_slice());
+        if !hash.eq_ignore_ascii_case(&download.hash) {
+            return Err(PromoteError::DownloadHashMismatch(
+                download.id,
+                download.hash.clone(),
+                hash,
+            ));
+        }
+
+        copies.push((download.id, key, data));
+    }
+
+    // Copy them
+    let mut downloadables: DownloadsList =
+        read_index(&production.bucket, "downloadables.json").await?;
+    let copied_downloads = copies.len();
+    for (id, key, data) in copies {
+        let download_url = match production.base_url.join(&key) {
+            Ok(url) => url,
+            Err(e) => return Err(PromoteError::Download(id, e.to_string())),
+        };
+
+        if let Err(e) = production.bucket.put_object(&key, &data).await {
+            return Err(PromoteError::Download(id, e.to_string()));
+        }
+        info!("Copied {}", key);
+
+        let download = match compiled.downloads.iter_mut().find(|d| d.id == id) {
+            Some(download) => download,
+            None => continue,
+        };
+        download.download_url = download_url;
+
+        if download.metalink.is_some() {
+            upload_metalink_to_s3(&production.bucket, id, &metalink(download)).await;
+            download.metalink = Some(generate_metalink_url(&production.base_url, &id));
+        }
+
+        if !downloadables.iter().any(|d| d.id == id) {
+            downloadables.push(Downloadable {
+                id,
+                file_size: download.file_size,
+                hash: download.hash.clone(),
+                download_url: download.download_url.clone(),
+                access: download.access,
+                mirrors: download.mirrors.clone(),
+            });
+        }
+    }
+    upload_downloadables_to_s3(&production.bucket, &downloadables).await;
+
+    // Publish the version
+    let data = match serde_json::to_vec(&compiled) {
+        Ok(data) => data,
+        Err(e) => return Err(PromoteError::UnreadableVersion(info.id, e.to_string())),
+    };
+    upload_version_to_s3(&production.bucket, info.id, &data).await;
+
+    let version_url = generate_versions_url(&production.base_url, &info.id);
+    versions.push(VersionInfo {
+        id: info.id,
+        version: version.clone(),
+        download_url: version_url.clone(),
+        hash: sha256::digest(data.as_slice()),
+        file_size: data.len() as u64,
+        mirrors: vec![],
+        expires_at: None,
+    });
+    upload_versions_to_s3(&production.bucket, versions).await;
+
+    Ok(Promoted {
+        id: info.id,
+        version_url,
+        copied_downloads,
+    })
+}
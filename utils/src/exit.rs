@@ -0,0 +1,8 @@
+// Exit codes of lint and update, so CI can tell catalog problems from infrastructure problems
+
+/// The catalog has errors (or more warnings than allowed)
+pub const LINT_ERRORS: i32 = 1;
+/// A file couldn't be read, written or parsed
+pub const IO: i32 = 2;
+/// A request failed
+pub const NETWORK: i32 = 3;
@@ -0,0 +1,448 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::types::{
+    CabextractInstalationExtractData, Compiled, CompiledApplication, CompiledDownloads,
+    CompiledFont, CompiledInstalationType, DownloadAccess,
+};
+
+/// The registry key fonts are registered under
+const FONTS_REGISTRY_KEY: &str =
+    "HKEY_LOCAL_MACHINE\\Software\\Microsoft\\Windows NT\\CurrentVersion\\Fonts";
+
+pub enum ExportError {
+    /// A download the font uses isn't in the database (Font, Id)
+    MissingDownload(String, Uuid),
+    /// Signed downloads have no public url to export (Font)
+    SignedDownload(String),
+    /// The manifest couldn't be serialized (Error)
+    Serialize(String),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExportError::MissingDownload(font, id) => {
+                write!(f, "The download {} of {} isn't in the database", id, font)
+            }
+            ExportError::SignedDownload(font) => {
+                write!(f, "{} uses a signed download, it can't be exported", font)
+            }
+            ExportError::Serialize(error) => write!(f, "Failed to serialize: {}", error),
+        }
+    }
+}
+
+/// The download of an installation (public downloads only)
+fn public_download<'a>(
+    compiled: &'a Compiled,
+    font: &CompiledFont,
+    installation: &CompiledInstalationType,
+) -> Result<&'a CompiledDownloads, ExportError> {
+    let download = match compiled
+        .downloads
+        .iter()
+        .find(|d| d.id == installation.download())
+    {
+        Some(download) => download,
+        None => {
+            return Err(ExportError::MissingDownload(
+                font.name.clone(),
+                installation.download(),
+            ))
+        }
+    };
+
+    match download.access {
+        DownloadAccess::Public => Ok(download),
+        DownloadAccess::Signed => Err(ExportError::SignedDownload(font.name.clone())),
+    }
+}
+
+/// The file name of a download (the last segment of its url)
+fn download_file_name(download: &CompiledDownloads) -> String {
+    download
+        .download_url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| download.id.to_string())
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+/// A step of a Bottles dependency
+enum BottlesStep {
+    DownloadArchive {
+        file_name: String,
+        url: String,
+        file_size: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        rename: Option<String>,
+    },
+    CabExtract {
+        file_name: String,
+        url: String,
+        dest: String,
+    },
+    InstallFonts {
+        url: String,
+        fonts: Vec<String>,
+    },
+    RegisterFont {
+        name: String,
+        dest: String,
+    },
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+/// A Bottles dependency manifest
+struct BottlesManifest {
+    name: String,
+    description: String,
+    provider: String,
+    dependencies: Vec<String>,
+    steps: Vec<BottlesStep>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "PascalCase")]
+/// An entry of the Bottles dependency index
+struct BottlesIndexEntry {
+    description: String,
+    category: String,
+}
+
+/// The Bottles dependency manifests of the fonts (file name, yaml), plus their `index.yml`
+///
+/// Every font is a dependency named after its short name.
+pub fn bottles(
+    compiled: &Compiled,
+    fonts: &[&CompiledFont],
+) -> Result<Vec<(String, String)>, ExportError> {
+    let mut files = vec![];
+    let mut index: BTreeMap<String, BottlesIndexEntry> = BTreeMap::new();
+
+    for font in fonts {
+        let mut steps = vec![];
+
+        for installation in &font.installations {
+            let download = public_download(compiled, font, installation)?;
+            let file_name = download_file_name(download);
+
+            match installation {
+                CompiledInstalationType::Cabextract(data) => {
+                    let dest = format!("temp/{}/", download.id);
+                    steps.push(BottlesStep::DownloadArchive {
+                        file_name: file_name.clone(),
+                        url: download.download_url.to_string(),
+                        file_size: download.file_size,
+                        rename: None,
+                    });
+                    steps.push(BottlesStep::CabExtract {
+                        file_name,
+                        url: "temp/".to_string(),
+                        dest: dest.clone(),
+                    });
+                    steps.push(BottlesStep::InstallFonts {
+                        url: dest,
+                        fonts: data.files.iter().map(|file| file.file.clone()).collect(),
+                    });
+                }
+                // The download is the converted font
+                CompiledInstalationType::Woff2(data) => {
+                    steps.push(BottlesStep::DownloadArchive {
+                        file_name,
+                        url: download.download_url.to_string(),
+                        file_size: download.file_size,
+                        rename: Some(data.file.clone()),
+                    });
+                    steps.push(BottlesStep::InstallFonts {
+                        url: "temp/".to_string(),
+                        fonts: vec![data.file.clone()],
+                    });
+                }
+            }
+
+            for file in installation.installed_files() {
+                steps.push(BottlesStep::RegisterFont {
+                    name: file.registry_name,
+                    dest: file.file,
+                });
+            }
+        }
+
+        let description = format!("{} font", font.name);
+        let manifest = BottlesManifest {
+            name: font.short_name.clone(),
+            description: description.clone(),
+            provider: font.publisher.clone(),
+            dependencies: vec![],
+            steps,
+        };
+
+        let data =
+            serde_yaml::to_string(&manifest).map_err(|e| ExportError::Serialize(e.to_string()))?;
+        files.push((format!("{}.yml", font.short_name), data));
+
+        index.insert(
+            font.short_name.clone(),
+            BottlesIndexEntry {
+                description,
+                category: "Fonts".to_string(),
+            },
+        );
+    }
+
+    let data = serde_yaml::to_string(&index).map_err(|e| ExportError::Serialize(e.to_string()))?;
+    files.push(("index.yml".to_string(), data));
+
+    Ok(files)
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// A file a Lutris installer downloads
+struct LutrisFile {
+    url: String,
+    filename: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(untagged)]
+/// The arguments of a Lutris installer directive
+enum LutrisDirective {
+    Extract {
+        file: String,
+        dst: String,
+    },
+    /// `merge` and `copy`
+    Files {
+        src: String,
+        dst: String,
+    },
+    Task {
+        name: String,
+        prefix: String,
+        path: String,
+        key: String,
+        value: String,
+        #[serde(rename = "type")]
+        kind: String,
+    },
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct LutrisScript {
+    files: Vec<BTreeMap<String, LutrisFile>>,
+    /// Every directive is a map with a single key, its name
+    installer: Vec<BTreeMap<&'static str, LutrisDirective>>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+/// A Lutris installer
+struct LutrisInstaller {
+    name: String,
+    game_slug: String,
+    version: String,
+    slug: String,
+    runner: String,
+    script: LutrisScript,
+}
+
+/// A Lutris installer (yaml) that installs the fonts into the prefix of the game (`$GAMEDIR`)
+pub fn lutris(compiled: &Compiled, fonts: &[&CompiledFont]) -> Result<String, ExportError> {
+    let fonts_directory = "$GAMEDIR/drive_c/windows/Fonts".to_string();
+    let mut files = vec![];
+    let mut installer = vec![];
+
+    for font in fonts {
+        for installation in &font.installations {
+            let download = public_download(compiled, font, installation)?;
+            let id = format!("{}_{}", font.short_name, download.id.simple());
+
+            // Converted fonts are downloaded under the name they're installed as
+            let filename = match installation {
+                CompiledInstalationType::Cabextract(_) => download_file_name(download),
+                CompiledInstalationType::Woff2(data) => data.file.clone(),
+            };
+            files.push(BTreeMap::from([(
+                id.clone(),
+                LutrisFile {
+                    url: download.download_url.to_string(),
+                    filename,
+                },
+            )]));
+
+            match installation {
+                // Wine looks files up case-insensitively, so the extracted names can be kept
+                CompiledInstalationType::Cabextract(_) => {
+                    let extracted = format!("$CACHE/{}", id);
+                    installer.push(BTreeMap::from([(
+                        "extract",
+                        LutrisDirective::Extract {
+                            file: id,
+                            dst: extracted.clone(),
+                        },
+                    )]));
+                    installer.push(BTreeMap::from([(
+                        "merge",
+                        LutrisDirective::Files {
+                            src: extracted,
+                            dst: fonts_directory.clone(),
+                        },
+                    )]));
+                }
+                CompiledInstalationType::Woff2(_) => installer.push(BTreeMap::from([(
+                    "copy",
+                    LutrisDirective::Files {
+                        src: id,
+                        dst: fonts_directory.clone(),
+                    },
+                )])),
+            }
+
+            for file in installation.installed_files() {
+                let task = LutrisDirective::Task {
+                    name: "set_regedit".to_string(),
+                    prefix: "$GAMEDIR".to_string(),
+                    path: FONTS_REGISTRY_KEY.to_string(),
+                    key: file.registry_name,
+                    value: file.file,
+                    kind: "REG_SZ".to_string(),
+                };
+                installer.push(BTreeMap::from([("task", task)]));
+            }
+        }
+    }
+
+    let installer = LutrisInstaller {
+        name: "WineFonts".to_string(),
+        game_slug: "winefonts".to_string(),
+        version: compiled.version.to_string(),
+        slug: format!("winefonts-{}", compiled.version),
+        runner: "wine".to_string(),
+        script: LutrisScript { files, installer },
+    };
+
+    serde_yaml::to_string(&installer).map_err(|e| ExportError::Serialize(e.to_string()))
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A file to install from a download
+struct SteamCompatFile {
+    file: String,
+    registry_name: String,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A download and the files installed from it
+struct SteamCompatDownload {
+    url: String,
+    sha256: String,
+    file_size: u64,
+    /// `cabextract` (extract the files) or `file` (the download is the font)
+    kind: &'static str,
+    files: Vec<SteamCompatFile>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SteamCompatFont {
+    id: Uuid,
+    name: String,
+    short_name: String,
+    downloads: Vec<SteamCompatDownload>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct SteamCompatApp {
+    name: String,
+    fonts: Vec<SteamCompatFont>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// Fonts to install into a game's prefix, by Steam app id
+struct SteamCompat {
+    version: String,
+    apps: BTreeMap<u32, SteamCompatApp>,
+}
+
+/// A lookup (json) of the fonts Steam games need, by app id, for helpers that set up Proton
+/// prefixes (`compatdata/<app id>/pfx`)
+///
+/// Applications without a Steam app id are skipped.
+pub fn steam_compat(
+    compiled: &Compiled,
+    applications: &[&CompiledApplication],
+) -> Result<String, ExportError> {
+    let mut apps = BTreeMap::new();
+
+    for application in applications {
+        let app_id = match application.steam_app_id {
+            Some(app_id) => app_id,
+            None => continue,
+        };
+
+        let mut fonts = vec![];
+        for font in application
+            .fonts
+            .iter()
+            .filter_map(|id| compiled.find_font(*id))
+        {
+            let mut downloads = vec![];
+            for installation in &font.installations {
+                let download = public_download(compiled, font, installation)?;
+                let files = |files: Vec<CabextractInstalationExtractData>| {
+                    files
+                        .into_iter()
+                        .map(|file| SteamCompatFile {
+                            file: file.file,
+                            registry_name: file.registry_name,
+                        })
+                        .collect()
+                };
+
+                downloads.push(SteamCompatDownload {
+                    url: download.download_url.to_string(),
+                    sha256: download.hash.clone(),
+                    file_size: download.file_size,
+                    kind: match installation {
+                        CompiledInstalationType::Cabextract(_) => "cabextract",
+                        CompiledInstalationType::Woff2(_) => "file",
+                    },
+                    files: files(installation.installed_files()),
+                });
+            }
+
+            fonts.push(SteamCompatFont {
+                id: font.id,
+                name: font.name.clone(),
+                short_name: font.short_name.clone(),
+                downloads,
+            });
+        }
+
+        apps.insert(
+            app_id,
+            SteamCompatApp {
+                name: application.name.clone(),
+                fonts,
+            },
+        );
+    }
+
+    let lookup = SteamCompat {
+        version: compiled.version.to_string(),
+        apps,
+    };
+
+    serde_json::to_string_pretty(&lookup).map_err(|e| ExportError::Serialize(e.to_string()))
+}
@@ -0,0 +1,120 @@
+#[cfg(any(feature = "client", feature = "publish"))]
+use ttf_parser::{name_id, Face, Language};
+
+#[cfg(any(feature = "client", feature = "publish"))]
+use crate::types::{FaceStyle, FontFace, VariationAxis};
+
+/// Font families Wine installs into every prefix
+pub const WINE_FONT_FAMILIES: &[&str] = &[
+    "Courier",
+    "Fixedsys",
+    "Marlett",
+    "Microsoft Sans Serif",
+    "Microsoft YaHei",
+    "Modern",
+    "MS Sans Serif",
+    "MS Serif",
+    "Nirmala UI",
+    "Roman",
+    "Script",
+    "Small Fonts",
+    "Symbol",
+    "System",
+    "Tahoma",
+    "Webdings",
+    "Wingdings",
+];
+
+/// Style suffixes of registry names (longest first)
+const STYLES: &[&str] = &[
+    " Bold Italic",
+    " Bold Oblique",
+    " Regular",
+    " Bold",
+    " Italic",
+    " Oblique",
+];
+
+/// The family of a registry name (e.g. `Arial Bold (TrueType)` -> `Arial`)
+pub fn family_name(registry_name: &str) -> String {
+    let mut name = registry_name.trim();
+    if let Some((family, kind)) = name.rsplit_once(" (") {
+        if kind.ends_with(')') {
+            name = family;
+        }
+    }
+
+    for style in STYLES {
+        if let Some(family) = name.strip_suffix(style) {
+            return family.to_string();
+        }
+    }
+
+    name.to_string()
+}
+
+#[cfg(any(feature = "client", feature = "publish"))]
+/// The faces of a font file (every face of a collection), empty if it isn't a font
+pub fn font_faces(data: &[u8]) -> Vec<FontFace> {
+    let count = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    let mut faces = vec![];
+
+    for index in 0..count {
+        let face = match Face::parse(data, index) {
+            Ok(face) => face,
+            Err(_) => continue,
+        };
+
+        // The typographic names group more than four styles, the legacy ones are the fallback.
+        // Older fonts localize the style names (e.g. `Negreta`), the English ones come first
+        let name = |ids: [u16; 2]| {
+            ids.iter().find_map(|id| {
+                let names = || {
+                    face.names()
+                        .into_iter()
+                        .filter(move |name| name.name_id == *id)
+                };
+                names()
+                    .filter(|name| name.language() == Language::English_UnitedStates)
+                    .find_map(|name| name.to_string())
+                    .or_else(|| names().find_map(|name| name.to_string()))
+            })
+        };
+        let family = match name([name_id::TYPOGRAPHIC_FAMILY, name_id::FAMILY]) {
+            Some(family) => family,
+            None => continue,
+        };
+
+        faces.push(FontFace {
+            family,
+            style_name: name([name_id::TYPOGRAPHIC_SUBFAMILY, name_id::SUBFAMILY])
+                .unwrap_or_else(|| "Regular".to_string()),
+            weight: face.weight().to_number(),
+            style: match face.style() {
+                ttf_parser::Style::Normal => FaceStyle::Normal,
+                ttf_parser::Style::Italic => FaceStyle::Italic,
+                ttf_parser::Style::Oblique => FaceStyle::Oblique,
+            },
+            axes: face
+                .variation_axes()
+                .into_iter()
+                .filter(|axis| !axis.hidden)
+                .map(|axis| VariationAxis {
+                    tag: axis.tag.to_string(),
+                    min: axis.min_value,
+                    default: axis.def_value,
+                    max: axis.max_value,
+                })
+                .collect(),
+        });
+    }
+
+    faces
+}
+
+/// Whether Wine already provides the family
+pub fn is_wine_family(family: &str) -> bool {
+    WINE_FONT_FAMILIES
+        .iter()
+        .any(|wine| wine.eq_ignore_ascii_case(family))
+}
@@ -0,0 +1,134 @@
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{metalink::escape, types::Compiled};
+
+/// How many releases the feed lists (the newest ones)
+const FEED_ENTRIES: usize = 50;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A published version as the release feed lists it
+pub struct Release {
+    pub id: Uuid,
+    pub version: Version,
+    pub download_url: Url,
+    /// When it was published (unix time, in seconds)
+    pub published_at: u64,
+    /// What changed since the version before it
+    pub summary: String,
+}
+
+/// What changed between two versions, for humans (e.g. `12 fonts, added Arial`)
+pub fn summary(built: &Compiled, previous: Option<&Compiled>) -> String {
+    let mut parts = vec![match built.fonts.len() {
+        1 => "1 font".to_string(),
+        fonts => format!("{} fonts", fonts),
+    }];
+
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return parts.remove(0),
+    };
+
+    let added: Vec<&str> = built
+        .fonts
+        .iter()
+        .filter(|f| !previous.fonts.iter().any(|p| p.id == f.id))
+        .map(|f| f.name.as_str())
+        .collect();
+    let removed: Vec<&str> = previous
+        .fonts
+        .iter()
+        .filter(|p| !built.fonts.iter().any(|f| f.id == p.id))
+        .map(|p| p.name.as_str())
+        .collect();
+
+    if !added.is_empty() {
+        parts.push(format!("added {}", added.join(", ")));
+    }
+    if !removed.is_empty() {
+        parts.push(format!("removed {}", removed.join(", ")));
+    }
+    if added.is_empty() && removed.is_empty() {
+        parts.push(format!("no font changes since {}", previous.version));
+    }
+
+    parts.join(", ")
+}
+
+/// A unix time as an RFC 3339 timestamp (in UTC)
+fn timestamp(seconds: u64) -> String {
+    let days = (seconds / 86400) as i64;
+    let time = seconds % 86400;
+
+    // Days to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+/// An Atom (RFC 4287) feed of the newest releases, `feed_url` is where the feed is served
+pub fn feed(feed_url: &Url, releases: &[Release]) -> String {
+    let mut releases: Vec<&Release> = releases.iter().collect();
+    releases.sort_by_key(|release| std::cmp::Reverse(release.published_at));
+    releases.truncate(FEED_ENTRIES);
+
+    let updated = releases.first().map(|r| r.published_at).unwrap_or_default();
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <id>{}</id>\n", escape(feed_url.as_str())));
+    xml.push_str("  <title>WineFonts database releases</title>\n");
+    xml.push_str(&format!("  <updated>{}</updated>\n", timestamp(updated)));
+    xml.push_str(&format!(
+        "  <link rel=\"self\" href=\"{}\"/>\n",
+        escape(feed_url.as_str())
+    ));
+    xml.push_str("  <author><name>WineFonts</name></author>\n");
+
+    for release in releases {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <id>urn:uuid:{}</id>\n", release.id));
+        xml.push_str(&format!(
+            "    <title>Database {}</title>\n",
+            escape(&release.version.to_string())
+        ));
+        xml.push_str(&format!(
+            "    <updated>{}</updated>\n",
+            timestamp(release.published_at)
+        ));
+        xml.push_str(&format!(
+            "    <link rel=\"alternate\" type=\"application/json\" href=\"{}\"/>\n",
+            escape(release.download_url.as_str())
+        ));
+        xml.push_str(&format!(
+            "    <summary>{}</summary>\n",
+            escape(&release.summary)
+        ));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
@@ -0,0 +1,315 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::{
+    header::{self, HeaderMap},
+    redirect::Policy,
+    Client, ClientBuilder, Response, StatusCode,
+};
+use tokio::sync::Mutex;
+use url::Url;
+
+/// The user agent sent with every external request
+pub const USER_AGENT: &str = concat!(
+    "WineFonts/",
+    env!("CARGO_PKG_VERSION"),
+    " (+https://github.com/JoshuaBrest/WineFonts)"
+);
+
+/// The default delay between two requests to the same host (in milliseconds)
+pub const DEFAULT_REQUEST_INTERVAL: u64 = 1000;
+
+/// The most redirects followed by default
+pub const DEFAULT_MAX_REDIRECTS: usize = 10;
+/// How long connecting to a host may take by default (in seconds)
+pub const DEFAULT_CONNECT_TIMEOUT: u64 = 30;
+/// How long a server may stay silent by default (in seconds)
+pub const DEFAULT_READ_TIMEOUT: u64 = 60;
+/// The biggest body read by default (in bytes)
+pub const DEFAULT_MAX_BODY_SIZE: u64 = 512 * 1024 * 1024;
+
+/// How long an idle connection is kept for the next request to the same host
+const POOL_IDLE_TIMEOUT: Duration = Duration::from_secs(90);
+/// The most idle connections kept per host
+const POOL_MAX_IDLE_PER_HOST: usize = 8;
+/// How often idle connections are probed so the pool doesn't hand out dead ones
+const TCP_KEEPALIVE: Duration = Duration::from_secs(60);
+
+/// How many times a request is retried after a 429/503
+const MAX_RETRIES: u32 = 3;
+/// The longest we are willing to wait for a Retry-After
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(120);
+/// The wait used when the server doesn't send a (valid) Retry-After
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(5);
+
+pub enum FetchError {
+    /// The request failed (Error)
+    Request(reqwest::Error),
+    /// The server didn't answer in time (Url)
+    Timeout(Url),
+    /// The body is bigger than allowed (Url, Limit)
+    TooLarge(Url, u64),
+    /// The fetcher is offline (Url)
+    Offline(Url),
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Request(error) => write!(f, "{}", error),
+            FetchError::Timeout(url) => write!(f, "{} timed out", url),
+            FetchError::TooLarge(url, limit) => {
+                write!(f, "{} is bigger than the limit of {} bytes", url, limit)
+            }
+            FetchError::Offline(url) => write!(f, "{} needs the network, which is off", url),
+        }
+    }
+}
+
+impl From<reqwest::Error> for FetchError {
+    fn from(error: reqwest::Error) -> Self {
+        FetchError::Request(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// Bounds on every request, so a misbehaving upstream can't hang or balloon a run
+pub struct Limits {
+    pub connect_timeout: Duration,
+    /// The longest wait for the headers or the next chunk of the body
+    pub read_timeout: Duration,
+    pub max_redirects: usize,
+    /// Checked against the Content-Length and while reading (with `read_body`)
+    pub max_body_size: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT),
+            read_timeout: Duration::from_secs(DEFAULT_READ_TIMEOUT),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+        }
+    }
+}
+
+#[derive(Clone)]
+/// A polite http client: identifies itself, rate limits per host and honors Retry-After
+///
+/// Clones share the connection pool, so a run should make one and pass it around: connections
+/// (and their TLS sessions) are reused per host and HTTP/2 is used when the server offers it.
+pub struct Fetcher {
+    client: Client,
+    no_redirect_client: Client,
+    interval: Duration,
+    limits: Limits,
+    next_request: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Every request fails (air-gapped builds)
+    offline: bool,
+}
+
+impl Fetcher {
+    pub fn new(interval: Duration) -> Result<Fetcher, reqwest::Error> {
+        Fetcher::with_limits(interval, Limits::default())
+    }
+
+    pub fn with_limits(interval: Duration, limits: Limits) -> Result<Fetcher, reqwest::Error> {
+        let client = client_builder(&limits)
+            .redirect(Policy::limited(limits.max_redirects))
+            .build()?;
+        let no_redirect_client = client_builder(&limits).redirect(Policy::none()).build()?;
+
+        Ok(Fetcher {
+            client,
+            no_redirect_client,
+            interval,
+            limits,
+            next_request: Arc::new(Mutex::new(HashMap::new())),
+            offline: false,
+        })
+    }
+
+    /// A fetcher that fails every request instead of touching the network
+    pub fn offline(self) -> Fetcher {
+        Fetcher {
+            offline: true,
+            ..self
+        }
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Waits until we are allowed to make another request to the host
+    async fn wait_for_host(&self, url: &Url) {
+        let host = url.host_str().unwrap_or_default().to_string();
+
+        let wait_until = {
+            let mut next_request = self.next_request.lock().await;
+            let now = Instant::now();
+            let slot = match next_request.get(&host) {
+                Some(next) if *next > now => *next,
+                _ => now,
+            };
+
+            next_request.insert(host, slot + self.interval);
+            slot
+        };
+
+        tokio::time::sleep_until(wait_until.into()).await;
+    }
+
+    /// Sends a GET request, retrying on 429/503
+    pub async fn get(&self, url: &Url) -> Result<Response, FetchError> {
+        self.get_with_headers(url, HeaderMap::new()).await
+    }
+
+    /// Sends a GET request with extra headers, retrying on 429/503
+    pub async fn get_with_headers(
+        &self,
+        url: &Url,
+        headers: HeaderMap,
+    ) -> Result<Response, FetchError> {
+        self.send(&self.client, url, &headers).await
+    }
+
+    /// Sends a GET request, following the redirects by hand so every hop can be audited
+    ///
+    /// Returns every url visited (starting with the original) and the last response
+    pub async fn get_traced(
+        &self,
+        url: &Url,
+        headers: HeaderMap,
+    ) -> Result<(Vec<Url>, Response), FetchError> {
+        let mut chain = vec![url.clone()];
+
+        loop {
+            let current = chain.last().unwrap().clone();
+            let response = self
+                .send(&self.no_redirect_client, &current, &headers)
+                .await?;
+
+            if !response.status().is_redirection() || chain.len() > self.limits.max_redirects {
+                return Ok((chain, response));
+            }
+
+            let next = response
+                .headers()
+                .get(header::LOCATION)
+                .and_then(|location| location.to_str().ok())
+                .and_then(|location| current.join(location).ok());
+
+            match next {
+                Some(next) => chain.push(next),
+                None => return Ok((chain, response)),
+            }
+        }
+    }
+
+    async fn send(
+        &self,
+        client: &Client,
+        url: &Url,
+        headers: &HeaderMap,
+    ) -> Result<Response, FetchError> {
+        if self.offline {
+            return Err(FetchError::Offline(url.clone()));
+        }
+
+        let mut attempt = 0;
+
+        loop {
+            self.wait_for_host(url).await;
+
+            let request = client.get(url.clone()).headers(headers.clone()).send();
+            let response = match tokio::time::timeout(self.limits.read_timeout, request).await {
+                Ok(response) => response?,
+                Err(_) => return Err(FetchError::Timeout(url.clone())),
+            };
+
+            let status = response.status();
+            if attempt >= MAX_RETRIES
+                || (status != StatusCode::TOO_MANY_REQUESTS
+                    && status != StatusCode::SERVICE_UNAVAILABLE)
+            {
+                return Ok(response);
+            }
+
+            attempt += 1;
+            let wait = retry_after(&response)
+                .unwrap_or(DEFAULT_RETRY_AFTER)
+                .min(MAX_RETRY_AFTER);
+            warn!(
+                "Got {} from {}, retrying in {}s ({}/{})",
+                status,
+                url,
+                wait.as_secs(),
+                attempt,
+                MAX_RETRIES
+            );
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Reads a body into memory, giving up when it goes silent or grows past the limit
+    pub async fn read_body(&self, mut response: Response) -> Result<Vec<u8>, FetchError> {
+        let url = response.url().clone();
+        let limit = self.limits.max_body_size;
+
+        if response
+            .content_length()
+            .is_some_and(|length| length > limit)
+        {
+            return Err(FetchError::TooLarge(url, limit));
+        }
+
+        let mut body = vec![];
+        loop {
+            let chunk = match tokio::time::timeout(self.limits.read_timeout, response.chunk()).await
+            {
+                Ok(chunk) => chunk?,
+                Err(_) => return Err(FetchError::Timeout(url)),
+            };
+
+            match chunk {
+                Some(chunk) if body.len() as u64 + chunk.len() as u64 > limit => {
+                    return Err(FetchError::TooLarge(url, limit))
+                }
+                Some(chunk) => body.extend_from_slice(&chunk),
+                None => return Ok(body),
+            }
+        }
+    }
+}
+
+/// The settings both clients share
+fn client_builder(limits: &Limits) -> ClientBuilder {
+    Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(limits.connect_timeout)
+        .pool_idle_timeout(POOL_IDLE_TIMEOUT)
+        .pool_max_idle_per_host(POOL_MAX_IDLE_PER_HOST)
+        .tcp_keepalive(TCP_KEEPALIVE)
+        .http2_adaptive_window(true)
+}
+
+/// Parses the Retry-After header (either seconds or an http date)
+fn retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(header::RETRY_AFTER)?.to_str().ok()?;
+
+    if let Ok(seconds) = value.trim().parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(
+        date.duration_since(SystemTime::now())
+            .unwrap_or(Duration::ZERO),
+    )
+}
@@ -0,0 +1,158 @@
+use std::fmt::Display;
+
+use s3::Bucket;
+use uuid::Uuid;
+
+use crate::{
+    repair::download_key,
+    utils::{
+        grab_version_from_s3, object_hash, Downloadable, DownloadsList, VersionInfo,
+        METALINKS_FILE_PATH, VERSIONS_FILE_PATH,
+    },
+};
+
+pub enum GcError {
+    /// A version that's kept couldn't be read, so the downloads it uses are unknown (Id)
+    UnreadableVersion(Uuid),
+    /// An object couldn't be deleted (Key, Error)
+    Delete(String, String),
+}
+
+impl Display for GcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GcError::UnreadableVersion(id) => write!(
+                f,
+                "Failed to read the version {}, run repair before collecting garbage",
+                id
+            ),
+            GcError::Delete(key, error) => write!(f, "Failed to delete {}: {}", key, error),
+        }
+    }
+}
+
+/// Pre-release versions past their expiry and the downloads no other version uses
+pub struct Expired {
+    pub versions: Vec<VersionInfo>,
+    pub downloads: Vec<Downloadable>,
+}
+
+impl Expired {
+    pub fn is_empty(&self) -> bool {
+        self.versions.is_empty() && self.downloads.is_empty()
+    }
+}
+
+fn is_expired(version: &VersionInfo, now: u64) -> bool {
+    !version.version.pre.is_empty() && version.expires_at.is_some_and(|expires| expires <= now)
+}
+
+/// Finds the expired pre-releases (every kept version is read to find the downloads they share)
+pub async fn expired(
+    s3: &Bucket,
+    downloadables: &DownloadsList,
+    versions: &[VersionInfo],
+    now: u64,
+) -> Result<Expired, GcError> {
+    let (expired, kept): (Vec<_>, Vec<_>) = versions.iter().partition(|v| is_expired(v, now));
+    if expired.is_empty() {
+        return Ok(Expired {
+            versions: vec![],
+            downloads: vec![],
+        });
+    }
+
+    let mut kept_downloads = vec![];
+    for version in kept {
+        match grab_version_from_s3(s3, &version.id).await {
+            Some(compiled) => kept_downloads.extend(compiled.downloads.iter().map(|d| d.id)),
+            None => return Err(GcError::UnreadableVersion(version.id)),
+        }
+    }
+
+    // An expired version that can't be read is still removed, its downloads are left alone
+    let mut expired_downloads = vec![];
+    for version in &expired {
+        if let Some(compiled) = grab_version_from_s3(s3, &version.id).await {
+            expired_downloads.extend(compiled.downloads.iter().map(|d| d.id));
+        }
+    }
+
+    Ok(Expired {
+        versions: expired.into_iter().cloned().collect(),
+        downloads: downloadables
+            .iter()
+            .filter(|d| expired_downloads.contains(&d.id) && !kept_downloads.contains(&d.id))
+            .cloned()
+            .collect(),
+    })
+}
+
+async fn delete(s3: &Bucket, key: &str) -> Result<(), GcError> {
+    match s3.delete_object(key).await {
+        Ok(_) => {
+            info!("Deleted {}", key);
+            Ok(())
+        }
+        Err(e) => Err(GcError::Delete(key.to_string(), e.to_string())),
+    }
+}
+
+/// Leaves out the downloads whose objects aren't the listed ones anymore (or can't be checked),
+/// they stay listed and aren't deleted
+pub async fn deletable(s3: &Bucket, expired: Expired) -> Expired {
+    let mut downloads = vec![];
+    for download in expired.downloads {
+        let key = download_key(&download);
+        // The sha256 the object was uploaded with (it's hashed if it has none) has to be the
+        // listed one, anything else was put there by someone else
+        match object_hash(s3, &key).await {
+            Ok((_, hash)) if !hash.eq_ignore_ascii_case(&download.hash) => {
+                warn!(
+                    "{} isn't the listed download anymore, leaving it alone",
+                    key
+                );
+            }
+            Ok(_) => downloads.push(download),
+            Err(e) => warn!("Failed to check {} ({}), leaving it alone", key, e),
+        }
+    }
+
+    Expired {
+        versions: expired.versions,
+        downloads,
+    }
+}
+
+/// Drops the expired objects from the indexes (after `deletable`), they have to be uploaded
+/// before `remove` so nothing points at a deleted object
+pub fn prune(
+    expired: &Expired,
+    downloadables: &mut DownloadsList,
+    versions: &mut Vec<VersionInfo>,
+) {
+    downloadables.retain(|d| !expired.downloads.iter().any(|e| e.id == d.id));
+    versions.retain(|v| !expired.versions.iter().any(|e| e.id == v.id));
+}
+
+/// Deletes the expired objects (after `prune`), a failed delete only leaves an unused object
+/// behind so the rest are still deleted
+pub async fn remove(s3: &Bucket, expired: &Expired) -> Vec<GcError> {
+    let mut keys = vec![];
+    for download in &expired.downloads {
+        keys.push(download_key(download));
+        keys.push(format!("{}/{}.meta4", METALINKS_FILE_PATH, download.id));
+    }
+    for version in &expired.versions {
+        keys.push(format!("{}/{}.json", VERSIONS_FILE_PATH, version.id));
+    }
+
+    let mut errors = vec![];
+    for key in keys {
+        if let Err(error) = delete(s3, &key).await {
+            errors.push(error);
+        }
+    }
+
+    errors
+}
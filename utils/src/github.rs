@@ -0,0 +1,300 @@
+use std::fmt::Display;
+
+use base64::Engine;
+use reqwest::{header, Client, Method, RequestBuilder, Response};
+use semver::Version;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{fetch::USER_AGENT, utils::VersionInfo};
+
+/// The public GitHub api
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+
+pub enum GitHubError {
+    /// The http client couldn't be created (Error)
+    Client(String),
+    /// A request to the api failed (Action, Error)
+    Api(String, String),
+}
+
+impl Display for GitHubError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitHubError::Client(error) => write!(f, "Failed to create http client: {}", error),
+            GitHubError::Api(action, error) => {
+                write!(f, "GitHub api error ({}): {}", action, error)
+            }
+        }
+    }
+}
+
+/// Where (and as who) to publish on GitHub
+pub struct GitHubTarget {
+    /// `owner/name`
+    pub repo: String,
+    pub token: String,
+    /// The release the files are attached to (created if missing)
+    pub tag: String,
+    /// The versions.json kept in the repo
+    pub versions_path: String,
+    /// The branch versions.json is committed to (the default branch if not set)
+    pub branch: Option<String>,
+    pub api_url: Url,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    upload_url: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Deserialize)]
+struct Asset {
+    id: u64,
+    name: String,
+    browser_download_url: Url,
+}
+
+#[derive(Deserialize)]
+struct Contents {
+    sha: String,
+    content: String,
+}
+
+#[derive(Serialize)]
+struct CreateRelease<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+}
+
+#[derive(Serialize)]
+struct UpdateContents<'a> {
+    message: String,
+    content: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<&'a str>,
+}
+
+pub struct GitHubPublisher<'a> {
+    client: Client,
+    target: &'a GitHubTarget,
+}
+
+impl GitHubPublisher<'_> {
+    pub fn new(target: &GitHubTarget) -> Result<GitHubPublisher<'_>, GitHubError> {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| GitHubError::Client(e.to_string()))?;
+
+        Ok(GitHubPublisher { client, target })
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        self.client
+            .request(method, url)
+            .bearer_auth(&self.target.token)
+            .header(header::ACCEPT, "application/vnd.github+json")
+    }
+
+    fn api(&self, path: &str) -> String {
+        format!(
+            "{}/repos/{}/{}",
+            self.target.api_url.as_str().trim_end_matches('/'),
+            self.target.repo,
+            path
+        )
+    }
+
+    /// Sends a request, non-2xx answers are errors
+    async fn send(&self, action: &str, request: RequestBuilder) -> Result<Response, GitHubError> {
+        let api_error = |error: String| GitHubError::Api(action.to_string(), error);
+
+        let response = request.send().await.map_err(|e| api_error(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(api_error(format!("Status code: {}", response.status())));
+        }
+
+        Ok(response)
+    }
+
+    async fn json<T: DeserializeOwned>(
+        &self,
+        action: &str,
+        request: RequestBuilder,
+    ) -> Result<T, GitHubError> {
+        self.send(action, request)
+            .await?
+            .json()
+            .await
+            .map_err(|e| GitHubError::Api(action.to_string(), e.to_string()))
+    }
+
+    /// The release for the tag, created if it doesn't exist yet
+    async fn release(&self) -> Result<Release, GitHubError> {
+        let tag = &self.target.tag;
+        let url = self.api(&format!("releases/tags/{}", urlencoding::encode(tag)));
+
+        match self.request(Method::GET, &url).send().await {
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => {
+                info!("Creating GitHub release {}", tag);
+
+                let request =
+                    self.request(Method::POST, &self.api("releases"))
+                        .json(&CreateRelease {
+                            tag_name: tag,
+                            name: tag,
+                        });
+                self.json("create release", request).await
+            }
+            Ok(response) if response.status().is_success() => response
+                .json()
+                .await
+                .map_err(|e| GitHubError::Api("get release".to_string(), e.to_string())),
+            Ok(response) => Err(GitHubError::Api(
+                "get release".to_string(),
+                format!("Status code: {}", response.status()),
+            )),
+            Err(e) => Err(GitHubError::Api("get release".to_string(), e.to_string())),
+        }
+    }
+
+    /// Uploads a release asset (replacing one with the same name)
+    async fn upload_asset(
+        &self,
+        release: &Release,
+        name: &str,
+        data: Vec<u8>,
+        content_type: &str,
+    ) -> Result<Url, GitHubError> {
+        if let Some(existing) = release.assets.iter().find(|a| a.name == name) {
+            let url = self.api(&format!("releases/assets/{}", existing.id));
+            self.send("delete asset", self.request(Method::DELETE, &url))
+                .await?;
+        }
+
+        // The upload url is a template (`.../assets{?name,label}`)
+        let upload_url = release
+            .upload_url
+            .split('{')
+            .next()
+            .unwrap_or(&release.upload_url);
+        let url = format!("{}?name={}", upload_url, urlencoding::encode(name));
+
+        let request = self
+            .request(Method::POST, &url)
+            .header(header::CONTENT_TYPE, content_type)
+            .body(data);
+        let asset: Asset = self.json("upload asset", request).await?;
+
+        info!("Uploaded GitHub release asset {}", name);
+
+        Ok(asset.browser_download_url)
+    }
+
+    /// Adds the version to the versions.json kept in the repo
+    async fn add_version(&self, version: VersionInfo) -> Result<(), GitHubError> {
+        let path = &self.target.versions_path;
+        let mut url = self.api(&format!("contents/{}", path));
+        if let Some(branch) = &self.target.branch {
+            url += &format!("?ref={}", urlencoding::encode(branch));
+        }
+
+        let (mut versions, sha) = match self.request(Method::GET, &url).send().await {
+            Ok(response) if response.status() == reqwest::StatusCode::NOT_FOUND => (vec![], None),
+            Ok(response) if response.status().is_success() => {
+                let contents: Contents = response.json().await.map_err(|e| {
+                    GitHubError::Api("get versions.json".to_string(), e.to_string())
+                })?;
+
+                // The content is base64 with line breaks
+                let encoded: String = contents.content.split_whitespace().collect();
+                let decoded = base64::engine::general_purpose::STANDARD
+                    .decode(encoded)
+                    .map_err(|e| {
+                        GitHubError::Api("decode versions.json".to_string(), e.to_string())
+                    })?;
+                let versions: Vec<VersionInfo> = serde_json::from_slice(&decoded).map_err(|e| {
+                    GitHubError::Api("parse versions.json".to_string(), e.to_string())
+                })?;
+
+                (versions, Some(contents.sha))
+            }
+            Ok(response) => {
+                return Err(GitHubError::Api(
+                    "get versions.json".to_string(),
+                    format!("Status code: {}", response.status()),
+                ))
+            }
+            Err(e) => {
+                return Err(GitHubError::Api(
+                    "get versions.json".to_string(),
+                    e.to_string(),
+                ))
+            }
+        };
+
+        let message = format!("Publish database {}", version.version);
+        versions.push(version);
+
+        let data = serde_json::to_vec_pretty(&versions)
+            .map_err(|e| GitHubError::Api("serialize versions.json".to_string(), e.to_string()))?;
+
+        let request = self
+            .request(Method::PUT, &self.api(&format!("contents/{}", path)))
+            .json(&UpdateContents {
+                message,
+                content: base64::engine::general_purpose::STANDARD.encode(data),
+                sha,
+                branch: self.target.branch.as_deref(),
+            });
+        self.send("update versions.json", request).await?;
+
+        info!("Updated {} in {}", path, self.target.repo);
+
+        Ok(())
+    }
+
+    /// Attaches the version file (and artifacts, as `name, data`) to the release and lists the
+    /// version in the repo's versions.json
+    pub async fn publish(
+        &self,
+        version: &Version,
+        id: Uuid,
+        file: &[u8],
+        artifacts: Vec<(String, Vec<u8>)>,
+    ) -> Result<Url, GitHubError> {
+        let release = self.release().await?;
+
+        for (name, data) in artifacts {
+            self.upload_asset(&release, &name, data, "application/octet-stream")
+                .await?;
+        }
+
+        let download_url = self
+            .upload_asset(
+                &release,
+                &format!("{}.json", id),
+                file.to_vec(),
+                "application/json",
+            )
+            .await?;
+
+        self.add_version(VersionInfo {
+            id,
+            version: version.clone(),
+            download_url: download_url.clone(),
+            hash: sha256::digest(file),
+            file_size: file.len() as u64,
+            mirrors: vec![],
+            expires_at: None,
+        })
+        .await?;
+
+        Ok(download_url)
+    }
+}
@@ -0,0 +1,131 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+use uuid::Uuid;
+
+use crate::{
+    client::DownloadManager,
+    install::{self, InstallError, InstallManifest, InstalledFont},
+    policy::{self, InstallTarget},
+    store::ArtifactCache,
+    types::{Compiled, CompiledFont},
+};
+
+fn home() -> PathBuf {
+    std::env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+/// Where host installs go: `~/Library/Fonts` on macOS (it doesn't look into subdirectories),
+/// `$XDG_DATA_HOME/fonts/winefonts` (or `~/.local/share/fonts/winefonts`) elsewhere
+pub fn fonts_directory() -> PathBuf {
+    if cfg!(target_os = "macos") {
+        return home().join("Library").join("Fonts");
+    }
+
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(data) => PathBuf::from(data),
+        None => home().join(".local").join("share"),
+    };
+    data.join("fonts").join("winefonts")
+}
+
+/// Where the manifest of the host installs is kept: `~/Library/Application Support/winefonts`
+/// on macOS (the fonts directory is shared with other fonts), next to the fonts elsewhere
+pub fn state_directory() -> PathBuf {
+    match cfg!(target_os = "macos") {
+        true => home()
+            .join("Library")
+            .join("Application Support")
+            .join("winefonts"),
+        false => fonts_directory(),
+    }
+}
+
+/// The fonts installed for the host
+pub async fn manifest() -> Result<InstallManifest, InstallError> {
+    InstallManifest::load(&state_directory()).await
+}
+
+/// The first file of the font that exists but wasn't installed by us (it's never overwritten)
+fn foreign_file(
+    directory: &Path,
+    manifest: &InstallManifest,
+    font: &CompiledFont,
+) -> Option<PathBuf> {
+    let ours = |file: &str| {
+        manifest.fonts.iter().any(|installed| {
+            installed
+                .installation
+                .installed_files()
+                .iter()
+                .any(|installed| installed.file.eq_ignore_ascii_case(file))
+        })
+    };
+
+    font.host_installations()
+        .flat_map(|installation| installation.installed_files())
+        .map(|file| file.file)
+        // Unsafe names are refused when installing
+        .filter_map(|file| Some((install::font_file_path(directory, &file).ok()?, file)))
+        .find(|(path, file)| path.exists() && !ours(file))
+        .map(|(path, _)| path)
+}
+
+/// Installs the files of a font for the host and records it in the manifest, returns the
+/// installed entries
+///
+/// Applications on Linux only see the font after `refresh`.
+pub async fn install_font(
+    compiled: &Compiled,
+    font: &CompiledFont,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<InstalledFont>, InstallError> {
+    if let Some(violation) = policy::check(&[font], InstallTarget::Host).pop() {
+        return Err(InstallError::NotAllowed(violation));
+    }
+
+    let directory = fonts_directory();
+    let state = state_directory();
+    for path in [&directory, &state] {
+        tokio::fs::create_dir_all(path)
+            .await
+            .map_err(|e| InstallError::Io(path.clone(), e.to_string()))?;
+    }
+
+    if let Some(path) = foreign_file(&directory, &manifest().await?, font) {
+        return Err(InstallError::Exists(path));
+    }
+
+    install::install_font_into(&state, &directory, compiled, font, manager, cache).await
+}
+
+/// Removes the files of a font installed for the host, returns the removed entries
+pub async fn uninstall_font(id: Uuid) -> Result<Vec<InstalledFont>, InstallError> {
+    install::uninstall_font_from(&state_directory(), &fonts_directory(), id).await
+}
+
+/// Rebuilds fontconfig's cache of the host installs (with `fc-cache`), macOS picks new fonts up
+/// on its own
+pub async fn refresh() -> Result<(), InstallError> {
+    if cfg!(target_os = "macos") {
+        return Ok(());
+    }
+
+    let status = Command::new("fc-cache")
+        .arg("-f")
+        .arg(fonts_directory())
+        .status()
+        .await;
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(InstallError::FontCache(format!(
+            "fc-cache exited with {}",
+            status
+        ))),
+        Err(e) => Err(InstallError::FontCache(format!("fc-cache: {}", e))),
+    }
+}
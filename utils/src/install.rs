@@ -0,0 +1,708 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tokio::{fs, process::Command};
+use uuid::Uuid;
+
+use crate::{
+    archive::Archive,
+    client::{ClientError, DownloadManager},
+    policy::PolicyViolation,
+    store::ArtifactCache,
+    types::{
+        is_plain_file_name, Compiled, CompiledDownloads, CompiledFont, CompiledInstalationType,
+        HostOs,
+    },
+};
+
+/// The manifest of what was installed, kept in the prefix
+pub const MANIFEST_FILE_NAME: &str = "winefonts.json";
+
+/// The registry keys Windows looks fonts up in
+const FONT_REGISTRY_KEYS: &[&str] = &[
+    "HKEY_LOCAL_MACHINE\\Software\\Microsoft\\Windows NT\\CurrentVersion\\Fonts",
+    "HKEY_LOCAL_MACHINE\\Software\\Microsoft\\Windows\\CurrentVersion\\Fonts",
+];
+
+pub enum InstallError {
+    /// A download the font uses isn't in the database (Font, Id)
+    MissingDownload(String, Uuid),
+    /// The download couldn't be fetched (Error)
+    Download(ClientError),
+    /// A file couldn't be extracted from the download (File, Error)
+    Extract(String, String),
+    /// An extracted file doesn't match the database (File, Expected hash, Hash)
+    HashMismatch(String, String, String),
+    /// A file couldn't be read or written (Path, Error)
+    Io(PathBuf, String),
+    /// The manifest couldn't be parsed (Path, Error)
+    Manifest(PathBuf, String),
+    /// Wine couldn't import the registry entries (Error)
+    Register(String),
+    /// None of the font's installations is for this host (Font)
+    UnsupportedHost(String),
+    /// Fonts can only be installed natively on Windows
+    NotWindows,
+    /// fontconfig's cache couldn't be refreshed (Error)
+    FontCache(String),
+    /// A file the font installs exists but wasn't installed by us (Path)
+    Exists(PathBuf),
+    /// The font's license doesn't allow the install target (Violation)
+    NotAllowed(PolicyViolation),
+    /// A file name from the database or manifest isn't a single plain path component (File)
+    UnsafeFileName(String),
+    /// A registry name or file has control characters, it'd break out of its registry entry
+    /// (Value)
+    UnsafeRegistryValue(String),
+}
+
+impl Display for InstallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallError::MissingDownload(font, id) => {
+                write!(f, "The download {} of {} isn't in the database", id, font)
+            }
+            InstallError::Download(error) => write!(f, "{}", error),
+            InstallError::Extract(file, error) => {
+                write!(f, "Failed to extract {}: {}", file, error)
+            }
+            InstallError::HashMismatch(file, expected, hash) => write!(
+                f,
+                "The extracted {} has the hash {} (expected {})",
+                file, hash, expected
+            ),
+            InstallError::Io(path, error) => {
+                write!(f, "File error (path: {}, error: {})", path.display(), error)
+            }
+            InstallError::Manifest(path, error) => write!(
+                f,
+                "Failed to parse the manifest (path: {}, error: {})",
+                path.display(),
+                error
+            ),
+            InstallError::Register(error) => {
+                write!(f, "Failed to register the fonts: {}", error)
+            }
+            InstallError::UnsupportedHost(font) => match HostOs::current() {
+                Some(host) => write!(f, "{} can't be installed on {}", font, host),
+                None => write!(f, "{} can't be installed on this host", font),
+            },
+            InstallError::NotWindows => {
+                write!(f, "Fonts can only be installed natively on Windows")
+            }
+            InstallError::FontCache(error) => {
+                write!(f, "Failed to refresh the font cache: {}", error)
+            }
+            InstallError::NotAllowed(violation) => write!(f, "{}", violation),
+            InstallError::UnsafeFileName(file) => write!(
+                f,
+                "The file name \"{}\" would point outside the fonts directory",
+                file
+            ),
+            InstallError::UnsafeRegistryValue(value) => {
+                write!(f, "The registry value {:?} has control characters", value)
+            }
+            InstallError::Exists(path) => write!(
+                f,
+                "{} already exists and wasn't installed by winefonts",
+                path.display()
+            ),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A font installed into the prefix
+pub struct InstalledFont {
+    pub id: Uuid,
+    pub name: String,
+    /// The database version it was installed from
+    pub version: Version,
+    pub installation: CompiledInstalationType,
+    /// Kept so the font can be repaired without the database it came from
+    pub download: CompiledDownloads,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+/// Every font installed into a prefix
+pub struct InstallManifest {
+    pub fonts: Vec<InstalledFont>,
+}
+
+impl InstallManifest {
+    /// Reads the manifest of a prefix (empty if nothing was installed yet)
+    pub async fn load(prefix: &Path) -> Result<InstallManifest, InstallError> {
+        let path = prefix.join(MANIFEST_FILE_NAME);
+
+        match fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| InstallError::Manifest(path.clone(), e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(InstallManifest::default()),
+            Err(e) => Err(InstallError::Io(path, e.to_string())),
+        }
+    }
+
+    /// The installed fonts with the database version they were installed from
+    pub fn font_versions(&self) -> BTreeMap<Uuid, &Version> {
+        self.fonts
+            .iter()
+            .map(|installed| (installed.id, &installed.version))
+            .collect()
+    }
+
+    pub async fn save(&self, prefix: &Path) -> Result<(), InstallError> {
+        let path = prefix.join(MANIFEST_FILE_NAME);
+
+        let data = serde_json::to_vec_pretty(self)
+            .map_err(|e| InstallError::Manifest(path.clone(), e.to_string()))?;
+
+        fs::write(&path, data)
+            .await
+            .map_err(|e| InstallError::Io(path, e.to_string()))
+    }
+}
+
+/// Where the font files of a prefix go
+pub fn fonts_directory(prefix: &Path) -> PathBuf {
+    prefix.join("drive_c").join("windows").join("Fonts")
+}
+
+/// Extracts the files (name, data) of an installation from its download, checked against the
+/// hashes the database records
+pub fn extract(
+    installation: &CompiledInstalationType,
+    data: &[u8],
+) -> Result<Vec<(String, Vec<u8>)>, InstallError> {
+    let files = match installation {
+        CompiledInstalationType::Cabextract(installation) => {
+            let mut archive = Archive::open(data).map_err(|e| {
+                InstallError::Extract(installation.download.to_string(), e.to_string())
+            })?;
+
+            let mut files = vec![];
+            for file in &installation.files {
+                let data = archive
+                    .read(&file.file)
+                    .map_err(|e| InstallError::Extract(file.file.clone(), e.to_string()))?;
+                files.push((file.file.clone(), data));
+            }
+
+            files
+        }
+        // The download is the converted font
+        CompiledInstalationType::Woff2(installation) => {
+            vec![(installation.file.clone(), data.to_vec())]
+        }
+    };
+
+    for (name, data) in &files {
+        if let Some(expected) = installation.file_hashes().get(name) {
+            let hash = sha256::digest(data);
+            if !hash.eq_ignore_ascii_case(expected) {
+                return Err(InstallError::HashMismatch(
+                    name.clone(),
+                    expected.clone(),
+                    hash,
+                ));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Gets a download through the cache (only fetched if it isn't cached or doesn't match), a
+/// cached file that got corrupted is fetched again
+async fn cached_download(
+    download: &CompiledDownloads,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<u8>, InstallError> {
+    let _lock = cache.lock(download).await;
+    let path = cache.path(download);
+
+    // Files the index doesn't know (e.g. from an older client) are kept if they match
+    let cached = cache.contains(download)
+        || sha256::try_digest(&path)
+            .map(|hash| hash.eq_ignore_ascii_case(&download.hash))
+            .unwrap_or(false);
+    if cached {
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| InstallError::Io(path.clone(), e.to_string()))?;
+        if sha256::digest(&data).eq_ignore_ascii_case(&download.hash) {
+            cache.insert(download).await;
+            return Ok(data);
+        }
+
+        // Changed since the cache was opened
+        warn!(
+            "The cached download {} is corrupted, fetching it again",
+            download.id
+        );
+        cache.remove(download).await;
+    }
+
+    fs::create_dir_all(cache.directory())
+        .await
+        .map_err(|e| InstallError::Io(cache.directory().to_path_buf(), e.to_string()))?;
+    manager
+        .download_to(download, &path)
+        .await
+        .map_err(InstallError::Download)?;
+    cache.insert(download).await;
+
+    fs::read(&path)
+        .await
+        .map_err(|e| InstallError::Io(path, e.to_string()))
+}
+
+/// The path of a file in a fonts directory, names that aren't a single plain component (e.g.
+/// `../x` from a malicious database) are refused
+pub(crate) fn font_file_path(directory: &Path, name: &str) -> Result<PathBuf, InstallError> {
+    match is_plain_file_name(name) {
+        true => Ok(directory.join(name)),
+        false => Err(InstallError::UnsafeFileName(name.to_string())),
+    }
+}
+
+/// Writes files into a fonts directory
+async fn write_files(directory: &Path, files: &[(String, Vec<u8>)]) -> Result<(), InstallError> {
+    let paths = files
+        .iter()
+        .map(|(name, _)| font_file_path(directory, name))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    fs::create_dir_all(directory)
+        .await
+        .map_err(|e| InstallError::Io(directory.to_owned(), e.to_string()))?;
+
+    for (path, (_, data)) in paths.into_iter().zip(files) {
+        fs::write(&path, data)
+            .await
+            .map_err(|e| InstallError::Io(path, e.to_string()))?;
+    }
+
+    Ok(())
+}
+
+/// Installs the files of a font into the prefix and records it in the manifest, returns the
+/// installed entries
+///
+/// Only the files are installed, see `register` for the registry.
+pub async fn install_font(
+    prefix: &Path,
+    compiled: &Compiled,
+    font: &CompiledFont,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<InstalledFont>, InstallError> {
+    install_font_into(
+        prefix,
+        &fonts_directory(prefix),
+        compiled,
+        font,
+        manager,
+        cache,
+    )
+    .await
+}
+
+/// Installs the files of a font into the fonts directory and records it in the manifest of the
+/// state directory (the prefix, or where native installs are tracked)
+pub(crate) async fn install_font_into(
+    state: &Path,
+    directory: &Path,
+    compiled: &Compiled,
+    font: &CompiledFont,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<InstalledFont>, InstallError> {
+    if !font.installations.is_empty() && font.host_installations().next().is_none() {
+        return Err(InstallError::UnsupportedHost(font.name.clone()));
+    }
+    // Before anything is downloaded or written
+    for file in font
+        .host_installations()
+        .flat_map(|installation| installation.installed_files())
+    {
+        font_file_path(directory, &file.file)?;
+    }
+
+    let mut manifest = InstallManifest::load(state).await?;
+    manifest.fonts.retain(|installed| installed.id != font.id);
+    let mut installed = vec![];
+
+    for installation in font.host_installations() {
+        let download = match compiled
+            .downloads
+            .iter()
+            .find(|d| d.id == installation.download())
+        {
+            Some(download) => download,
+            None => {
+                return Err(InstallError::MissingDownload(
+                    font.name.clone(),
+                    installation.download(),
+                ))
+            }
+        };
+
+        let data = cached_download(download, manager, cache).await?;
+        write_files(directory, &extract(installation, &data)?).await?;
+
+        installed.push(InstalledFont {
+            id: font.id,
+            name: font.name.clone(),
+            version: compiled.version.clone(),
+            installation: installation.clone(),
+            download: download.clone(),
+        });
+    }
+
+    manifest.fonts.extend(installed.iter().cloned());
+    manifest.save(state).await?;
+
+    Ok(installed)
+}
+
+/// Removes the files of a font from the prefix and forgets it in the manifest, returns the
+/// removed entries (files other fonts still use are kept)
+///
+/// Only the files are removed, see `unregister` for the registry.
+pub async fn uninstall_font(prefix: &Path, id: Uuid) -> Result<Vec<InstalledFont>, InstallError> {
+    uninstall_font_from(prefix, &fonts_directory(prefix), id).await
+}
+
+/// Removes the files of a font from the fonts directory and forgets it in the manifest of the
+/// state directory
+pub(crate) async fn uninstall_font_from(
+    state: &Path,
+    directory: &Path,
+    id: Uuid,
+) -> Result<Vec<InstalledFont>, InstallError> {
+    let mut manifest = InstallManifest::load(state).await?;
+    let (removed, kept): (Vec<InstalledFont>, Vec<InstalledFont>) = manifest
+        .fonts
+        .into_iter()
+        .partition(|installed| installed.id == id);
+    manifest.fonts = kept;
+
+    let used: BTreeSet<String> = manifest
+        .fonts
+        .iter()
+        .flat_map(|installed| installed.installation.installed_files())
+        .map(|file| file.file)
+        .collect();
+    // A tampered manifest must not remove anything outside the fonts directory
+    let paths = removed
+        .iter()
+        .flat_map(|installed| installed.installation.installed_files())
+        .filter(|file| !used.contains(&file.file))
+        .map(|file| font_file_path(directory, &file.file))
+        .collect::<Result<Vec<_>, _>>()?;
+    for path in paths {
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(InstallError::Io(path, e.to_string()))
+            }
+            _ => {}
+        }
+    }
+
+    manifest.save(state).await?;
+    Ok(removed)
+}
+
+#[derive(Debug, Default)]
+/// What has to change for a prefix to have exactly the desired fonts
+pub struct SyncPlan<'a> {
+    /// Desired fonts that aren't installed
+    pub install: Vec<&'a CompiledFont>,
+    /// Installed fonts the database changed since (their files or downloads)
+    pub update: Vec<&'a CompiledFont>,
+    /// Installed fonts that aren't desired (Id, Name)
+    pub uninstall: Vec<(Uuid, String)>,
+    /// Desired fonts that are up to date
+    pub unchanged: usize,
+}
+
+impl SyncPlan<'_> {
+    pub fn is_empty(&self) -> bool {
+        self.install.is_empty() && self.update.is_empty() && self.uninstall.is_empty()
+    }
+}
+
+/// Compares the prefix's manifest against the desired fonts
+pub fn plan_sync<'a>(
+    manifest: &InstallManifest,
+    compiled: &Compiled,
+    desired: &[&'a CompiledFont],
+) -> SyncPlan<'a> {
+    let mut plan = SyncPlan::default();
+
+    for font in desired {
+        let installed: Vec<(&CompiledInstalationType, &str)> = manifest
+            .fonts
+            .iter()
+            .filter(|installed| installed.id == font.id)
+            .map(|installed| (&installed.installation, installed.download.hash.as_str()))
+            .collect();
+        if installed.is_empty() {
+            plan.install.push(font);
+            continue;
+        }
+
+        let current: Vec<(&CompiledInstalationType, &str)> = font
+            .host_installations()
+            .map(|installation| {
+                let hash = compiled
+                    .downloads
+                    .iter()
+                    .find(|download| download.id == installation.download())
+                    .map(|download| download.hash.as_str())
+                    .unwrap_or_default();
+                (installation, hash)
+            })
+            .collect();
+        match installed == current {
+            true => plan.unchanged += 1,
+            false => plan.update.push(font),
+        }
+    }
+
+    let desired: BTreeSet<Uuid> = desired.iter().map(|font| font.id).collect();
+    for installed in &manifest.fonts {
+        if !desired.contains(&installed.id)
+            && !plan.uninstall.iter().any(|(id, _)| *id == installed.id)
+        {
+            plan.uninstall.push((installed.id, installed.name.clone()));
+        }
+    }
+
+    plan
+}
+
+/// Fails for values with control characters, a line break would start a new registry entry
+pub fn check_registry_value(value: &str) -> Result<(), InstallError> {
+    match value.chars().any(char::is_control) {
+        true => Err(InstallError::UnsafeRegistryValue(value.to_string())),
+        false => Ok(()),
+    }
+}
+
+/// Escapes a .reg string value
+fn registry_string(value: &str) -> Result<String, InstallError> {
+    check_registry_value(value)?;
+
+    Ok(format!(
+        "\"{}\"",
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    ))
+}
+
+/// The .reg file (REGEDIT4) registering the files of the fonts
+pub fn registry_file(fonts: &[InstalledFont]) -> Result<String, InstallError> {
+    registry_entries(fonts, registry_string)
+}
+
+/// The .reg file (REGEDIT4) removing the registry entries of the fonts
+pub fn unregistry_file(fonts: &[InstalledFont]) -> Result<String, InstallError> {
+    registry_entries(fonts, |_| Ok("-".to_string()))
+}
+
+fn registry_entries(
+    fonts: &[InstalledFont],
+    value: impl Fn(&str) -> Result<String, InstallError>,
+) -> Result<String, InstallError> {
+    let mut entries = String::new();
+    for installed in fonts {
+        for file in installed.installation.installed_files() {
+            entries += &format!(
+                "{}={}\r\n",
+                registry_string(&file.registry_name)?,
+                value(&file.file)?
+            );
+        }
+    }
+
+    let mut file = "REGEDIT4\r\n".to_string();
+    for key in FONT_REGISTRY_KEYS {
+        file += &format!("\r\n[{}]\r\n{}", key, entries);
+    }
+
+    Ok(file)
+}
+
+/// Registers the fonts in the prefix's registry (with `wine regedit`)
+pub async fn register(
+    prefix: &Path,
+    wine: &Path,
+    fonts: &[InstalledFont],
+) -> Result<(), InstallError> {
+    import_registry(prefix, wine, registry_file(fonts)?).await
+}
+
+/// Removes the fonts from the prefix's registry (with `wine regedit`)
+pub async fn unregister(
+    prefix: &Path,
+    wine: &Path,
+    fonts: &[InstalledFont],
+) -> Result<(), InstallError> {
+    import_registry(prefix, wine, unregistry_file(fonts)?).await
+}
+
+async fn import_registry(prefix: &Path, wine: &Path, file: String) -> Result<(), InstallError> {
+    let path = prefix.join("winefonts.reg");
+    fs::write(&path, file)
+        .await
+        .map_err(|e| InstallError::Io(path.clone(), e.to_string()))?;
+
+    let status = Command::new(wine)
+        .env("WINEPREFIX", prefix)
+        .env("WINEDEBUG", "-all")
+        .arg("regedit")
+        .arg("/S")
+        .arg(&path)
+        .status()
+        .await;
+
+    // The file is only needed for the import
+    if let Err(e) = fs::remove_file(&path).await {
+        debug!("Failed to remove {}: {}", path.display(), e);
+    }
+
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(InstallError::Register(format!(
+            "{} exited with {}",
+            wine.display(),
+            status
+        ))),
+        Err(e) => Err(InstallError::Register(format!("{}: {}", wine.display(), e))),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// What is wrong with an installed file
+pub enum FileDamage {
+    Missing,
+    /// The file doesn't match the database (Expected hash, Hash)
+    Corrupt(String, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// An installed file that has to be repaired
+pub struct DamagedFile {
+    /// The font it belongs to
+    pub font: String,
+    pub file: String,
+    pub damage: FileDamage,
+}
+
+impl Display for DamagedFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.damage {
+            FileDamage::Missing => write!(f, "{} ({}) is missing", self.file, self.font),
+            FileDamage::Corrupt(expected, hash) => write!(
+                f,
+                "{} ({}) has the hash {} (expected {})",
+                self.file, self.font, hash, expected
+            ),
+        }
+    }
+}
+
+/// The damaged files of one installation
+fn check_installed(prefix: &Path, installed: &InstalledFont) -> Vec<DamagedFile> {
+    let directory = fonts_directory(prefix);
+    let hashes = installed.installation.file_hashes();
+
+    installed
+        .installation
+        .installed_files()
+        .into_iter()
+        .map(|file| file.file)
+        .filter_map(|file| {
+            let path = match font_file_path(&directory, &file) {
+                Ok(path) => path,
+                Err(e) => {
+                    warn!("Not checking {}: {}", file, e);
+                    return None;
+                }
+            };
+            if !path.is_file() {
+                return Some((file, FileDamage::Missing));
+            }
+
+            // Databases without per-file hashes can only tell missing files
+            let expected = hashes.get(&file)?;
+            match sha256::try_digest(&path) {
+                Ok(hash) if hash.eq_ignore_ascii_case(expected) => None,
+                Ok(hash) => Some((file, FileDamage::Corrupt(expected.clone(), hash))),
+                Err(e) => Some((file, FileDamage::Corrupt(expected.clone(), e.to_string()))),
+            }
+        })
+        .map(|(file, damage)| DamagedFile {
+            font: installed.name.clone(),
+            file,
+            damage,
+        })
+        .collect()
+}
+
+/// Scans the prefix for missing or corrupted font files (of the fonts in the manifest)
+pub fn check(prefix: &Path, manifest: &InstallManifest) -> Vec<DamagedFile> {
+    manifest
+        .fonts
+        .iter()
+        .flat_map(|installed| check_installed(prefix, installed))
+        .collect()
+}
+
+/// Re-extracts the damaged files of the prefix (only the downloads of broken installations are
+/// fetched), returns what was repaired
+pub async fn repair(
+    prefix: &Path,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<DamagedFile>, InstallError> {
+    let manifest = InstallManifest::load(prefix).await?;
+    let mut repaired = vec![];
+
+    for installed in &manifest.fonts {
+        let damaged = check_installed(prefix, installed);
+        if damaged.is_empty() {
+            continue;
+        }
+
+        let data = cached_download(&installed.download, manager, cache).await?;
+        let files: BTreeMap<String, Vec<u8>> = extract(&installed.installation, &data)?
+            .into_iter()
+            .collect();
+
+        let broken: Vec<(String, Vec<u8>)> = damaged
+            .iter()
+            .filter_map(|damaged| {
+                files
+                    .get(&damaged.file)
+                    .map(|data| (damaged.file.clone(), data.clone()))
+            })
+            .collect();
+        write_files(&fonts_directory(prefix), &broken).await?;
+
+        for file in damaged {
+            info!("Repaired {}", file);
+            repaired.push(file);
+        }
+    }
+
+    Ok(repaired)
+}
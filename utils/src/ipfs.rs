@@ -0,0 +1,161 @@
+use std::fmt::Display;
+
+use reqwest::{multipart, Client};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::fetch::USER_AGENT;
+
+pub enum IpfsError {
+    /// The http client couldn't be created (Error)
+    Client(String),
+    /// The file couldn't be added to the node (Name, Error)
+    Add(String, String),
+    /// The pinning service refused the pin (Cid, Error)
+    Pin(String, String),
+}
+
+impl Display for IpfsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IpfsError::Client(error) => write!(f, "Failed to create http client: {}", error),
+            IpfsError::Add(name, error) => {
+                write!(f, "Failed to add {} to IPFS: {}", name, error)
+            }
+            IpfsError::Pin(cid, error) => {
+                write!(f, "Failed to pin {} remotely: {}", cid, error)
+            }
+        }
+    }
+}
+
+/// A remote pinning service (IPFS Pinning Service API)
+pub struct PinningService {
+    pub endpoint: Url,
+    pub token: String,
+}
+
+/// Where artifacts are added and how they are linked
+pub struct IpfsTarget {
+    /// The RPC api of the node the files are added to (e.g. http://127.0.0.1:5001)
+    pub api_url: Url,
+    /// The gateway the mirror urls point to, `ipfs://` urls are used without one
+    pub gateway: Option<Url>,
+    /// Also pin everything here, so the files outlive the local node
+    pub pinning_service: Option<PinningService>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct AddResponse {
+    hash: String,
+}
+
+#[derive(Serialize)]
+struct Pin<'a> {
+    cid: &'a str,
+    name: &'a str,
+}
+
+pub struct IpfsClient<'a> {
+    client: Client,
+    target: &'a IpfsTarget,
+}
+
+impl IpfsClient<'_> {
+    pub fn new(target: &IpfsTarget) -> Result<IpfsClient<'_>, IpfsError> {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .build()
+            .map_err(|e| IpfsError::Client(e.to_string()))?;
+
+        Ok(IpfsClient { client, target })
+    }
+
+    /// Adds (and pins) a file on the node, returns its cid
+    async fn add(&self, name: &str, data: Vec<u8>) -> Result<String, IpfsError> {
+        let add_error = |error: String| IpfsError::Add(name.to_string(), error);
+
+        let mut url = self
+            .target
+            .api_url
+            .join("api/v0/add")
+            .map_err(|e| add_error(e.to_string()))?;
+        url.query_pairs_mut()
+            .append_pair("pin", "true")
+            .append_pair("cid-version", "1");
+
+        let form = multipart::Form::new().part(
+            "file",
+            multipart::Part::bytes(data).file_name(name.to_string()),
+        );
+
+        let response = self
+            .client
+            .post(url)
+            .multipart(form)
+            .send()
+            .await
+            .map_err(|e| add_error(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(add_error(format!("Status code: {}", response.status())));
+        }
+
+        let added: AddResponse = response
+            .json()
+            .await
+            .map_err(|e| add_error(e.to_string()))?;
+
+        Ok(added.hash)
+    }
+
+    async fn pin_remote(&self, cid: &str, name: &str) -> Result<(), IpfsError> {
+        let service = match &self.target.pinning_service {
+            Some(service) => service,
+            None => return Ok(()),
+        };
+        let pin_error = |error: String| IpfsError::Pin(cid.to_string(), error);
+
+        let url = service
+            .endpoint
+            .join("pins")
+            .map_err(|e| pin_error(e.to_string()))?;
+
+        let response = self
+            .client
+            .post(url)
+            .bearer_auth(&service.token)
+            .json(&Pin { cid, name })
+            .send()
+            .await
+            .map_err(|e| pin_error(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(pin_error(format!("Status code: {}", response.status())));
+        }
+
+        Ok(())
+    }
+
+    fn mirror_url(&self, cid: &str, name: &str) -> Result<Url, IpfsError> {
+        let url = match &self.target.gateway {
+            Some(gateway) => gateway.join(&format!("ipfs/{}", cid)),
+            None => Url::parse(&format!("ipfs://{}", cid)),
+        };
+        let mut url = url.map_err(|e| IpfsError::Add(name.to_string(), e.to_string()))?;
+
+        // Gateways use the filename for the download
+        url.query_pairs_mut().append_pair("filename", name);
+
+        Ok(url)
+    }
+
+    /// Adds a file and returns the url it is mirrored at
+    pub async fn publish(&self, name: &str, data: Vec<u8>) -> Result<Url, IpfsError> {
+        let cid = self.add(name, data).await?;
+        self.pin_remote(&cid, name).await?;
+
+        info!("Added {} to IPFS ({})", name, cid);
+
+        self.mirror_url(&cid, name)
+    }
+}
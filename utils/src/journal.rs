@@ -0,0 +1,206 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use uuid::Uuid;
+
+use crate::{
+    report::PrunedDownloads,
+    types::Compiled,
+    utils::{UploadableData, UploadableDownloadInfo},
+};
+
+pub const DEFAULT_JOURNAL_FILE: &str = "update-journal.json";
+
+pub enum JournalError {
+    /// The journal couldn't be read or parsed (Path, Error)
+    Read(PathBuf, String),
+    /// The journal is for another version (Journal version, Requested version)
+    VersionMismatch(Version, Version),
+    /// A generated download couldn't be staged (Path, Error)
+    Stage(PathBuf, String),
+}
+
+impl Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JournalError::Read(path, error) => {
+                write!(
+                    f,
+                    "Failed to read the journal {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+            JournalError::VersionMismatch(journal, requested) => write!(
+                f,
+                "The journal is for version {}, not {} (delete it to start over)",
+                journal, requested
+            ),
+            JournalError::Stage(path, error) => {
+                write!(f, "Failed to stage {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+/// The plan of an update and the steps it finished, saved as it goes so an interrupted update
+/// can be resumed
+pub struct Journal {
+    #[serde(skip)]
+    path: PathBuf,
+    pub version: Version,
+    /// The database to publish (built, merged and mirrored)
+    pub file: Compiled,
+    /// Every download the update uploads, generated ones point at their staged copy
+    pub uploads: Vec<UploadableDownloadInfo>,
+    /// The publish tasks that finished (by the key they write)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub completed: Vec<String>,
+    /// The id of the version file, fixed once publishing starts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_id: Option<Uuid>,
+    /// Redundant downloads the build dropped (for the report)
+    #[serde(default, skip_serializing_if = "PrunedDownloads::is_empty")]
+    pub pruned: PrunedDownloads,
+}
+
+/// Where the generated downloads of a journal are kept (`<journal>.staged`)
+fn staging_directory(path: &Path) -> PathBuf {
+    path.with_extension("staged")
+}
+
+impl Journal {
+    /// Starts a journal, generated downloads are written next to it so a resumed update
+    /// doesn't have to build them again
+    pub async fn create(
+        path: PathBuf,
+        version: Version,
+        file: Compiled,
+        uploads: Vec<UploadableDownloadInfo>,
+        pruned: PrunedDownloads,
+    ) -> Result<Journal, JournalError> {
+        let staging = staging_directory(&path);
+        let mut staged = vec![];
+
+        for upload in uploads {
+            let data = match &upload.data {
+                UploadableData::Generated(data, _) => data,
+                UploadableData::File(_) => {
+                    staged.push(upload);
+                    continue;
+                }
+            };
+
+            let target = std::path::absolute(staging.join(upload.file_name()))
+                .map_err(|e| JournalError::Stage(staging.clone(), e.to_string()))?;
+            fs::create_dir_all(&staging)
+                .await
+                .map_err(|e| JournalError::Stage(staging.clone(), e.to_string()))?;
+            fs::write(&target, data)
+                .await
+                .map_err(|e| JournalError::Stage(target.clone(), e.to_string()))?;
+
+            staged.push(UploadableDownloadInfo {
+                data: UploadableData::File(target),
+                ..upload
+            });
+        }
+
+        let journal = Journal {
+            path,
+            version,
+            file,
+            uploads: staged,
+            completed: vec![],
+            version_id: None,
+            pruned,
+        };
+        journal.save().await;
+
+        Ok(journal)
+    }
+
+    /// Loads the journal of an interrupted update of `version`
+    pub async fn load(path: PathBuf, version: &Version) -> Result<Journal, JournalError> {
+        let data = fs::read(&path)
+            .await
+            .map_err(|e| JournalError::Read(path.clone(), e.to_string()))?;
+        let mut journal: Journal = serde_json::from_slice(&data)
+            .map_err(|e| JournalError::Read(path.clone(), e.to_string()))?;
+
+        if &journal.version != version {
+            return Err(JournalError::VersionMismatch(
+                journal.version,
+                version.clone(),
+            ));
+        }
+
+        journal.path = path;
+        Ok(journal)
+    }
+
+    /// Records the id of the version file and the database it will contain, before anything
+    /// that depends on them is published
+    pub async fn start_publishing(&mut self, id: Uuid, file: &Compiled) {
+        self.version_id = Some(id);
+        self.file = file.clone();
+        self.save().await;
+    }
+
+    pub fn is_done(&self, task: &str) -> bool {
+        self.completed.iter().any(|completed| completed == task)
+    }
+
+    /// Records a finished publish task
+    pub async fn mark_done(&mut self, task: &str) {
+        if !self.is_done(task) {
+            self.completed.push(task.to_string());
+            self.save().await;
+        }
+    }
+
+    /// Forgets a publish task so it runs again
+    pub async fn forget(&mut self, task: &str) {
+        let before = self.completed.len();
+        self.completed.retain(|completed| completed != task);
+        if self.completed.len() != before {
+            self.save().await;
+        }
+    }
+
+    /// Failing to save only loses the ability to resume, the update goes on
+    pub async fn save(&self) {
+        let data = match serde_json::to_vec_pretty(self) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!("Failed to serialize the journal: {}", error);
+                return;
+            }
+        };
+
+        if let Err(error) = fs::write(&self.path, data).await {
+            warn!("Failed to write the journal: {}", error);
+        }
+    }
+
+    /// Deletes the journal and the staged downloads, once the update is done
+    pub async fn remove(self) {
+        if let Err(error) = fs::remove_file(&self.path).await {
+            warn!("Failed to remove the journal: {}", error);
+        }
+
+        let staging = staging_directory(&self.path);
+        if fs::try_exists(&staging).await.unwrap_or(false) {
+            if let Err(error) = fs::remove_dir_all(&staging).await {
+                warn!("Failed to remove {}: {}", staging.display(), error);
+            }
+        }
+    }
+}
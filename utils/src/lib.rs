@@ -0,0 +1,178 @@
+#[macro_use]
+extern crate tracing;
+
+#[cfg(feature = "publish")]
+pub mod approval;
+#[cfg(any(feature = "client", feature = "publish"))]
+pub mod archive;
+#[cfg(feature = "publish")]
+pub mod audit;
+#[cfg(feature = "publish")]
+pub mod badge;
+#[cfg(feature = "client")]
+pub mod batch;
+#[cfg(feature = "publish")]
+pub mod build;
+#[cfg(feature = "publish")]
+pub mod cache;
+#[cfg(feature = "net")]
+pub mod cancel;
+pub mod catalog;
+pub mod checksums;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod collation;
+#[cfg(feature = "publish")]
+pub mod coverage;
+#[cfg(feature = "publish")]
+pub mod environment;
+#[cfg(feature = "publish")]
+pub mod exit;
+#[cfg(feature = "client")]
+pub mod export;
+pub mod families;
+#[cfg(feature = "publish")]
+pub mod feed;
+#[cfg(feature = "net")]
+pub mod fetch;
+#[cfg(feature = "publish")]
+pub mod gc;
+#[cfg(feature = "publish")]
+pub mod github;
+#[cfg(feature = "client")]
+pub mod host;
+#[cfg(feature = "client")]
+pub mod install;
+#[cfg(feature = "publish")]
+pub mod ipfs;
+#[cfg(feature = "publish")]
+pub mod journal;
+#[cfg(feature = "publish")]
+pub mod lint;
+#[cfg(feature = "publish")]
+pub mod lock;
+#[cfg(any(feature = "client", feature = "publish"))]
+pub mod logging;
+#[cfg(feature = "publish")]
+pub mod metalink;
+pub mod metrics;
+#[cfg(feature = "windows-native")]
+pub mod native;
+#[cfg(feature = "publish")]
+pub mod notify;
+#[cfg(feature = "publish")]
+pub mod outdated;
+#[cfg(feature = "publish")]
+pub mod overlay;
+#[cfg(feature = "publish")]
+pub mod pipeline;
+pub mod policy;
+#[cfg(feature = "client")]
+pub mod preflight;
+#[cfg(feature = "client")]
+pub mod profile;
+#[cfg(feature = "publish")]
+pub mod repair;
+pub mod report;
+#[cfg(feature = "publish")]
+pub mod resolve;
+pub mod scan;
+#[cfg(feature = "client")]
+pub mod store;
+#[cfg(feature = "publish")]
+pub mod strict;
+#[cfg(feature = "publish")]
+pub mod subset;
+#[cfg(feature = "client")]
+pub mod system;
+pub mod ttc;
+#[cfg(feature = "client")]
+pub mod tui;
+pub mod types;
+pub mod utils;
+#[cfg(feature = "publish")]
+pub mod vendor;
+pub mod verify;
+pub mod wine;
+
+#[macro_export]
+macro_rules! instalation_struct {
+    {
+        $(#[$source_attr:meta])*
+        $vis:vis struct $source_struct_name:ident, $compiled_struct_name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $attr_vis:vis $variant_name:ident: $variant_struct_name:ty,
+            )*
+        }
+    } => {
+        $(#[$source_attr])*
+        #[serde(deny_unknown_fields)]
+        $vis struct $source_struct_name {
+            $vis download: SourceDownload,
+            /// The expected hash and size of the download
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            $vis pin: Option<DownloadPin>,
+            /// Signed downloads are uploaded privately
+            #[serde(default, skip_serializing_if = "DownloadAccess::is_public")]
+            $vis access: DownloadAccess,
+            /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+            #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+            $vis notes: Option<String>,
+            /// The hosts the installation is for (every host if empty)
+            #[serde(default, skip_serializing_if = "HostCondition::is_empty")]
+            $vis hosts: HostCondition,
+            $(
+                $(#[$variant_attr])*
+                $attr_vis $variant_name: $variant_struct_name,
+            )*
+        }
+
+        $(#[$source_attr])*
+        $vis struct $compiled_struct_name {
+            $vis download: Uuid,
+            /// The uncompressed size of the installed files
+            #[serde(default, skip_serializing_if = "Option::is_none")]
+            $vis installed_size: Option<u64>,
+            /// The sha256 of every installed file (by name)
+            #[serde(default, skip_serializing_if = "std::collections::BTreeMap::is_empty")]
+            $vis file_hashes: std::collections::BTreeMap<String, String>,
+            /// The hosts the installation is for (every host if empty)
+            #[serde(default, skip_serializing_if = "HostCondition::is_empty")]
+            $vis hosts: HostCondition,
+            $(
+                $(#[$variant_attr])*
+                $attr_vis $variant_name: $variant_struct_name,
+            )*
+        }
+    }
+}
+
+#[macro_export]
+macro_rules! instalation_options {
+    {
+        $(#[$source_attr:meta])*
+        $vis:vis enum {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant_name:ident($source_struct_name:ident, $compiled_struct_name:ident)
+            )*
+        }
+    } => {
+        $(#[$source_attr])*
+        $vis enum SourceInstalationType {
+            $(
+                $(#[$variant_attr])*
+                $variant_name($source_struct_name),
+            )*
+        }
+
+        $(#[$source_attr])*
+        $vis enum CompiledInstalationType {
+            $(
+                $(#[$variant_attr])*
+                $variant_name($compiled_struct_name),
+            )*
+        }
+    }
+}
@@ -1,9 +1,32 @@
-use std::{collections::HashMap, fmt::Display, path::PathBuf};
+use std::{
+    collections::{hash_map::Entry, HashMap, HashSet},
+    fmt::Display,
+    path::PathBuf,
+};
 
+use reqwest::{header::HeaderMap, StatusCode};
+use serde::Serialize;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
 use url::Url;
 use uuid::Uuid;
 
-use crate::types::{Source, SourceDownload, SourceInstalationType, SourceUUID};
+use crate::{
+    cache::HttpCache,
+    checksums::known_hashes,
+    collation::collate,
+    exit,
+    families::{family_name, is_wine_family},
+    fetch::Fetcher,
+    lock::{describe, LockedDownload, Lockfile},
+    resolve::{resolve, ResolvedDownload, LATEST_TAG},
+    subset::parse_range,
+    types::{
+        is_plain_file_name, Compiled, DownloadAccess, DownloadPin, FontCategory, FontLicense,
+        FontReference, HostOs, PublisherReference, Source, SourceDownload, SourceFont,
+        SourceInstalationType, SourceLintSettings, SourcePublisher, SourceUUID,
+    },
+    wine::parse_requirement,
+};
 
 #[derive(PartialEq, Eq, Clone, Copy)]
 pub enum LintMode {
@@ -13,14 +36,56 @@ pub enum LintMode {
     Fix,
 }
 
+/// The namespace of deterministic ids (the v5 uuid of the repository url)
+pub const DEFAULT_ID_NAMESPACE: Uuid = Uuid::from_u128(0x50a08d7f_b8f1_5c96_bbe3_3dba72c39734);
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+/// How missing ids are created in fix mode
+pub enum IdScheme {
+    /// Random (v4) uuids
+    Random,
+    /// v5 uuids of the namespace and the kind and name of the entity (e.g. `font:Arial`), so
+    /// forks and overlays create the same ids for the same entities
+    Deterministic(Uuid),
+}
+
+impl IdScheme {
+    /// Creates the id of an entity
+    pub fn create(&self, context: &ErrorContext) -> Uuid {
+        let name = match context {
+            ErrorContext::Publisher(name) => format!("publisher:{}", name),
+            ErrorContext::Group(name) => format!("group:{}", name),
+            ErrorContext::Application(name) => format!("application:{}", name),
+            ErrorContext::Font(name) => format!("font:{}", name),
+            ErrorContext::Publishers
+            | ErrorContext::Groups
+            | ErrorContext::Applications
+            | ErrorContext::Fonts => return Uuid::new_v4(),
+        };
+
+        match self {
+            IdScheme::Random => Uuid::new_v4(),
+            IdScheme::Deterministic(namespace) => Uuid::new_v5(namespace, name.as_bytes()),
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone)]
 pub enum ErrorContext {
+    /// -> Publisher
+    Publishers,
     /// -> Group
     Groups,
+    /// -> Application
+    Applications,
     /// -> Font
     Fonts,
+    /// -> Publisher -> Name
+    Publisher(String),
     /// -> Group -> Name
     Group(String),
+    /// -> Application -> Name
+    Application(String),
     /// -> Font -> Name
     Font(String),
 }
@@ -28,14 +93,108 @@ pub enum ErrorContext {
 impl Display for ErrorContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            ErrorContext::Publishers => write!(f, "publishers"),
             ErrorContext::Groups => write!(f, "groups"),
+            ErrorContext::Applications => write!(f, "applications"),
             ErrorContext::Fonts => write!(f, "fonts"),
+            ErrorContext::Publisher(name) => write!(f, "publishers -> {}", name),
             ErrorContext::Group(name) => write!(f, "groups -> {}", name),
+            ErrorContext::Application(name) => write!(f, "applications -> {}", name),
             ErrorContext::Font(name) => write!(f, "fonts -> {}", name),
         }
     }
 }
 
+/// The path of a sorted list
+fn list_path(context: &ErrorContext) -> String {
+    match context {
+        ErrorContext::Publishers => "publishers".to_string(),
+        ErrorContext::Groups => "groups".to_string(),
+        ErrorContext::Applications => "applications".to_string(),
+        ErrorContext::Fonts => "fonts".to_string(),
+        ErrorContext::Publisher(name) => format!("publishers -> {} -> aliases", name),
+        ErrorContext::Group(name) => format!("groups -> {} -> fonts", name),
+        ErrorContext::Application(name) => format!("applications -> {} -> fonts", name),
+        ErrorContext::Font(name) => format!("fonts -> {} -> Installations", name),
+    }
+}
+
+/// Lowercase kebab-case version of a text (e.g. `Some Tag` -> `some-tag`)
+fn to_kebab_case(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The same url with https
+fn with_https(url: &Url) -> String {
+    format!("https{}", &url.as_str()[url.scheme().len()..])
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "action", rename_all = "camelCase")]
+/// A machine-readable way to resolve a lint error (fields are relative to the error's context)
+pub enum Suggestion {
+    /// Running lint --fix resolves it
+    RunFix,
+    /// Remove the field and run lint --fix to create a new one
+    Regenerate { field: String },
+    /// Sort the list by name (lint --fix sorts it)
+    Sort { list: String },
+    /// Set the field to the value
+    Set { field: String, value: String },
+    /// Replace the value in the field (a list)
+    Replace {
+        field: String,
+        value: String,
+        with: String,
+    },
+    /// Add the value to the field
+    Add { field: String, value: String },
+    /// Remove the value from the field
+    Remove { field: String, value: String },
+    /// Use a name with a length in the range
+    Rename { min: usize, max: usize },
+    /// Check where the download redirects to, it can't be trusted by itself
+    ReviewRedirect { url: String },
+}
+
+impl Display for Suggestion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Suggestion::RunFix => write!(f, "run lint --fix"),
+            Suggestion::Regenerate { field } => {
+                write!(
+                    f,
+                    "remove the {} and run lint --fix to create a new one",
+                    field
+                )
+            }
+            Suggestion::Sort { list } => {
+                write!(f, "sort \"{}\" by name, lint --fix does it", list)
+            }
+            Suggestion::Set { field, value } => write!(f, "set {} to \"{}\"", field, value),
+            Suggestion::Replace { field, value, with } => {
+                write!(f, "replace \"{}\" with \"{}\" in {}", value, with, field)
+            }
+            Suggestion::Add { field, value } => write!(f, "add \"{}\" to {}", value, field),
+            Suggestion::Remove { field, value } => {
+                write!(f, "remove \"{}\" from {}", value, field)
+            }
+            Suggestion::Rename { min, max } => {
+                write!(f, "use a name of {} to {} characters", min, max)
+            }
+            Suggestion::ReviewRedirect { url } => write!(
+                f,
+                "check that \"{}\" serves the real resource and pin its sha256",
+                url
+            ),
+        }
+    }
+}
+
 pub enum LintErrors {
     /* Common */
     /// The UUID has been reused (UUID)
@@ -44,12 +203,27 @@ pub enum LintErrors {
     MissingUuid(ErrorContext),
     /// The name has been reused (Name, Context)
     DuplicatedName(String, ErrorContext),
+    /// A group or application has the name of a font or of the other kind, a reference to it
+    /// is ambiguous (Name, Context, Other context)
+    NameCollision(String, ErrorContext, ErrorContext),
     /// The name is too long (Name, Context)
     NameTooLong(String, ErrorContext),
     /// The name is too short (Name, Context)
     NameTooShort(String, ErrorContext),
     /// Unsorted list (Context)
     UnsortedList(ErrorContext),
+    /// The namespace isn't lowercase kebab-case (Namespace)
+    InvalidNamespace(String),
+    /// The name has surrounding whitespace or isn't in NFC (Name, Context)
+    NameNotNormalized(String, ErrorContext),
+    /// The name has a control or invisible character (Name, Context, Character)
+    NameInvalidCharacter(String, ErrorContext, char),
+    /// The name mixes latin letters with a character that looks like one (Name, Context, Character)
+    NameLookalikeCharacter(String, ErrorContext, char),
+
+    /* Publishers */
+    /// The publisher's url isn't https (Context, Url)
+    PublisherUrlNotHttps(ErrorContext, Url),
 
     /* Groups */
     /// The group has no fonts (Group name)
@@ -58,10 +232,46 @@ pub enum LintErrors {
     GroupDuplicateFont(ErrorContext, String),
     /// The group has a font that doesn't exist (Group name, Font name)
     GroupFontDoesntExist(ErrorContext, String),
+    /// The group references a font by name instead of id (Group name, Font name)
+    GroupFontNotId(ErrorContext, String),
+
+    /* Applications */
+    /// The application needs no fonts (Context)
+    ApplicationEmpty(ErrorContext),
+    /// The application has a duplicate font (Context, Font name)
+    ApplicationDuplicateFont(ErrorContext, String),
+    /// The application has a font that doesn't exist (Context, Font name)
+    ApplicationFontDoesntExist(ErrorContext, String),
+    /// The application references a font by name instead of id (Context, Font name)
+    ApplicationFontNotId(ErrorContext, String),
+    /// Another application has the same Steam app id (Context, App id, Other application)
+    ApplicationDuplicateSteamId(ErrorContext, u32, String),
 
     /* Fonts */
     /// The font has no installations (Context)
     FontEmpty(ErrorContext),
+    /// The font references its publisher by name instead of id (Context, Name)
+    FontPublisherNotId(ErrorContext, String),
+    /// The font's publisher doesn't exist (Context, Reference)
+    FontPublisherDoesntExist(ErrorContext, String),
+    /// The category isn't known or allowlisted (Context, Category)
+    FontUnknownCategory(ErrorContext, String),
+    /// The tag isn't lowercase or is duplicated (Context, Tag)
+    FontInvalidTag(ErrorContext, String),
+    /// The license isn't known (Context, License)
+    FontUnknownLicense(ErrorContext, String),
+    /// The font has an invalid subset declaration (Context, Error)
+    FontInvalidSubset(ErrorContext, String),
+    /// The converted file doesn't have a font extension (Context, File)
+    FontInvalidFileName(ErrorContext, String),
+    /// A file name isn't a single plain path component, clients would write outside the fonts
+    /// directory (Context, File)
+    FontUnsafeFileName(ErrorContext, String),
+    /// A file or registry name has control characters, they'd break out of the registry entry
+    /// (Context, Name)
+    FontControlCharacters(ErrorContext, String),
+    /// The file split into faces isn't a valid collection declaration (Context, File)
+    FontInvalidCollectionSplit(ErrorContext, String),
 
     /* Downloads */
     /// The local resource doesn't exist (Context, Path)
@@ -70,6 +280,163 @@ pub enum LintErrors {
     DownloadExternalResourceNotHttps(ErrorContext, Url),
     /// The external resource doesn't exist (Context, Url, Status)
     DownloadExternalResourceError(ErrorContext, Url, u16),
+    /// The download couldn't be resolved to a url (Context, Error)
+    DownloadResolveFailed(ErrorContext, String),
+    /// The external resource redirects through a non-https url (Context, Url)
+    DownloadRedirectNotHttps(ErrorContext, Url),
+    /// The external resource redirects to a domain not on the allowlist (Context, Url)
+    DownloadRedirectCrossDomain(ErrorContext, Url),
+    /// The pin isn't valid or disagrees with another pin of the download (Context, Error)
+    DownloadInvalidPin(ErrorContext, String),
+    /// The font conflicts with a font that doesn't exist (Context, Reference)
+    FontConflictDoesntExist(ErrorContext, String),
+    /// The conflicting font doesn't declare the conflict back (Context, Other font)
+    FontConflictNotSymmetric(ErrorContext, String),
+    /// The font installs a file another font also installs (Context, File, Other font)
+    FontFileCollision(ErrorContext, String, String),
+    /// The font installs a family Wine already provides (Context, Family)
+    FontWineFamilyCollision(ErrorContext, String),
+    /// The short name isn't a lowercase kebab-case slug of the allowed length (Context, Short name)
+    FontInvalidShortName(ErrorContext, String),
+    /// The font was renamed since it was published without keeping the old name (Context, Old name)
+    FontRenameNotRecorded(ErrorContext, String),
+    /// A previous name of the font is the name of another font (Context, Name, Other font)
+    FontPreviousNameTaken(ErrorContext, String, String),
+    /// The font installs a family another font also installs (Context, Family, Other font)
+    FontFamilyCollision(ErrorContext, String, String),
+    /// A wine version requirement of the font can't be parsed (Context, Requirement, Error)
+    FontInvalidWineRequirement(ErrorContext, String, String),
+    /// The resource doesn't match any known-good release of the file (Context, File, Hash)
+    DownloadUnknownChecksum(ErrorContext, String, String),
+    /// Only local resources can be signed, anything else is already public (Context)
+    DownloadSignedNotLocal(ErrorContext),
+    /// An installation is both for and not for a host (Context, Host)
+    InstallationContradictoryHosts(ErrorContext, HostOs),
+    /// An installation excludes every host, it's never installed (Context)
+    InstallationNoHosts(ErrorContext),
+    /// Two installations put the same file in place on a host (Context, File, Host)
+    InstallationHostsOverlap(ErrorContext, String, HostOs),
+    /// The external resource can change under us (Context, Url or release, Reason)
+    DownloadUnpinned(ErrorContext, String, &'static str),
+    /// The download has no lockfile entry (Context, Source)
+    DownloadNotLocked(ErrorContext, String),
+    /// The download is locked as something else (Context, Source, Locked as)
+    DownloadLockChanged(ErrorContext, String, String),
+    /// The lockfile has an entry for a download the catalog doesn't have (Source)
+    DownloadLockStale(String),
+
+    /* Custom rules */
+    /// A registered rule failed (Rule, Context, Message)
+    Rule(String, ErrorContext, String),
+    /// A registered rule warns (Rule, Context, Message)
+    RuleWarning(String, ErrorContext, String),
+}
+
+impl LintErrors {
+    /// Warnings are reported but don't fail the lint
+    pub fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            LintErrors::FontWineFamilyCollision(_, _)
+                | LintErrors::FontFamilyCollision(_, _, _)
+                | LintErrors::DownloadUnknownChecksum(_, _, _)
+                | LintErrors::DownloadUnpinned(_, _, _)
+                | LintErrors::RuleWarning(_, _, _)
+                | LintErrors::NameLookalikeCharacter(_, _, _)
+                | LintErrors::GroupFontNotId(_, _)
+                | LintErrors::ApplicationFontNotId(_, _)
+        )
+    }
+}
+
+impl LintErrors {
+    /// How to resolve the error, if there's a mechanical way
+    pub fn suggestion(&self) -> Option<Suggestion> {
+        let set = |field: &str, value: String| Suggestion::Set {
+            field: field.to_string(),
+            value,
+        };
+        let add = |field: &str, value: &str| Suggestion::Add {
+            field: field.to_string(),
+            value: value.to_string(),
+        };
+        let remove = |field: &str, value: &str| Suggestion::Remove {
+            field: field.to_string(),
+            value: value.to_string(),
+        };
+
+        Some(match self {
+            LintErrors::ReusedUuid(_) => Suggestion::Regenerate {
+                field: "id".to_string(),
+            },
+            LintErrors::MissingUuid(_)
+            | LintErrors::FontPublisherNotId(_, _)
+            | LintErrors::NameNotNormalized(_, _)
+            | LintErrors::GroupFontNotId(_, _)
+            | LintErrors::ApplicationFontNotId(_, _) => Suggestion::RunFix,
+            LintErrors::NameTooLong(_, _) | LintErrors::NameTooShort(_, _) => Suggestion::Rename {
+                min: MIN_NAME_LENGTH,
+                max: MAX_NAME_LENGTH,
+            },
+            LintErrors::UnsortedList(context) => Suggestion::Sort {
+                list: list_path(context),
+            },
+            LintErrors::InvalidNamespace(namespace) => set("namespace", to_kebab_case(namespace)),
+            LintErrors::PublisherUrlNotHttps(_, url)
+            | LintErrors::DownloadExternalResourceNotHttps(_, url) => set("url", with_https(url)),
+            LintErrors::DownloadRedirectCrossDomain(_, url) => Suggestion::ReviewRedirect {
+                url: url.to_string(),
+            },
+            LintErrors::GroupDuplicateFont(_, font)
+            | LintErrors::GroupFontDoesntExist(_, font)
+            | LintErrors::ApplicationDuplicateFont(_, font)
+            | LintErrors::ApplicationFontDoesntExist(_, font) => remove("fonts", font),
+            LintErrors::FontInvalidTag(_, tag) => match to_kebab_case(tag) {
+                kebab if &kebab == tag => remove("tags", tag),
+                kebab => Suggestion::Replace {
+                    field: "tags".to_string(),
+                    value: tag.clone(),
+                    with: kebab,
+                },
+            },
+            LintErrors::FontConflictDoesntExist(_, reference) => remove("conflictsWith", reference),
+            LintErrors::FontConflictNotSymmetric(ErrorContext::Font(font), other) => {
+                add(&format!("fonts -> {} -> conflictsWith", other), font)
+            }
+            LintErrors::FontFileCollision(_, _, other)
+            | LintErrors::FontFamilyCollision(_, _, other) => add("conflictsWith", other),
+            LintErrors::DownloadSignedNotLocal(_) => set("access", "public".to_string()),
+            LintErrors::InstallationContradictoryHosts(_, host) => {
+                remove("hosts -> except", &host.to_string())
+            }
+            LintErrors::FontRenameNotRecorded(_, old) => add("previousNames", old),
+            LintErrors::FontPreviousNameTaken(_, name, _) => remove("previousNames", name),
+            LintErrors::FontInvalidShortName(ErrorContext::Font(name), _) => {
+                set("shortName", slug(name)?)
+            }
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A lint error as written to the json report
+pub struct LintIssue {
+    pub warning: bool,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suggestion: Option<Suggestion>,
+}
+
+impl From<&LintErrors> for LintIssue {
+    fn from(error: &LintErrors) -> Self {
+        LintIssue {
+            warning: error.is_warning(),
+            message: error.to_string(),
+            suggestion: error.suggestion(),
+        }
+    }
 }
 
 impl Display for LintErrors {
@@ -83,6 +450,11 @@ impl Display for LintErrors {
                 "The name/short name {} has been reused in {}",
                 name, context
             ),
+            LintErrors::NameCollision(name, context, other) => write!(
+                f,
+                "The name {} of {} is also used by {}, references to it are ambiguous",
+                name, context, other
+            ),
             LintErrors::NameTooLong(name, context) => {
                 write!(f, "The name/short name {} is too long in {}", name, context)
             }
@@ -91,15 +463,36 @@ impl Display for LintErrors {
                 "The name/short name {} is too short in {}",
                 name, context
             ),
-            LintErrors::UnsortedList(context) => write!(
+            LintErrors::UnsortedList(context) => {
+                write!(f, "The list \"{}\" is not sorted", list_path(context))
+            }
+
+            LintErrors::InvalidNamespace(namespace) => write!(
+                f,
+                "The namespace \"{}\" should be lowercase and kebab-case",
+                namespace
+            ),
+            LintErrors::NameNotNormalized(name, context) => write!(
                 f,
-                "The list \"{}\" is not sorted",
-                match context {
-                    ErrorContext::Groups => "groups".to_string(),
-                    ErrorContext::Fonts => "fonts".to_string(),
-                    ErrorContext::Group(name) => format!("groups -> {} -> fonts", name),
-                    ErrorContext::Font(name) => format!("fonts -> {} -> Installations", name),
-                }
+                "The name/short name \"{}\" in {} has surrounding whitespace or isn't in NFC",
+                name, context
+            ),
+            LintErrors::NameInvalidCharacter(name, context, character) => write!(
+                f,
+                "The name/short name \"{}\" in {} has the invisible character {:?}",
+                name, context, character
+            ),
+            LintErrors::NameLookalikeCharacter(name, context, character) => write!(
+                f,
+                "The name/short name \"{}\" in {} has \"{}\" (U+{:04X}) which looks like a latin letter",
+                name, context, character, *character as u32
+            ),
+
+            /* Publishers */
+            LintErrors::PublisherUrlNotHttps(context, url) => write!(
+                f,
+                "The url \"{}\" of \"{}\" should use https",
+                url, context
             ),
 
             /* Groups */
@@ -114,11 +507,89 @@ impl Display for LintErrors {
                 "The group \"{}\" has a font named \"{}\" that doesn't exist",
                 context, font
             ),
+            LintErrors::GroupFontNotId(context, font) => write!(
+                f,
+                "The group \"{}\" references the font \"{}\" by name, run lint --fix to use its id",
+                context, font
+            ),
+
+            /* Applications */
+            LintErrors::ApplicationEmpty(context) => {
+                write!(f, "The application \"{}\" needs no fonts", context)
+            }
+            LintErrors::ApplicationDuplicateFont(context, font) => write!(
+                f,
+                "The application \"{}\" has a duplicate font named \"{}\"",
+                context, font
+            ),
+            LintErrors::ApplicationFontDoesntExist(context, font) => write!(
+                f,
+                "The application \"{}\" has a font named \"{}\" that doesn't exist",
+                context, font
+            ),
+            LintErrors::ApplicationFontNotId(context, font) => write!(
+                f,
+                "The application \"{}\" references the font \"{}\" by name, run lint --fix to use its id",
+                context, font
+            ),
+            LintErrors::ApplicationDuplicateSteamId(context, id, other) => write!(
+                f,
+                "The application \"{}\" has the Steam app id {} of \"{}\"",
+                context, id, other
+            ),
 
             /* Fonts */
             LintErrors::FontEmpty(context) => {
                 write!(f, "There are no installations for the font \"{}\"", context)
             }
+            LintErrors::FontPublisherNotId(context, name) => write!(
+                f,
+                "The font \"{}\" references the publisher \"{}\" by name, run lint --fix to use its id",
+                context, name
+            ),
+            LintErrors::FontPublisherDoesntExist(context, reference) => write!(
+                f,
+                "The font \"{}\" has the publisher \"{}\" which doesn't exist",
+                context, reference
+            ),
+            LintErrors::FontUnknownCategory(context, category) => write!(
+                f,
+                "The category \"{}\" of the font \"{}\" is unknown (allowlist it before clients support it)",
+                category, context
+            ),
+            LintErrors::FontUnknownLicense(context, license) => write!(
+                f,
+                "The license \"{}\" of the font \"{}\" is unknown (use open, proprietary-redistributable or proprietary)",
+                license, context
+            ),
+            LintErrors::FontInvalidTag(context, tag) => write!(
+                f,
+                "The tag \"{}\" of the font \"{}\" should be unique, lowercase and kebab-case",
+                tag, context
+            ),
+            LintErrors::FontInvalidSubset(context, error) => {
+                write!(f, "Invalid subset for the font \"{}\": {}", context, error)
+            }
+            LintErrors::FontInvalidFileName(context, file) => write!(
+                f,
+                "The file \"{}\" of the font \"{}\" should end with .ttf or .otf",
+                file, context
+            ),
+            LintErrors::FontUnsafeFileName(context, file) => write!(
+                f,
+                "The file \"{}\" of the font \"{}\" has to be a plain file name (no directories)",
+                file, context
+            ),
+            LintErrors::FontControlCharacters(context, name) => write!(
+                f,
+                "The file or registry name {:?} of the font \"{}\" has control characters",
+                name, context
+            ),
+            LintErrors::FontInvalidCollectionSplit(context, file) => write!(
+                f,
+                "The file \"{}\" of the font \"{}\" is split but isn't a .ttc with at least one face",
+                file, context
+            ),
 
             /* Downloads */
             LintErrors::DownloadLocalResourceDoesntExist(context, path) => write!(
@@ -137,12 +608,279 @@ impl Display for LintErrors {
                 "Failed to download the external resource for \"{}\" at \"{}\" with status code {}",
                 context, url, status
             ),
+            LintErrors::DownloadResolveFailed(context, error) => write!(
+                f,
+                "Failed to resolve the download for \"{}\": {}",
+                context, error
+            ),
+            LintErrors::DownloadRedirectNotHttps(context, url) => write!(
+                f,
+                "The external resource for \"{}\" redirects through \"{}\" which isn't https",
+                context, url
+            ),
+            LintErrors::DownloadRedirectCrossDomain(context, url) => write!(
+                f,
+                "The external resource for \"{}\" redirects to \"{}\" which isn't an allowed domain",
+                context, url
+            ),
+            LintErrors::DownloadInvalidPin(context, error) => {
+                write!(f, "Invalid pin for \"{}\": {}", context, error)
+            }
+            LintErrors::DownloadSignedNotLocal(context) => write!(
+                f,
+                "The download for \"{}\" is signed but isn't a local resource",
+                context
+            ),
+            LintErrors::InstallationContradictoryHosts(context, host) => write!(
+                f,
+                "An installation of \"{}\" is both only for and not for {}",
+                context, host
+            ),
+            LintErrors::InstallationNoHosts(context) => write!(
+                f,
+                "An installation of \"{}\" excludes every host and is never installed",
+                context
+            ),
+            LintErrors::InstallationHostsOverlap(context, file, host) => write!(
+                f,
+                "Two installations of \"{}\" install \"{}\" on {}, make their hosts exclusive",
+                context, file, host
+            ),
+            LintErrors::FontConflictDoesntExist(context, reference) => write!(
+                f,
+                "The font \"{}\" conflicts with \"{}\" which doesn't exist",
+                context, reference
+            ),
+            LintErrors::FontConflictNotSymmetric(context, other) => write!(
+                f,
+                "The font \"{}\" conflicts with \"{}\" but not the other way around",
+                context, other
+            ),
+            LintErrors::FontFileCollision(context, file, other) => write!(
+                f,
+                "The font \"{}\" installs the file \"{}\" which \"{}\" also installs (declare conflictsWith if intended)",
+                context, file, other
+            ),
+            LintErrors::FontInvalidShortName(context, short_name) => write!(
+                f,
+                "The short name \"{}\" of the font \"{}\" should be lowercase, kebab-case and {} to {} characters",
+                short_name, context, MIN_NAME_LENGTH, MAX_SHORT_NAME_LENGTH
+            ),
+            LintErrors::FontRenameNotRecorded(context, old) => write!(
+                f,
+                "The font \"{}\" was published as \"{}\", keep the old name in previousNames",
+                context, old
+            ),
+            LintErrors::FontPreviousNameTaken(context, name, other) => write!(
+                f,
+                "The previous name \"{}\" of the font \"{}\" is the name of \"{}\"",
+                name, context, other
+            ),
+            LintErrors::FontInvalidWineRequirement(context, requirement, error) => write!(
+                f,
+                "The wine requirement \"{}\" of the font \"{}\" is invalid: {}",
+                requirement, context, error
+            ),
+            LintErrors::FontWineFamilyCollision(context, family) => write!(
+                f,
+                "The font \"{}\" installs the family \"{}\" which Wine already provides",
+                context, family
+            ),
+            LintErrors::FontFamilyCollision(context, family, other) => write!(
+                f,
+                "The font \"{}\" installs the family \"{}\" which \"{}\" also installs",
+                context, family, other
+            ),
+            LintErrors::DownloadUnknownChecksum(context, file, hash) => write!(
+                f,
+                "The resource \"{}\" for \"{}\" doesn't match any known-good release (sha256: {})",
+                file, context, hash
+            ),
+            LintErrors::DownloadUnpinned(context, resource, reason) => write!(
+                f,
+                "The resource \"{}\" for \"{}\" has {}, pin it to a release so it can't change",
+                resource, context, reason
+            ),
+            LintErrors::DownloadNotLocked(context, download) => write!(
+                f,
+                "The resource {} for \"{}\" isn't in the lockfile",
+                download, context
+            ),
+            LintErrors::DownloadLockChanged(context, download, locked) => write!(
+                f,
+                "The resource {} for \"{}\" is locked as {}",
+                download, context, locked
+            ),
+            LintErrors::DownloadLockStale(download) => write!(
+                f,
+                "The lockfile has the resource {} which no font uses",
+                download
+            ),
+
+            /* Custom rules */
+            LintErrors::Rule(rule, context, message)
+            | LintErrors::RuleWarning(rule, context, message) => {
+                write!(f, "[{}] {} for \"{}\"", rule, message, context)
+            }
         }
     }
 }
 
+/// Categories the source may use before they get a `FontCategory` variant
+///
+/// Released clients deserialize them as `FontCategory::Other`.
+const ALLOWED_EXTRA_CATEGORIES: &[&str] = &[];
+/// Names (lowercase) a group or application may share with a font or with each other
+const ALLOWED_NAME_COLLISIONS: &[&str] = &[];
+
 const MAX_NAME_LENGTH: usize = 50;
 const MIN_NAME_LENGTH: usize = 3;
+/// Short names are stable keys (exports, selections), so they're kept short
+const MAX_SHORT_NAME_LENGTH: usize = 32;
+
+/// A short name made from a display name (e.g. `Times New Roman` -> `times-new-roman`), none
+/// when too little of it is ASCII (the short name has to be picked by hand)
+pub fn slug(name: &str) -> Option<String> {
+    let plain: String = name.nfd().filter(|c| !is_combining_mark(*c)).collect();
+    let slug = to_kebab_case(&plain);
+    let slug = match slug.get(..MAX_SHORT_NAME_LENGTH) {
+        Some(truncated) => truncated.trim_end_matches('-').to_string(),
+        None => slug,
+    };
+
+    (slug.len() >= MIN_NAME_LENGTH).then_some(slug)
+}
+
+/// A slug of the name no other font uses yet (numbered if it has to be)
+fn unique_slug(name: &str, taken: &HashSet<String>) -> Option<String> {
+    let base = slug(name)?;
+    let mut candidate = base.clone();
+    let mut number = 2;
+
+    while taken.contains(&candidate) {
+        let suffix = format!("-{}", number);
+        let keep = MAX_SHORT_NAME_LENGTH
+            .saturating_sub(suffix.len())
+            .min(base.len());
+        candidate = format!("{}{}", base[..keep].trim_end_matches('-'), suffix);
+        number += 1;
+    }
+
+    Some(candidate)
+}
+
+fn is_valid_short_name(short_name: &str) -> bool {
+    is_kebab_case(short_name)
+        && !short_name.starts_with('-')
+        && !short_name.ends_with('-')
+        && !short_name.contains("--")
+        && (MIN_NAME_LENGTH..=MAX_SHORT_NAME_LENGTH).contains(&short_name.len())
+}
+
+/// Checks a download against the known-good hashes of its file name
+fn check_known_checksum(context: &ErrorContext, file_name: &str, hash: &str) -> Option<LintErrors> {
+    let known = known_hashes(file_name);
+    if known.is_empty() || known.contains(&hash) {
+        return None;
+    }
+
+    Some(LintErrors::DownloadUnknownChecksum(
+        context.clone(),
+        file_name.to_string(),
+        hash.to_string(),
+    ))
+}
+
+/// Path segments that follow whatever upstream released last
+const MOVING_SEGMENTS: &[&str] = &[
+    "current", "head", "latest", "main", "master", "nightly", "snapshot", "stable", "trunk",
+];
+
+/// Whether the text has something that looks like a version (`1.2`, `v3`, `2024`, a commit)
+fn has_version(text: &str) -> bool {
+    let bytes = text.as_bytes();
+
+    let dotted = bytes.windows(3).any(|window| {
+        window[0].is_ascii_digit() && b"._-".contains(&window[1]) && window[2].is_ascii_digit()
+    });
+    let prefixed = bytes.windows(2).enumerate().any(|(index, window)| {
+        matches!(window[0], b'v' | b'V')
+            && window[1].is_ascii_digit()
+            && (index == 0 || !bytes[index - 1].is_ascii_alphabetic())
+    });
+    let long_number = text
+        .split(|c: char| !c.is_ascii_digit())
+        .any(|run| run.len() >= 4);
+    let commit = text
+        .split(|c: char| !c.is_ascii_hexdigit())
+        .any(|run| run.len() >= 7 && run.chars().any(|c| c.is_ascii_digit()));
+
+    dotted || prefixed || long_number || commit
+}
+
+/// Why an external url might change content under us
+fn unpinned_reason(url: &Url) -> Option<&'static str> {
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+
+    let moving = segments.iter().any(|segment| {
+        let segment = segment.to_lowercase();
+        let stem = segment.split('.').next().unwrap_or_default();
+        MOVING_SEGMENTS.contains(&stem) || segment.contains("latest")
+    });
+    if moving {
+        return Some("a \"latest\" style path");
+    }
+
+    let versioned =
+        segments.iter().any(|segment| has_version(segment)) || url.query().is_some_and(has_version);
+    match versioned {
+        true => None,
+        false => Some("no version in its path"),
+    }
+}
+
+/// Warns about external resources that aren't pinned to a release
+fn check_pinned(context: &ErrorContext, download: &SourceDownload) -> Option<LintErrors> {
+    match download {
+        SourceDownload::ExternalResource(url) => unpinned_reason(url)
+            .map(|reason| LintErrors::DownloadUnpinned(context.clone(), url.to_string(), reason)),
+        SourceDownload::GitHubRelease { repo, tag, asset } if tag == LATEST_TAG => {
+            Some(LintErrors::DownloadUnpinned(
+                context.clone(),
+                format!("{}@{} ({})", repo, tag, asset),
+                "the latest release as its tag",
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Domains we allow external resources to redirect to
+const REDIRECT_ALLOWLIST: &[&str] = &["archive.org", "githubusercontent.com", "sourceforge.net"];
+
+/// The last two labels of a host (good enough for the hosts we use)
+fn base_domain(host: &str) -> &str {
+    match host.rmatch_indices('.').nth(1) {
+        Some((index, _)) => &host[index + 1..],
+        None => host,
+    }
+}
+
+/// Whether a redirect stays on the same domain or goes to an allowlisted one
+fn redirect_allowed(from: &Url, to: &Url) -> bool {
+    let (from, to) = match (from.host_str(), to.host_str()) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return false,
+    };
+
+    base_domain(from) == base_domain(to)
+        || REDIRECT_ALLOWLIST
+            .iter()
+            .any(|allowed| to == *allowed || to.ends_with(&format!(".{}", allowed)))
+}
 
 fn check_name(name: &str, context: ErrorContext) -> Result<(), LintErrors> {
     if name.len() > MAX_NAME_LENGTH {
@@ -154,16 +892,489 @@ fn check_name(name: &str, context: ErrorContext) -> Result<(), LintErrors> {
     }
 }
 
-fn check_sorted<T>(list: &Vec<T>, sort_fn: &dyn Fn(&T, &T) -> bool) -> bool {
-    let mut sorted = true;
-    for i in 0..list.len() - 1 {
-        if !sort_fn(&list[i], &list[i + 1]) {
-            sorted = false;
-            break;
-        }
+/// Trims the whitespace around a name and puts it in NFC
+fn normalize_name(name: &str) -> String {
+    name.trim().nfc().collect()
+}
+
+/// Control characters, zero-width characters and whitespace other than a space
+fn is_invisible(c: char) -> bool {
+    c.is_control()
+        || (c.is_whitespace() && c != ' ')
+        || matches!(c, '\u{200B}'..='\u{200F}' | '\u{2060}' | '\u{FEFF}')
+}
+
+/// Greek and Cyrillic letters that look like latin ones (fullwidth forms are too), catalogs add
+/// to them with `lint.lookalikes`
+const LOOKALIKES: &str = "АВЕКМНОРСТХаеорсухіјѕԁԛԝΑΒΕΖΗΙΚΜΝΟΡΤΥΧοντ";
+
+fn is_lookalike(c: char, settings: &SourceLintSettings) -> bool {
+    if settings.allowed_lookalikes.contains(&c) {
+        return false;
+    }
+
+    LOOKALIKES.contains(c)
+        || ('\u{FF01}'..='\u{FF5E}').contains(&c)
+        || settings.lookalikes.contains(&c)
+}
+
+fn check_characters(
+    name: &str,
+    context: &ErrorContext,
+    settings: &SourceLintSettings,
+) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    if normalize_name(name) != name {
+        errors.push(LintErrors::NameNotNormalized(
+            name.to_string(),
+            context.clone(),
+        ));
+    }
+
+    if let Some(c) = name.chars().find(|c| is_invisible(*c)) {
+        errors.push(LintErrors::NameInvalidCharacter(
+            name.to_string(),
+            context.clone(),
+            c,
+        ));
+    }
+
+    if name.chars().any(|c| c.is_ascii_alphabetic()) {
+        if let Some(c) = name.chars().find(|c| is_lookalike(*c, settings)) {
+            errors.push(LintErrors::NameLookalikeCharacter(
+                name.to_string(),
+                context.clone(),
+                c,
+            ));
+        }
+    }
+
+    errors
+}
+
+/// Checks the characters of every name, short name and alias
+pub fn check_names(source: &Source) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    for publisher in &source.publishers {
+        let context = ErrorContext::Publisher(publisher.name.to_string());
+        for name in std::iter::once(&publisher.name).chain(&publisher.aliases) {
+            errors.extend(check_characters(name, &context, &source.lint));
+        }
+    }
+
+    for group in &source.groups {
+        errors.extend(check_characters(
+            &group.name,
+            &ErrorContext::Group(group.name.to_string()),
+            &source.lint,
+        ));
+    }
+
+    for application in &source.applications {
+        errors.extend(check_characters(
+            &application.name,
+            &ErrorContext::Application(application.name.to_string()),
+            &source.lint,
+        ));
+    }
+
+    for font in &source.fonts {
+        let context = ErrorContext::Font(font.name.to_string());
+        errors.extend(check_characters(&font.name, &context, &source.lint));
+        if font.short_name != font.name {
+            errors.extend(check_characters(&font.short_name, &context, &source.lint));
+        }
+    }
+
+    errors
+}
+
+/// Checks no group or application shares a name with a font (name, short name or previous name)
+/// or with the other kind, clients search them all at once
+pub fn check_name_collisions(source: &Source) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    // Name (lowercase) -> what has it, fonts first
+    let mut names: HashMap<String, ErrorContext> = HashMap::new();
+    for font in &source.fonts {
+        let context = ErrorContext::Font(font.name.to_string());
+        let font_names = std::iter::once(&font.name)
+            .chain(std::iter::once(&font.short_name))
+            .chain(&font.previous_names);
+        for name in font_names {
+            names
+                .entry(name.to_lowercase())
+                .or_insert_with(|| context.clone());
+        }
+    }
+
+    let others = source
+        .groups
+        .iter()
+        .map(|group| (&group.name, ErrorContext::Group(group.name.to_string())))
+        .chain(source.applications.iter().map(|application| {
+            (
+                &application.name,
+                ErrorContext::Application(application.name.to_string()),
+            )
+        }));
+    for (name, context) in others {
+        let key = name.to_lowercase();
+        if ALLOWED_NAME_COLLISIONS.contains(&key.as_str()) {
+            continue;
+        }
+
+        match names.entry(key) {
+            // Duplicates within a kind are reported on their own
+            Entry::Occupied(other)
+                if std::mem::discriminant(other.get()) != std::mem::discriminant(&context) =>
+            {
+                errors.push(LintErrors::NameCollision(
+                    name.clone(),
+                    context,
+                    other.get().clone(),
+                ));
+            }
+            Entry::Occupied(_) => {}
+            Entry::Vacant(entry) => {
+                entry.insert(context);
+            }
+        }
+    }
+
+    errors
+}
+
+/// Checks the lockfile has exactly the downloads of the catalog (and nothing it changed), so
+/// builds from it are reproducible
+pub fn check_lock(source: &Source, lock: &Lockfile) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    let used: HashSet<&SourceDownload> = source
+        .fonts
+        .iter()
+        .flat_map(|font| &font.installations)
+        .map(|installation| installation.download())
+        .collect();
+    let mut stale: Vec<&LockedDownload> = lock
+        .downloads
+        .iter()
+        .filter(|locked| !used.contains(&locked.source))
+        .collect();
+
+    let mut checked: HashSet<&SourceDownload> = HashSet::new();
+    for font in &source.fonts {
+        for installation in &font.installations {
+            let download = installation.download();
+            if !checked.insert(download) {
+                continue;
+            }
+            let context = ErrorContext::Font(font.name.to_string());
+
+            let locked = match lock.find(download) {
+                Some(locked) => locked,
+                None => {
+                    // A moved download keeps its pinned content
+                    let moved = installation.pin().and_then(|pin| {
+                        stale
+                            .iter()
+                            .position(|locked| locked.hash.eq_ignore_ascii_case(&pin.hash))
+                    });
+                    errors.push(match moved {
+                        Some(index) => LintErrors::DownloadLockChanged(
+                            context,
+                            describe(download),
+                            describe(&stale.remove(index).source),
+                        ),
+                        None => LintErrors::DownloadNotLocked(context, describe(download)),
+                    });
+                    continue;
+                }
+            };
+
+            // A locked external resource has to resolve to itself
+            if let (SourceDownload::ExternalResource(url), Some(locked_url)) =
+                (download, &locked.url)
+            {
+                if url != locked_url {
+                    errors.push(LintErrors::DownloadLockChanged(
+                        context,
+                        describe(download),
+                        locked_url.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+
+    for locked in stale {
+        errors.push(LintErrors::DownloadLockStale(describe(&locked.source)));
+    }
+
+    errors
+}
+
+/// Locks the downloads of the catalog the lockfile doesn't have yet (resolving and hashing them)
+/// and drops the ones it doesn't use anymore, the locked ones are kept as they are
+pub async fn update_lock(
+    source: &Source,
+    base_path: PathBuf,
+    fetcher: &Fetcher,
+    lock: &mut Lockfile,
+) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    let mut used: Vec<(&SourceDownload, ErrorContext)> = vec![];
+    for font in &source.fonts {
+        for installation in &font.installations {
+            let download = installation.download();
+            if !used.iter().any(|(used, _)| *used == download) {
+                used.push((download, ErrorContext::Font(font.name.to_string())));
+            }
+        }
+    }
+    lock.retain_sources(used.iter().map(|(download, _)| *download));
+
+    for (download, context) in used {
+        if lock.find(download).is_some() {
+            continue;
+        }
+
+        let resolved = match resolve(download, fetcher).await {
+            Ok(resolved) => resolved,
+            Err(error) => {
+                errors.push(LintErrors::DownloadResolveFailed(
+                    context,
+                    error.to_string(),
+                ));
+                continue;
+            }
+        };
+
+        let (url, redirected_to, data) = match resolved {
+            ResolvedDownload::External(url) => {
+                let (chain, res) = match fetcher.get_traced(&url, HeaderMap::new()).await {
+                    Ok(traced) => traced,
+                    Err(error) => {
+                        error!("Failed to get external resource: {}", error);
+                        std::process::exit(exit::NETWORK);
+                    }
+                };
+                if !res.status().is_success() {
+                    errors.push(LintErrors::DownloadExternalResourceError(
+                        context,
+                        url.clone(),
+                        res.status().as_u16(),
+                    ));
+                    continue;
+                }
+
+                let data = match fetcher.read_body(res).await {
+                    Ok(data) => data,
+                    Err(error) => {
+                        error!("Failed to get external resource: {}", error);
+                        std::process::exit(exit::NETWORK);
+                    }
+                };
+                let redirected_to = chain.last().filter(|last| **last != url).cloned();
+
+                (Some(url), redirected_to, data)
+            }
+            ResolvedDownload::Local(path) => match std::fs::read(base_path.join(&path)) {
+                Ok(data) => (None, None, data),
+                Err(error) => {
+                    error!("Failed to read local resource: {}", error);
+                    std::process::exit(exit::IO);
+                }
+            },
+        };
+
+        info!("Locked {}", describe(download));
+        lock.record(LockedDownload {
+            source: download.clone(),
+            url,
+            redirected_to,
+            hash: sha256::digest(data.as_slice()),
+            file_size: data.len() as u64,
+        });
+    }
+
+    errors
+}
+
+/// Normalizes every name and the references to them (fix mode), renamed fonts keep their old
+/// name in `previousNames` so clients and references that use it still find them
+fn normalize_names(source: &mut Source) {
+    for publisher in &mut source.publishers {
+        publisher.name = normalize_name(&publisher.name);
+        for alias in &mut publisher.aliases {
+            *alias = normalize_name(alias);
+        }
+    }
+
+    for group in &mut source.groups {
+        group.name = normalize_name(&group.name);
+        for font in &mut group.fonts {
+            if let FontReference::Name(name) = font {
+                *name = normalize_name(name);
+            }
+        }
+    }
+
+    for application in &mut source.applications {
+        application.name = normalize_name(&application.name);
+        for font in &mut application.fonts {
+            if let FontReference::Name(name) = font {
+                *name = normalize_name(name);
+            }
+        }
+    }
+
+    for font in &mut source.fonts {
+        let name = normalize_name(&font.name);
+        if name != font.name && !font.previous_names.contains(&font.name) {
+            font.previous_names.push(font.name.clone());
+        }
+        font.name = name;
+        font.short_name = normalize_name(&font.short_name);
+        if let PublisherReference::Name(name) = &mut font.publisher {
+            *name = normalize_name(name);
+        }
+        for reference in &mut font.conflicts_with {
+            if let FontReference::Name(name) = reference {
+                *name = normalize_name(name);
+            }
+        }
+    }
+}
+
+fn check_sorted<T>(list: &[T], sort_fn: &dyn Fn(&T, &T) -> bool) -> bool {
+    list.windows(2).all(|pair| sort_fn(&pair[0], &pair[1]))
+}
+
+/// Whether the text is lowercase kebab-case (e.g. `some-tag2`)
+fn is_kebab_case(text: &str) -> bool {
+    !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
+
+/// The name a group's font sorts by (the reference itself if the font doesn't exist)
+fn reference_name(fonts: &[SourceFont], reference: &FontReference) -> String {
+    match fonts.iter().find(|font| reference.matches(font)) {
+        Some(font) => font.name.clone(),
+        None => reference.to_string(),
+    }
+}
+
+/// Checks the publishers, groups and applications (and their fonts) and fonts are sorted by name
+pub fn check_order(source: &Source) -> Vec<LintErrors> {
+    let mut errors = vec![];
+
+    if !check_sorted(&source.publishers, &|a, b| {
+        collate(&a.name, &b.name).is_lt()
+    }) {
+        errors.push(LintErrors::UnsortedList(ErrorContext::Publishers));
+    }
+
+    if !check_sorted(&source.groups, &|a, b| collate(&a.name, &b.name).is_lt()) {
+        errors.push(LintErrors::UnsortedList(ErrorContext::Groups));
+    }
+
+    for group in &source.groups {
+        let by_name = |a: &FontReference, b: &FontReference| {
+            collate(
+                &reference_name(&source.fonts, a),
+                &reference_name(&source.fonts, b),
+            )
+            .is_lt()
+        };
+        if !check_sorted(&group.fonts, &by_name) {
+            errors.push(LintErrors::UnsortedList(ErrorContext::Group(
+                group.name.to_string(),
+            )));
+        }
+    }
+
+    if !check_sorted(&source.applications, &|a, b| {
+        collate(&a.name, &b.name).is_lt()
+    }) {
+        errors.push(LintErrors::UnsortedList(ErrorContext::Applications));
+    }
+
+    for application in &source.applications {
+        let by_name = |a: &FontReference, b: &FontReference| {
+            collate(
+                &reference_name(&source.fonts, a),
+                &reference_name(&source.fonts, b),
+            )
+            .is_lt()
+        };
+        if !check_sorted(&application.fonts, &by_name) {
+            errors.push(LintErrors::UnsortedList(ErrorContext::Application(
+                application.name.to_string(),
+            )));
+        }
+    }
+
+    if !check_sorted(&source.fonts, &|a, b| collate(&a.name, &b.name).is_lt()) {
+        errors.push(LintErrors::UnsortedList(ErrorContext::Fonts));
+    }
+
+    errors
+}
+
+/// A check that runs alongside the built-in ones, e.g. an overlay's own policies
+pub trait LintRule: Send + Sync {
+    /// Shown with the rule's issues
+    fn name(&self) -> &str;
+
+    /// Checks the source (after the built-in fixes in fix mode)
+    fn check(&self, source: &Source) -> Vec<LintErrors>;
+
+    /// Fixes what the rule can, the source is checked again afterwards
+    fn fix(&self, _source: &mut Source) {}
+}
+
+#[derive(Default)]
+/// The custom rules a lint runs
+pub struct LintRules {
+    rules: Vec<Box<dyn LintRule>>,
+}
+
+impl LintRules {
+    pub fn register(&mut self, rule: Box<dyn LintRule>) {
+        self.rules.push(rule);
+    }
+}
+
+/// Every font needs a tag starting with the prefix (e.g. an internal ticket id)
+pub struct RequiredTagRule {
+    pub prefix: String,
+}
+
+impl LintRule for RequiredTagRule {
+    fn name(&self) -> &str {
+        "required-tag"
     }
 
-    sorted
+    fn check(&self, source: &Source) -> Vec<LintErrors> {
+        source
+            .fonts
+            .iter()
+            .filter(|font| !font.tags.iter().any(|tag| tag.starts_with(&self.prefix)))
+            .map(|font| {
+                LintErrors::Rule(
+                    self.name().to_string(),
+                    ErrorContext::Font(font.name.to_string()),
+                    format!("Missing a tag starting with \"{}\"", self.prefix),
+                )
+            })
+            .collect()
+    }
 }
 
 fn check_or_create_uuid(
@@ -171,20 +1382,23 @@ fn check_or_create_uuid(
     uuid: SourceUUID,
     context: ErrorContext,
     lint_mode: LintMode,
+    id_scheme: IdScheme,
 ) -> Result<Uuid, LintErrors> {
     match uuid {
         SourceUUID::Uuid(id) => {
-            if uuid_map.contains_key(&id) {
-                Err(LintErrors::ReusedUuid(id))
-            } else {
-                uuid_map.insert(id, ());
+            if let Entry::Vacant(e) = uuid_map.entry(id) {
+                e.insert(());
                 Ok(id)
+            } else {
+                Err(LintErrors::ReusedUuid(id))
             }
         }
         SourceUUID::Null => {
             if lint_mode == LintMode::Fix {
-                let new_id = Uuid::new_v4();
-                uuid_map.insert(new_id, ());
+                let new_id = id_scheme.create(&context);
+                if uuid_map.insert(new_id, ()).is_some() {
+                    return Err(LintErrors::ReusedUuid(new_id));
+                }
                 Ok(new_id)
             } else {
                 Err(LintErrors::MissingUuid(context))
@@ -193,26 +1407,107 @@ fn check_or_create_uuid(
     }
 }
 
+/// The network, cache and custom rules a lint uses
+pub struct LintContext<'a> {
+    pub fetcher: &'a Fetcher,
+    pub cache: &'a HttpCache,
+    pub rules: &'a LintRules,
+    /// Only the downloads of these fonts are checked over the network (all of them if `None`),
+    /// the other checks always cover the whole source
+    pub changed_fonts: Option<&'a HashSet<String>>,
+    /// The last published database, fonts renamed since have to keep their old names
+    pub published: Option<&'a Compiled>,
+}
+
 pub async fn lint(
     original: &Source,
     base_path: PathBuf,
     lint_mode: LintMode,
+    id_scheme: IdScheme,
+    ctx: &LintContext<'_>,
 ) -> (Source, Vec<LintErrors>) {
+    let LintContext {
+        fetcher,
+        cache,
+        rules,
+        changed_fonts,
+        published,
+    } = ctx;
+
     let mut new = original.clone().to_owned();
 
     let mut errors = Vec::<LintErrors>::new();
 
     let mut uuids = HashMap::<Uuid, ()>::new();
 
-    // Check groups are in the correct order
+    // Check the namespace (of overlays)
+    if let Some(namespace) = &new.namespace {
+        if !is_kebab_case(namespace) {
+            errors.push(LintErrors::InvalidNamespace(namespace.clone()));
+        }
+    }
+
+    // Normalize the names (or check their characters)
+    if lint_mode == LintMode::Fix {
+        normalize_names(&mut new);
+    }
+    errors.extend(check_names(&new));
+    errors.extend(check_name_collisions(&new));
+
+    // Sort the lists (or check they are sorted)
     if lint_mode == LintMode::Fix {
-        new.groups.sort_by(|a, b| a.name.cmp(&b.name));
+        new.publishers.sort_by(|a, b| collate(&a.name, &b.name));
+        new.groups.sort_by(|a, b| collate(&a.name, &b.name));
+        new.applications.sort_by(|a, b| collate(&a.name, &b.name));
+        new.fonts.sort_by(|a, b| collate(&a.name, &b.name));
     } else {
-        if !check_sorted(&new.groups, &|a, b| a.name < b.name) {
-            errors.push(LintErrors::UnsortedList(ErrorContext::Groups));
+        errors.extend(check_order(original));
+    }
+
+    // Check the publishers names (and aliases) are unique, valid and have ids
+    let mut publisher_names = HashMap::<String, ()>::new();
+    for publisher in &mut new.publishers {
+        let context = ErrorContext::Publisher(publisher.name.to_string());
+
+        for name in std::iter::once(&publisher.name).chain(&publisher.aliases) {
+            if let Err(error) = check_name(name, context.clone()) {
+                errors.push(error);
+            }
+
+            match publisher_names.entry(name.to_lowercase()) {
+                Entry::Occupied(_) => {
+                    errors.push(LintErrors::DuplicatedName(name.clone(), context.clone()))
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(());
+                }
+            }
         }
+
+        if let Some(url) = &publisher.url {
+            if url.scheme() != "https" {
+                errors.push(LintErrors::PublisherUrlNotHttps(
+                    context.clone(),
+                    url.clone(),
+                ));
+            }
+        }
+
+        match check_or_create_uuid(&mut uuids, publisher.id, context, lint_mode, id_scheme) {
+            Ok(id) => {
+                // If in fix mode, set the uuid
+                if lint_mode == LintMode::Fix {
+                    publisher.id = SourceUUID::Uuid(id);
+                }
+            }
+            Err(error) => errors.push(error),
+        };
     }
 
+    // Publishers created while migrating font publisher names
+    let publishers = new.publishers.clone();
+    let mut created_publishers: Vec<SourcePublisher> = vec![];
+
     // Check any groups with duplicate names or no fonts or invalid ids
     let mut group_names = HashMap::<String, ()>::new();
     for group in &mut new.groups {
@@ -229,7 +1524,7 @@ pub async fn lint(
         }
 
         // Check if the group has any fonts
-        if group.fonts.len() == 0 {
+        if group.fonts.is_empty() {
             errors.push(LintErrors::GroupEmpty(ErrorContext::Group(
                 group.name.to_string(),
             )));
@@ -241,6 +1536,7 @@ pub async fn lint(
             group.id,
             ErrorContext::Group(group.name.to_string()),
             lint_mode,
+            id_scheme,
         ) {
             Ok(id) => {
                 // If in fix mode, set the uuid
@@ -251,66 +1547,157 @@ pub async fn lint(
             Err(error) => errors.push(error),
         };
 
-        // Make sure all the fonts are exist and are unique
+        // Make sure all the fonts exist, are unique and are referenced by id
         let mut fonts = HashMap::<String, ()>::new();
-        for font in &group.fonts {
-            if fonts.contains_key(font) {
+        for reference in &group.fonts {
+            let context = ErrorContext::Group(group.name.to_string());
+
+            let font = match new.fonts.iter().find(|font| reference.matches(font)) {
+                Some(font) => font,
+                None => {
+                    errors.push(LintErrors::GroupFontDoesntExist(
+                        context,
+                        reference.to_string(),
+                    ));
+                    continue;
+                }
+            };
+
+            if fonts.insert(font.name.clone(), ()).is_some() {
                 errors.push(LintErrors::GroupDuplicateFont(
-                    ErrorContext::Group(group.name.to_string()),
-                    font.to_string(),
+                    context.clone(),
+                    font.name.clone(),
                 ));
             }
 
-            fonts.insert(font.clone(), ());
+            // Fix mode migrates the names once every font has an id
+            if let (FontReference::Name(name), LintMode::Check) = (reference, lint_mode) {
+                errors.push(LintErrors::GroupFontNotId(context, name.clone()));
+            }
+        }
+
+        group_names.insert(group.name.clone(), ());
+    }
+
+    // Check the applications like the groups, and that no two share a Steam app id
+    let mut application_names = HashMap::<String, ()>::new();
+    let mut steam_app_ids = HashMap::<u32, String>::new();
+    for application in &mut new.applications {
+        let context = ErrorContext::Application(application.name.to_string());
+
+        if application_names
+            .insert(application.name.clone(), ())
+            .is_some()
+        {
+            errors.push(LintErrors::DuplicatedName(
+                application.name.clone(),
+                context.clone(),
+            ));
+        }
+
+        if let Err(error) = check_name(&application.name, context.clone()) {
+            errors.push(error);
+        }
+
+        if let Some(id) = application.steam_app_id {
+            match steam_app_ids.entry(id) {
+                Entry::Occupied(other) => errors.push(LintErrors::ApplicationDuplicateSteamId(
+                    context.clone(),
+                    id,
+                    other.get().clone(),
+                )),
+                Entry::Vacant(entry) => {
+                    entry.insert(application.name.clone());
+                }
+            }
+        }
 
-            let mut found = false;
+        if application.fonts.is_empty() {
+            errors.push(LintErrors::ApplicationEmpty(context.clone()));
+        }
 
-            for font_item in &new.fonts {
-                if font == &font_item.name {
-                    found = true;
-                    break;
+        match check_or_create_uuid(
+            &mut uuids,
+            application.id,
+            context.clone(),
+            lint_mode,
+            id_scheme,
+        ) {
+            Ok(id) => {
+                // If in fix mode, set the uuid
+                if lint_mode == LintMode::Fix {
+                    application.id = SourceUUID::Uuid(id);
                 }
             }
+            Err(error) => errors.push(error),
+        };
+
+        let mut fonts = HashMap::<String, ()>::new();
+        for reference in &application.fonts {
+            let font = match new.fonts.iter().find(|font| reference.matches(font)) {
+                Some(font) => font,
+                None => {
+                    errors.push(LintErrors::ApplicationFontDoesntExist(
+                        context.clone(),
+                        reference.to_string(),
+                    ));
+                    continue;
+                }
+            };
 
-            if !found {
-                errors.push(LintErrors::GroupFontDoesntExist(
-                    ErrorContext::Group(group.name.to_string()),
-                    font.to_string(),
+            if fonts.insert(font.name.clone(), ()).is_some() {
+                errors.push(LintErrors::ApplicationDuplicateFont(
+                    context.clone(),
+                    font.name.clone(),
                 ));
             }
-        }
 
-        // Sort the fonts by name
-        if lint_mode == LintMode::Fix {
-            group.fonts.sort_by(|a, b| a.cmp(&b));
-        } else {
-            // Check if the fonts are sorted
-            if !check_sorted(&group.fonts, &|a, b| a < b) {
-                errors.push(LintErrors::UnsortedList(ErrorContext::Group(
-                    group.name.to_string(),
-                )));
+            // Fix mode migrates the names once every font has an id
+            if let (FontReference::Name(name), LintMode::Check) = (reference, lint_mode) {
+                errors.push(LintErrors::ApplicationFontNotId(
+                    context.clone(),
+                    name.clone(),
+                ));
             }
         }
-
-        group_names.insert(group.name.clone(), ());
     }
 
     // Check any fonts with duplicate names or invalid ids
     let mut font_names = HashMap::<String, ()>::new();
 
-    // Sort the fonts by name
-    if lint_mode == LintMode::Fix {
-        new.fonts.sort_by(|a, b| a.name.cmp(&b.name));
-    } else {
-        // Check if the groups are sorted
-        if !check_sorted(&new.fonts, &|a, b| a.name < b.name) {
-            errors.push(LintErrors::UnsortedList(ErrorContext::Fonts));
-        }
-    }
-
     let mut downloads: Vec<(ErrorContext, SourceDownload)> = Vec::new();
+    // Family (lowercase) -> font
+    let mut families: HashMap<String, String> = HashMap::new();
+    // Installed file (lowercase) -> font
+    let mut installed_files: HashMap<String, String> = HashMap::new();
+
+    // Fonts that declared each other as conflicting may collide
+    let conflicting = |a: &str, b: &str| match (
+        original.fonts.iter().find(|font| font.name == a),
+        original.fonts.iter().find(|font| font.name == b),
+    ) {
+        (Some(a), Some(b)) => original.conflicting(a, b),
+        _ => false,
+    };
+    let mut pins: HashMap<SourceDownload, DownloadPin> = HashMap::new();
+
+    // Valid short names in use, generated ones mustn't collide with them
+    let mut short_names: HashSet<String> = new
+        .fonts
+        .iter()
+        .filter(|font| is_valid_short_name(&font.short_name))
+        .map(|font| font.short_name.clone())
+        .collect();
 
     for font in &mut new.fonts {
+        // Generate a slug for invalid (or missing) short names
+        if lint_mode == LintMode::Fix && !is_valid_short_name(&font.short_name) {
+            if let Some(slug) = unique_slug(&font.name, &short_names) {
+                font.short_name = slug;
+                short_names.insert(font.short_name.clone());
+            }
+        }
+
         // Check if the font & short name is valid
         if font_names.contains_key(&font.name) {
             errors.push(LintErrors::DuplicatedName(
@@ -319,7 +1706,7 @@ pub async fn lint(
             ));
         }
 
-        if &font.name != &font.short_name && font_names.contains_key(&font.short_name) {
+        if font.name != font.short_name && font_names.contains_key(&font.short_name) {
             errors.push(LintErrors::DuplicatedName(
                 font.short_name.clone(),
                 ErrorContext::Font(font.name.to_string()),
@@ -330,14 +1717,65 @@ pub async fn lint(
             errors.push(error);
         }
 
-        if let Err(error) = check_name(&font.short_name, ErrorContext::Font(font.name.to_string()))
-        {
-            errors.push(error);
+        if !is_valid_short_name(&font.short_name) {
+            errors.push(LintErrors::FontInvalidShortName(
+                ErrorContext::Font(font.name.to_string()),
+                font.short_name.clone(),
+            ));
         }
 
-        // Check the publisher is valid
-        if let Err(error) = check_name(&font.publisher, ErrorContext::Font(font.name.to_string())) {
-            errors.push(error);
+        for requirement in font.wine.iter().flat_map(|wine| wine.requirements()) {
+            if let Err(error) = parse_requirement(requirement) {
+                errors.push(LintErrors::FontInvalidWineRequirement(
+                    ErrorContext::Font(font.name.to_string()),
+                    requirement.clone(),
+                    error,
+                ));
+            }
+        }
+
+        // Check the publisher exists (and migrate names to ids in fix mode)
+        let publisher = publishers
+            .iter()
+            .chain(&created_publishers)
+            .find(|publisher| font.publisher.matches(publisher))
+            .cloned();
+        match (&font.publisher, publisher, lint_mode) {
+            (PublisherReference::Id(_), Some(_), _) => {}
+            (PublisherReference::Name(_), Some(publisher), LintMode::Fix) => {
+                if let SourceUUID::Uuid(id) = publisher.id {
+                    font.publisher = PublisherReference::Id(id);
+                }
+            }
+            (PublisherReference::Name(name), Some(_), LintMode::Check) => {
+                errors.push(LintErrors::FontPublisherNotId(
+                    ErrorContext::Font(font.name.to_string()),
+                    name.clone(),
+                ));
+            }
+            (PublisherReference::Name(name), None, LintMode::Fix) => {
+                match check_name(name, ErrorContext::Font(font.name.to_string())) {
+                    Ok(()) => {
+                        let id = id_scheme.create(&ErrorContext::Publisher(name.clone()));
+                        uuids.insert(id, ());
+                        created_publishers.push(SourcePublisher {
+                            id: SourceUUID::Uuid(id),
+                            name: name.clone(),
+                            url: None,
+                            aliases: vec![],
+                            notes: None,
+                        });
+                        font.publisher = PublisherReference::Id(id);
+                    }
+                    Err(error) => errors.push(error),
+                }
+            }
+            (reference, None, _) => {
+                errors.push(LintErrors::FontPublisherDoesntExist(
+                    ErrorContext::Font(font.name.to_string()),
+                    reference.to_string(),
+                ));
+            }
         }
 
         // Check Uuid
@@ -346,6 +1784,7 @@ pub async fn lint(
             font.id,
             ErrorContext::Font(font.name.to_string()),
             lint_mode,
+            id_scheme,
         ) {
             Ok(id) => {
                 // If in fix mode, set the uuid
@@ -356,20 +1795,326 @@ pub async fn lint(
             Err(error) => errors.push(error),
         };
 
+        // Check renames since the last publish are recorded (and record them in fix mode)
+        let published_font = match (published, font.id) {
+            (Some(published), SourceUUID::Uuid(id)) => published.find_font(id),
+            _ => None,
+        };
+        if let Some(published_font) = published_font {
+            let old = &published_font.name;
+            if old != &font.name && !font.previous_names.contains(old) {
+                match lint_mode {
+                    LintMode::Fix => font.previous_names.push(old.clone()),
+                    LintMode::Check => errors.push(LintErrors::FontRenameNotRecorded(
+                        ErrorContext::Font(font.name.to_string()),
+                        old.clone(),
+                    )),
+                }
+            }
+        }
+
         // Check if the font has any installations
-        if font.installations.len() == 0 {
+        if font.installations.is_empty() {
             errors.push(LintErrors::FontEmpty(ErrorContext::Font(
                 font.name.to_string(),
             )));
         }
 
+        // Every file ends up in the fonts directory of the prefix
+        for installation in &font.installations {
+            let mut files = installation.installed_files();
+            // Split collections are extracted too
+            if let SourceInstalationType::Cabextract(data) = installation {
+                files.extend(data.files.iter().filter(|f| f.split.is_some()).cloned());
+            }
+
+            for file in files {
+                for name in [&file.file, &file.registry_name] {
+                    if name.chars().any(char::is_control) {
+                        errors.push(LintErrors::FontControlCharacters(
+                            ErrorContext::Font(font.name.to_string()),
+                            name.clone(),
+                        ));
+                    }
+                }
+                if !file.file.chars().any(char::is_control) && !is_plain_file_name(&file.file) {
+                    errors.push(LintErrors::FontUnsafeFileName(
+                        ErrorContext::Font(font.name.to_string()),
+                        file.file,
+                    ));
+                }
+            }
+        }
+
+        // Check the subset declarations
+        for installation in &font.installations {
+            let subset = match installation {
+                SourceInstalationType::Cabextract(data) => {
+                    // Only collections can be split, and every face must be a font
+                    for file in &data.files {
+                        if let Some(faces) = &file.split {
+                            if !file.file.to_lowercase().ends_with(".ttc") || faces.is_empty() {
+                                errors.push(LintErrors::FontInvalidCollectionSplit(
+                                    ErrorContext::Font(font.name.to_string()),
+                                    file.file.clone(),
+                                ));
+                            }
+
+                            for face in faces {
+                                let face_file = face.file.to_lowercase();
+                                if !face_file.ends_with(".ttf") && !face_file.ends_with(".otf") {
+                                    errors.push(LintErrors::FontInvalidFileName(
+                                        ErrorContext::Font(font.name.to_string()),
+                                        face.file.clone(),
+                                    ));
+                                }
+                            }
+                        }
+                    }
+
+                    &data.subset
+                }
+                SourceInstalationType::Woff2(data) => {
+                    // The converted file is always a TrueType/OpenType font
+                    let file = data.file.to_lowercase();
+                    if !file.ends_with(".ttf") && !file.ends_with(".otf") {
+                        errors.push(LintErrors::FontInvalidFileName(
+                            ErrorContext::Font(font.name.to_string()),
+                            data.file.clone(),
+                        ));
+                    }
+
+                    continue;
+                }
+            };
+
+            if let Some(subset) = subset {
+                if subset.unicode_ranges.is_empty() {
+                    errors.push(LintErrors::FontInvalidSubset(
+                        ErrorContext::Font(font.name.to_string()),
+                        "no unicode ranges".to_string(),
+                    ));
+                }
+
+                for range in &subset.unicode_ranges {
+                    if let Err(error) = parse_range(range) {
+                        errors.push(LintErrors::FontInvalidSubset(
+                            ErrorContext::Font(font.name.to_string()),
+                            error.to_string(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Host conditions can't contradict themselves, and host variants of a file can't both
+        // apply to a host
+        for installation in &font.installations {
+            let hosts = installation.hosts();
+            for host in hosts.only.iter().filter(|host| hosts.except.contains(host)) {
+                errors.push(LintErrors::InstallationContradictoryHosts(
+                    ErrorContext::Font(font.name.to_string()),
+                    *host,
+                ));
+            }
+            if HostOs::ALL.iter().all(|host| hosts.except.contains(host)) {
+                errors.push(LintErrors::InstallationNoHosts(ErrorContext::Font(
+                    font.name.to_string(),
+                )));
+            }
+        }
+        for (index, first) in font.installations.iter().enumerate() {
+            for second in &font.installations[index + 1..] {
+                if first.hosts().is_empty() && second.hosts().is_empty() {
+                    continue;
+                }
+                let host = match HostOs::ALL.into_iter().find(|host| {
+                    first.hosts().applies(Some(*host)) && second.hosts().applies(Some(*host))
+                }) {
+                    Some(host) => host,
+                    None => continue,
+                };
+
+                let files = first.installed_files();
+                if let Some(file) = second
+                    .installed_files()
+                    .into_iter()
+                    .find(|file| files.iter().any(|other| other.file == file.file))
+                {
+                    errors.push(LintErrors::InstallationHostsOverlap(
+                        ErrorContext::Font(font.name.to_string()),
+                        file.file,
+                        host,
+                    ));
+                }
+            }
+        }
+
         // Find all downloads
         for installation in &font.installations {
-            let download = match installation {
-                SourceInstalationType::Cabextract(data) => &data.download,
+            // We can only keep the files we upload private
+            if installation.access() == DownloadAccess::Signed
+                && !matches!(installation.download(), SourceDownload::LocalResource(_))
+            {
+                errors.push(LintErrors::DownloadSignedNotLocal(ErrorContext::Font(
+                    font.name.to_string(),
+                )));
+            }
+
+            // Pins are sha256 hashes and have to agree for the same download
+            if let Some(pin) = installation.pin() {
+                let context = ErrorContext::Font(font.name.to_string());
+
+                if pin.hash.len() != 64 || !pin.hash.chars().all(|c| c.is_ascii_hexdigit()) {
+                    errors.push(LintErrors::DownloadInvalidPin(
+                        context,
+                        format!("\"{}\" isn't a sha256 hash", pin.hash),
+                    ));
+                } else {
+                    match pins.entry(installation.download().clone()) {
+                        Entry::Occupied(existing) => {
+                            if !existing.get().hash.eq_ignore_ascii_case(&pin.hash)
+                                || existing.get().file_size != pin.file_size
+                            {
+                                errors.push(LintErrors::DownloadInvalidPin(
+                                    context,
+                                    "another installation pins the download differently"
+                                        .to_string(),
+                                ));
+                            }
+                        }
+                        Entry::Vacant(entry) => {
+                            entry.insert(pin.clone());
+                        }
+                    }
+                }
+            }
+
+            downloads.push((
+                ErrorContext::Font(font.name.to_string()),
+                installation.download().clone(),
+            ));
+        }
+
+        // Check the categories are known (or allowlisted)
+        for category in &font.categories {
+            if let FontCategory::Other(name) = category {
+                if !ALLOWED_EXTRA_CATEGORIES.contains(&name.as_str()) {
+                    errors.push(LintErrors::FontUnknownCategory(
+                        ErrorContext::Font(font.name.to_string()),
+                        name.clone(),
+                    ));
+                }
+            }
+        }
+
+        if let Some(FontLicense::Other(license)) = &font.license {
+            errors.push(LintErrors::FontUnknownLicense(
+                ErrorContext::Font(font.name.to_string()),
+                license.clone(),
+            ));
+        }
+
+        // Check the tags are unique and lowercase kebab-case
+        let mut tags = HashMap::<String, ()>::new();
+        for tag in &font.tags {
+            if !is_kebab_case(tag) || tags.insert(tag.clone(), ()).is_some() {
+                errors.push(LintErrors::FontInvalidTag(
+                    ErrorContext::Font(font.name.to_string()),
+                    tag.clone(),
+                ));
+            }
+        }
+
+        // Check the conflicts exist and are declared on both sides
+        for reference in &font.conflicts_with {
+            let context = ErrorContext::Font(font.name.to_string());
+
+            let other = match original.find_font(reference) {
+                Some(other) if other.name != font.name => other,
+                _ => {
+                    errors.push(LintErrors::FontConflictDoesntExist(
+                        context,
+                        reference.to_string(),
+                    ));
+                    continue;
+                }
             };
 
-            downloads.push((ErrorContext::Font(font.name.to_string()), download.clone()));
+            let declared_back = other
+                .conflicts_with
+                .iter()
+                .filter_map(|reference| original.find_font(reference))
+                .any(|found| found.name == font.name);
+            if !declared_back {
+                errors.push(LintErrors::FontConflictNotSymmetric(
+                    context,
+                    other.name.clone(),
+                ));
+            }
+        }
+
+        // Check the installed families don't overwrite others
+        let mut font_families: Vec<String> = font
+            .installations
+            .iter()
+            .flat_map(|installation| installation.installed_files())
+            .map(|file| family_name(&file.registry_name))
+            .collect();
+        font_families.sort_by_key(|family| family.to_lowercase());
+        font_families.dedup_by_key(|family| family.to_lowercase());
+
+        for family in font_families {
+            let context = ErrorContext::Font(font.name.to_string());
+
+            if is_wine_family(&family) {
+                errors.push(LintErrors::FontWineFamilyCollision(
+                    context.clone(),
+                    family.clone(),
+                ));
+            }
+
+            match families.entry(family.to_lowercase()) {
+                Entry::Occupied(other) => {
+                    if !conflicting(&font.name, other.get()) {
+                        errors.push(LintErrors::FontFamilyCollision(
+                            context,
+                            family,
+                            other.get().clone(),
+                        ))
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(font.name.clone());
+                }
+            }
+        }
+
+        // Check the installed files don't overwrite another font's (Windows is case-insensitive)
+        let mut font_files: Vec<String> = font
+            .installations
+            .iter()
+            .flat_map(|installation| installation.installed_files())
+            .map(|file| file.file)
+            .collect();
+        font_files.sort_by_key(|file| file.to_lowercase());
+        font_files.dedup_by_key(|file| file.to_lowercase());
+
+        for file in font_files {
+            match installed_files.entry(file.to_lowercase()) {
+                Entry::Occupied(other) => {
+                    if !conflicting(&font.name, other.get()) {
+                        errors.push(LintErrors::FontFileCollision(
+                            ErrorContext::Font(font.name.to_string()),
+                            file,
+                            other.get().clone(),
+                        ))
+                    }
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(font.name.clone());
+                }
+            }
         }
 
         font_names.insert(font.name.clone(), ());
@@ -378,8 +2123,30 @@ pub async fn lint(
 
     // Check all the downloads
     for (context, download) in downloads {
+        errors.extend(check_pinned(&context, &download));
+
+        // Unchanged fonts skip the network checks (local resources are still checked)
+        let unchanged = match (changed_fonts, &context) {
+            (Some(changed), ErrorContext::Font(name)) => !changed.contains(name),
+            _ => false,
+        };
+        if unchanged && !matches!(download, SourceDownload::LocalResource(_)) {
+            continue;
+        }
+
+        let download = match resolve(&download, fetcher).await {
+            Ok(download) => download,
+            Err(error) => {
+                errors.push(LintErrors::DownloadResolveFailed(
+                    context,
+                    error.to_string(),
+                ));
+                continue;
+            }
+        };
+
         match download {
-            SourceDownload::ExternalResource(url) => {
+            ResolvedDownload::External(url) => {
                 if url.scheme() != "https" {
                     errors.push(LintErrors::DownloadExternalResourceNotHttps(
                         context.clone(),
@@ -388,29 +2155,156 @@ pub async fn lint(
                 }
 
                 // Make the request
-                let res = match reqwest::get(url.clone()).await {
-                    Ok(response) => response,
+                let headers = match cache.get(&url) {
+                    Some(cached) => cached.conditional_headers(),
+                    None => HeaderMap::new(),
+                };
+
+                let (chain, res) = match fetcher.get_traced(&url, headers).await {
+                    Ok(traced) => traced,
                     Err(error) => {
                         error!("Failed to get external resource: {}", error);
-                        std::process::exit(1);
+                        std::process::exit(exit::NETWORK);
                     }
                 };
 
-                if !res.status().is_success() {
+                // Audit the redirects
+                for (from, to) in chain.iter().zip(chain.iter().skip(1)) {
+                    if to.scheme() != "https" {
+                        errors.push(LintErrors::DownloadRedirectNotHttps(
+                            context.clone(),
+                            to.clone(),
+                        ));
+                    }
+
+                    if !redirect_allowed(from, to) {
+                        errors.push(LintErrors::DownloadRedirectCrossDomain(
+                            context.clone(),
+                            to.clone(),
+                        ));
+                    }
+                }
+
+                if chain.len() > 1 {
+                    info!("{} redirects to {}", url, chain.last().unwrap());
+                }
+
+                let file_name = url
+                    .path_segments()
+                    .and_then(|mut segments| segments.next_back())
+                    .unwrap_or_default()
+                    .to_string();
+                let tracked = !known_hashes(&file_name).is_empty();
+
+                if res.status() == StatusCode::NOT_MODIFIED {
+                    info!("Unchanged upstream: {}", url);
+
+                    if let Some(cached) = cache.get(&url) {
+                        errors.extend(check_known_checksum(&context, &file_name, &cached.hash));
+                    }
+                } else if !res.status().is_success() {
                     errors.push(LintErrors::DownloadExternalResourceError(
                         context,
                         url.clone(),
                         res.status().as_u16(),
                     ));
+                } else if tracked {
+                    // Only download the body of files we know the hashes for
+                    match fetcher.read_body(res).await {
+                        Ok(bytes) => errors.extend(check_known_checksum(
+                            &context,
+                            &file_name,
+                            &sha256::digest(bytes),
+                        )),
+                        Err(error) => {
+                            error!("Failed to get external resource: {}", error);
+                            std::process::exit(exit::NETWORK);
+                        }
+                    }
                 }
             }
-            SourceDownload::LocalResource(path) => {
+            ResolvedDownload::Local(path) => {
                 let path = base_path.join(path);
                 if !path.exists() {
                     errors.push(LintErrors::DownloadLocalResourceDoesntExist(context, path));
+                    continue;
+                }
+
+                let file_name = path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                if known_hashes(&file_name).is_empty() {
+                    continue;
+                }
+
+                match std::fs::read(&path) {
+                    Ok(data) => errors.extend(check_known_checksum(
+                        &context,
+                        &file_name,
+                        &sha256::digest(&data),
+                    )),
+                    Err(error) => {
+                        error!("Failed to read local resource: {}", error);
+                        std::process::exit(exit::IO);
+                    }
+                }
+            }
+        }
+    }
+
+    if !created_publishers.is_empty() {
+        new.publishers.extend(created_publishers);
+        new.publishers.sort_by(|a, b| collate(&a.name, &b.name));
+    }
+
+    // Previous names can't be taken by another font
+    for font in &new.fonts {
+        for name in &font.previous_names {
+            let other = new.fonts.iter().find(|other| {
+                other.name != font.name
+                    && (&other.name == name || other.previous_names.contains(name))
+            });
+            if let Some(other) = other {
+                errors.push(LintErrors::FontPreviousNameTaken(
+                    ErrorContext::Font(font.name.to_string()),
+                    name.clone(),
+                    other.name.clone(),
+                ));
+            }
+        }
+    }
+
+    // Reference the group and application fonts by id and sort them by name
+    if lint_mode == LintMode::Fix {
+        let lists = new.groups.iter_mut().map(|group| &mut group.fonts).chain(
+            new.applications
+                .iter_mut()
+                .map(|application| &mut application.fonts),
+        );
+        for list in lists {
+            for reference in list.iter_mut() {
+                let font = new.fonts.iter().find(|font| reference.matches(font));
+                if let Some(SourceUUID::Uuid(id)) = font.map(|font| font.id) {
+                    *reference = FontReference::Uuid(id);
                 }
             }
+
+            list.sort_by(|a, b| {
+                collate(
+                    &reference_name(&new.fonts, a),
+                    &reference_name(&new.fonts, b),
+                )
+            });
+        }
+    }
+
+    // Run the custom rules
+    for rule in &rules.rules {
+        if lint_mode == LintMode::Fix {
+            rule.fix(&mut new);
         }
+        errors.extend(rule.check(&new));
     }
 
     (new, errors)
@@ -0,0 +1,159 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use url::Url;
+
+use crate::types::SourceDownload;
+
+pub const DEFAULT_LOCK_FILE: &str = "fonts.lock.json";
+
+/// The lockfile format we write (and the newest we can read)
+pub const LOCK_VERSION: u32 = 1;
+
+pub enum LockError {
+    /// The lockfile couldn't be read or parsed (Path, Error)
+    Read(PathBuf, String),
+    /// The lockfile was written by a newer version (Path, Version)
+    UnsupportedVersion(PathBuf, u32),
+    /// The lockfile couldn't be written (Path, Error)
+    Write(PathBuf, String),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Read(path, error) => {
+                write!(
+                    f,
+                    "Failed to read the lockfile {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+            LockError::UnsupportedVersion(path, version) => write!(
+                f,
+                "The lockfile {} is version {}, this build only knows version {}",
+                path.display(),
+                version,
+                LOCK_VERSION
+            ),
+            LockError::Write(path, error) => {
+                write!(
+                    f,
+                    "Failed to write the lockfile {}: {}",
+                    path.display(),
+                    error
+                )
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// What a download of the catalog resolved to the last time it was built
+pub struct LockedDownload {
+    pub source: SourceDownload,
+    /// The url the source resolved to (none for local resources)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// Where the url redirected to when it was downloaded, kept for auditing (none without
+    /// redirects)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redirected_to: Option<Url>,
+    pub hash: String,
+    pub file_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// The resolved url, hash and size of every download of the catalog (`fonts.lock.json`), builds
+/// use the locked urls and fail when a download no longer matches its hash
+pub struct Lockfile {
+    pub version: u32,
+    pub downloads: Vec<LockedDownload>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Lockfile {
+            version: LOCK_VERSION,
+            downloads: vec![],
+        }
+    }
+}
+
+/// A source as it's written in the catalog (e.g. `"./corefonts/arial32.exe"`)
+pub fn describe(source: &SourceDownload) -> String {
+    serde_json::to_string(source).unwrap_or_else(|_| format!("{:?}", source))
+}
+
+/// The lockfile next to a config
+pub fn lock_path(config: &Path) -> PathBuf {
+    config.with_file_name(DEFAULT_LOCK_FILE)
+}
+
+impl Lockfile {
+    /// Loads a lockfile (a missing file results in an empty lockfile)
+    pub async fn load(path: &Path) -> Result<Lockfile, LockError> {
+        let data = match fs::read_to_string(path).await {
+            Ok(data) => data,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Lockfile::default())
+            }
+            Err(error) => return Err(LockError::Read(path.to_owned(), error.to_string())),
+        };
+
+        let lock: Lockfile = serde_json::from_str(&data)
+            .map_err(|e| LockError::Read(path.to_owned(), e.to_string()))?;
+        if lock.version > LOCK_VERSION {
+            return Err(LockError::UnsupportedVersion(path.to_owned(), lock.version));
+        }
+
+        Ok(lock)
+    }
+
+    pub fn find(&self, source: &SourceDownload) -> Option<&LockedDownload> {
+        self.downloads
+            .iter()
+            .find(|download| &download.source == source)
+    }
+
+    /// Records what a source resolved to (replacing its old entry)
+    pub fn record(&mut self, locked: LockedDownload) {
+        match self
+            .downloads
+            .iter_mut()
+            .find(|download| download.source == locked.source)
+        {
+            Some(download) => *download = locked,
+            None => self.downloads.push(locked),
+        }
+    }
+
+    /// Drops the entries of sources the catalog no longer has
+    pub fn retain_sources<'a>(&mut self, sources: impl IntoIterator<Item = &'a SourceDownload>) {
+        let sources: Vec<&SourceDownload> = sources.into_iter().collect();
+        self.downloads
+            .retain(|download| sources.contains(&&download.source));
+    }
+
+    /// Writes the lockfile, sorted by source so it diffs well
+    pub async fn save(&mut self, path: &Path) -> Result<(), LockError> {
+        self.version = LOCK_VERSION;
+        self.downloads
+            .sort_by_cached_key(|download| describe(&download.source));
+
+        let mut data = serde_json::to_string_pretty(self)
+            .map_err(|e| LockError::Write(path.to_owned(), e.to_string()))?;
+        data.push('\n');
+
+        fs::write(path, data)
+            .await
+            .map_err(|e| LockError::Write(path.to_owned(), e.to_string()))
+    }
+}
@@ -0,0 +1,31 @@
+use clap::ValueEnum;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+/// How log lines are written (to stderr)
+pub enum LogFormat {
+    #[default]
+    /// Human readable lines
+    Text,
+    /// One json object per line (for CI systems and log collectors)
+    Json,
+}
+
+/// Installs the global logger
+///
+/// `RUST_LOG` overrides `default_level`. Phases and artifacts are spans, closing one logs how
+/// long it took.
+pub fn init(format: LogFormat, default_level: &str) {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_level));
+
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(FmtSpan::CLOSE)
+        .with_writer(std::io::stderr);
+
+    match format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+}
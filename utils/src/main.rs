@@ -1,91 +1,43 @@
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
-use std::path::PathBuf;
+use std::{
+    collections::HashSet,
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use clap::{Parser, Subcommand};
 use s3::{creds::Credentials, Bucket, Region};
 use semver::Version;
 use serde::Serialize;
-use tokio::fs;
+use tokio::{fs, io::AsyncWriteExt};
+use tracing::Instrument;
 use url::Url;
 
-use crate::utils::{
-    generate_versions_url, upload_version_to_s3, upload_versions_to_s3, VersionInfo,
+use utils::{
+    approval, audit, badge, build, cache, cancel, catalog, coverage,
+    environment::{self, Environment},
+    exit, feed, fetch, gc, github, ipfs, journal, lint, lock,
+    logging::{self, LogFormat},
+    metalink, metrics, notify, outdated, overlay, pipeline, repair, report, scan, strict, types,
+    utils::{
+        generate_metalink_url, generate_versions_url, now, put_metalink, put_version,
+        upload_versions_to_s3, VersionInfo,
+    },
+    vendor, verify,
 };
 
-pub mod build;
-pub mod lint;
-pub mod types;
-pub mod utils;
-
-#[macro_export]
-macro_rules! instalation_struct {
-    {
-        $(#[$source_attr:meta])*
-        $vis:vis struct $source_struct_name:ident, $compiled_struct_name:ident {
-            $(
-                $(#[$variant_attr:meta])*
-                $attr_vis:vis $variant_name:ident: $variant_struct_name:ty,
-            )*
-        }
-    } => {
-        $(#[$source_attr])*
-        $vis struct $source_struct_name {
-            $vis download: SourceDownload,
-            $(
-                $(#[$variant_attr])*
-                $attr_vis $variant_name: $variant_struct_name,
-            )*
-        }
-
-        $(#[$source_attr])*
-        $vis struct $compiled_struct_name {
-            $vis download: Uuid,
-            $(
-                $(#[$variant_attr])*
-                $attr_vis $variant_name: $variant_struct_name,
-            )*
-        }
-    }
-}
-
-#[macro_export]
-macro_rules! instalation_options {
-    {
-        $(#[$source_attr:meta])*
-        $vis:vis enum {
-            $(
-                $(#[$variant_attr:meta])*
-                $variant_name:ident($source_struct_name:ident, $compiled_struct_name:ident)
-            )*
-        }
-    } => {
-        $(#[$source_attr])*
-        $vis enum SourceInstalationType {
-            $(
-                $(#[$variant_attr])*
-                $variant_name($source_struct_name),
-            )*
-        }
-
-        $(#[$source_attr])*
-        $vis enum CompiledInstalationType {
-            $(
-                $(#[$variant_attr])*
-                $variant_name($compiled_struct_name),
-            )*
-        }
-    }
-}
-
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Lints the fonts.json file and updates the database
     Lint {
-        #[clap(short, long)]
-        /// Path to config (fonts.json)
-        config: PathBuf,
+        #[clap(short, long, required = true)]
+        /// Path to config (fonts.json), repeat to merge several catalogs (local resources are
+        /// relative to the base path)
+        config: Vec<PathBuf>,
 
         #[clap(long)]
         /// Path to the base directory
@@ -94,20 +46,94 @@ enum Commands {
         #[clap(long)]
         /// Whether to fix the issues
         fix: bool,
+
+        #[clap(long)]
+        /// Create missing ids from the names (v5 uuids) instead of randomly, forks and overlays
+        /// then get the same ids for the same fonts
+        deterministic_ids: bool,
+
+        #[clap(long, requires = "deterministic_ids", default_value_t = lint::DEFAULT_ID_NAMESPACE)]
+        /// Namespace of the deterministic ids
+        id_namespace: uuid::Uuid,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_CONNECT_TIMEOUT)]
+        /// How long connecting to a host may take (in seconds)
+        connect_timeout: u64,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_READ_TIMEOUT)]
+        /// How long a server may stay silent before the request fails (in seconds)
+        read_timeout: u64,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_MAX_REDIRECTS)]
+        /// Most redirects followed per request
+        max_redirects: usize,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_MAX_BODY_SIZE)]
+        /// Biggest download accepted (in bytes)
+        max_body_size: u64,
+
+        #[clap(long)]
+        /// Path to the http cache (ETag/Last-Modified of external resources)
+        cache_file: Option<PathBuf>,
+
+        #[clap(long, env)]
+        /// Require every font to have a tag starting with this prefix (e.g. "ticket-")
+        required_tag_prefix: Option<String>,
+
+        #[clap(short, long)]
+        /// Path to write a machine-readable (json) report of the errors and their fixes to
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Fail when there are more warnings than this
+        max_warnings: Option<usize>,
+
+        #[clap(long, env)]
+        /// Last published database (versions.json or a version file, url or path), fonts
+        /// renamed since have to keep their old names (fix mode records them)
+        published: Option<String>,
+
+        #[clap(long)]
+        /// Only check the downloads of fonts changed since this git ref (e.g. origin/main), the
+        /// other checks still cover every font
+        changed_since: Option<String>,
+
+        #[clap(long)]
+        /// Fail when the lockfile doesn't have exactly the downloads of the configs (for CI)
+        locked: bool,
+
+        #[clap(long, conflicts_with = "locked")]
+        /// Lock the downloads the lockfile doesn't have yet (and drop the unused ones), so it
+        /// can be committed without publishing
+        update_lock: bool,
+
+        #[clap(long)]
+        /// Path to the lockfile (default: fonts.lock.json next to the first config)
+        lock_file: Option<PathBuf>,
     },
     /// Updates the database
     Update {
-        #[clap(short, long)]
-        /// Path to config (fonts.json)
-        config: PathBuf,
+        #[clap(short, long, required_unless_present = "from_vendor")]
+        /// Path to config (fonts.json), repeat to merge several catalogs (local resources are
+        /// relative to the base path)
+        config: Vec<PathBuf>,
 
         #[clap(long)]
         /// Version to insert
         version: Version,
 
-        #[clap(long)]
+        #[clap(long, required_unless_present = "from_vendor")]
         /// Base path
-        base_path: PathBuf,
+        base_path: Option<PathBuf>,
+
+        #[clap(long, conflicts_with_all = ["config", "base_path", "lock_file", "cache_file", "refresh_lock"])]
+        /// Build from a bundle made with `export vendor` instead of the configs, without network
+        /// access (only the bucket is contacted): every download has to be bundled or locked
+        from_vendor: Option<PathBuf>,
 
         #[clap(long, env)]
         /// Base access S3 url
@@ -128,228 +154,2372 @@ enum Commands {
         /// S3 bucket
         #[clap(long, env)]
         bucket: String,
-    },
-}
 
-#[derive(Parser)]
-#[command(
-    author = "WineFonts Team",
-    about = "Lints the fonts.json file and updates the database"
-)]
-struct Cli {
-    #[command(subcommand)]
-    command: Commands,
-}
+        #[clap(long, env, value_enum, default_value_t = Environment::Production)]
+        /// The environment to publish to (staging uses the staging bucket and base url)
+        environment: Environment,
 
-async fn file_from_path(path: PathBuf) -> Option<types::Source> {
-    // Check valid file
-    let file_contents = match fs::read_to_string(path).await {
-        Ok(file) => file,
-        Err(error) => {
-            error!("Failed to read file: {}", error);
-            return None;
-        }
-    };
+        #[clap(long, env)]
+        /// S3 bucket of the staging environment
+        staging_bucket: Option<String>,
 
-    // Parse the file
-    let json = match serde_json::from_str::<types::Source>(&file_contents) {
-        Ok(json) => json,
-        Err(error) => {
-            error!("Failed to parse file: {}", error);
-            return None;
-        }
-    };
+        #[clap(long, env)]
+        /// Base access url of the staging environment
+        staging_base_url: Option<Url>,
 
-    Some(json)
-}
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
 
-#[tokio::main]
-async fn main() {
-    // Dotenv
-    dotenv::dotenv().ok();
+        #[clap(long, default_value_t = fetch::DEFAULT_CONNECT_TIMEOUT)]
+        /// How long connecting to a host may take (in seconds)
+        connect_timeout: u64,
 
-    // Check if the log level is set in the env (if not, default to info)
-    if std::env::var("RUST_LOG").is_err() {
-        std::env::set_var("RUST_LOG", "info");
-    }
-    // Init the logger
-    pretty_env_logger::init();
-    // CLI parser
-    let parser = Cli::parse();
+        #[clap(long, default_value_t = fetch::DEFAULT_READ_TIMEOUT)]
+        /// How long a server may stay silent before the request fails (in seconds)
+        read_timeout: u64,
 
-    match parser.command {
-        Commands::Lint {
-            config,
-            base_path,
-            fix,
-        } => {
-            let json = match file_from_path(config.clone()).await {
-                Some(json) => json,
-                None => return,
-            };
+        #[clap(long, default_value_t = fetch::DEFAULT_MAX_REDIRECTS)]
+        /// Most redirects followed per request
+        max_redirects: usize,
 
-            // If errors are found, print them and exit
-            let (new_json, errors) = lint::lint(
-                &json,
-                base_path,
-                match fix {
-                    true => lint::LintMode::Fix,
-                    false => lint::LintMode::Check,
-                },
-            )
-            .await;
-            if errors.len() > 0 {
-                for error in &errors {
-                    error!("{}", error);
-                }
-            }
+        #[clap(long, default_value_t = fetch::DEFAULT_MAX_BODY_SIZE)]
+        /// Biggest download accepted (in bytes)
+        max_body_size: u64,
 
-            if errors.len() > 0 {
-                warn!("Found {} unresolved errors", errors.len());
-            } else {
-                info!("No errors found");
-            }
+        #[clap(long)]
+        /// Path to the http cache (ETag/Last-Modified of external resources)
+        cache_file: Option<PathBuf>,
 
-            // Write the new json
-            if fix {
-                let mut buf = Vec::new();
-                let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
-                let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
-                match new_json.serialize(&mut ser) {
-                    Ok(_) => info!("Serialized json"),
-                    Err(error) => {
-                        error!("Failed to serialize json: {}", error);
-                        std::process::exit(1);
-                    }
-                }
+        #[clap(long)]
+        /// Path to the lockfile (default: fonts.lock.json next to the first config)
+        lock_file: Option<PathBuf>,
 
-                let new_json_string = match String::from_utf8(buf) {
-                    Ok(string) => string,
-                    Err(error) => {
-                        error!("Failed to convert json to string: {}", error);
-                        std::process::exit(1);
-                    }
-                };
+        #[clap(long)]
+        /// Resolve and hash every download again instead of honoring the lockfile (it's rewritten)
+        refresh_lock: bool,
 
-                // Write the new json
-                match fs::write(config, new_json_string).await {
-                    Ok(_) => info!("Wrote new json"),
-                    Err(error) => {
-                        error!("Failed to write new json: {}", error);
-                        std::process::exit(1);
-                    }
-                }
-            }
-        }
-        Commands::Update {
-            config,
-            base_path,
-            endpoint,
-            access_key_id,
-            secret_access_key,
-            bucket,
-            base_url,
-            version,
-        } => {
-            // Get the json
-            let json = match file_from_path(config.clone()).await {
-                Some(json) => json,
-                None => return,
-            };
+        #[clap(long, env)]
+        /// Require every font to have a tag starting with this prefix (e.g. "ticket-")
+        required_tag_prefix: Option<String>,
 
-            // Check for any lint errors
-            let (_, errors) = lint::lint(&json, base_path.clone(), lint::LintMode::Check).await;
-            if errors.len() > 0 {
-                for error in &errors {
-                    error!("{}", error);
-                }
-                warn!("Found {} unresolved errors", errors.len());
-                error!("Please fix any unresolved errors before updating the database");
-                std::process::exit(1);
-            }
+        #[clap(long)]
+        /// Fail when the lint finds more warnings than this
+        max_warnings: Option<usize>,
 
-            info!("No errors found");
+        #[clap(long, env)]
+        /// Command every artifact is run through before publishing (e.g. "clamscan --no-summary"),
+        /// the artifact path is appended and a non-zero exit fails the update
+        scan_command: Option<String>,
 
-            let region = Region::Custom {
-                region: "us-east-1".to_string(),
-                endpoint: endpoint.to_string(),
-            };
+        #[clap(long, env)]
+        /// Upstream database (versions.json or a version file, url or path) the catalogs are
+        /// merged into as an overlay
+        upstream: Option<String>,
 
-            let creds = match Credentials::new(
-                Some(&access_key_id),
-                Some(&secret_access_key),
-                None,
-                None,
-                None,
-            ) {
-                Ok(creds) => creds,
-                Err(error) => {
-                    error!("Failed to create credentials: {}", error);
-                    std::process::exit(1);
-                }
-            };
+        #[clap(long)]
+        /// Skip uploading artifacts whose content is already in the bucket (e.g. after an
+        /// interrupted run)
+        skip_unchanged_upload: bool,
 
-            // Get the s3 client
-            let s3 = match Bucket::new(&bucket, region, creds) {
-                Ok(s3) => s3,
-                Err(error) => {
-                    error!("Failed to create s3 client: {}", error);
-                    std::process::exit(1);
-                }
-            };
+        #[clap(long)]
+        /// Rebuild downloadables.json and versions.json from the bucket contents when they are
+        /// corrupt, and versions.json when it is missing (the corrupt files are backed up first, a
+        /// missing downloadables.json is always rebuilt)
+        rebuild_index: bool,
 
-            // Get the downloadables
-            let downloadables = utils::grab_downloadables_from_s3(&s3).await;
+        #[clap(long)]
+        /// Path to write a machine-readable (json) publish report to
+        report: Option<PathBuf>,
 
-            // Build the database
-            let (new, file) = match build::build(
-                version.clone(),
-                &json,
-                base_url.clone(),
-                base_path.clone(),
-                downloadables.clone(),
-            )
-            .await
-            {
-                Ok(built) => built,
-                Err(error) => {
-                    error!("Failed to build database: {}", error);
-                    std::process::exit(1);
-                }
-            };
+        #[clap(long)]
+        /// Path to write the publish metrics to (in the Prometheus text format)
+        metrics_file: Option<PathBuf>,
+
+        #[clap(long, env, value_delimiter = ',', hide_env_values = true)]
+        /// Webhooks (e.g. Discord, Slack or Matrix hookshot) the publish is posted to as json
+        /// (their urls are secrets, only their hosts are logged)
+        webhook: Vec<Url>,
 
-            // Upload the database
-            utils::upload_files_to_s3(&s3, &base_url, base_path, downloadables, new).await;
+        #[clap(long, env)]
+        /// Also publish to the releases of this GitHub repo (owner/name)
+        github_repo: Option<String>,
 
-            // New UUID
-            let new_uuid = uuid::Uuid::new_v4();
+        #[clap(long, env)]
+        /// GitHub token (needs contents write access to the repo)
+        github_token: Option<String>,
 
-            // Serialize the file
-            let file = match serde_json::to_vec(&file) {
-                Ok(file) => file,
-                Err(error) => {
-                    error!("Failed to serialize file: {}", error);
-                    std::process::exit(1);
-                }
-            };
+        #[clap(long)]
+        /// The release tag the version file is attached to (default: v<version>)
+        github_tag: Option<String>,
 
-            // Upload the file
-            upload_version_to_s3(&s3, new_uuid, &file).await;
+        #[clap(long, default_value = "versions.json")]
+        /// Path of the versions.json kept in the GitHub repo
+        github_versions_path: String,
 
-            // Get version list
-            let mut versions = utils::grab_versions_from_s3(&s3).await;
+        #[clap(long)]
+        /// Branch the GitHub versions.json is committed to (default: the default branch)
+        github_branch: Option<String>,
 
-            // Add the new version
-            versions.push(VersionInfo {
-                id: new_uuid,
-                version,
-                download_url: generate_versions_url(&base_url, &new_uuid),
-                hash: sha256::digest(&file),
-                file_size: file.len() as u64,
-            });
+        #[clap(long, env, default_value = github::DEFAULT_API_URL)]
+        /// GitHub api url (for GitHub Enterprise)
+        github_api_url: Url,
 
-            // Upload the versions
-            upload_versions_to_s3(&s3, versions).await;
+        #[clap(long)]
+        /// Also attach the newly uploaded artifacts to the GitHub release
+        github_artifacts: bool,
+
+        #[clap(long, env)]
+        /// RPC api of an IPFS node (e.g. http://127.0.0.1:5001), new artifacts and the version
+        /// file are added to it and listed as mirrors
+        ipfs_api: Option<Url>,
+
+        #[clap(long, env)]
+        /// IPFS gateway the mirror urls point to (e.g. https://ipfs.io/), ipfs:// urls are used
+        /// without one
+        ipfs_gateway: Option<Url>,
+
+        #[clap(long, env)]
+        /// IPFS Pinning Service API endpoint everything added is also pinned to
+        ipfs_pinning_service: Option<Url>,
+
+        #[clap(long, env)]
+        /// Access token of the pinning service
+        ipfs_pinning_token: Option<String>,
+
+        #[clap(long, env)]
+        /// Publish a metalink (url, mirrors and hash) for public downloads at least this big (in
+        /// bytes)
+        metalink_threshold: Option<u64>,
+
+        #[clap(long, default_value = journal::DEFAULT_JOURNAL_FILE)]
+        /// Where the plan and progress of the update are recorded (removed once it's done)
+        journal_file: PathBuf,
+
+        #[clap(long)]
+        /// Finish the interrupted update recorded in the journal instead of starting over
+        resume: bool,
+
+        #[clap(long, default_value_t = pipeline::DEFAULT_CONCURRENCY)]
+        /// Most uploads running at once
+        parallel_uploads: usize,
+
+        #[clap(long, default_value_t = pipeline::DEFAULT_RETRIES)]
+        /// Times a failed upload is tried again before the update stops (it can be resumed)
+        upload_retries: u32,
+
+        #[clap(long)]
+        /// Days a pre-release version is kept, `gc` removes it (and the downloads only it uses)
+        /// once it expires
+        ttl: Option<u64>,
+
+        #[clap(long, env)]
+        /// Maintainers allowed to approve releases (`<name> <public key>` per line), when set
+        /// nothing is published without an approval from one of them. An `approval-keys.txt`
+        /// next to the first config always applies, `--from-vendor` needs this (the keys in the
+        /// bundle aren't trusted)
+        approval_keys: Option<PathBuf>,
+
+        #[clap(long, default_value = approval::DEFAULT_APPROVAL_FILE)]
+        /// Approval of the configs' git commit by a second maintainer (made with `approve`)
+        approval: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_PUBLISHER_KEY")]
+        /// Your private key (made with `approval-key`, listed in the trusted keys), needed when
+        /// releases are approved
+        publisher_key: Option<PathBuf>,
+    },
+    /// Generates a key for approving releases
+    ApprovalKey {
+        #[clap(short, long)]
+        /// Where to write the private key (keep it secret)
+        output: PathBuf,
+    },
+    /// Signs off the configs' git commit for another maintainer to publish
+    Approve {
+        #[clap(short, long, required_unless_present = "bundle")]
+        /// Path to config (fonts.json), repeat for several catalogs (they have to be committed)
+        config: Vec<PathBuf>,
+
+        #[clap(long, conflicts_with = "config")]
+        /// Approve a bundle made with `export vendor` instead (for `update --from-vendor`)
+        bundle: Option<PathBuf>,
+
+        #[clap(long, env = "WINEFONTS_APPROVAL_KEY")]
+        /// Private key (made with `approval-key`)
+        key: PathBuf,
+
+        #[clap(long, env = "WINEFONTS_ACTOR")]
+        /// Your name in the trusted keys
+        name: String,
+
+        #[clap(short, long, default_value = approval::DEFAULT_APPROVAL_FILE)]
+        /// Where to write the approval
+        output: PathBuf,
+    },
+    /// Checks a compiled database (a version file, path or url) for internal consistency
+    ValidateCompiled {
+        /// Path or url of the version file
+        location: String,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+    },
+    /// Checks pinned GitHub release downloads for newer upstream releases
+    Outdated {
+        #[clap(short, long, required = true)]
+        /// Path to config (fonts.json), repeat to merge several catalogs (local resources are
+        /// relative to the base path)
+        config: Vec<PathBuf>,
+
+        #[clap(long, default_value_t = fetch::DEFAULT_REQUEST_INTERVAL)]
+        /// Minimum delay between requests to the same host (in milliseconds)
+        request_interval: u64,
+
+        #[clap(short, long)]
+        /// Path to write a machine-readable (json) report to
+        output: Option<PathBuf>,
+    },
+    /// Reports which families of application requirement profiles the catalog provides
+    Coverage {
+        #[clap(short, long, required = true)]
+        /// Path to config (fonts.json), repeat to merge several catalogs
+        config: Vec<PathBuf>,
+
+        #[clap(short, long, value_parser = parse_profile)]
+        /// Profiles to check (core-web, office-2016, adobe-cc), every profile if not given
+        profile: Vec<&'static coverage::Profile>,
+
+        #[clap(short, long)]
+        /// Path to write a machine-readable (json) report to
+        output: Option<PathBuf>,
+    },
+    /// Exports the catalog for use elsewhere
+    Export {
+        #[command(subcommand)]
+        format: ExportCommands,
+    },
+    /// Cross-checks downloadables.json and versions.json against the bucket and fixes them
+    Repair {
+        #[clap(long, env)]
+        /// Base access S3 url
+        base_url: Url,
+
+        /// S3 endpoint
+        #[clap(long, env)]
+        endpoint: String,
+
+        /// S3 access key id
+        #[clap(long, env)]
+        access_key_id: String,
+
+        /// S3 secret access key
+        #[clap(long, env)]
+        secret_access_key: String,
+
+        /// S3 bucket
+        #[clap(long, env)]
+        bucket: String,
+
+        #[clap(long, env, value_enum, default_value_t = Environment::Production)]
+        /// The environment to publish to (staging uses the staging bucket and base url)
+        environment: Environment,
+
+        #[clap(long, env)]
+        /// S3 bucket of the staging environment
+        staging_bucket: Option<String>,
+
+        #[clap(long, env)]
+        /// Base access url of the staging environment
+        staging_base_url: Option<Url>,
+
+        #[clap(long)]
+        /// Trust the sha256 the downloads' metadata records instead of hashing every download
+        /// (faster, but an object replaced with its metadata kept goes unnoticed)
+        trust_metadata: bool,
+
+        #[clap(long)]
+        /// Base path of the resources, downloads that don't match are uploaded again from it
+        base_path: Option<PathBuf>,
+
+        #[clap(short, long)]
+        /// Apply every fix without asking
+        yes: bool,
+    },
+    /// Removes expired pre-release versions and the downloads only they use
+    Gc {
+        #[clap(long, env)]
+        /// Base access S3 url
+        base_url: Url,
+
+        /// S3 endpoint
+        #[clap(long, env)]
+        endpoint: String,
+
+        /// S3 access key id
+        #[clap(long, env)]
+        access_key_id: String,
+
+        /// S3 secret access key
+        #[clap(long, env)]
+        secret_access_key: String,
+
+        /// S3 bucket
+        #[clap(long, env)]
+        bucket: String,
+
+        #[clap(long, env, value_enum, default_value_t = Environment::Production)]
+        /// The environment to publish to (staging uses the staging bucket and base url)
+        environment: Environment,
+
+        #[clap(long, env)]
+        /// S3 bucket of the staging environment
+        staging_bucket: Option<String>,
+
+        #[clap(long, env)]
+        /// Base access url of the staging environment
+        staging_base_url: Option<Url>,
+
+        #[clap(long)]
+        /// Only list what would be removed
+        dry_run: bool,
+    },
+    /// Copies a version from staging to production once its files check out
+    Promote {
+        /// The version to promote
+        version: Version,
+
+        #[clap(long, env)]
+        /// Base access S3 url
+        base_url: Url,
+
+        /// S3 endpoint
+        #[clap(long, env)]
+        endpoint: String,
+
+        /// S3 access key id
+        #[clap(long, env)]
+        access_key_id: String,
+
+        /// S3 secret access key
+        #[clap(long, env)]
+        secret_access_key: String,
+
+        /// S3 bucket
+        #[clap(long, env)]
+        bucket: String,
+
+        #[clap(long, env)]
+        /// S3 bucket of the staging environment
+        staging_bucket: String,
+
+        #[clap(long, env)]
+        /// Base access url of the staging environment
+        staging_base_url: Url,
+    },
+}
+
+#[derive(Subcommand)]
+enum ExportCommands {
+    /// Bundles the configs, the lockfile and the local resources into a tarball (with a manifest
+    /// of their hashes) for air-gapped builds and archival
+    Vendor {
+        #[clap(short, long, required = true)]
+        /// Path to config (fonts.json), repeat to merge several catalogs (local resources are
+        /// relative to the base path)
+        config: Vec<PathBuf>,
+
+        #[clap(long)]
+        /// Base path
+        base_path: PathBuf,
+
+        #[clap(long)]
+        /// Path to the lockfile (default: fonts.lock.json next to the first config)
+        lock_file: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Path to the http cache, bundled with the rest (the locked external resources are
+        /// downloaded and bundled themselves)
+        cache_file: Option<PathBuf>,
+
+        #[clap(long)]
+        /// Also bundle the signed (not redistributable) local resources
+        include_signed: bool,
+
+        #[clap(long)]
+        /// The published version the bundle is for (recorded in the manifest)
+        version: Option<Version>,
+
+        #[clap(short, long)]
+        /// Where to write the tarball
+        output: PathBuf,
+    },
+}
+
+#[derive(Parser)]
+#[command(
+    author = "WineFonts Team",
+    about = "Lints the fonts.json file and updates the database"
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Commands,
+
+    #[clap(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    /// How logs are written
+    log_format: LogFormat,
+}
+
+/// The commit a bundle was made from, exits if it didn't record one
+fn bundle_commit(bundle: &Path, manifest: &vendor::VendorManifest) -> String {
+    match &manifest.commit {
+        Some(commit) => commit.clone(),
+        None => {
+            error!(
+                "{} doesn't record a commit, make it from committed configs to have it approved",
+                bundle.display()
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The hash of a bundle, exits if it can't be read
+fn bundle_hash(bundle: &Path) -> String {
+    match vendor::bundle_hash(bundle) {
+        Ok(hash) => hash,
+        Err(error) => {
+            error!("{}", error);
+            std::process::exit(exit::IO);
+        }
+    }
+}
+
+/// Downloads the locked external resources (checked against the lockfile) to bundle them
+async fn locked_externals(lock_file: Option<&Path>) -> Vec<Vec<u8>> {
+    let lock = match lock_file {
+        Some(lock_file) => match lock::Lockfile::load(lock_file).await {
+            Ok(lock) => lock,
+            Err(error) => {
+                error!("{}", error);
+                std::process::exit(exit::IO);
+            }
+        },
+        None => return vec![],
+    };
+    let fetcher = fetcher_from_interval(fetch::DEFAULT_REQUEST_INTERVAL);
+
+    let mut externals = vec![];
+    for locked in &lock.downloads {
+        let url = match &locked.url {
+            Some(url) => url,
+            None => continue,
+        };
+
+        info!("Downloading {}", url);
+        let data = match fetcher.get(url).await {
+            Ok(response) if response.status().is_success() => fetcher.read_body(response).await,
+            Ok(response) => {
+                error!("Failed to download {}: {}", url, response.status());
+                std::process::exit(exit::NETWORK);
+            }
+            Err(error) => Err(error),
+        };
+        let data = match data {
+            Ok(data) => data,
+            Err(error) => {
+                error!("Failed to download {}: {}", url, error);
+                std::process::exit(exit::NETWORK);
+            }
+        };
+        if data.len() as u64 != locked.file_size || sha256::digest(&data[..]) != locked.hash {
+            error!(
+                "{} no longer matches the lockfile, refresh it before bundling",
+                url
+            );
+            std::process::exit(exit::IO);
+        }
+        externals.push(data);
+    }
+
+    externals
+}
+
+/// Unpacks a vendored bundle next to the journal (`<journal>.vendor`), exits if it's invalid
+fn unpack_vendor(bundle: &Path, journal_file: &Path) -> (PathBuf, vendor::VendorManifest) {
+    let directory = journal_file.with_extension("vendor");
+    if directory.exists() {
+        if let Err(error) = std::fs::remove_dir_all(&directory) {
+            error!("Failed to remove {}: {}", directory.display(), error);
+            std::process::exit(exit::IO);
+        }
+    }
+
+    let manifest = match vendor::unpack(bundle, &directory) {
+        Ok(manifest) => manifest,
+        Err(error) => {
+            error!("{}", error);
+            std::process::exit(exit::IO);
+        }
+    };
+    if !manifest.excluded.is_empty() {
+        for excluded in &manifest.excluded {
+            error!("The bundle doesn't have the signed resource {}", excluded);
+        }
+        error!("Make the bundle with --include-signed to build from it");
+        std::process::exit(exit::IO);
+    }
+
+    info!(
+        "Unpacked {} ({} files) into {}",
+        bundle.display(),
+        manifest.files.len(),
+        directory.display()
+    );
+    (directory, manifest)
+}
+
+/// Loads every config as a catalog
+async fn catalogs_from_paths(paths: Vec<PathBuf>) -> Option<Vec<catalog::Catalog>> {
+    let mut catalogs = vec![];
+    for path in paths {
+        let source = file_from_path(path.clone()).await?;
+        catalogs.push(catalog::Catalog { path, source });
+    }
+
+    if !catalog::same_namespace(&catalogs) {
+        error!("Catalogs with different namespaces can't be merged");
+        return None;
+    }
+
+    Some(catalogs)
+}
+
+/// Whether the warnings exceed `--max-warnings` (logs it if they do)
+fn too_many_warnings(warnings: usize, max_warnings: Option<usize>) -> bool {
+    match max_warnings {
+        Some(max) if warnings > max => {
+            error!(
+                "Found {} warnings, more than the {} allowed (--max-warnings)",
+                warnings, max
+            );
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Logs lint errors (or warnings) with how to fix them
+fn log_lint_errors(errors: &[lint::LintErrors]) {
+    for error in errors {
+        match (error.is_warning(), error.suggestion()) {
+            (true, Some(suggestion)) => warn!("{} (help: {})", error, suggestion),
+            (true, None) => warn!("{}", error),
+            (false, Some(suggestion)) => error!("{} (help: {})", error, suggestion),
+            (false, None) => error!("{}", error),
+        }
+    }
+}
+
+/// Lint errors only the individual catalogs can have (the merged source is always sorted)
+fn catalog_order_errors(catalogs: &[catalog::Catalog]) -> Vec<lint::LintErrors> {
+    match catalogs.len() {
+        1 => vec![],
+        _ => catalogs
+            .iter()
+            .flat_map(|catalog| lint::check_order(&catalog.source))
+            .collect(),
+    }
+}
+
+async fn write_source(path: &PathBuf, source: &types::Source) {
+    let value = match serde_json::to_value(source) {
+        Ok(value) => value,
+        Err(error) => {
+            error!("Failed to serialize json: {}", error);
+            std::process::exit(exit::IO);
+        }
+    };
+
+    // Keep the key order of the file we're rewriting
+    let value = match fs::read(path)
+        .await
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+    {
+        Some(original) => catalog::reorder_like(&original, value),
+        None => value,
+    };
+
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut ser = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    match value.serialize(&mut ser) {
+        Ok(_) => info!("Serialized json"),
+        Err(error) => {
+            error!("Failed to serialize json: {}", error);
+            std::process::exit(exit::IO);
+        }
+    }
+
+    let new_json_string = match String::from_utf8(buf) {
+        Ok(string) => string,
+        Err(error) => {
+            error!("Failed to convert json to string: {}", error);
+            std::process::exit(exit::IO);
+        }
+    };
+
+    // Write the new json
+    match fs::write(path, new_json_string).await {
+        Ok(_) => info!("Wrote new json ({})", path.display()),
+        Err(error) => {
+            error!("Failed to write new json: {}", error);
+            std::process::exit(exit::IO);
+        }
+    }
+}
+
+async fn file_from_path(path: PathBuf) -> Option<types::Source> {
+    // Check valid file
+    let file_contents = match fs::read_to_string(path).await {
+        Ok(file) => file,
+        Err(error) => {
+            error!("Failed to read file: {}", error);
+            return None;
+        }
+    };
+
+    // Parse the file
+    let json = match strict::from_str::<types::Source>(&file_contents) {
+        Ok(json) => json,
+        Err(error) => {
+            error!("Failed to parse file: {}", error);
+            return None;
+        }
+    };
+
+    Some(json)
+}
+
+/// The fonts changed since a git revision of the catalogs
+fn changed_fonts_since(
+    catalogs: &[catalog::Catalog],
+    merged: &types::Source,
+    revision: &str,
+) -> HashSet<String> {
+    let mut old = vec![];
+    for catalog in catalogs {
+        match catalog::at_revision(&catalog.path, revision) {
+            Ok(Some(source)) => old.push(catalog::Catalog {
+                path: catalog.path.clone(),
+                source,
+            }),
+            Ok(None) => {}
+            Err(error) => {
+                error!(
+                    "Failed to read {} at {}: {}",
+                    catalog.path.display(),
+                    revision,
+                    error
+                );
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let changed = catalog::changed_fonts(&catalog::merge(&old), merged);
+    info!(
+        "{} fonts changed since {}, only their downloads are checked",
+        changed.len(),
+        revision
+    );
+    changed
+}
+
+/// The custom lint rules enabled from the command line
+fn lint_rules(required_tag_prefix: Option<String>) -> lint::LintRules {
+    let mut rules = lint::LintRules::default();
+    if let Some(prefix) = required_tag_prefix {
+        rules.register(Box::new(lint::RequiredTagRule { prefix }));
+    }
+    rules
+}
+
+fn fetcher_from_interval(request_interval: u64) -> fetch::Fetcher {
+    fetcher_with_limits(request_interval, fetch::Limits::default())
+}
+
+fn fetcher_with_limits(request_interval: u64, limits: fetch::Limits) -> fetch::Fetcher {
+    match fetch::Fetcher::with_limits(Duration::from_millis(request_interval), limits) {
+        Ok(fetcher) => fetcher,
+        Err(error) => {
+            error!("Failed to create http client: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The bucket and base url of an environment, exits if staging isn't configured
+fn environment_target(
+    environment: Environment,
+    bucket: String,
+    base_url: Url,
+    staging_bucket: Option<String>,
+    staging_base_url: Option<Url>,
+) -> (String, Url) {
+    match environment {
+        Environment::Production => (bucket, base_url),
+        Environment::Staging => match (staging_bucket, staging_base_url) {
+            (Some(bucket), Some(base_url)) => (bucket, base_url),
+            _ => {
+                error!("The staging environment needs --staging-bucket and --staging-base-url");
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// The trusted keys publishing has to be approved by: the repository's (next to the first config)
+/// or the given ones, the repository's can't be replaced. A vendored bundle needs the given ones,
+/// the keys it ships can't approve it
+fn trusted_keys(configs: &[PathBuf], keys: Option<PathBuf>, vendored: bool) -> Option<PathBuf> {
+    if vendored {
+        if keys.is_none() {
+            error!("Publishing a vendored bundle needs the trusted keys, pass --approval-keys");
+            std::process::exit(1);
+        }
+        return keys;
+    }
+
+    let repository = configs
+        .first()
+        .and_then(|config| config.parent())
+        .map(|directory| directory.join(approval::DEFAULT_APPROVAL_KEYS_FILE));
+    let repository = match repository {
+        Some(path) if path.is_file() => Some(path),
+        // Deleting the committed keys doesn't turn the approvals off
+        Some(path) if approval::is_committed(&path) => {
+            error!(
+                "The repository's trusted keys ({}) are committed but missing",
+                path.display()
+            );
+            std::process::exit(1);
+        }
+        _ => None,
+    };
+
+    match (repository, keys) {
+        (Some(repository), Some(keys)) if repository != keys => {
+            error!(
+                "The repository's trusted keys ({}) can't be replaced with --approval-keys",
+                repository.display()
+            );
+            std::process::exit(1);
+        }
+        (Some(repository), _) => Some(repository),
+        (None, keys) => keys,
+    }
+}
+
+/// Exits unless a trusted maintainer (other than the publisher) approved the configs' commit, or
+/// the vendored bundle being built (by its hash and the commit it records)
+async fn check_approval(
+    configs: &[PathBuf],
+    keys: &PathBuf,
+    approval: &PathBuf,
+    publisher_key: Option<&PathBuf>,
+    bundle: Option<(&Path, &vendor::VendorManifest)>,
+) {
+    // The publisher is whoever holds the key, not whatever name the environment says
+    let publisher_key = match publisher_key {
+        Some(path) => match fs::read_to_string(path).await {
+            Ok(key) => match approval::public_key(&key) {
+                Ok(key) => key,
+                Err(error) => {
+                    error!("{}: {}", path.display(), error);
+                    std::process::exit(1);
+                }
+            },
+            Err(error) => {
+                error!("Failed to read {}: {}", path.display(), error);
+                std::process::exit(1);
+            }
+        },
+        None => {
+            error!("Publishing needs an approval, pass your key with --publisher-key");
+            std::process::exit(1);
+        }
+    };
+
+    let keys_path = keys;
+    let keys = match fs::read_to_string(keys).await {
+        Ok(data) => data,
+        Err(error) => {
+            error!("Failed to read {}: {}", keys.display(), error);
+            std::process::exit(1);
+        }
+    };
+    let keys = match approval::TrustedKeys::parse(&keys) {
+        Ok(keys) => keys,
+        Err(error) => {
+            error!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let approval: approval::Approval = match fs::read(approval).await {
+        Ok(data) => match serde_json::from_slice(&data) {
+            Ok(approval) => approval,
+            Err(error) => {
+                error!("Failed to parse {}: {}", approval.display(), error);
+                std::process::exit(1);
+            }
+        },
+        Err(error) => {
+            error!(
+                "Publishing needs an approval ({}): {}",
+                approval.display(),
+                error
+            );
+            std::process::exit(1);
+        }
+    };
+
+    // The unpacked bundle isn't in git, it's approved by what it is
+    let approved = match bundle {
+        Some((bundle, manifest)) => (bundle_commit(bundle, manifest), Some(bundle_hash(bundle))),
+        // The repository's trusted keys have to be committed with the configs
+        None => match approval::commit_of(
+            &configs
+                .iter()
+                .cloned()
+                .chain(
+                    configs
+                        .first()
+                        .map(|config| config.with_file_name(approval::DEFAULT_APPROVAL_KEYS_FILE))
+                        .filter(|repository| repository == keys_path),
+                )
+                .collect::<Vec<_>>(),
+        ) {
+            Ok(commit) => (commit, None),
+            Err(error) => {
+                error!("{}", error);
+                std::process::exit(1);
+            }
+        },
+    };
+    let result = keys.verify(
+        &approval,
+        &approved.0,
+        approved.1.as_deref(),
+        &publisher_key,
+    );
+    match result {
+        Ok(_) => info!(
+            "Approved by {} (commit {})",
+            approval.approver, approval.commit
+        ),
+        Err(error) => {
+            error!("{}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reports what an interrupted update did and didn't publish, then exits (with 130, like a
+/// shell does after SIGINT)
+fn interrupted(published: &[String], pending: &[String]) -> ! {
+    warn!("The update was interrupted");
+    if published.is_empty() {
+        warn!("Nothing was published");
+    }
+    for name in published {
+        warn!("Published: {}", name);
+    }
+    for name in pending {
+        warn!("Not published: {}", name);
+    }
+    if !published.is_empty() {
+        warn!("Run the update again with --resume to finish it");
+    }
+
+    std::process::exit(130);
+}
+
+/// Parses a requirement profile name
+fn parse_profile(name: &str) -> Result<&'static coverage::Profile, String> {
+    coverage::find_profile(name).ok_or_else(|| {
+        let names: Vec<&str> = coverage::PROFILES.iter().map(|p| p.name).collect();
+        format!("Unknown profile (known: {})", names.join(", "))
+    })
+}
+
+/// Writes a machine-readable (json) report, exits on failure
+async fn write_report<T: Serialize>(path: &PathBuf, report: &T) {
+    let data = match serde_json::to_vec_pretty(report) {
+        Ok(data) => data,
+        Err(error) => {
+            error!("Failed to serialize report: {}", error);
+            std::process::exit(exit::IO);
+        }
+    };
+
+    match fs::write(path, data).await {
+        Ok(_) => info!("Wrote report to {}", path.display()),
+        Err(error) => {
+            error!("Failed to write report: {}", error);
+            std::process::exit(exit::IO);
+        }
+    }
+}
+
+fn bucket_from_args(
+    endpoint: &str,
+    access_key_id: &str,
+    secret_access_key: &str,
+    bucket: &str,
+) -> Bucket {
+    let region = Region::Custom {
+        region: "us-east-1".to_string(),
+        endpoint: endpoint.to_string(),
+    };
+
+    let creds = match Credentials::new(
+        Some(access_key_id),
+        Some(secret_access_key),
+        None,
+        None,
+        None,
+    ) {
+        Ok(creds) => creds,
+        Err(error) => {
+            error!("Failed to create credentials: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    // Get the s3 client
+    match Bucket::new(bucket, region, creds) {
+        Ok(s3) => s3,
+        Err(error) => {
+            error!("Failed to create s3 client: {}", error);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Asks a yes/no question on the terminal (defaults to no)
+fn confirm(question: &str) -> bool {
+    print!("{} [y/N] ", question);
+    if std::io::stdout().flush().is_err() {
+        return false;
+    }
+
+    let mut answer = String::new();
+    match std::io::stdin().read_line(&mut answer) {
+        Ok(_) => matches!(answer.trim(), "y" | "Y" | "yes"),
+        Err(_) => false,
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    // Dotenv
+    dotenv::dotenv().ok();
+
+    // CLI parser
+    let parser = Cli::parse();
+
+    // Init the logger (the level defaults to info unless RUST_LOG is set)
+    logging::init(parser.log_format, "info");
+
+    match parser.command {
+        Commands::Lint {
+            config,
+            base_path,
+            fix,
+            deterministic_ids,
+            id_namespace,
+            request_interval,
+            connect_timeout,
+            read_timeout,
+            max_redirects,
+            max_body_size,
+            cache_file,
+            required_tag_prefix,
+            output,
+            max_warnings,
+            published,
+            changed_since,
+            locked,
+            update_lock,
+            lock_file,
+        } => {
+            let lock_file = lock_file.unwrap_or_else(|| lock::lock_path(&config[0]));
+            let catalogs = match catalogs_from_paths(config).await {
+                Some(catalogs) => catalogs,
+                None => std::process::exit(exit::IO),
+            };
+            let json = catalog::merge(&catalogs);
+            let fetcher = fetcher_with_limits(
+                request_interval,
+                fetch::Limits {
+                    connect_timeout: Duration::from_secs(connect_timeout),
+                    read_timeout: Duration::from_secs(read_timeout),
+                    max_redirects,
+                    max_body_size,
+                },
+            );
+            let cache = cache::HttpCache::load(cache_file).await;
+            let changed_fonts =
+                changed_since.map(|revision| changed_fonts_since(&catalogs, &json, &revision));
+            let published = match published {
+                Some(location) => match overlay::load_upstream(&location, &fetcher).await {
+                    Ok(published) => Some(published),
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(match Url::parse(&location) {
+                            Ok(_) => exit::NETWORK,
+                            Err(_) => exit::IO,
+                        });
+                    }
+                },
+                None => None,
+            };
+
+            // If errors are found, print them and exit
+            let (new_json, mut errors) = lint::lint(
+                &json,
+                base_path.clone(),
+                match fix {
+                    true => lint::LintMode::Fix,
+                    false => lint::LintMode::Check,
+                },
+                match deterministic_ids {
+                    true => lint::IdScheme::Deterministic(id_namespace),
+                    false => lint::IdScheme::Random,
+                },
+                &lint::LintContext {
+                    fetcher: &fetcher,
+                    cache: &cache,
+                    rules: &lint_rules(required_tag_prefix),
+                    changed_fonts: changed_fonts.as_ref(),
+                    published: published.as_ref(),
+                },
+            )
+            .instrument(info_span!("lint"))
+            .await;
+            if !fix {
+                errors.extend(catalog_order_errors(&catalogs));
+            }
+            if locked {
+                match lock::Lockfile::load(&lock_file).await {
+                    Ok(lock) => errors.extend(lint::check_lock(&json, &lock)),
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(exit::IO);
+                    }
+                }
+            }
+            if update_lock {
+                let mut lock = match lock::Lockfile::load(&lock_file).await {
+                    Ok(lock) => lock,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(exit::IO);
+                    }
+                };
+                errors.extend(lint::update_lock(&new_json, base_path, &fetcher, &mut lock).await);
+                if let Err(error) = lock.save(&lock_file).await {
+                    error!("{}", error);
+                    std::process::exit(exit::IO);
+                }
+                info!("Wrote {}", lock_file.display());
+            }
+            if let Some(output) = output {
+                let issues: Vec<lint::LintIssue> = errors.iter().map(Into::into).collect();
+                write_report(&output, &issues).await;
+            }
+            let (warnings, errors): (Vec<_>, Vec<_>) =
+                errors.into_iter().partition(|error| error.is_warning());
+            log_lint_errors(&warnings);
+            log_lint_errors(&errors);
+
+            if !errors.is_empty() {
+                warn!("Found {} unresolved errors", errors.len());
+            } else {
+                info!("No errors found");
+            }
+            if !warnings.is_empty() {
+                warn!("Found {} warnings", warnings.len());
+            }
+
+            // Write the new json (back into each catalog)
+            if fix {
+                let sources = match catalog::split(&new_json, &catalogs) {
+                    Ok(sources) => sources,
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(exit::IO);
+                    }
+                };
+                for (catalog, source) in catalogs.iter().zip(sources) {
+                    write_source(&catalog.path, &source).await;
+                }
+            }
+
+            if !errors.is_empty() || too_many_warnings(warnings.len(), max_warnings) {
+                std::process::exit(exit::LINT_ERRORS);
+            }
+        }
+        Commands::Update {
+            config,
+            base_path,
+            from_vendor,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+            bucket,
+            environment,
+            staging_bucket,
+            staging_base_url,
+            base_url,
+            version,
+            request_interval,
+            connect_timeout,
+            read_timeout,
+            max_redirects,
+            max_body_size,
+            cache_file,
+            lock_file,
+            refresh_lock,
+            required_tag_prefix,
+            max_warnings,
+            scan_command,
+            upstream,
+            skip_unchanged_upload,
+            rebuild_index,
+            report,
+            metrics_file,
+            webhook,
+            github_repo,
+            github_token,
+            github_tag,
+            github_versions_path,
+            github_branch,
+            github_api_url,
+            github_artifacts,
+            ipfs_api,
+            ipfs_gateway,
+            ipfs_pinning_service,
+            ipfs_pinning_token,
+            metalink_threshold,
+            journal_file,
+            resume,
+            parallel_uploads,
+            upload_retries,
+            ttl,
+            approval_keys,
+            approval,
+            publisher_key,
+        } => {
+            let (bucket, base_url) = environment_target(
+                environment,
+                bucket,
+                base_url,
+                staging_bucket,
+                staging_base_url,
+            );
+            if ttl.is_some() && version.pre.is_empty() {
+                error!("Only pre-release versions can expire (--ttl)");
+                std::process::exit(1);
+            }
+
+            // A vendored bundle brings the configs, the lockfile, the cache and the resources
+            let vendored = from_vendor
+                .as_ref()
+                .map(|bundle| unpack_vendor(bundle, &journal_file));
+            let (config, base_path, lock_file, cache_file) = match &vendored {
+                Some((directory, manifest)) => (
+                    manifest
+                        .catalogs
+                        .iter()
+                        .map(|catalog| directory.join(catalog))
+                        .collect(),
+                    vendor::VendorManifest::resources(directory),
+                    Some(
+                        directory.join(manifest.lock.as_deref().unwrap_or(lock::DEFAULT_LOCK_FILE)),
+                    ),
+                    manifest.cache.as_ref().map(|cache| directory.join(cache)),
+                ),
+                None => (config, base_path.unwrap_or_default(), lock_file, cache_file),
+            };
+
+            if let Some(approval_keys) = trusted_keys(&config, approval_keys, vendored.is_some()) {
+                check_approval(
+                    &config,
+                    &approval_keys,
+                    &approval,
+                    publisher_key.as_ref(),
+                    from_vendor
+                        .as_deref()
+                        .zip(vendored.as_ref().map(|(_, manifest)| manifest)),
+                )
+                .await;
+            }
+            let externals = vendored
+                .as_ref()
+                .map(|(directory, _)| vendor::VendorManifest::externals(directory));
+            let cancellation = cancel::Cancellation::on_ctrl_c();
+
+            let github = match (github_repo, github_token) {
+                (Some(repo), Some(token)) => Some(github::GitHubTarget {
+                    repo,
+                    token,
+                    tag: github_tag.unwrap_or_else(|| format!("v{}", version)),
+                    versions_path: github_versions_path,
+                    branch: github_branch,
+                    api_url: github_api_url,
+                }),
+                (Some(_), None) => {
+                    error!("--github-repo needs --github-token");
+                    std::process::exit(1);
+                }
+                (None, _) => None,
+            };
+
+            let pinning_service = match (ipfs_pinning_service, ipfs_pinning_token) {
+                (Some(endpoint), Some(token)) => Some(ipfs::PinningService { endpoint, token }),
+                (Some(_), None) => {
+                    error!("--ipfs-pinning-service needs --ipfs-pinning-token");
+                    std::process::exit(1);
+                }
+                (None, _) => None,
+            };
+            let ipfs = ipfs_api.map(|api_url| ipfs::IpfsTarget {
+                api_url,
+                gateway: ipfs_gateway,
+                pinning_service,
+            });
+            let ipfs = match ipfs.as_ref().map(ipfs::IpfsClient::new) {
+                Some(Ok(client)) => Some(client),
+                Some(Err(error)) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+                None => None,
+            };
+
+            let mut timings = metrics::Timings::default();
+            let s3 = bucket_from_args(&endpoint, &access_key_id, &secret_access_key, &bucket);
+
+            // Pick up where an interrupted update stopped
+            let resumed = match resume {
+                true => match journal::Journal::load(journal_file.clone(), &version).await {
+                    Ok(journal) => {
+                        info!("Resuming the update from {}", journal_file.display());
+                        Some(journal)
+                    }
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                },
+                false => None,
+            };
+            if resumed.is_none() && journal_file.exists() {
+                error!(
+                    "An interrupted update left {}, pass --resume to finish it or delete it to start over",
+                    journal_file.display()
+                );
+                std::process::exit(1);
+            }
+
+            let resuming = resumed.is_some();
+            let (mut file, new, downloadables, mut journal) = match resumed {
+                Some(journal) => {
+                    let downloadables =
+                        utils::utils::grab_downloadables_from_s3(&s3, &base_url, rebuild_index)
+                            .await;
+
+                    (
+                        journal.file.clone(),
+                        journal.uploads.clone(),
+                        downloadables,
+                        journal,
+                    )
+                }
+                None => {
+                    // Get the json
+                    let started = Instant::now();
+                    let lock_file = lock_file.unwrap_or_else(|| lock::lock_path(&config[0]));
+                    let catalogs = match catalogs_from_paths(config).await {
+                        Some(catalogs) => catalogs,
+                        None => std::process::exit(exit::IO),
+                    };
+                    let json = catalog::merge(&catalogs);
+                    timings.add_phase("parse", started.elapsed());
+                    let fetcher = fetcher_with_limits(
+                        request_interval,
+                        fetch::Limits {
+                            connect_timeout: Duration::from_secs(connect_timeout),
+                            read_timeout: Duration::from_secs(read_timeout),
+                            max_redirects,
+                            max_body_size,
+                        },
+                    );
+                    // Vendored builds fail instead of fetching anything
+                    let fetcher = match vendored.is_some() {
+                        true => fetcher.offline(),
+                        false => fetcher,
+                    };
+                    let mut cache = cache::HttpCache::load(cache_file).await;
+                    let mut lock = match refresh_lock {
+                        true => lock::Lockfile::default(),
+                        false => match lock::Lockfile::load(&lock_file).await {
+                            Ok(lock) => lock,
+                            Err(error) => {
+                                error!("{}", error);
+                                std::process::exit(exit::IO);
+                            }
+                        },
+                    };
+                    let scanner = match scan_command.as_deref().map(scan::Scanner::parse) {
+                        Some(Ok(scanner)) => Some(scanner),
+                        Some(Err(error)) => {
+                            error!("Invalid scan command: {}", error);
+                            std::process::exit(1);
+                        }
+                        None => None,
+                    };
+
+                    // Check for any lint errors (vendored builds skip the network checks, but
+                    // everything has to be locked)
+                    let started = Instant::now();
+                    let offline_fonts = HashSet::new();
+                    let (_, mut errors) = lint::lint(
+                        &json,
+                        base_path.clone(),
+                        lint::LintMode::Check,
+                        lint::IdScheme::Random,
+                        &lint::LintContext {
+                            fetcher: &fetcher,
+                            cache: &cache,
+                            rules: &lint_rules(required_tag_prefix),
+                            changed_fonts: vendored.is_some().then_some(&offline_fonts),
+                            published: None,
+                        },
+                    )
+                    .instrument(info_span!("lint"))
+                    .await;
+                    errors.extend(catalog_order_errors(&catalogs));
+                    if vendored.is_some() {
+                        errors.extend(lint::check_lock(&json, &lock));
+                    }
+                    timings.add_phase("lint", started.elapsed());
+                    let (warnings, errors): (Vec<_>, Vec<_>) =
+                        errors.into_iter().partition(|error| error.is_warning());
+                    log_lint_errors(&warnings);
+                    if !errors.is_empty() {
+                        log_lint_errors(&errors);
+                        warn!("Found {} unresolved errors", errors.len());
+                        error!("Please fix any unresolved errors before updating the database");
+                        std::process::exit(exit::LINT_ERRORS);
+                    }
+                    if too_many_warnings(warnings.len(), max_warnings) {
+                        std::process::exit(exit::LINT_ERRORS);
+                    }
+
+                    info!("No errors found");
+
+                    // Get the downloadables
+                    let mut downloadables =
+                        utils::utils::grab_downloadables_from_s3(&s3, &base_url, rebuild_index)
+                            .await;
+
+                    // Build the database
+                    let (mut new, file) = match build::build(
+                        version.clone(),
+                        &json,
+                        base_url.clone(),
+                        base_path.clone(),
+                        downloadables.clone(),
+                        &mut build::BuildContext {
+                            fetcher: &fetcher,
+                            cache: &mut cache,
+                            scanner: scanner.as_ref(),
+                            timings: &mut timings,
+                            cancellation: &cancellation,
+                            lock: &mut lock,
+                            externals: externals.as_deref(),
+                        },
+                    )
+                    .instrument(info_span!("build", %version))
+                    .await
+                    {
+                        Ok(built) => built,
+                        Err(build::BuildError::Cancelled) => {
+                            interrupted(&[], &[format!("version {}", version)])
+                        }
+                        Err(error) => {
+                            error!("Failed to build database: {}", error);
+                            std::process::exit(error.exit_code());
+                        }
+                    };
+
+                    // Make sure we don't publish inconsistent metadata
+                    let inconsistencies = verify::verify(&json, &file, &downloadables);
+                    if !inconsistencies.is_empty() {
+                        for error in &inconsistencies {
+                            error!("{}", error);
+                        }
+                        error!("The built database is inconsistent, not publishing");
+                        std::process::exit(1);
+                    }
+
+                    // Drop redundant downloads (and don't upload them)
+                    let mut file = file;
+                    let pruned = build::prune_downloads(&mut file, &downloadables);
+                    for merged in &pruned.merged {
+                        info!("Merged download {} into {}", merged.id, merged.into);
+                    }
+                    for id in &pruned.duplicated {
+                        info!("Removed a duplicate entry of download {}", id);
+                    }
+                    for id in &pruned.dropped {
+                        info!("Dropped unused download {}", id);
+                    }
+                    new.retain(|d| file.downloads.iter().any(|m| m.id == d.uuid));
+
+                    // Merge the overlay into the upstream database
+                    let mut file = match upstream {
+                        Some(location) => {
+                            let upstream = match overlay::load_upstream(&location, &fetcher).await {
+                                Ok(upstream) => upstream,
+                                Err(error) => {
+                                    error!("{}", error);
+                                    std::process::exit(1);
+                                }
+                            };
+
+                            match overlay::merge_upstream(upstream, file) {
+                                Ok(merged) => {
+                                    // Downloads upstream already has don't need uploading
+                                    new.retain(|d| merged.downloads.iter().any(|m| m.id == d.uuid));
+                                    merged
+                                }
+                                Err(errors) => {
+                                    for error in &errors {
+                                        error!("{}", error);
+                                    }
+                                    error!("The overlay conflicts with the upstream database");
+                                    std::process::exit(1);
+                                }
+                            }
+                        }
+                        None => file,
+                    };
+
+                    // Remember the validators for the next build
+                    cache.save().await;
+
+                    // Commit the lockfile next to the catalog for reproducible builds
+                    match lock.save(&lock_file).await {
+                        Ok(_) => info!("Wrote lockfile to {}", lock_file.display()),
+                        Err(error) => {
+                            error!("{}", error);
+                            std::process::exit(exit::IO);
+                        }
+                    }
+
+                    if cancellation.is_cancelled() {
+                        interrupted(&[], &[format!("version {}", version)]);
+                    }
+
+                    // Reuse what an interrupted run already uploaded
+                    if skip_unchanged_upload {
+                        let existing;
+                        (new, existing) = utils::utils::skip_unchanged_uploads(
+                            &s3, &base_url, &base_path, &mut file, new,
+                        )
+                        .await;
+                        downloadables.extend(existing);
+                    }
+
+                    // Mirror the new artifacts on IPFS, what's added there can't be taken back so
+                    // signed downloads never are
+                    if let Some(ipfs) = &ipfs {
+                        for download in &mut new {
+                            if !download.access.is_public() {
+                                info!("Not mirroring {} on IPFS (it isn't public)", download.uuid);
+                                continue;
+                            }
+
+                            let (name, data) = utils::utils::read_artifact(&base_path, download);
+                            let mirror = match ipfs.publish(&name, data).await {
+                                Ok(mirror) => mirror,
+                                Err(error) => {
+                                    error!("{}", error);
+                                    std::process::exit(1);
+                                }
+                            };
+
+                            for compiled in
+                                file.downloads.iter_mut().filter(|d| d.id == download.uuid)
+                            {
+                                compiled.mirrors.push(mirror.clone());
+                            }
+                            download.mirrors.push(mirror);
+                        }
+                    }
+
+                    // Generated downloads are staged, the plan survives an interruption
+                    let journal = match journal::Journal::create(
+                        journal_file,
+                        version.clone(),
+                        file.clone(),
+                        new,
+                        pruned,
+                    )
+                    .await
+                    {
+                        Ok(journal) => journal,
+                        Err(error) => {
+                            error!("{}", error);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    let uploads = journal.uploads.clone();
+                    (file, uploads, downloadables, journal)
+                }
+            };
+
+            // The bucket is the truth for uploads: a journaled one may have been deleted since and
+            // one that finished just before the interruption may not be journaled yet
+            if resuming {
+                for download in &new {
+                    let key = download.key();
+                    let done = journal.is_done(&key);
+                    match utils::utils::is_uploaded(&s3, &base_path, download).await {
+                        Ok(true) if !done => {
+                            info!("{} is already uploaded, skipping it", key);
+                            journal.mark_done(&key).await;
+                        }
+                        Ok(false) if done => {
+                            warn!(
+                                "{} isn't in the bucket as uploaded, uploading it again",
+                                key
+                            );
+                            journal.forget(&key).await;
+                        }
+                        Ok(_) => {}
+                        // Unverified uploads are done again
+                        Err(error) => {
+                            warn!("Failed to check {}: {}", key, error);
+                            journal.forget(&key).await;
+                        }
+                    }
+                }
+            }
+
+            // Everything the update uploads, including what a resumed run already did
+            let uploaded_downloads = journal.uploads.len();
+            let uploaded_bytes = journal.uploads.iter().map(|d| d.file_size).sum();
+
+            // Read before the upload consumes them, release assets are public so signed downloads
+            // stay in the bucket
+            let github_artifacts = match github.is_some() && github_artifacts {
+                true => journal
+                    .uploads
+                    .iter()
+                    .filter(|download| {
+                        if !download.access.is_public() {
+                            info!(
+                                "Not attaching {} to the GitHub release (it isn't public)",
+                                download.uuid
+                            );
+                        }
+                        download.access.is_public()
+                    })
+                    .map(|download| utils::utils::read_artifact(&base_path, download))
+                    .collect(),
+                false => vec![],
+            };
+
+            // Large downloads get a metalink for multi-source downloading
+            let mut metalinks = vec![];
+            if let Some(threshold) = metalink_threshold {
+                for download in &mut file.downloads {
+                    if download.file_size < threshold || !download.access.is_public() {
+                        continue;
+                    }
+
+                    metalinks.push((download.id, metalink::metalink(download)));
+                    download.metalink = Some(generate_metalink_url(&base_url, &download.id));
+                }
+            }
+
+            // New UUID (a resumed update keeps the one it started publishing)
+            let new_uuid = journal.version_id.unwrap_or_else(uuid::Uuid::new_v4);
+            journal.start_publishing(new_uuid, &file).await;
+
+            // Serialize the file
+            let data = match serde_json::to_vec(&file) {
+                Ok(data) => data,
+                Err(error) => {
+                    error!("Failed to serialize file: {}", error);
+                    std::process::exit(1);
+                }
+            };
+
+            // downloadables.json lists the uploads once they're all in the bucket
+            let mut downloadables = downloadables;
+            for download in &new {
+                if !downloadables.iter().any(|d| d.id == download.uuid) {
+                    downloadables.push(download.downloadable(&base_url));
+                }
+            }
+
+            // Previous versions (to report what changed), read before this one is listed
+            let previous = {
+                let versions =
+                    utils::utils::grab_versions_from_s3(&s3, &base_url, rebuild_index).await;
+                match versions
+                    .iter()
+                    .filter(|v| v.id != new_uuid)
+                    .max_by(|a, b| a.version.cmp(&b.version))
+                {
+                    Some(latest) => utils::utils::grab_version_from_s3(&s3, &latest.id).await,
+                    None => None,
+                }
+            };
+
+            // The publish graph: artifacts and their metalinks, then the version file, then the
+            // indexes that point at them
+            let version_url = generate_versions_url(&base_url, &new_uuid);
+            let version_key = utils::utils::version_key(&new_uuid);
+            let expires_at = ttl.map(|days| now() + days * 24 * 60 * 60);
+            let badges = badge::badges(&file);
+            // What the release feed says about this version
+            let release = feed::Release {
+                id: new_uuid,
+                version: version.clone(),
+                download_url: version_url.clone(),
+                published_at: now(),
+                summary: feed::summary(&file, previous.as_ref()),
+            };
+            let mut tasks = vec![];
+            for download in &new {
+                let (s3, base_path) = (&s3, &base_path);
+                tasks.push(
+                    pipeline::Task::new(download.key(), move || {
+                        utils::utils::put_download(s3, base_path, download)
+                    })
+                    .uploading(download.file_size),
+                );
+            }
+            for (id, metalink) in &metalinks {
+                let s3 = &s3;
+                let mut task = pipeline::Task::new(utils::utils::metalink_key(id), move || async {
+                    put_metalink(s3, *id, metalink)
+                        .await
+                        .map_err(|e| e.to_string())
+                });
+                if let Some(download) = new.iter().find(|d| d.uuid == *id) {
+                    task = task.after(download.key());
+                }
+                tasks.push(task);
+            }
+
+            let mut version_task = pipeline::Task::new(version_key.clone(), || async {
+                put_version(&s3, new_uuid, &data)
+                    .await
+                    .map_err(|e| e.to_string())
+            });
+            for task in &tasks {
+                version_task = version_task.after(task.id.clone());
+            }
+            let mut downloadables_task = pipeline::Task::new("downloadables.json", || {
+                utils::utils::put_downloadables(&s3, &downloadables)
+            });
+            for download in &new {
+                downloadables_task = downloadables_task.after(download.key());
+            }
+            tasks.push(version_task);
+            tasks.push(downloadables_task);
+
+            tasks.push(
+                pipeline::Task::new("versions.json", || async {
+                    let mut versions =
+                        utils::utils::grab_versions_from_s3(&s3, &base_url, rebuild_index).await;
+                    if versions.iter().any(|v| v.id == new_uuid) {
+                        return Ok(());
+                    }
+
+                    let mut mirrors = vec![];
+                    if let Some(ipfs) = &ipfs {
+                        let mirror = ipfs
+                            .publish(&format!("{}.json", new_uuid), data.clone())
+                            .await
+                            .map_err(|e| e.to_string())?;
+                        mirrors.push(mirror);
+                    }
+
+                    // Add the new version
+                    versions.push(VersionInfo {
+                        id: new_uuid,
+                        version: version.clone(),
+                        download_url: version_url.clone(),
+                        hash: sha256::digest(&data),
+                        file_size: data.len() as u64,
+                        mirrors,
+                        expires_at,
+                    });
+                    utils::utils::put_versions(&s3, &versions).await
+                })
+                .after(version_key.clone())
+                .after("downloadables.json"),
+            );
+
+            // The release feed
+            tasks.push(
+                pipeline::Task::new(utils::utils::FEED_FILE_PATH, || async {
+                    let mut releases = utils::utils::grab_releases_from_s3(&s3).await?;
+                    if releases.iter().any(|r| r.id == new_uuid) {
+                        return Ok(());
+                    }
+
+                    releases.push(release.clone());
+                    utils::utils::put_releases(&s3, &base_url, &releases).await
+                })
+                .after("versions.json"),
+            );
+
+            // Badges only ever show the latest version
+            for (name, badge) in &badges {
+                let (s3, base_url, version, name) = (&s3, &base_url, &version, *name);
+                tasks.push(
+                    pipeline::Task::new(utils::utils::badge_key(name), move || async move {
+                        let versions =
+                            utils::utils::grab_versions_from_s3(s3, base_url, false).await;
+                        if versions.iter().any(|v| &v.version > version) {
+                            info!(
+                                "Not updating the {} badge, a newer version is published",
+                                name
+                            );
+                            return Ok(());
+                        }
+
+                        utils::utils::put_badge(s3, name, badge).await
+                    })
+                    .after("versions.json"),
+                );
+            }
+
+            // Publish the database
+            let started = Instant::now();
+            let published = pipeline::Scheduler {
+                concurrency: parallel_uploads,
+                retries: upload_retries,
+                journal: &mut journal,
+                timings: &mut timings,
+                cancellation: &cancellation,
+            }
+            .run(&tasks)
+            .instrument(info_span!("upload", downloads = uploaded_downloads))
+            .await;
+            drop(tasks);
+            timings.add_phase("upload", started.elapsed());
+
+            match published {
+                Ok(()) => {}
+                // The version isn't listed until everything it points at is published
+                Err(pipeline::PipelineError::Cancelled(finished, pending)) => {
+                    interrupted(&finished, &pending)
+                }
+                // The scheduler logged the failures
+                Err(pipeline::PipelineError::Failed(_, _)) => {
+                    error!("Run the update again with --resume to finish it");
+                    std::process::exit(exit::NETWORK);
+                }
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+
+            // The version is out, only the announcements are left
+            if cancellation.is_cancelled() {
+                let mut not_published = vec![];
+                if github.is_some() {
+                    not_published.push("GitHub release".to_string());
+                }
+                if !webhook.is_empty() {
+                    not_published.push("webhooks".to_string());
+                }
+                interrupted(
+                    &[format!("version {} ({})", file.version, version_url)],
+                    &not_published,
+                );
+            }
+
+            if let Some(target) = &github {
+                let published = match github::GitHubPublisher::new(target) {
+                    Ok(publisher) => {
+                        publisher
+                            .publish(&file.version, new_uuid, &data, github_artifacts)
+                            .await
+                    }
+                    Err(error) => Err(error),
+                };
+
+                match published {
+                    Ok(url) => info!("Published to GitHub: {}", url),
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            let summary = report::PublishReport::new(
+                &file,
+                previous.as_ref(),
+                uploaded_downloads,
+                uploaded_bytes,
+                new_uuid,
+                version_url,
+                data.len() as u64,
+            )
+            .with_timings(timings)
+            .with_pruned(journal.pruned.clone());
+            summary.print_summary();
+
+            let record = audit::AuditRecord::new(
+                audit::Operation::Update,
+                format!("Published {} ({} fonts)", summary.version, summary.fonts),
+            )
+            .with_version(summary.version.clone(), new_uuid)
+            .with_changed_fonts(summary.new_fonts.len() + summary.removed_fonts.len());
+            audit::append(&s3, &record).await;
+
+            if let Some(report) = report {
+                write_report(&report, &summary).await;
+            }
+            if let Some(path) = metrics_file {
+                match fs::write(&path, metrics::prometheus(&summary)).await {
+                    Ok(_) => info!("Wrote metrics to {}", path.display()),
+                    Err(error) => {
+                        error!("Failed to write metrics: {}", error);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            // The database is already published, failing webhooks are only reported
+            for error in notify::notify(&webhook, &summary).await {
+                warn!("{}", error);
+            }
+
+            journal.remove().await;
+            if let Some((directory, _)) = vendored {
+                if let Err(error) = fs::remove_dir_all(&directory).await {
+                    warn!("Failed to remove {}: {}", directory.display(), error);
+                }
+            }
+        }
+        Commands::ValidateCompiled {
+            location,
+            request_interval,
+        } => {
+            let data = match Url::parse(&location) {
+                Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {
+                    let fetcher = fetcher_from_interval(request_interval);
+                    let response = match fetcher.get(&url).await {
+                        Ok(response) if response.status().is_success() => response,
+                        Ok(response) => {
+                            error!("Failed to get {}: {}", url, response.status());
+                            std::process::exit(1);
+                        }
+                        Err(error) => {
+                            error!("Failed to get {}: {}", url, error);
+                            std::process::exit(1);
+                        }
+                    };
+
+                    match fetcher.read_body(response).await {
+                        Ok(data) => String::from_utf8_lossy(&data).into_owned(),
+                        Err(error) => {
+                            error!("Failed to get {}: {}", url, error);
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                _ => match fs::read_to_string(&location).await {
+                    Ok(data) => data,
+                    Err(error) => {
+                        error!("Failed to read {}: {}", location, error);
+                        std::process::exit(1);
+                    }
+                },
+            };
+
+            let compiled = match strict::from_str::<types::Compiled>(&data) {
+                Ok(compiled) => compiled,
+                Err(error) => {
+                    error!("Failed to parse {}: {}", location, error);
+                    std::process::exit(1);
+                }
+            };
+
+            let errors = verify::validate_compiled(&compiled);
+            for error in &errors {
+                error!("{}", error);
+            }
+            if !errors.is_empty() {
+                error!("Found {} errors in {}", errors.len(), location);
+                std::process::exit(1);
+            }
+
+            info!(
+                "{} is valid ({} fonts, {} downloads)",
+                compiled.version,
+                compiled.fonts.len(),
+                compiled.downloads.len()
+            );
+        }
+        Commands::Outdated {
+            config,
+            request_interval,
+            output,
+        } => {
+            let json = match catalogs_from_paths(config).await {
+                Some(catalogs) => catalog::merge(&catalogs),
+                None => return,
+            };
+            let fetcher = fetcher_from_interval(request_interval);
+
+            let (outdated, errors) = outdated::outdated(&json, &fetcher).await;
+            for error in &errors {
+                error!("{}", error);
+            }
+
+            for download in &outdated {
+                warn!(
+                    "{}: {} is pinned to {} but {} is available{}",
+                    download.font,
+                    download.repo,
+                    download.current_tag,
+                    download.latest_tag,
+                    match download.asset_available {
+                        true => "",
+                        false => " (asset name changed)",
+                    }
+                );
+            }
+
+            if outdated.is_empty() {
+                info!("Everything is up to date");
+            } else {
+                info!("Found {} outdated downloads", outdated.len());
+            }
+
+            // Write the report
+            if let Some(output) = output {
+                write_report(&output, &outdated).await;
+            }
+
+            if !errors.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Commands::Coverage {
+            config,
+            profile,
+            output,
+        } => {
+            let json = match catalogs_from_paths(config).await {
+                Some(catalogs) => catalog::merge(&catalogs),
+                None => return,
+            };
+            let profiles = match profile.is_empty() {
+                true => coverage::PROFILES.iter().collect(),
+                false => profile,
+            };
+
+            let report: Vec<coverage::ProfileCoverage> = profiles
+                .into_iter()
+                .map(|profile| coverage::coverage(&json, profile))
+                .collect();
+
+            for covered in &report {
+                info!(
+                    "{} ({}/{} families): {}",
+                    covered.profile,
+                    covered.satisfied(),
+                    covered.families.len(),
+                    covered.description
+                );
+                for family in covered.missing() {
+                    warn!("{}: missing {}", covered.profile, family.family);
+                }
+            }
+
+            // Write the report
+            if let Some(output) = output {
+                write_report(&output, &report).await;
+            }
+        }
+        Commands::Export {
+            format:
+                ExportCommands::Vendor {
+                    config,
+                    base_path,
+                    lock_file,
+                    cache_file,
+                    include_signed,
+                    version,
+                    output,
+                },
+        } => {
+            let lock_file = lock_file.unwrap_or_else(|| lock::lock_path(&config[0]));
+            // Approvals of the bundle are for this commit
+            let commit = match approval::commit_of(&config) {
+                Ok(commit) => Some(commit),
+                Err(error) => {
+                    warn!("{}, the bundle can't be approved", error);
+                    None
+                }
+            };
+            let catalogs = match catalogs_from_paths(config).await {
+                Some(catalogs) => catalogs,
+                None => std::process::exit(exit::IO),
+            };
+            let json = catalog::merge(&catalogs);
+
+            // Without a lockfile the external resources aren't pinned
+            let lock_file = match lock_file.exists() {
+                true => Some(lock_file),
+                false => {
+                    warn!(
+                        "No lockfile at {}, the external resources aren't pinned",
+                        lock_file.display()
+                    );
+                    None
+                }
+            };
+
+            let manifest = match vendor::vendor(
+                &vendor::VendorOptions {
+                    catalogs: &catalogs,
+                    source: &json,
+                    base_path: &base_path,
+                    lock_file: lock_file.as_deref(),
+                    cache_file: cache_file.as_deref(),
+                    include_signed,
+                    built_version: version,
+                    commit,
+                    externals: &locked_externals(lock_file.as_deref()).await,
+                },
+                &output,
+            ) {
+                Ok(manifest) => manifest,
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(exit::IO);
+                }
+            };
+
+            for excluded in &manifest.excluded {
+                warn!("Left out the signed resource {}", excluded);
+            }
+            info!(
+                "Wrote {} files ({} bytes) to {}",
+                manifest.files.len(),
+                manifest
+                    .files
+                    .iter()
+                    .map(|file| file.file_size)
+                    .sum::<u64>(),
+                output.display()
+            );
+        }
+        Commands::Repair {
+            base_url,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+            bucket,
+            environment,
+            staging_bucket,
+            staging_base_url,
+            trust_metadata,
+            base_path,
+            yes,
+        } => {
+            let (bucket, base_url) = environment_target(
+                environment,
+                bucket,
+                base_url,
+                staging_bucket,
+                staging_base_url,
+            );
+            let s3 = bucket_from_args(&endpoint, &access_key_id, &secret_access_key, &bucket);
+
+            let mut downloadables =
+                utils::utils::grab_downloadables_from_s3(&s3, &base_url, false).await;
+            let mut versions = utils::utils::grab_versions_from_s3(&s3, &base_url, false).await;
+
+            let discrepancies =
+                repair::check(&s3, &base_url, &downloadables, &versions, trust_metadata).await;
+            if discrepancies.is_empty() {
+                info!("The indexes match the bucket");
+                return;
+            }
+
+            let mut fixed = 0;
+            for discrepancy in discrepancies {
+                warn!("{}", discrepancy);
+
+                if matches!(discrepancy, repair::Discrepancy::UnknownObject(_, _)) {
+                    continue;
+                }
+                let question = format!("Fix ({})?", discrepancy.fix_description());
+                if !yes && !confirm(&question) {
+                    continue;
+                }
+
+                if repair::fix(
+                    &s3,
+                    discrepancy,
+                    base_path.as_deref(),
+                    &mut downloadables,
+                    &mut versions,
+                )
+                .await
+                {
+                    fixed += 1;
+                }
+            }
+
+            if fixed == 0 {
+                info!("Nothing was fixed");
+                return;
+            }
+
+            utils::utils::upload_downloadables_to_s3(&s3, &downloadables).await;
+            upload_versions_to_s3(&s3, versions).await;
+
+            let summary = format!("Fixed {} discrepancies", fixed);
+            audit::append(
+                &s3,
+                &audit::AuditRecord::new(audit::Operation::Repair, summary.clone()),
+            )
+            .await;
+            info!("{}", summary);
+        }
+        Commands::ApprovalKey { output } => {
+            let (key, public_key) = match approval::generate_key() {
+                Ok(pair) => pair,
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+
+            // Only readable by its owner, and never over an existing key
+            let mut options = fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let written = match options.open(&output).await {
+                Ok(mut file) => file.write_all(key.as_bytes()).await,
+                Err(error) => Err(error),
+            };
+            if let Err(error) = written {
+                error!("Failed to write {}: {}", output.display(), error);
+                std::process::exit(1);
+            }
+
+            info!("Wrote the private key to {}", output.display());
+            info!("Add this line (with your name) to the trusted keys:");
+            println!("<name> {}", public_key);
+        }
+        Commands::Approve {
+            config,
+            bundle,
+            key,
+            name,
+            output,
+        } => {
+            let key = match fs::read_to_string(&key).await {
+                Ok(key) => key,
+                Err(error) => {
+                    error!("Failed to read {}: {}", key.display(), error);
+                    std::process::exit(1);
+                }
+            };
+
+            let approved = match &bundle {
+                Some(bundle) => match vendor::manifest(bundle) {
+                    Ok(manifest) => {
+                        Ok((bundle_commit(bundle, &manifest), Some(bundle_hash(bundle))))
+                    }
+                    Err(error) => {
+                        error!("{}", error);
+                        std::process::exit(exit::IO);
+                    }
+                },
+                None => approval::commit_of(&config).map(|commit| (commit, None)),
+            };
+            let approval = match approved
+                .and_then(|(commit, bundle)| approval::sign(&key, name, commit, bundle))
+            {
+                Ok(approval) => approval,
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            let data = match serde_json::to_vec_pretty(&approval) {
+                Ok(data) => data,
+                Err(error) => {
+                    error!("Failed to serialize the approval: {}", error);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(error) = fs::write(&output, data).await {
+                error!("Failed to write {}: {}", output.display(), error);
+                std::process::exit(1);
+            }
+
+            info!("Approved commit {} ({})", approval.commit, output.display());
+        }
+        Commands::Gc {
+            base_url,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+            bucket,
+            environment,
+            staging_bucket,
+            staging_base_url,
+            dry_run,
+        } => {
+            let (bucket, base_url) = environment_target(
+                environment,
+                bucket,
+                base_url,
+                staging_bucket,
+                staging_base_url,
+            );
+            let s3 = bucket_from_args(&endpoint, &access_key_id, &secret_access_key, &bucket);
+
+            let mut downloadables =
+                utils::utils::grab_downloadables_from_s3(&s3, &base_url, false).await;
+            let mut versions = utils::utils::grab_versions_from_s3(&s3, &base_url, false).await;
+
+            // Expire pre-releases
+            let expired = match gc::expired(&s3, &downloadables, &versions, now()).await {
+                Ok(expired) => expired,
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            };
+            // Only what's actually deleted is dropped from the indexes
+            let expired = gc::deletable(&s3, expired).await;
+            if expired.is_empty() {
+                info!("Nothing has expired");
+                return;
+            }
+
+            for version in &expired.versions {
+                info!("Expired version {} ({})", version.version, version.id);
+            }
+            for download in &expired.downloads {
+                info!("Unused download {}", download.id);
+            }
+            if dry_run {
+                return;
+            }
+
+            // The indexes stop pointing at the objects before they're deleted
+            gc::prune(&expired, &mut downloadables, &mut versions);
+            upload_versions_to_s3(&s3, versions).await;
+            utils::utils::upload_downloadables_to_s3(&s3, &downloadables).await;
+            let errors = gc::remove(&s3, &expired).await;
+
+            let summary = format!(
+                "Removed {} versions ({}) and {} downloads",
+                expired.versions.len(),
+                expired
+                    .versions
+                    .iter()
+                    .map(|v| v.version.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                expired.downloads.len()
+            );
+            audit::append(
+                &s3,
+                &audit::AuditRecord::new(audit::Operation::Gc, summary.clone()),
+            )
+            .await;
+            info!("{}", summary);
+
+            if !errors.is_empty() {
+                for error in &errors {
+                    error!("{}", error);
+                }
+                error!("Nothing uses the objects that weren't deleted, delete them by hand");
+                std::process::exit(1);
+            }
+        }
+        Commands::Promote {
+            version,
+            base_url,
+            endpoint,
+            access_key_id,
+            secret_access_key,
+            bucket,
+            staging_bucket,
+            staging_base_url,
+        } => {
+            let staging = environment::Target {
+                bucket: bucket_from_args(
+                    &endpoint,
+                    &access_key_id,
+                    &secret_access_key,
+                    &staging_bucket,
+                ),
+                base_url: staging_base_url,
+            };
+            let production = environment::Target {
+                bucket: bucket_from_args(&endpoint, &access_key_id, &secret_access_key, &bucket),
+                base_url,
+            };
+
+            match environment::promote(&staging, &production, &version).await {
+                Ok(promoted) => {
+                    let summary = format!(
+                        "Promoted {} ({} downloads copied): {}",
+                        version, promoted.copied_downloads, promoted.version_url
+                    );
+                    let record =
+                        audit::AuditRecord::new(audit::Operation::Promote, summary.clone())
+                            .with_version(version.clone(), promoted.id);
+                    audit::append(&production.bucket, &record).await;
+                    info!("{}", summary);
+                }
+                Err(error) => {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
+            }
         }
     }
 }
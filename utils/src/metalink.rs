@@ -0,0 +1,51 @@
+use crate::{fetch::USER_AGENT, types::CompiledDownloads};
+
+/// Escapes text for an xml element or attribute
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+/// The file name clients save the download as (the last segment of its url)
+pub fn file_name(download: &CompiledDownloads) -> String {
+    let name = download
+        .download_url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|name| !name.is_empty())
+        .map(|name| urlencoding::decode(name).map(|n| n.into_owned()))
+        .and_then(Result::ok);
+
+    name.unwrap_or_else(|| download.id.to_string())
+}
+
+/// A Metalink 4 (RFC 5854) document listing every url of the download with its size and hash
+pub fn metalink(download: &CompiledDownloads) -> String {
+    let mut urls = vec![&download.download_url];
+    urls.extend(&download.mirrors);
+
+    let mut xml = String::new();
+    xml += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    xml += "<metalink xmlns=\"urn:ietf:params:xml:ns:metalink\">\n";
+    xml += &format!("  <generator>{}</generator>\n", escape(USER_AGENT));
+    xml += &format!("  <file name=\"{}\">\n", escape(&file_name(download)));
+    xml += &format!("    <size>{}</size>\n", download.file_size);
+    xml += &format!(
+        "    <hash type=\"sha-256\">{}</hash>\n",
+        escape(&download.hash.to_lowercase())
+    );
+    for (priority, url) in urls.iter().enumerate() {
+        xml += &format!(
+            "    <url priority=\"{}\">{}</url>\n",
+            priority + 1,
+            escape(url.as_str())
+        );
+    }
+    xml += "  </file>\n";
+    xml += "</metalink>\n";
+
+    xml
+}
@@ -0,0 +1,184 @@
+use std::{fmt::Write, time::Duration};
+
+use serde::Serialize;
+
+use crate::report::PublishReport;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransferDirection {
+    Download,
+    Upload,
+}
+
+impl TransferDirection {
+    fn name(&self) -> &'static str {
+        match self {
+            TransferDirection::Download => "download",
+            TransferDirection::Upload => "upload",
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PhaseTiming {
+    pub name: String,
+    pub seconds: f64,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A single artifact moved over the network
+pub struct Transfer {
+    pub direction: TransferDirection,
+    /// The url or object path
+    pub artifact: String,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl Transfer {
+    /// Bytes per second
+    pub fn rate(&self) -> f64 {
+        match self.seconds > 0.0 {
+            true => self.bytes as f64 / self.seconds,
+            false => 0.0,
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+/// Where an update spent its time
+pub struct Timings {
+    /// In the order they first ran (parse, lint, download, hash, upload...)
+    pub phases: Vec<PhaseTiming>,
+    pub transfers: Vec<Transfer>,
+}
+
+impl Timings {
+    /// Adds time to a phase (phases that run several times add up)
+    pub fn add_phase(&mut self, name: &str, elapsed: Duration) {
+        match self.phases.iter_mut().find(|phase| phase.name == name) {
+            Some(phase) => phase.seconds += elapsed.as_secs_f64(),
+            None => self.phases.push(PhaseTiming {
+                name: name.to_string(),
+                seconds: elapsed.as_secs_f64(),
+            }),
+        }
+    }
+
+    pub fn add_transfer(
+        &mut self,
+        direction: TransferDirection,
+        artifact: &str,
+        bytes: u64,
+        elapsed: Duration,
+    ) {
+        self.transfers.push(Transfer {
+            direction,
+            artifact: artifact.to_string(),
+            bytes,
+            seconds: elapsed.as_secs_f64(),
+        });
+    }
+}
+
+/// A byte rate for humans (e.g. `5.2 MB/s`)
+pub fn format_rate(bytes_per_second: f64) -> String {
+    const UNITS: &[&str] = &["B/s", "KB/s", "MB/s", "GB/s"];
+
+    let mut rate = bytes_per_second;
+    let mut unit = 0;
+    while rate >= 1000.0 && unit < UNITS.len() - 1 {
+        rate /= 1000.0;
+        unit += 1;
+    }
+
+    format!("{:.1} {}", rate, UNITS[unit])
+}
+
+/// Escapes a Prometheus label value
+fn label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// The report in the Prometheus text format (e.g. for the node exporter's textfile collector)
+pub fn prometheus(report: &PublishReport) -> String {
+    let mut out = String::new();
+    let mut metric = |name: &str, help: &str, samples: Vec<(String, String)>| {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} gauge", name);
+        for (labels, value) in samples {
+            let _ = match labels.is_empty() {
+                true => writeln!(out, "{} {}", name, value),
+                false => writeln!(out, "{}{{{}}} {}", name, labels, value),
+            };
+        }
+    };
+
+    metric(
+        "winefonts_publish_fonts",
+        "Fonts in the published version",
+        vec![(String::new(), report.fonts.to_string())],
+    );
+    metric(
+        "winefonts_publish_uploaded_downloads",
+        "Downloads the update uploaded",
+        vec![(String::new(), report.uploaded_downloads.to_string())],
+    );
+    metric(
+        "winefonts_publish_uploaded_bytes",
+        "Bytes the update uploaded",
+        vec![(String::new(), report.bytes_uploaded.to_string())],
+    );
+    metric(
+        "winefonts_publish_phase_seconds",
+        "Time the update spent in each phase",
+        report
+            .timings
+            .phases
+            .iter()
+            .map(|phase| {
+                (
+                    format!("phase=\"{}\"", label(&phase.name)),
+                    phase.seconds.to_string(),
+                )
+            })
+            .collect(),
+    );
+
+    let transfer_labels = |transfer: &Transfer| {
+        format!(
+            "direction=\"{}\",artifact=\"{}\"",
+            transfer.direction.name(),
+            label(&transfer.artifact)
+        )
+    };
+    metric(
+        "winefonts_publish_transfer_bytes",
+        "Size of each transferred artifact",
+        report
+            .timings
+            .transfers
+            .iter()
+            .map(|t| (transfer_labels(t), t.bytes.to_string()))
+            .collect(),
+    );
+    metric(
+        "winefonts_publish_transfer_seconds",
+        "Time each artifact took to transfer",
+        report
+            .timings
+            .transfers
+            .iter()
+            .map(|t| (transfer_labels(t), t.seconds.to_string()))
+            .collect(),
+    );
+
+    out
+}
@@ -0,0 +1,191 @@
+use std::path::PathBuf;
+
+use tokio::fs;
+
+use crate::{
+    client::DownloadManager,
+    install::{self, InstallError, InstalledFont},
+    policy::{self, InstallTarget},
+    store::ArtifactCache,
+    types::{Compiled, CompiledFont},
+};
+
+/// The fonts directory of Windows (`%WINDIR%\Fonts`)
+pub fn fonts_directory() -> PathBuf {
+    std::env::var_os("WINDIR")
+        .or_else(|| std::env::var_os("SystemRoot"))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\Windows"))
+        .join("Fonts")
+}
+
+/// Where the manifest of the natively installed fonts is kept (`%PROGRAMDATA%\winefonts`)
+pub fn state_directory() -> PathBuf {
+    std::env::var_os("PROGRAMDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(r"C:\ProgramData"))
+        .join("winefonts")
+}
+
+/// Installs the files of a font into the Windows fonts directory and records it in the native
+/// manifest, returns the installed entries
+///
+/// Only the files are installed, see `register` for the registry. Writing to the fonts
+/// directory needs an elevated process.
+pub async fn install_font(
+    compiled: &Compiled,
+    font: &CompiledFont,
+    manager: &DownloadManager<'_>,
+    cache: &ArtifactCache,
+) -> Result<Vec<InstalledFont>, InstallError> {
+    if !cfg!(windows) {
+        return Err(InstallError::NotWindows);
+    }
+    if let Some(violation) = policy::check(&[font], InstallTarget::Windows).pop() {
+        return Err(InstallError::NotAllowed(violation));
+    }
+
+    let state = state_directory();
+    fs::create_dir_all(&state)
+        .await
+        .map_err(|e| InstallError::Io(state.clone(), e.to_string()))?;
+
+    install::install_font_into(&state, &fonts_directory(), compiled, font, manager, cache).await
+}
+
+/// Registers the fonts in the machine's registry (through the registry API) and loads them, so
+/// running applications see them without signing out
+pub async fn register(fonts: &[InstalledFont]) -> Result<(), InstallError> {
+    if !cfg!(windows) {
+        return Err(InstallError::NotWindows);
+    }
+
+    // Every value is checked before anything is written
+    let mut values = vec![];
+    for installed in fonts {
+        for file in installed.installation.installed_files() {
+            install::check_registry_value(&file.registry_name)?;
+            install::check_registry_value(&file.file)?;
+            values.push((file.registry_name, file.file));
+        }
+    }
+
+    #[cfg(windows)]
+    {
+        registry::set_fonts(&values).map_err(InstallError::Register)?;
+
+        let directory = fonts_directory();
+        let files: Vec<PathBuf> = values
+            .iter()
+            .filter_map(|(_, file)| install::font_file_path(&directory, file).ok())
+            .collect();
+        gdi::load(&files);
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+mod registry {
+    use std::{ffi::OsStr, os::windows::ffi::OsStrExt};
+
+    const HKEY_LOCAL_MACHINE: isize = 0x8000_0002_u32 as i32 as isize;
+    const REG_SZ: u32 = 1;
+    /// Where Windows looks up the files of the installed fonts
+    const FONTS_KEY: &str = r"SOFTWARE\Microsoft\Windows NT\CurrentVersion\Fonts";
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        fn RegSetKeyValueW(
+            key: isize,
+            subkey: *const u16,
+            value_name: *const u16,
+            kind: u32,
+            data: *const u8,
+            size: u32,
+        ) -> i32;
+    }
+
+    fn wide(value: &str) -> Vec<u16> {
+        OsStr::new(value).encode_wide().chain([0]).collect()
+    }
+
+    /// Sets the (registry name, file) values of the fonts
+    pub fn set_fonts(values: &[(String, String)]) -> Result<(), String> {
+        let key = wide(FONTS_KEY);
+        for (name, file) in values {
+            let name_wide = wide(name);
+            let data = wide(file);
+            let status = unsafe {
+                RegSetKeyValueW(
+                    HKEY_LOCAL_MACHINE,
+                    key.as_ptr(),
+                    name_wide.as_ptr(),
+                    REG_SZ,
+                    data.as_ptr().cast(),
+                    (data.len() * 2) as u32,
+                )
+            };
+            if status != 0 {
+                return Err(format!(
+                    "{}: {}",
+                    name,
+                    std::io::Error::from_raw_os_error(status)
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+mod gdi {
+    use std::{os::windows::ffi::OsStrExt, path::PathBuf};
+
+    const HWND_BROADCAST: isize = 0xffff;
+    const WM_FONTCHANGE: u32 = 0x001d;
+    const SMTO_ABORTIFHUNG: u32 = 0x0002;
+
+    #[link(name = "gdi32")]
+    extern "system" {
+        fn AddFontResourceW(file: *const u16) -> i32;
+    }
+
+    #[link(name = "user32")]
+    extern "system" {
+        fn SendMessageTimeoutW(
+            window: isize,
+            message: u32,
+            wparam: usize,
+            lparam: isize,
+            flags: u32,
+            timeout: u32,
+            result: *mut usize,
+        ) -> isize;
+    }
+
+    /// Adds the fonts to the session and tells the running applications (a font that can't be
+    /// loaded is still there after signing in again)
+    pub fn load(files: &[PathBuf]) {
+        for file in files {
+            let wide: Vec<u16> = file.as_os_str().encode_wide().chain([0]).collect();
+            if unsafe { AddFontResourceW(wide.as_ptr()) } == 0 {
+                warn!("Failed to load {}", file.display());
+            }
+        }
+
+        let mut result = 0;
+        unsafe {
+            SendMessageTimeoutW(
+                HWND_BROADCAST,
+                WM_FONTCHANGE,
+                0,
+                0,
+                SMTO_ABORTIFHUNG,
+                1000,
+                &mut result,
+            );
+        }
+    }
+}
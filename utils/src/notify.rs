@@ -0,0 +1,119 @@
+use std::fmt::Display;
+
+use reqwest::Client;
+use serde::Serialize;
+use url::Url;
+
+use crate::{fetch::USER_AGENT, report::PublishReport};
+
+/// The most characters Discord accepts in `content`
+const DISCORD_CONTENT_LIMIT: usize = 2000;
+
+pub enum NotifyError {
+    /// The http client couldn't be created (Error)
+    Client(String),
+    /// The webhook couldn't be reached or refused the payload (Host, Error), webhook urls carry
+    /// their secret so only the host is kept
+    Webhook(String, String),
+}
+
+impl Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifyError::Client(error) => write!(f, "Failed to create http client: {}", error),
+            NotifyError::Webhook(host, error) => {
+                write!(
+                    f,
+                    "Failed to notify webhook (host: {}, error: {})",
+                    host, error
+                )
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// The payload posted to every webhook
+pub struct PublishNotification<'a> {
+    pub event: &'static str,
+    #[serde(flatten)]
+    pub report: &'a PublishReport,
+    /// Human-readable summary, `content` is what Discord shows and `text` what Slack/Matrix
+    /// (hookshot) show
+    pub content: String,
+    pub text: String,
+}
+
+/// Cuts the text to at most `limit` characters (ending with `…` if it was cut)
+fn truncate(text: &str, limit: usize) -> String {
+    if text.chars().count() <= limit {
+        return text.to_string();
+    }
+
+    let mut truncated: String = text.chars().take(limit.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+impl PublishNotification<'_> {
+    pub fn new(report: &PublishReport) -> PublishNotification<'_> {
+        let mut summary = format!("WineFonts database {} published", report.version);
+        if !report.new_fonts.is_empty() {
+            summary += &format!("\nNew fonts: {}", report.new_fonts.join(", "));
+        }
+        if !report.removed_fonts.is_empty() {
+            summary += &format!("\nRemoved fonts: {}", report.removed_fonts.join(", "));
+        }
+        let link = format!("\n{}", report.version_url);
+
+        // Discord refuses longer messages, the font lists are cut but the link is kept
+        let content = format!(
+            "{}{}",
+            truncate(
+                &summary,
+                DISCORD_CONTENT_LIMIT.saturating_sub(link.chars().count())
+            ),
+            link
+        );
+
+        PublishNotification {
+            event: "publish",
+            report,
+            content,
+            text: format!("{}{}", summary, link),
+        }
+    }
+}
+
+/// Posts the publish to every webhook, a failing webhook doesn't stop the others
+pub async fn notify(webhooks: &[Url], report: &PublishReport) -> Vec<NotifyError> {
+    let client = match Client::builder().user_agent(USER_AGENT).build() {
+        Ok(client) => client,
+        Err(e) => return vec![NotifyError::Client(e.to_string())],
+    };
+    let notification = PublishNotification::new(report);
+
+    let mut errors = vec![];
+    for webhook in webhooks {
+        let host = webhook.host_str().unwrap_or_default();
+        match client
+            .post(webhook.clone())
+            .json(&notification)
+            .send()
+            .await
+        {
+            Ok(response) if response.status().is_success() => info!("Notified {}", host),
+            Ok(response) => errors.push(NotifyError::Webhook(
+                host.to_string(),
+                format!("Status code: {}", response.status()),
+            )),
+            Err(e) => errors.push(NotifyError::Webhook(
+                host.to_string(),
+                e.without_url().to_string(),
+            )),
+        }
+    }
+
+    errors
+}
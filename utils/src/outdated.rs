@@ -0,0 +1,64 @@
+use serde::Serialize;
+
+use crate::{
+    fetch::Fetcher,
+    resolve::{github_release, ResolveError, LATEST_TAG},
+    types::{Source, SourceDownload},
+};
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A pinned download that has a newer upstream release
+pub struct OutdatedDownload {
+    pub font: String,
+    pub repo: String,
+    pub asset: String,
+    pub current_tag: String,
+    pub latest_tag: String,
+    /// Whether the newest release still has an asset with the same name
+    pub asset_available: bool,
+}
+
+/// Checks every pinned resolver-based download for a newer upstream release
+pub async fn outdated(
+    source: &Source,
+    fetcher: &Fetcher,
+) -> (Vec<OutdatedDownload>, Vec<ResolveError>) {
+    let mut outdated = vec![];
+    let mut errors = vec![];
+
+    for font in &source.fonts {
+        for installation in &font.installations {
+            // Only pinned GitHub releases can be out of date
+            let (repo, tag, asset) = match installation.download() {
+                SourceDownload::GitHubRelease { repo, tag, asset } if tag != LATEST_TAG => {
+                    (repo, tag, asset)
+                }
+                _ => continue,
+            };
+
+            let latest = match github_release(repo, LATEST_TAG, fetcher).await {
+                Ok(latest) => latest,
+                Err(error) => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+
+            if &latest.tag_name == tag {
+                continue;
+            }
+
+            outdated.push(OutdatedDownload {
+                font: font.name.clone(),
+                repo: repo.clone(),
+                asset: asset.clone(),
+                current_tag: tag.clone(),
+                asset_available: latest.assets.iter().any(|a| &a.name == asset),
+                latest_tag: latest.tag_name,
+            });
+        }
+    }
+
+    (outdated, errors)
+}
@@ -0,0 +1,203 @@
+use std::{collections::HashMap, fmt::Display};
+
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    fetch::Fetcher,
+    types::{Compiled, CompiledIndexes},
+    utils::VersionInfo,
+};
+
+pub enum OverlayError {
+    /// The upstream database couldn't be loaded (Location, Error)
+    Upstream(String, String),
+    /// The upstream database doesn't match its versions.json entry (Url)
+    UpstreamHashMismatch(Url),
+    /// The upstream versions.json has no versions (Location)
+    UpstreamEmpty(String),
+    /// An id is used upstream and in the overlay (Kind, Id)
+    UuidConflict(&'static str, Uuid),
+    /// A name is used upstream and in the overlay (Kind, Name)
+    NameConflict(&'static str, String),
+}
+
+impl Display for OverlayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverlayError::Upstream(location, error) => write!(
+                f,
+                "Failed to load upstream database (location: {}, error: {})",
+                location, error
+            ),
+            OverlayError::UpstreamHashMismatch(url) => {
+                write!(f, "Upstream database hash mismatch (url: {})", url)
+            }
+            OverlayError::UpstreamEmpty(location) => {
+                write!(f, "Upstream has no versions (location: {})", location)
+            }
+            OverlayError::UuidConflict(kind, id) => {
+                write!(f, "The {} id {} is also used upstream", kind, id)
+            }
+            OverlayError::NameConflict(kind, name) => {
+                write!(f, "The {} name \"{}\" is also used upstream", kind, name)
+            }
+        }
+    }
+}
+
+/// Reads a url or a local path
+async fn read_location(location: &str, fetcher: &Fetcher) -> Result<Vec<u8>, OverlayError> {
+    let upstream_error = |error: String| OverlayError::Upstream(location.to_string(), error);
+
+    let url = match Url::parse(location) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => url,
+        _ => return std::fs::read(location).map_err(|e| upstream_error(e.to_string())),
+    };
+
+    let response = fetcher
+        .get(&url)
+        .await
+        .map_err(|e| upstream_error(e.to_string()))?;
+    if !response.status().is_success() {
+        return Err(upstream_error(format!(
+            "Status code: {}",
+            response.status()
+        )));
+    }
+
+    fetcher
+        .read_body(response)
+        .await
+        .map_err(|e| upstream_error(e.to_string()))
+}
+
+/// Loads the upstream database from a compiled file or a versions.json (the newest version is used)
+pub async fn load_upstream(location: &str, fetcher: &Fetcher) -> Result<Compiled, OverlayError> {
+    let upstream_error = |error: String| OverlayError::Upstream(location.to_string(), error);
+
+    let data = read_location(location, fetcher).await?;
+
+    if let Ok(versions) = serde_json::from_slice::<Vec<VersionInfo>>(&data) {
+        let latest = match versions.iter().max_by(|a, b| a.version.cmp(&b.version)) {
+            Some(latest) => latest,
+            None => return Err(OverlayError::UpstreamEmpty(location.to_string())),
+        };
+
+        let data = read_location(latest.download_url.as_str(), fetcher).await?;
+        if sha256::digest(&data) != latest.hash {
+            return Err(OverlayError::UpstreamHashMismatch(
+                latest.download_url.clone(),
+            ));
+        }
+
+        return serde_json::from_slice(&data).map_err(|e| upstream_error(e.to_string()));
+    }
+
+    serde_json::from_slice(&data).map_err(|e| upstream_error(e.to_string()))
+}
+
+/// Merges the built overlay into the upstream database
+///
+/// Downloads with the same content as an upstream one reuse it, any other shared id or
+/// name is a conflict.
+pub fn merge_upstream(
+    upstream: Compiled,
+    overlay: Compiled,
+) -> Result<Compiled, Vec<OverlayError>> {
+    let mut errors = vec![];
+
+    let mut merged = upstream;
+    merged.version = overlay.version;
+
+    // Overlay download id -> upstream download id
+    let mut replaced: HashMap<Uuid, Uuid> = HashMap::new();
+    for download in overlay.downloads {
+        if let Some(existing) = merged.downloads.iter().find(|d| d.hash == download.hash) {
+            replaced.insert(download.id, existing.id);
+            continue;
+        }
+
+        if merged.downloads.iter().any(|d| d.id == download.id) {
+            errors.push(OverlayError::UuidConflict("download", download.id));
+        }
+        merged.downloads.push(download);
+    }
+    for download in &mut merged.downloads {
+        if let Some(parent) = download.derived_from.and_then(|p| replaced.get(&p)) {
+            download.derived_from = Some(*parent);
+        }
+    }
+
+    for publisher in overlay.publishers {
+        match merged.publishers.iter().find(|p| p.id == publisher.id) {
+            // Overlays can share the upstream publishers
+            Some(existing) if existing == &publisher => {}
+            Some(_) => errors.push(OverlayError::UuidConflict("publisher", publisher.id)),
+            None => merged.publishers.push(publisher),
+        }
+    }
+
+    for group in overlay.groups {
+        if merged.groups.iter().any(|g| g.id == group.id) {
+            errors.push(OverlayError::UuidConflict("group", group.id));
+        }
+        if merged
+            .groups
+            .iter()
+            .any(|g| g.name.eq_ignore_ascii_case(&group.name))
+        {
+            errors.push(OverlayError::NameConflict("group", group.name.clone()));
+        }
+        merged.groups.push(group);
+    }
+
+    for application in overlay.applications {
+        if merged.applications.iter().any(|a| a.id == application.id) {
+            errors.push(OverlayError::UuidConflict("application", application.id));
+        }
+        if merged
+            .applications
+            .iter()
+            .any(|a| a.name.eq_ignore_ascii_case(&application.name))
+        {
+            errors.push(OverlayError::NameConflict(
+                "application",
+                application.name.clone(),
+            ));
+        }
+        merged.applications.push(application);
+    }
+
+    for mut font in overlay.fonts {
+        if merged.fonts.iter().any(|f| f.id == font.id) {
+            errors.push(OverlayError::UuidConflict("font", font.id));
+        }
+        for existing in &merged.fonts {
+            if existing.name.eq_ignore_ascii_case(&font.name) {
+                errors.push(OverlayError::NameConflict("font", font.name.clone()));
+            }
+            if existing.short_name.eq_ignore_ascii_case(&font.short_name) {
+                errors.push(OverlayError::NameConflict(
+                    "font short",
+                    font.short_name.clone(),
+                ));
+            }
+        }
+
+        for installation in &mut font.installations {
+            if let Some(upstream) = replaced.get(&installation.download()) {
+                installation.set_download(*upstream);
+            }
+        }
+        merged.fonts.push(font);
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    merged.indexes = CompiledIndexes::from_fonts(&merged.fonts);
+
+    Ok(merged)
+}
@@ -0,0 +1,275 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    future::Future,
+    pin::Pin,
+    time::{Duration, Instant},
+};
+
+use futures::stream::{FuturesUnordered, StreamExt};
+use tracing::Instrument;
+
+use crate::{
+    cancel::Cancellation,
+    journal::Journal,
+    metrics::{Timings, TransferDirection},
+};
+
+/// Most tasks running at once
+pub const DEFAULT_CONCURRENCY: usize = 4;
+/// Times a failing task is tried again
+pub const DEFAULT_RETRIES: u32 = 3;
+/// Wait before the first retry, doubled for every retry after it
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+pub enum PipelineError {
+    /// A task depends on a task that isn't in the graph (Task, Dependency)
+    UnknownDependency(String, String),
+    /// Two tasks have the same id (Task)
+    Duplicate(String),
+    /// The tasks depend on each other in a cycle (Tasks)
+    Cycle(Vec<String>),
+    /// A task still failed after its retries (Task, Error)
+    Failed(String, String),
+    /// The run was cancelled before every task finished (Finished tasks, Pending tasks)
+    Cancelled(Vec<String>, Vec<String>),
+}
+
+impl Display for PipelineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PipelineError::UnknownDependency(task, dependency) => {
+                write!(f, "{} depends on {}, which isn't a task", task, dependency)
+            }
+            PipelineError::Duplicate(task) => write!(f, "{} is in the graph twice", task),
+            PipelineError::Cycle(tasks) => {
+                write!(f, "The tasks depend on each other: {}", tasks.join(", "))
+            }
+            PipelineError::Failed(task, error) => {
+                write!(f, "Failed to publish {}: {}", task, error)
+            }
+            PipelineError::Cancelled(_, pending) => {
+                write!(f, "Cancelled with {} tasks left", pending.len())
+            }
+        }
+    }
+}
+
+type TaskFuture<'a> = Pin<Box<dyn Future<Output = Result<(), String>> + 'a>>;
+
+/// A step of a publish, named by the key it writes
+pub struct Task<'a> {
+    pub id: String,
+    /// Tasks that have to finish first
+    pub dependencies: Vec<String>,
+    /// Bytes the task uploads (recorded as a transfer)
+    pub bytes: Option<u64>,
+    run: Box<dyn Fn() -> TaskFuture<'a> + 'a>,
+}
+
+impl<'a> Task<'a> {
+    /// A task without dependencies, `run` is called again for every retry
+    pub fn new<F, R>(id: impl Into<String>, run: F) -> Task<'a>
+    where
+        F: Fn() -> R + 'a,
+        R: Future<Output = Result<(), String>> + 'a,
+    {
+        Task {
+            id: id.into(),
+            dependencies: vec![],
+            bytes: None,
+            run: Box::new(move || Box::pin(run())),
+        }
+    }
+
+    pub fn after(mut self, dependency: impl Into<String>) -> Task<'a> {
+        self.dependencies.push(dependency.into());
+        self
+    }
+
+    pub fn uploading(mut self, bytes: u64) -> Task<'a> {
+        self.bytes = Some(bytes);
+        self
+    }
+}
+
+/// Checks every dependency is a task and there are no cycles
+fn check(tasks: &[Task<'_>]) -> Result<(), PipelineError> {
+    let mut waiting: HashMap<&str, usize> = HashMap::new();
+    for task in tasks {
+        if waiting.insert(&task.id, task.dependencies.len()).is_some() {
+            return Err(PipelineError::Duplicate(task.id.clone()));
+        }
+    }
+    for task in tasks {
+        if let Some(dependency) = task
+            .dependencies
+            .iter()
+            .find(|dependency| !waiting.contains_key(dependency.as_str()))
+        {
+            return Err(PipelineError::UnknownDependency(
+                task.id.clone(),
+                dependency.clone(),
+            ));
+        }
+    }
+
+    // Take the tasks whose dependencies are all taken, whatever is left is in a cycle
+    let mut ready: Vec<&str> = waiting
+        .iter()
+        .filter(|(_, count)| **count == 0)
+        .map(|(id, _)| *id)
+        .collect();
+    while let Some(id) = ready.pop() {
+        waiting.remove(id);
+        for task in tasks {
+            if !task.dependencies.iter().any(|dependency| dependency == id) {
+                continue;
+            }
+            if let Some(count) = waiting.get_mut(task.id.as_str()) {
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(&task.id);
+                }
+            }
+        }
+    }
+
+    if waiting.is_empty() {
+        Ok(())
+    } else {
+        let mut cycle: Vec<String> = waiting.keys().map(|id| id.to_string()).collect();
+        cycle.sort();
+        Err(PipelineError::Cycle(cycle))
+    }
+}
+
+/// Runs a task, retrying with a growing wait
+async fn attempt<'t, 'a>(
+    task: &'t Task<'a>,
+    retries: u32,
+) -> (&'t Task<'a>, Result<(), String>, Duration) {
+    let started = Instant::now();
+    let mut delay = RETRY_DELAY;
+    let mut tries = 0;
+
+    let result = loop {
+        match (task.run)().await {
+            Ok(()) => break Ok(()),
+            Err(error) if tries < retries => {
+                tries += 1;
+                warn!(
+                    "Failed to publish {}: {}, retrying in {}s ({}/{})",
+                    task.id,
+                    error,
+                    delay.as_secs(),
+                    tries,
+                    retries
+                );
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+            }
+            Err(error) => break Err(error),
+        }
+    };
+
+    (task, result, started.elapsed())
+}
+
+/// Runs the tasks of a publish once their dependencies finished, several at a time
+///
+/// Finished tasks are recorded in the journal, a resumed publish skips them.
+pub struct Scheduler<'a> {
+    /// Most tasks running at once
+    pub concurrency: usize,
+    /// Times a failing task is tried again
+    pub retries: u32,
+    pub journal: &'a mut Journal,
+    /// Where the upload time goes
+    pub timings: &'a mut Timings,
+    /// Checked before every task, the running ones are finished
+    pub cancellation: &'a Cancellation,
+}
+
+impl Scheduler<'_> {
+    pub async fn run(&mut self, tasks: &[Task<'_>]) -> Result<(), PipelineError> {
+        check(tasks)?;
+
+        let mut done: HashSet<&str> = tasks
+            .iter()
+            .filter(|task| self.journal.is_done(&task.id))
+            .map(|task| task.id.as_str())
+            .collect();
+        if !done.is_empty() {
+            info!("Resuming, {} of {} tasks are done", done.len(), tasks.len());
+        }
+
+        let mut started = done.clone();
+        let mut running = FuturesUnordered::new();
+        let mut failed = None;
+
+        loop {
+            // Once a task failed (or the run is cancelled) the running ones are only finished
+            if failed.is_none() && !self.cancellation.is_cancelled() {
+                for task in tasks {
+                    if running.len() >= self.concurrency.max(1) {
+                        break;
+                    }
+                    if started.contains(task.id.as_str())
+                        || !task
+                            .dependencies
+                            .iter()
+                            .all(|dependency| done.contains(dependency.as_str()))
+                    {
+                        continue;
+                    }
+
+                    started.insert(&task.id);
+                    running.push(
+                        attempt(task, self.retries).instrument(info_span!("task", id = %task.id)),
+                    );
+                }
+            }
+
+            let (task, result, elapsed) = match running.next().await {
+                Some(finished) => finished,
+                None => break,
+            };
+
+            match result {
+                Ok(()) => {
+                    done.insert(&task.id);
+                    self.journal.mark_done(&task.id).await;
+                    if let Some(bytes) = task.bytes {
+                        self.timings.add_transfer(
+                            TransferDirection::Upload,
+                            &task.id,
+                            bytes,
+                            elapsed,
+                        );
+                    }
+                    info!("Published {} ({}/{})", task.id, done.len(), tasks.len());
+                }
+                Err(error) => {
+                    error!("Failed to publish {}: {}", task.id, error);
+                    failed.get_or_insert(PipelineError::Failed(task.id.clone(), error));
+                }
+            }
+        }
+
+        if let Some(error) = failed {
+            return Err(error);
+        }
+
+        // The graph has no cycles, only a cancellation leaves tasks behind
+        if done.len() < tasks.len() {
+            let (finished, pending): (Vec<_>, Vec<_>) = tasks
+                .iter()
+                .map(|task| task.id.clone())
+                .partition(|id| done.contains(id.as_str()));
+            return Err(PipelineError::Cancelled(finished, pending));
+        }
+
+        Ok(())
+    }
+}
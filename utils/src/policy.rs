@@ -0,0 +1,89 @@
+use std::fmt::Display;
+
+use crate::types::{CompiledFont, FontLicense};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Where fonts get installed
+pub enum InstallTarget {
+    /// A wine prefix (or a manifest another wine manager installs into one)
+    Prefix,
+    /// The font directories of the host (its native apps)
+    Host,
+    /// Windows itself
+    Windows,
+}
+
+impl Display for InstallTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallTarget::Prefix => write!(f, "wine prefixes"),
+            InstallTarget::Host => write!(f, "the host"),
+            InstallTarget::Windows => write!(f, "Windows"),
+        }
+    }
+}
+
+/// Where fonts under a license may be installed (our reading of the licenses, e.g. the core
+/// fonts EULA lets them be passed on to run Windows software but not to become host fonts)
+pub fn allowed_targets(license: Option<&FontLicense>) -> &'static [InstallTarget] {
+    match license {
+        Some(FontLicense::Open) => &[
+            InstallTarget::Prefix,
+            InstallTarget::Host,
+            InstallTarget::Windows,
+        ],
+        // Unknown (or missing) licenses get the strictest treatment
+        Some(FontLicense::ProprietaryRedistributable)
+        | Some(FontLicense::Proprietary)
+        | Some(FontLicense::Other(_))
+        | None => &[InstallTarget::Prefix],
+    }
+}
+
+pub fn allows(font: &CompiledFont, target: InstallTarget) -> bool {
+    allowed_targets(font.license.as_ref()).contains(&target)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A font whose license doesn't allow the install target
+pub struct PolicyViolation {
+    pub font: String,
+    pub license: Option<FontLicense>,
+    pub target: InstallTarget,
+}
+
+impl Display for PolicyViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let allowed = allowed_targets(self.license.as_ref())
+            .iter()
+            .map(|target| target.to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        match &self.license {
+            Some(license) => write!(
+                f,
+                "{} is {}, it can't be installed into {} (only {})",
+                self.font, license, self.target, allowed
+            ),
+            None => write!(
+                f,
+                "{} has no license in the database, it can't be installed into {} (only {})",
+                self.font, self.target, allowed
+            ),
+        }
+    }
+}
+
+/// The fonts whose license doesn't allow the install target
+pub fn check(fonts: &[&CompiledFont], target: InstallTarget) -> Vec<PolicyViolation> {
+    fonts
+        .iter()
+        .filter(|font| !allows(font, target))
+        .map(|font| PolicyViolation {
+            font: font.name.clone(),
+            license: font.license.clone(),
+            target,
+        })
+        .collect()
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use uuid::Uuid;
+
+use crate::types::{Compiled, CompiledFont};
+
+/// How much bigger than its download an installation is assumed to be when the database
+/// doesn't say
+pub const ESTIMATED_EXPANSION: u64 = 2;
+
+pub enum PreflightError {
+    /// A download the fonts use isn't in the database (Id)
+    MissingDownload(Uuid),
+    /// The free space couldn't be read (Path, Error)
+    Unreadable(PathBuf, String),
+    /// Not enough free space (Path, Needed, Available)
+    InsufficientSpace(PathBuf, u64, u64),
+}
+
+impl Display for PreflightError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PreflightError::MissingDownload(id) => {
+                write!(f, "The download {} isn't in the database", id)
+            }
+            PreflightError::Unreadable(path, error) => write!(
+                f,
+                "Failed to read the free space (path: {}, error: {})",
+                path.display(),
+                error
+            ),
+            PreflightError::InsufficientSpace(path, needed, available) => write!(
+                f,
+                "Not enough free space on {} ({} bytes needed, {} available)",
+                path.display(),
+                needed,
+                available
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+/// The space installing a set of fonts takes
+pub struct SpaceRequirement {
+    /// Downloads (each counted once) that end up in the cache
+    pub download: u64,
+    /// Files written to the prefix
+    pub installed: u64,
+}
+
+/// The space needed to download and install the fonts
+pub fn required_space(
+    compiled: &Compiled,
+    fonts: &[&CompiledFont],
+) -> Result<SpaceRequirement, PreflightError> {
+    let mut requirement = SpaceRequirement::default();
+    let mut downloaded: HashSet<Uuid> = HashSet::new();
+
+    for font in fonts {
+        for installation in font.host_installations() {
+            let id = installation.download();
+            let download = match compiled.downloads.iter().find(|d| d.id == id) {
+                Some(download) => download,
+                None => return Err(PreflightError::MissingDownload(id)),
+            };
+
+            if downloaded.insert(id) {
+                requirement.download += download.file_size;
+            }
+            requirement.installed += installation
+                .installed_size()
+                .unwrap_or(download.file_size * ESTIMATED_EXPANSION);
+        }
+    }
+
+    Ok(requirement)
+}
+
+/// The closest existing directory (the target may not be created yet)
+fn existing_ancestor(path: &Path) -> &Path {
+    path.ancestors()
+        .find(|ancestor| ancestor.exists())
+        .unwrap_or(path)
+}
+
+#[cfg(unix)]
+fn same_volume(a: &Path, b: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    match (std::fs::metadata(a), std::fs::metadata(b)) {
+        (Ok(a), Ok(b)) => a.dev() == b.dev(),
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn same_volume(a: &Path, b: &Path) -> bool {
+    a.components().next() == b.components().next()
+}
+
+fn check_free(path: &Path, needed: u64) -> Result<(), PreflightError> {
+    let available = fs4::available_space(path)
+        .map_err(|e| PreflightError::Unreadable(path.to_path_buf(), e.to_string()))?;
+
+    if available < needed {
+        return Err(PreflightError::InsufficientSpace(
+            path.to_path_buf(),
+            needed,
+            available,
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks the cache and prefix volumes can hold the downloads and the installed files
+pub fn check_space(
+    cache: &Path,
+    prefix: &Path,
+    requirement: SpaceRequirement,
+) -> Result<(), PreflightError> {
+    let cache = existing_ancestor(cache);
+    let prefix = existing_ancestor(prefix);
+
+    if same_volume(cache, prefix) {
+        return check_free(cache, requirement.download + requirement.installed);
+    }
+
+    check_free(cache, requirement.download)?;
+    check_free(prefix, requirement.installed)
+}
@@ -0,0 +1,224 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    types::{Compiled, CompiledFont},
+    wine,
+};
+
+/// The profile `sync --profile` reads when no path is given
+pub const DEFAULT_PROFILE_FILE: &str = "winefonts-profile.toml";
+
+/// The newest profile format we can read
+pub const PROFILE_VERSION: u32 = 1;
+
+/// How close a name has to be to be suggested for an unknown reference
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+
+pub enum ProfileError {
+    /// The profile couldn't be read (Path, Error)
+    Read(PathBuf, String),
+    /// The profile isn't valid TOML or doesn't match the schema (Path, Error)
+    Parse(PathBuf, String),
+    /// The profile is newer than this client (Path, Version)
+    UnsupportedVersion(PathBuf, u32),
+    /// A field has a value the schema doesn't allow (Path, Field, Error)
+    Invalid(PathBuf, String, String),
+    /// A font reference isn't in the database (Path, Reference, Suggestion)
+    UnknownReference(PathBuf, String, Option<String>),
+}
+
+impl Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileError::Read(path, error) => {
+                write!(f, "Failed to read {}: {}", path.display(), error)
+            }
+            ProfileError::Parse(path, error) => {
+                write!(f, "{} isn't a valid profile: {}", path.display(), error)
+            }
+            ProfileError::UnsupportedVersion(path, version) => write!(
+                f,
+                "{} has version {}, this client only knows version {}",
+                path.display(),
+                version,
+                PROFILE_VERSION
+            ),
+            ProfileError::Invalid(path, field, error) => {
+                write!(f, "{}: `{}` {}", path.display(), field, error)
+            }
+            ProfileError::UnknownReference(path, reference, suggestion) => {
+                write!(
+                    f,
+                    "{}: no font, group or application is called \"{}\"",
+                    path.display(),
+                    reference
+                )?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, ", did you mean \"{}\"?", suggestion)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+fn default_version() -> u32 {
+    PROFILE_VERSION
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+/// The options of a profile, flags given to `sync` win over them
+pub struct ProfileOptions {
+    /// The versions.json of the database
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_url: Option<Url>,
+    /// The wine version fonts have to work with (e.g. `9.0` or `wine-9.0 (Staging)`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wine_version: Option<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub ignore_wine_version: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub race: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+/// The fonts a prefix should have (e.g. "here's the profile for running Office"), see
+/// `winefonts sync --profile`
+pub struct Profile {
+    #[serde(default = "default_version")]
+    pub version: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Font, group or application names (or uuids, or `steam:<app id>`)
+    pub fonts: Vec<String>,
+    #[serde(default)]
+    pub options: ProfileOptions,
+}
+
+impl Profile {
+    /// Reads and validates a profile
+    pub fn load(path: &Path) -> Result<Profile, ProfileError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| ProfileError::Read(path.to_owned(), e.to_string()))?;
+
+        Profile::parse(path, &text)
+    }
+
+    /// Parses and validates a profile (the path is only used for errors)
+    pub fn parse(path: &Path, text: &str) -> Result<Profile, ProfileError> {
+        let profile: Profile = toml::from_str(text)
+            .map_err(|e| ProfileError::Parse(path.to_owned(), e.to_string()))?;
+        let invalid = |field: &str, error: &str| {
+            ProfileError::Invalid(path.to_owned(), field.into(), error.into())
+        };
+
+        if profile.version > PROFILE_VERSION {
+            return Err(ProfileError::UnsupportedVersion(
+                path.to_owned(),
+                profile.version,
+            ));
+        }
+
+        if profile.fonts.is_empty() {
+            return Err(invalid(
+                "fonts",
+                "is empty, list at least one font or group",
+            ));
+        }
+        let mut seen = HashSet::new();
+        for reference in &profile.fonts {
+            if reference.trim().is_empty() {
+                return Err(invalid("fonts", "has an empty name"));
+            }
+            if !seen.insert(reference.to_lowercase()) {
+                return Err(invalid(
+                    "fonts",
+                    &format!("lists \"{}\" more than once", reference),
+                ));
+            }
+        }
+
+        if let Some(url) = &profile.options.database_url {
+            if !matches!(url.scheme(), "http" | "https") {
+                return Err(invalid("options.database-url", "has to be an http(s) url"));
+            }
+        }
+        if let Some(version) = &profile.options.wine_version {
+            if wine::parse_version(version).is_none() {
+                return Err(invalid(
+                    "options.wine-version",
+                    &format!("\"{}\" isn't a wine version", version),
+                ));
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// The wine version of the options (it was validated when the profile was parsed)
+    pub fn wine_version(&self) -> Option<Version> {
+        self.options
+            .wine_version
+            .as_deref()
+            .and_then(wine::parse_version)
+    }
+
+    /// Resolves the fonts of the profile against the database (they can overlap), unknown
+    /// references are errors with the closest name as a suggestion
+    pub fn select<'a>(
+        &self,
+        path: &Path,
+        compiled: &'a Compiled,
+    ) -> Result<Vec<&'a CompiledFont>, ProfileError> {
+        let mut selected: Vec<&CompiledFont> = vec![];
+        let mut seen = HashSet::new();
+
+        for reference in &self.fonts {
+            match compiled.select(reference) {
+                Some(found) => selected.extend(found.into_iter().filter(|f| seen.insert(f.id))),
+                None => {
+                    return Err(ProfileError::UnknownReference(
+                        path.to_owned(),
+                        reference.clone(),
+                        suggest(reference, compiled),
+                    ))
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+}
+
+/// The closest font, group or application name
+fn suggest(reference: &str, compiled: &Compiled) -> Option<String> {
+    let reference = reference.to_lowercase();
+
+    compiled
+        .fonts
+        .iter()
+        .map(|font| &font.name)
+        .chain(compiled.groups.iter().map(|group| &group.name))
+        .chain(
+            compiled
+                .applications
+                .iter()
+                .map(|application| &application.name),
+        )
+        .map(|name| (name, strsim::jaro_winkler(&name.to_lowercase(), &reference)))
+        .filter(|(_, similarity)| *similarity >= SUGGESTION_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(name, _)| name.clone())
+}
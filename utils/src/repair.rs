@@ -0,0 +1,362 @@
+use std::{
+    fmt::Display,
+    path::{Component, Path},
+};
+
+use s3::{serde_types::Object, Bucket};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    types::{Compiled, DownloadAccess},
+    utils::{
+        download_directory, generate_url, generate_versions_url, list_objects, object_hash,
+        object_source, object_uuid, put_download_object, Downloadable, DownloadsList,
+        UploadableData, VersionInfo, VERSIONS_FILE_PATH,
+    },
+};
+
+pub enum Discrepancy {
+    /// A download in downloadables.json has no object (Id, Key)
+    MissingDownload(Uuid, String),
+    /// A download object doesn't match downloadables.json (Id, Key, Expected hash, Hash)
+    DownloadHashMismatch(Uuid, String, String, String),
+    /// A download object isn't in downloadables.json (Key, Downloadable)
+    UnlistedDownload(String, Downloadable),
+    /// A version in versions.json has no object (Id, Key)
+    MissingVersion(Uuid, String),
+    /// A version object doesn't match versions.json (Id, Key, Expected hash, Hash)
+    VersionHashMismatch(Uuid, String, String, String),
+    /// A version object isn't in versions.json (Key, Version)
+    UnlistedVersion(String, VersionInfo),
+    /// An object in a managed directory we can't make sense of (Key, Reason)
+    UnknownObject(String, String),
+}
+
+impl Display for Discrepancy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Discrepancy::MissingDownload(id, key) => {
+                write!(f, "The download {} is listed but {} doesn't exist", id, key)
+            }
+            Discrepancy::DownloadHashMismatch(id, key, expected, hash) => write!(
+                f,
+                "The download {} ({}) has the hash {} but is listed with {}",
+                id, key, hash, expected
+            ),
+            Discrepancy::UnlistedDownload(key, _) => {
+                write!(f, "The download {} isn't in downloadables.json", key)
+            }
+            Discrepancy::MissingVersion(id, key) => {
+                write!(f, "The version {} is listed but {} doesn't exist", id, key)
+            }
+            Discrepancy::VersionHashMismatch(id, key, expected, hash) => write!(
+                f,
+                "The version {} ({}) has the hash {} but is listed with {}",
+                id, key, hash, expected
+            ),
+            Discrepancy::UnlistedVersion(key, _) => {
+                write!(f, "The version {} isn't in versions.json", key)
+            }
+            Discrepancy::UnknownObject(key, reason) => {
+                write!(f, "Unknown object {} ({})", key, reason)
+            }
+        }
+    }
+}
+
+impl Discrepancy {
+    /// What `fix` does about it
+    pub fn fix_description(&self) -> &'static str {
+        match self {
+            Discrepancy::MissingDownload(_, _) => "remove it from downloadables.json",
+            Discrepancy::DownloadHashMismatch(_, _, _, _) => {
+                "upload it again from the resource it was uploaded from (under --base-path)"
+            }
+            Discrepancy::UnlistedDownload(_, _) => "add it to downloadables.json",
+            Discrepancy::MissingVersion(_, _) => "remove it from versions.json",
+            Discrepancy::VersionHashMismatch(_, _, _, _) => "remove it from versions.json",
+            Discrepancy::UnlistedVersion(_, _) => "add it to versions.json",
+            Discrepancy::UnknownObject(_, _) => "leave it alone",
+        }
+    }
+}
+
+/// Reads an object, exits on failure
+async fn get_object(s3: &Bucket, key: &str) -> Vec<u8> {
+    match s3.get_object(key).await {
+        Ok(data) => data.to_vec(),
+        Err(e) => {
+            error!("Failed to get {}: {}", key, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The size and hash of a download object, hashed unless `trust_metadata` (then the metadata is
+/// used if it has them), exits on failure
+async fn download_hash(s3: &Bucket, key: &str, trust_metadata: bool) -> (u64, String) {
+    if !trust_metadata {
+        let data = get_object(s3, key).await;
+        return (data.len() as u64, sha256::digest(&data));
+    }
+
+    match object_hash(s3, key).await {
+        Ok(found) => found,
+        Err(e) => {
+            error!("Failed to get {}: {}", key, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// The bucket key of a listed download
+pub fn download_key(download: &Downloadable) -> String {
+    let file_name = download
+        .download_url
+        .path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .unwrap_or_default();
+    let file_name = urlencoding::decode(file_name)
+        .map(|name| name.into_owned())
+        .unwrap_or_else(|_| file_name.to_string());
+
+    format!("{}/{}", download_directory(download.access), file_name)
+}
+
+/// Cross-checks the indexes against the bucket, every object is hashed unless `trust_metadata`
+/// (then downloads are checked against the hash their metadata records)
+pub async fn check(
+    s3: &Bucket,
+    base_url: &Url,
+    downloadables: &DownloadsList,
+    versions: &[VersionInfo],
+    trust_metadata: bool,
+) -> Vec<Discrepancy> {
+    let mut discrepancies = vec![];
+
+    // Downloads
+    let mut objects: Vec<(DownloadAccess, Object)> = vec![];
+    for access in [DownloadAccess::Public, DownloadAccess::Signed] {
+        for object in list_objects(s3, download_directory(access)).await {
+            objects.push((access, object));
+        }
+    }
+
+    for download in downloadables {
+        let key = download_key(download);
+
+        if !objects.iter().any(|(_, object)| object.key == key) {
+            discrepancies.push(Discrepancy::MissingDownload(download.id, key));
+            continue;
+        }
+
+        let (_, hash) = download_hash(s3, &key, trust_metadata).await;
+        if !hash.eq_ignore_ascii_case(&download.hash) {
+            discrepancies.push(Discrepancy::DownloadHashMismatch(
+                download.id,
+                key,
+                download.hash.clone(),
+                hash,
+            ));
+        }
+    }
+
+    for (access, object) in &objects {
+        if downloadables.iter().any(|d| download_key(d) == object.key) {
+            continue;
+        }
+
+        let (uuid, extension) = match object_uuid(&object.key) {
+            Some(found) => found,
+            None => {
+                discrepancies.push(Discrepancy::UnknownObject(
+                    object.key.clone(),
+                    "not named after a uuid".to_string(),
+                ));
+                continue;
+            }
+        };
+
+        let (file_size, hash) = download_hash(s3, &object.key, trust_metadata).await;
+        discrepancies.push(Discrepancy::UnlistedDownload(
+            object.key.clone(),
+            Downloadable {
+                id: uuid,
+                file_size,
+                hash,
+                download_url: generate_url(base_url, extension.as_deref(), &uuid, *access),
+                access: *access,
+                mirrors: vec![],
+            },
+        ));
+    }
+
+    // Versions
+    let objects = list_objects(s3, VERSIONS_FILE_PATH).await;
+
+    for version in versions {
+        let key = format!("{}/{}.json", VERSIONS_FILE_PATH, version.id);
+
+        if !objects.iter().any(|object| object.key == key) {
+            discrepancies.push(Discrepancy::MissingVersion(version.id, key));
+            continue;
+        }
+
+        let hash = sha256::digest(get_object(s3, &key).await);
+        if !hash.eq_ignore_ascii_case(&version.hash) {
+            discrepancies.push(Discrepancy::VersionHashMismatch(
+                version.id,
+                key,
+                version.hash.clone(),
+                hash,
+            ));
+        }
+    }
+
+    for object in &objects {
+        let uuid = match object_uuid(&object.key) {
+            Some((uuid, _)) => uuid,
+            None => {
+                discrepancies.push(Discrepancy::UnknownObject(
+                    object.key.clone(),
+                    "not named after a uuid".to_string(),
+                ));
+                continue;
+            }
+        };
+
+        if versions.iter().any(|v| v.id == uuid) {
+            continue;
+        }
+
+        let data = get_object(s3, &object.key).await;
+        let compiled: Compiled = match serde_json::from_slice(&data) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                discrepancies.push(Discrepancy::UnknownObject(
+                    object.key.clone(),
+                    e.to_string(),
+                ));
+                continue;
+            }
+        };
+
+        discrepancies.push(Discrepancy::UnlistedVersion(
+            object.key.clone(),
+            VersionInfo {
+                id: uuid,
+                version: compiled.version,
+                download_url: generate_versions_url(base_url, &uuid),
+                hash: sha256::digest(&data),
+                file_size: data.len() as u64,
+                mirrors: vec![],
+                expires_at: None,
+            },
+        ));
+    }
+
+    discrepancies
+}
+
+/// Uploads a download whose object doesn't match again from the resource its metadata records,
+/// published versions still point at it so it's never deleted
+async fn reupload(s3: &Bucket, base_path: Option<&Path>, key: &str, expected: &str) -> bool {
+    let base_path = match base_path {
+        Some(base_path) => base_path,
+        None => {
+            warn!(
+                "Can't upload {} again without --base-path, leaving it alone",
+                key
+            );
+            return false;
+        }
+    };
+    let source = match object_source(s3, key).await {
+        Ok(Some(source)) => source,
+        Ok(None) => {
+            warn!(
+                "{} doesn't record its resource, upload it again by hand",
+                key
+            );
+            return false;
+        }
+        Err(e) => {
+            error!("Failed to get {}: {}", key, e);
+            std::process::exit(1);
+        }
+    };
+    // The metadata is only trusted to name a file under the base path
+    if !source
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+    {
+        warn!(
+            "{} records the resource {} outside of the base path",
+            key,
+            source.display()
+        );
+        return false;
+    }
+
+    let path = base_path.join(&source);
+    let hash = match sha256::try_digest(&path) {
+        Ok(hash) => hash,
+        Err(e) => {
+            warn!(
+                "Can't upload {} again, failed to read {}: {}",
+                key,
+                path.display(),
+                e
+            );
+            return false;
+        }
+    };
+    if !hash.eq_ignore_ascii_case(expected) {
+        warn!(
+            "Can't upload {} again, {} no longer has the listed hash",
+            key,
+            path.display()
+        );
+        return false;
+    }
+
+    match put_download_object(s3, key, base_path, &UploadableData::File(source), expected).await {
+        Ok(_) => {
+            info!("Uploaded {} again from {}", key, path.display());
+            true
+        }
+        Err(e) => {
+            error!("Failed to upload {}: {}", key, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Applies the fix of a discrepancy to the indexes (and the bucket), returns whether it could be
+/// fixed
+pub async fn fix(
+    s3: &Bucket,
+    discrepancy: Discrepancy,
+    base_path: Option<&Path>,
+    downloadables: &mut DownloadsList,
+    versions: &mut Vec<VersionInfo>,
+) -> bool {
+    match discrepancy {
+        Discrepancy::MissingDownload(id, _) => downloadables.retain(|d| d.id != id),
+        Discrepancy::DownloadHashMismatch(_, key, expected, _) => {
+            return reupload(s3, base_path, &key, &expected).await
+        }
+        Discrepancy::UnlistedDownload(_, download) => downloadables.push(download),
+        Discrepancy::MissingVersion(id, _) | Discrepancy::VersionHashMismatch(id, _, _, _) => {
+            versions.retain(|v| v.id != id)
+        }
+        Discrepancy::UnlistedVersion(_, version) => {
+            versions.push(version);
+            versions.sort_by(|a, b| a.version.cmp(&b.version));
+        }
+        Discrepancy::UnknownObject(_, _) => return false,
+    }
+
+    true
+}
@@ -0,0 +1,200 @@
+use semver::Version;
+use serde::{Deserialize, Serialize};
+use url::Url;
+use uuid::Uuid;
+
+use crate::{
+    metrics::{format_rate, Timings},
+    types::Compiled,
+};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// A download merged into an identical one
+pub struct MergedDownload {
+    pub id: Uuid,
+    pub into: Uuid,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+/// Redundant downloads removed from a build
+pub struct PrunedDownloads {
+    /// Identical downloads (same content and access) merged into one
+    pub merged: Vec<MergedDownload>,
+    /// Downloads listed more than once (the extra entries are removed)
+    pub duplicated: Vec<Uuid>,
+    /// Downloads nothing installs or derives from
+    pub dropped: Vec<Uuid>,
+}
+
+impl PrunedDownloads {
+    pub fn is_empty(&self) -> bool {
+        self.merged.is_empty() && self.duplicated.is_empty() && self.dropped.is_empty()
+    }
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+/// What an update published
+pub struct PublishReport {
+    pub version: Version,
+    pub version_id: Uuid,
+    pub version_url: Url,
+    /// The newest version before this one
+    pub previous_version: Option<Version>,
+    /// Fonts that weren't in the previous version (names)
+    pub new_fonts: Vec<String>,
+    /// Fonts that were in the previous version but aren't anymore (names)
+    pub removed_fonts: Vec<String>,
+    pub fonts: usize,
+    pub uploaded_downloads: usize,
+    pub reused_downloads: usize,
+    /// Uploaded downloads and the version file (in bytes)
+    pub bytes_uploaded: u64,
+    pub timings: Timings,
+    #[serde(skip_serializing_if = "PrunedDownloads::is_empty")]
+    pub pruned: PrunedDownloads,
+}
+
+impl PublishReport {
+    pub fn new(
+        built: &Compiled,
+        previous: Option<&Compiled>,
+        uploaded_downloads: usize,
+        uploaded_bytes: u64,
+        version_id: Uuid,
+        version_url: Url,
+        version_size: u64,
+    ) -> PublishReport {
+        let (new_fonts, removed_fonts) = match previous {
+            Some(previous) => (
+                built
+                    .fonts
+                    .iter()
+                    .filter(|f| !previous.fonts.iter().any(|p| p.id == f.id))
+                    .map(|f| f.name.clone())
+                    .collect(),
+                previous
+                    .fonts
+                    .iter()
+                    .filter(|p| !built.fonts.iter().any(|f| f.id == p.id))
+                    .map(|p| p.name.clone())
+                    .collect(),
+            ),
+            None => (built.fonts.iter().map(|f| f.name.clone()).collect(), vec![]),
+        };
+
+        PublishReport {
+            version: built.version.clone(),
+            version_id,
+            version_url,
+            previous_version: previous.map(|p| p.version.clone()),
+            new_fonts,
+            removed_fonts,
+            fonts: built.fonts.len(),
+            uploaded_downloads,
+            reused_downloads: built.downloads.len().saturating_sub(uploaded_downloads),
+            bytes_uploaded: uploaded_bytes + version_size,
+            timings: Timings::default(),
+            pruned: PrunedDownloads::default(),
+        }
+    }
+
+    /// Adds where the update spent its time
+    pub fn with_timings(mut self, timings: Timings) -> PublishReport {
+        self.timings = timings;
+        self
+    }
+
+    /// Adds the downloads the build pruned
+    pub fn with_pruned(mut self, pruned: PrunedDownloads) -> PublishReport {
+        self.pruned = pruned;
+        self
+    }
+
+    /// Logs the report as a table
+    pub fn print_summary(&self) {
+        let mut rows = vec![
+            ("Version".to_string(), self.version.to_string()),
+            (
+                "Previous version".to_string(),
+                match &self.previous_version {
+                    Some(version) => version.to_string(),
+                    None => "none".to_string(),
+                },
+            ),
+            ("Version url".to_string(), self.version_url.to_string()),
+            ("Fonts".to_string(), self.fonts.to_string()),
+            ("New fonts".to_string(), list(&self.new_fonts)),
+            ("Removed fonts".to_string(), list(&self.removed_fonts)),
+            (
+                "Uploaded downloads".to_string(),
+                self.uploaded_downloads.to_string(),
+            ),
+            (
+                "Reused downloads".to_string(),
+                self.reused_downloads.to_string(),
+            ),
+            (
+                "Bytes uploaded".to_string(),
+                self.bytes_uploaded.to_string(),
+            ),
+            (
+                "Merged downloads".to_string(),
+                self.pruned.merged.len().to_string(),
+            ),
+            (
+                "Duplicated downloads".to_string(),
+                self.pruned.duplicated.len().to_string(),
+            ),
+            (
+                "Dropped downloads".to_string(),
+                self.pruned.dropped.len().to_string(),
+            ),
+        ];
+
+        for phase in &self.timings.phases {
+            rows.push((
+                format!("{} time", capitalize(&phase.name)),
+                format!("{:.2}s", phase.seconds),
+            ));
+        }
+
+        let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+
+        info!("Publish summary");
+        for (name, value) in rows {
+            info!("  {:width$}  {}", name, value, width = width);
+        }
+
+        if !self.timings.transfers.is_empty() {
+            info!("Transfers");
+        }
+        for transfer in &self.timings.transfers {
+            info!(
+                "  {:?} {} ({} bytes in {:.2}s, {})",
+                transfer.direction,
+                transfer.artifact,
+                transfer.bytes,
+                transfer.seconds,
+                format_rate(transfer.rate())
+            );
+        }
+    }
+}
+
+fn list(names: &[String]) -> String {
+    match names.is_empty() {
+        true => "none".to_string(),
+        false => format!("{} ({})", names.len(), names.join(", ")),
+    }
+}
+
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
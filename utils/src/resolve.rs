@@ -0,0 +1,154 @@
+use std::{fmt::Display, path::PathBuf};
+
+use reqwest::header::{self, HeaderMap, HeaderValue};
+use serde::Deserialize;
+use url::Url;
+
+use crate::{fetch::Fetcher, types::SourceDownload};
+
+/// The tag used to track the newest release
+pub const LATEST_TAG: &str = "latest";
+
+pub enum ResolveError {
+    /// The GitHub API request failed (Repo, Error)
+    GitHubRequest(String, String),
+    /// The release doesn't have the asset (Repo, Tag, Asset)
+    GitHubAssetMissing(String, String, String),
+    /// The resolved url is invalid (Url, Error)
+    InvalidUrl(String, String),
+}
+
+impl Display for ResolveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveError::GitHubRequest(repo, error) => {
+                write!(
+                    f,
+                    "GitHub request failed (repo: {}, error: {})",
+                    repo, error
+                )
+            }
+            ResolveError::GitHubAssetMissing(repo, tag, asset) => write!(
+                f,
+                "GitHub release asset missing (repo: {}, tag: {}, asset: {})",
+                repo, tag, asset
+            ),
+            ResolveError::InvalidUrl(url, error) => {
+                write!(f, "Invalid resolved url (url: {}, error: {})", url, error)
+            }
+        }
+    }
+}
+
+/// A download after the resolvers have run
+pub enum ResolvedDownload {
+    /// A concrete url we don't redistribute
+    External(Url),
+    /// A local file we redistribute
+    Local(PathBuf),
+}
+
+#[derive(Deserialize, Debug, Clone)]
+/// The parts of a GitHub release we care about
+pub struct GitHubRelease {
+    pub tag_name: String,
+    pub assets: Vec<GitHubAsset>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct GitHubAsset {
+    pub name: String,
+    pub browser_download_url: Url,
+}
+
+/// Fetches a release of a GitHub repository (`latest` for the newest one)
+pub async fn github_release(
+    repo: &str,
+    tag: &str,
+    fetcher: &Fetcher,
+) -> Result<GitHubRelease, ResolveError> {
+    let api_url = match tag {
+        LATEST_TAG => format!("https://api.github.com/repos/{}/releases/latest", repo),
+        tag => format!(
+            "https://api.github.com/repos/{}/releases/tags/{}",
+            repo,
+            urlencoding::encode(tag)
+        ),
+    };
+    let api_url = match Url::parse(&api_url) {
+        Ok(url) => url,
+        Err(e) => return Err(ResolveError::InvalidUrl(api_url, e.to_string())),
+    };
+
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::ACCEPT,
+        HeaderValue::from_static("application/vnd.github+json"),
+    );
+    // Use a token if we have one, the anonymous rate limit is tiny
+    if let Some(token) = std::env::var("GITHUB_TOKEN")
+        .ok()
+        .and_then(|token| HeaderValue::from_str(&format!("Bearer {}", token)).ok())
+    {
+        headers.insert(header::AUTHORIZATION, token);
+    }
+
+    let github_error = |error: String| ResolveError::GitHubRequest(repo.to_string(), error);
+
+    let response = match fetcher.get_with_headers(&api_url, headers).await {
+        Ok(response) => response,
+        Err(e) => return Err(github_error(e.to_string())),
+    };
+
+    if !response.status().is_success() {
+        return Err(github_error(format!("Status code: {}", response.status())));
+    }
+
+    let bytes = match fetcher.read_body(response).await {
+        Ok(bytes) => bytes,
+        Err(e) => return Err(github_error(e.to_string())),
+    };
+
+    match serde_json::from_slice(&bytes) {
+        Ok(release) => Ok(release),
+        Err(e) => Err(github_error(e.to_string())),
+    }
+}
+
+/// Turns a source download into something we can fetch
+pub async fn resolve(
+    download: &SourceDownload,
+    fetcher: &Fetcher,
+) -> Result<ResolvedDownload, ResolveError> {
+    match download {
+        SourceDownload::ExternalResource(url) => Ok(ResolvedDownload::External(url.clone())),
+        SourceDownload::LocalResource(path) => Ok(ResolvedDownload::Local(path.clone())),
+        SourceDownload::GitHubRelease { repo, tag, asset } => {
+            let release = github_release(repo, tag, fetcher).await?;
+
+            match release.assets.into_iter().find(|a| &a.name == asset) {
+                Some(found) => Ok(ResolvedDownload::External(found.browser_download_url)),
+                None => Err(ResolveError::GitHubAssetMissing(
+                    repo.clone(),
+                    release.tag_name,
+                    asset.clone(),
+                )),
+            }
+        }
+        SourceDownload::SourceForge { project, file } => {
+            let url = format!(
+                "https://downloads.sourceforge.net/project/{}/{}",
+                urlencoding::encode(project),
+                file.split('/')
+                    .map(|segment| urlencoding::encode(segment).into_owned())
+                    .collect::<Vec<_>>()
+                    .join("/")
+            );
+
+            match Url::parse(&url) {
+                Ok(url) => Ok(ResolvedDownload::External(url)),
+                Err(e) => Err(ResolveError::InvalidUrl(url, e.to_string())),
+            }
+        }
+    }
+}
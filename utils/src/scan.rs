@@ -0,0 +1,94 @@
+use std::{fmt::Display, path::Path, process::Command};
+
+use uuid::Uuid;
+
+pub enum ScanError {
+    /// The scanner command is empty
+    EmptyCommand,
+    /// The artifact couldn't be written for the scanner (Name, Error)
+    Io(String, String),
+    /// The scanner couldn't be started (Program, Error)
+    Spawn(String, String),
+    /// The scanner rejected the artifact (Name, Exit status)
+    Rejected(String, String),
+}
+
+impl Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScanError::EmptyCommand => write!(f, "The scan command is empty"),
+            ScanError::Io(name, error) => {
+                write!(
+                    f,
+                    "Failed to write artifact (name: {}, error: {})",
+                    name, error
+                )
+            }
+            ScanError::Spawn(program, error) => write!(
+                f,
+                "Failed to run scanner (program: {}, error: {})",
+                program, error
+            ),
+            ScanError::Rejected(name, status) => write!(
+                f,
+                "The scanner rejected the artifact (name: {}, status: {})",
+                name, status
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+/// An external command every artifact is run through (e.g. `clamscan --no-summary`)
+///
+/// The path of the artifact is appended to the arguments and a non-zero exit rejects it.
+pub struct Scanner {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Scanner {
+    /// Parses a whitespace separated command
+    pub fn parse(command: &str) -> Result<Scanner, ScanError> {
+        let mut parts = command.split_whitespace().map(|part| part.to_string());
+
+        match parts.next() {
+            Some(program) => Ok(Scanner {
+                program,
+                args: parts.collect(),
+            }),
+            None => Err(ScanError::EmptyCommand),
+        }
+    }
+
+    /// Writes the artifact to a temp file and runs the scanner on it
+    pub fn scan(&self, name: &str, data: &[u8]) -> Result<(), ScanError> {
+        // Keep the file name so scanners can detect the type from the extension
+        let file_name = Path::new(name)
+            .file_name()
+            .map(|file_name| file_name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let dir = std::env::temp_dir().join(format!("winefonts-scan-{}", Uuid::new_v4()));
+        let path = dir.join(&file_name);
+
+        let io_error = |e: std::io::Error| ScanError::Io(name.to_string(), e.to_string());
+
+        std::fs::create_dir_all(&dir).map_err(io_error)?;
+        let result = std::fs::write(&path, data).map_err(io_error).and_then(|_| {
+            Command::new(&self.program)
+                .args(&self.args)
+                .arg(&path)
+                .status()
+                .map_err(|e| ScanError::Spawn(self.program.clone(), e.to_string()))
+        });
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let status = result?;
+        if !status.success() {
+            return Err(ScanError::Rejected(name.to_string(), status.to_string()));
+        }
+
+        info!("Scanned {}", name);
+        Ok(())
+    }
+}
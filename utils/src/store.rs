@@ -0,0 +1,331 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::{fs, sync::OwnedMutexGuard};
+use uuid::Uuid;
+
+use crate::{types::CompiledDownloads, utils::now};
+
+/// The index of the downloads in the cache directory
+pub const INDEX_FILE: &str = "index.json";
+
+/// The platform cache directory: `$XDG_CACHE_HOME/winefonts` (or `~/.cache/winefonts`),
+/// `~/Library/Caches/winefonts` on macOS
+pub fn default_cache_dir() -> PathBuf {
+    if let Some(cache) = std::env::var_os("XDG_CACHE_HOME") {
+        return PathBuf::from(cache).join("winefonts");
+    }
+
+    match std::env::var_os("HOME") {
+        Some(home) if cfg!(target_os = "macos") => PathBuf::from(home)
+            .join("Library")
+            .join("Caches")
+            .join("winefonts"),
+        Some(home) => PathBuf::from(home).join(".cache").join("winefonts"),
+        None => std::env::temp_dir().join("winefonts"),
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A cached download as it was when it was verified
+pub struct IndexEntry {
+    pub hash: String,
+    pub file_size: u64,
+    /// When an install last used it (unix time in seconds)
+    #[serde(default)]
+    pub last_used: u64,
+}
+
+/// Parses a byte count (e.g. `500M`, `2GB` or `1048576`), the units are powers of 1000 like
+/// `utils::format_size`
+pub fn parse_size(text: &str) -> Option<u64> {
+    let text = text.trim().to_ascii_uppercase();
+    let text = text.strip_suffix('B').unwrap_or(&text);
+    let (number, multiplier) = match text.char_indices().last()? {
+        (index, 'K') => (&text[..index], 1_000.0),
+        (index, 'M') => (&text[..index], 1_000_000.0),
+        (index, 'G') => (&text[..index], 1_000_000_000.0),
+        _ => (text, 1.0),
+    };
+
+    let number: f64 = number.trim().parse().ok()?;
+    (number.is_finite() && number >= 0.0).then_some((number * multiplier) as u64)
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// How big and how old the cache may get, the least recently used downloads go first
+pub struct EvictionPolicy {
+    pub max_size: Option<u64>,
+    pub max_age: Option<Duration>,
+}
+
+impl EvictionPolicy {
+    /// Whether there's anything to enforce
+    pub fn is_unbounded(&self) -> bool {
+        self.max_size.is_none() && self.max_age.is_none()
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// What the cache holds
+pub struct CacheStats {
+    pub downloads: usize,
+    /// The size of the cached downloads (in bytes)
+    pub size: u64,
+    /// When the least recently used download was last used (unix time in seconds)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub least_recently_used: Option<u64>,
+}
+
+/// The downloads kept in the cache directory, indexed by id with the hash they were verified
+/// against
+///
+/// Opening the cache checks every indexed file, corrupted ones are removed so they're fetched
+/// again instead of failing an install at extract time.
+pub struct ArtifactCache {
+    directory: PathBuf,
+    index: Mutex<BTreeMap<Uuid, IndexEntry>>,
+    /// Held while a download is fetched or read (prefixes installed concurrently share it)
+    downloads: Mutex<HashMap<Uuid, Arc<tokio::sync::Mutex<()>>>>,
+    /// Held while the index is written
+    saving: tokio::sync::Mutex<()>,
+}
+
+impl ArtifactCache {
+    /// Opens the cache, a missing or broken index results in an empty one (the files it
+    /// doesn't know are checked against the database when they're used)
+    pub async fn open(directory: PathBuf) -> ArtifactCache {
+        let index: BTreeMap<Uuid, IndexEntry> = match fs::read(directory.join(INDEX_FILE)).await {
+            Ok(data) => serde_json::from_slice(&data).unwrap_or_else(|error| {
+                warn!(
+                    "Failed to parse the cache index: {}... Using an empty index",
+                    error
+                );
+                BTreeMap::new()
+            }),
+            Err(_) => BTreeMap::new(),
+        };
+
+        let indexed = index.len();
+        let mut backfilled = false;
+        let mut verified = BTreeMap::new();
+        for (id, mut entry) in index {
+            let path = directory.join(id.to_string());
+            let intact = sha256::try_digest(&path)
+                .map(|hash| hash.eq_ignore_ascii_case(&entry.hash))
+                .unwrap_or(false);
+
+            if intact {
+                // Indexes from older clients don't know when a download was used
+                if entry.last_used == 0 {
+                    entry.last_used = fs::metadata(&path)
+                        .await
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                        .map(|duration| duration.as_secs())
+                        .unwrap_or_else(now);
+                    backfilled = true;
+                }
+                verified.insert(id, entry);
+                continue;
+            }
+            if fs::try_exists(&path).await.unwrap_or(false) {
+                warn!(
+                    "The cached download {} is corrupted, it's fetched again",
+                    id
+                );
+                if let Err(error) = fs::remove_file(&path).await {
+                    warn!("Failed to remove {}: {}", path.display(), error);
+                }
+            }
+        }
+
+        let cache = ArtifactCache {
+            directory,
+            index: Mutex::new(verified),
+            downloads: Mutex::new(HashMap::new()),
+            saving: tokio::sync::Mutex::new(()),
+        };
+        if backfilled || cache.index.lock().unwrap().len() != indexed {
+            cache.save().await;
+        }
+        cache
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Where a download is kept
+    pub fn path(&self, download: &CompiledDownloads) -> PathBuf {
+        self.directory.join(download.id.to_string())
+    }
+
+    /// Whether the download is cached (and was intact when the cache was opened)
+    pub fn contains(&self, download: &CompiledDownloads) -> bool {
+        self.index
+            .lock()
+            .unwrap()
+            .get(&download.id)
+            .is_some_and(|entry| entry.hash.eq_ignore_ascii_case(&download.hash))
+    }
+
+    /// Waits until nothing else uses the download, it's kept until the guard is dropped
+    pub async fn lock(&self, download: &CompiledDownloads) -> OwnedMutexGuard<()> {
+        self.lock_id(download.id).await
+    }
+
+    async fn lock_id(&self, id: Uuid) -> OwnedMutexGuard<()> {
+        let lock = self
+            .downloads
+            .lock()
+            .unwrap()
+            .entry(id)
+            .or_default()
+            .clone();
+
+        lock.lock_owned().await
+    }
+
+    /// Records a verified download as just used
+    pub async fn insert(&self, download: &CompiledDownloads) {
+        self.index.lock().unwrap().insert(
+            download.id,
+            IndexEntry {
+                hash: download.hash.clone(),
+                file_size: download.file_size,
+                last_used: now(),
+            },
+        );
+        self.save().await;
+    }
+
+    /// Forgets a download (and removes its file), the caller holds its `lock`
+    pub async fn remove(&self, download: &CompiledDownloads) {
+        self.remove_all(&[download.id]).await;
+    }
+
+    /// Removes the downloads once nothing else uses them
+    async fn remove_unused(&self, ids: &[Uuid]) {
+        let mut guards = vec![];
+        for id in ids {
+            guards.push(self.lock_id(*id).await);
+        }
+
+        self.remove_all(ids).await;
+    }
+
+    /// Removes the downloads, the caller holds their locks
+    async fn remove_all(&self, ids: &[Uuid]) {
+        for id in ids {
+            self.index.lock().unwrap().remove(id);
+            let path = self.directory.join(id.to_string());
+            if let Err(error) = fs::remove_file(&path).await {
+                if error.kind() != std::io::ErrorKind::NotFound {
+                    warn!("Failed to remove {}: {}", path.display(), error);
+                }
+            }
+        }
+        self.save().await;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        let index = self.index.lock().unwrap();
+
+        CacheStats {
+            downloads: index.len(),
+            size: index.values().map(|entry| entry.file_size).sum(),
+            least_recently_used: index.values().map(|entry| entry.last_used).min(),
+        }
+    }
+
+    /// Removes the downloads older than the max age, then the least recently used ones until
+    /// the cache fits the max size, returns what was removed
+    pub async fn evict(&self, policy: &EvictionPolicy) -> Vec<Uuid> {
+        let evicted: Vec<Uuid> = {
+            let index = self.index.lock().unwrap();
+            let mut entries: Vec<(&Uuid, &IndexEntry)> = index.iter().collect();
+            entries.sort_by_key(|(_, entry)| entry.last_used);
+
+            let oldest = policy
+                .max_age
+                .map(|age| now().saturating_sub(age.as_secs()));
+            let mut size: u64 = entries.iter().map(|(_, entry)| entry.file_size).sum();
+
+            entries
+                .into_iter()
+                .take_while(|(_, entry)| {
+                    let expired = oldest.is_some_and(|oldest| entry.last_used < oldest);
+                    let too_big = policy.max_size.is_some_and(|max_size| size > max_size);
+                    if expired || too_big {
+                        size -= entry.file_size;
+                    }
+                    expired || too_big
+                })
+                .map(|(id, _)| *id)
+                .collect()
+        };
+
+        if !evicted.is_empty() {
+            self.remove_unused(&evicted).await;
+        }
+        evicted
+    }
+
+    /// Removes every cached download (and the partial ones), returns how many bytes were freed
+    pub async fn clear(&self) -> u64 {
+        let ids: Vec<Uuid> = self.index.lock().unwrap().keys().copied().collect();
+        let mut freed = self.stats().size;
+        self.remove_unused(&ids).await;
+
+        // Partial downloads aren't indexed
+        if let Ok(mut entries) = fs::read_dir(&self.directory).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let path = entry.path();
+                if path
+                    .extension()
+                    .is_some_and(|extension| extension == "part")
+                {
+                    freed += entry
+                        .metadata()
+                        .await
+                        .map(|metadata| metadata.len())
+                        .unwrap_or_default();
+                    if let Err(error) = fs::remove_file(&path).await {
+                        warn!("Failed to remove {}: {}", path.display(), error);
+                    }
+                }
+            }
+        }
+
+        freed
+    }
+
+    /// Writes the index back (a cache that can't be written still works, it's only checked
+    /// again next time)
+    async fn save(&self) {
+        let _saving = self.saving.lock().await;
+        let data = match serde_json::to_vec_pretty(&*self.index.lock().unwrap()) {
+            Ok(data) => data,
+            Err(error) => {
+                warn!("Failed to serialize the cache index: {}", error);
+                return;
+            }
+        };
+
+        if fs::create_dir_all(&self.directory).await.is_err() {
+            return;
+        }
+        if let Err(error) = fs::write(self.directory.join(INDEX_FILE), data).await {
+            warn!("Failed to write the cache index: {}", error);
+        }
+    }
+}
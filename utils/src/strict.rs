@@ -0,0 +1,95 @@
+use std::fmt::Display;
+
+use serde::de::DeserializeOwned;
+
+/// The minimum similarity for a field to be suggested
+const SUGGESTION_THRESHOLD: f64 = 0.8;
+
+pub struct StrictError {
+    /// Where the error happened (e.g. `fonts[0].shortname`)
+    pub path: String,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// The closest known field for an unknown field
+    pub suggestion: Option<String>,
+}
+
+impl Display for StrictError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} (path: {}, line: {}, column: {})",
+            self.message, self.path, self.line, self.column
+        )?;
+
+        if let Some(suggestion) = &self.suggestion {
+            write!(f, ", did you mean `{}`?", suggestion)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Splits serde's "unknown field `x`, expected one of `a`, `b`" into the field and the known fields
+fn parse_unknown_field(message: &str) -> Option<(String, Vec<String>)> {
+    let rest = message.strip_prefix("unknown field `")?;
+    let (field, rest) = rest.split_once('`')?;
+
+    // Every other quoted word is a known field
+    let known = rest
+        .split('`')
+        .skip(1)
+        .step_by(2)
+        .map(|field| field.to_string())
+        .collect();
+
+    Some((field.to_string(), known))
+}
+
+/// Finds the known field closest to a misspelled one
+fn suggest(field: &str, known: &[String]) -> Option<String> {
+    known
+        .iter()
+        .map(|candidate| {
+            let similarity = match candidate.eq_ignore_ascii_case(field) {
+                true => 1.0,
+                false => strsim::jaro_winkler(&candidate.to_lowercase(), &field.to_lowercase()),
+            };
+            (candidate, similarity)
+        })
+        .filter(|(_, similarity)| *similarity >= SUGGESTION_THRESHOLD)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(candidate, _)| candidate.clone())
+}
+
+/// Parses json, reporting where it failed and suggesting fixes for misspelled fields
+pub fn from_str<T: DeserializeOwned>(contents: &str) -> Result<T, StrictError> {
+    let deserializer = &mut serde_json::Deserializer::from_str(contents);
+
+    match serde_path_to_error::deserialize(deserializer) {
+        Ok(value) => Ok(value),
+        Err(error) => {
+            let path = error.path().to_string();
+            let inner = error.into_inner();
+
+            // serde_json appends the location to the message
+            let message = inner.to_string();
+            let message = match message.rsplit_once(" at line ") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            };
+
+            let suggestion =
+                parse_unknown_field(&message).and_then(|(field, known)| suggest(&field, &known));
+
+            Err(StrictError {
+                path,
+                line: inner.line(),
+                column: inner.column(),
+                message,
+                suggestion,
+            })
+        }
+    }
+}
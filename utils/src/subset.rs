@@ -0,0 +1,139 @@
+use std::fmt::Display;
+
+use crate::{
+    archive::{build_cabinet, Archive, ArchiveError},
+    ttc::split_collection,
+    types::CabextractInstalationExtractData,
+};
+
+pub enum SubsetError {
+    /// A unicode range couldn't be parsed (range)
+    InvalidRange(String),
+    /// The font couldn't be parsed or subset (file, error)
+    Font(String, String),
+    /// Reading or writing the archive failed
+    Archive(ArchiveError),
+}
+
+impl Display for SubsetError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubsetError::InvalidRange(range) => write!(f, "Invalid unicode range ({})", range),
+            SubsetError::Font(file, error) => {
+                write!(
+                    f,
+                    "Failed to subset font (file: {}, error: {})",
+                    file, error
+                )
+            }
+            SubsetError::Archive(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl From<ArchiveError> for SubsetError {
+    fn from(error: ArchiveError) -> Self {
+        SubsetError::Archive(error)
+    }
+}
+
+/// Parses a unicode range like `U+0000-00FF` or `U+3000` (inclusive)
+pub fn parse_range(range: &str) -> Result<(u32, u32), SubsetError> {
+    let invalid = || SubsetError::InvalidRange(range.to_string());
+
+    let digits = range
+        .strip_prefix("U+")
+        .or_else(|| range.strip_prefix("u+"))
+        .ok_or_else(invalid)?;
+    let (start, end) = match digits.split_once('-') {
+        Some((start, end)) => (start, end),
+        None => (digits, digits),
+    };
+
+    let start = u32::from_str_radix(start, 16).map_err(|_| invalid())?;
+    let end = u32::from_str_radix(end, 16).map_err(|_| invalid())?;
+    if start > end || end > char::MAX as u32 {
+        return Err(invalid());
+    }
+
+    Ok((start, end))
+}
+
+/// Keeps only the glyphs needed for the unicode ranges
+pub fn subset_font(file: &str, data: &[u8], ranges: &[(u32, u32)]) -> Result<Vec<u8>, SubsetError> {
+    let font_error = |error: String| SubsetError::Font(file.to_string(), error);
+
+    let face = ttf_parser::Face::parse(data, 0).map_err(|e| font_error(e.to_string()))?;
+
+    // Always keep .notdef
+    let mut glyphs = vec![0];
+    for (start, end) in ranges {
+        for codepoint in *start..=*end {
+            if let Some(glyph) = char::from_u32(codepoint).and_then(|c| face.glyph_index(c)) {
+                glyphs.push(glyph.0);
+            }
+        }
+    }
+    glyphs.sort_unstable();
+    glyphs.dedup();
+
+    subsetter::subset(data, 0, subsetter::Profile::pdf(&glyphs))
+        .map_err(|e| font_error(format!("{:?}", e)))
+}
+
+/// Splits and/or subsets the listed files of an archive and packs them into a new cabinet
+pub fn repack_archive(
+    data: &[u8],
+    files: &[CabextractInstalationExtractData],
+    ranges: Option<&[String]>,
+) -> Result<Vec<u8>, SubsetError> {
+    let ranges = ranges
+        .map(|ranges| {
+            ranges
+                .iter()
+                .map(|range| parse_range(range))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+
+    let mut archive = Archive::open(data)?;
+    let mut repacked = vec![];
+    for file in files {
+        let font = archive.read(&file.file)?;
+
+        // (name, data)
+        let fonts = match &file.split {
+            Some(faces) => {
+                let split =
+                    split_collection(&font).map_err(|e| SubsetError::Font(file.file.clone(), e))?;
+                if split.len() != faces.len() {
+                    return Err(SubsetError::Font(
+                        file.file.clone(),
+                        format!(
+                            "The collection has {} faces but {} are declared",
+                            split.len(),
+                            faces.len()
+                        ),
+                    ));
+                }
+
+                faces
+                    .iter()
+                    .map(|face| face.file.clone())
+                    .zip(split)
+                    .collect()
+            }
+            None => vec![(file.file.clone(), font)],
+        };
+
+        for (name, font) in fonts {
+            let font = match &ranges {
+                Some(ranges) => subset_font(&name, &font, ranges)?,
+                None => font,
+            };
+            repacked.push((name, font));
+        }
+    }
+
+    Ok(build_cabinet(&repacked)?)
+}
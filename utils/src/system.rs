@@ -0,0 +1,193 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use ttf_parser::{name_id, Face};
+
+use crate::{families::family_name, install::fonts_directory, types::CompiledFont};
+
+/// The extensions of font files worth parsing
+const FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "ttc", "otc"];
+
+/// Where the host keeps its fonts (Wine also picks these up)
+pub fn host_font_directories() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut directories = vec![];
+
+    if cfg!(target_os = "macos") {
+        directories.push(PathBuf::from("/System/Library/Fonts"));
+        directories.push(PathBuf::from("/Library/Fonts"));
+        if let Some(home) = &home {
+            directories.push(home.join("Library").join("Fonts"));
+        }
+    } else if cfg!(windows) {
+        if let Some(windows) = std::env::var_os("WINDIR") {
+            directories.push(PathBuf::from(windows).join("Fonts"));
+        }
+    } else {
+        directories.push(PathBuf::from("/usr/share/fonts"));
+        directories.push(PathBuf::from("/usr/local/share/fonts"));
+        if let Some(data) = std::env::var_os("XDG_DATA_HOME") {
+            directories.push(PathBuf::from(data).join("fonts"));
+        }
+        if let Some(home) = &home {
+            directories.push(home.join(".local").join("share").join("fonts"));
+            directories.push(home.join(".fonts"));
+        }
+    }
+
+    directories
+}
+
+/// The family names (legacy and typographic) of every face in a font file
+pub fn font_families(data: &[u8]) -> Vec<String> {
+    let faces = ttf_parser::fonts_in_collection(data).unwrap_or(1);
+    let mut families = vec![];
+
+    for index in 0..faces {
+        let face = match Face::parse(data, index) {
+            Ok(face) => face,
+            Err(_) => continue,
+        };
+
+        for name in face.names() {
+            if name.name_id != name_id::FAMILY && name.name_id != name_id::TYPOGRAPHIC_FAMILY {
+                continue;
+            }
+
+            if let Some(family) = name.to_string() {
+                if !families.contains(&family) {
+                    families.push(family);
+                }
+            }
+        }
+    }
+
+    families
+}
+
+/// Every font file under a directory
+fn font_files(directory: &Path) -> Vec<PathBuf> {
+    let mut files = vec![];
+    let mut directories = vec![directory.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        let entries = match std::fs::read_dir(&directory) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+
+            let is_font = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| {
+                    FONT_EXTENSIONS
+                        .iter()
+                        .any(|ext| ext.eq_ignore_ascii_case(e))
+                })
+                .unwrap_or(false);
+            if is_font {
+                files.push(path);
+            }
+        }
+    }
+
+    files
+}
+
+#[derive(Debug, Clone, Default)]
+/// The font families already present on the host or in a prefix
+pub struct PresentFamilies {
+    /// Lowercase family names
+    families: HashSet<String>,
+}
+
+impl PresentFamilies {
+    /// Parses every font file under the directories (unreadable files are skipped)
+    pub fn scan(directories: &[PathBuf]) -> PresentFamilies {
+        let mut families = HashSet::new();
+
+        for directory in directories {
+            for file in font_files(directory) {
+                let data = match std::fs::read(&file) {
+                    Ok(data) => data,
+                    Err(e) => {
+                        debug!("Failed to read {}: {}", file.display(), e);
+                        continue;
+                    }
+                };
+
+                for family in font_families(&data) {
+                    families.insert(family.to_lowercase());
+                }
+            }
+        }
+
+        PresentFamilies { families }
+    }
+
+    /// Scans the host font directories and the fonts directory of the prefix
+    pub fn scan_host_and_prefix(prefix: &Path) -> PresentFamilies {
+        let mut directories = host_font_directories();
+        directories.push(fonts_directory(prefix));
+
+        PresentFamilies::scan(&directories)
+    }
+
+    /// Adds the fonts directory of the prefix to families scanned before (the host only has to
+    /// be scanned once for many prefixes)
+    pub fn with_prefix(&self, prefix: &Path) -> PresentFamilies {
+        let mut present = self.clone();
+        present
+            .families
+            .extend(PresentFamilies::scan(&[fonts_directory(prefix)]).families);
+
+        present
+    }
+
+    pub fn contains(&self, family: &str) -> bool {
+        self.families.contains(&family.to_lowercase())
+    }
+
+    /// Whether every family the font installs is already present
+    pub fn has_font(&self, font: &CompiledFont) -> bool {
+        let mut families = font
+            .host_installations()
+            .flat_map(|installation| installation.installed_files())
+            .map(|file| family_name(&file.registry_name))
+            .peekable();
+
+        families.peek().is_some() && families.all(|family| self.contains(&family))
+    }
+}
+
+/// The fonts that still have to be installed, `reinstall` installs present fonts anyway
+pub fn missing_fonts<'a>(
+    fonts: Vec<&'a CompiledFont>,
+    present: &PresentFamilies,
+    reinstall: bool,
+) -> Vec<&'a CompiledFont> {
+    if reinstall {
+        return fonts;
+    }
+
+    fonts
+        .into_iter()
+        .filter(|font| {
+            let has_font = present.has_font(font);
+            if has_font {
+                info!("{} is already installed, skipping", font.name);
+            }
+
+            !has_font
+        })
+        .collect()
+}
@@ -0,0 +1,123 @@
+/// Reads a big endian u16
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset.checked_add(2)?)?.try_into().ok()?,
+    ))
+}
+
+/// Reads a big endian u32
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset.checked_add(4)?)?.try_into().ok()?,
+    ))
+}
+
+/// Calculates an OpenType checksum (the data is treated as zero padded)
+fn checksum(data: &[u8]) -> u32 {
+    data.chunks(4).fold(0u32, |sum, chunk| {
+        let mut bytes = [0; 4];
+        bytes[..chunk.len()].copy_from_slice(chunk);
+        sum.wrapping_add(u32::from_be_bytes(bytes))
+    })
+}
+
+/// Whether the data is a TrueType Collection
+pub fn is_collection(data: &[u8]) -> bool {
+    data.starts_with(b"ttcf")
+}
+
+/// The most tables a face can have, the table directory fields are u16s of `tables * 16`
+const MAX_TABLES: usize = (u16::MAX / 16) as usize;
+
+/// Extracts a single face of a collection as a standalone font
+fn extract_face(data: &[u8], offset: usize) -> Result<Vec<u8>, String> {
+    let truncated = || "Truncated face".to_string();
+
+    let sfnt_version = read_u32(data, offset).ok_or_else(truncated)?;
+    let num_tables = read_u16(data, offset.checked_add(4).ok_or_else(truncated)?)
+        .ok_or_else(truncated)? as usize;
+    if num_tables == 0 || num_tables > MAX_TABLES {
+        return Err(format!("Invalid number of tables ({})", num_tables));
+    }
+
+    // (tag, data)
+    let mut tables = vec![];
+    for index in 0..num_tables {
+        let record = offset.checked_add(12 + index * 16).ok_or_else(truncated)?;
+        let tag = read_u32(data, record).ok_or_else(truncated)?;
+        let table_offset = read_u32(data, record + 8).ok_or_else(truncated)? as usize;
+        let length = read_u32(data, record + 12).ok_or_else(truncated)? as usize;
+        let table = table_offset
+            .checked_add(length)
+            .and_then(|end| data.get(table_offset..end))
+            .ok_or_else(|| format!("Table {} is outside of the collection", index))?;
+        tables.push((tag, table.to_vec()));
+    }
+    tables.sort_by_key(|(tag, _)| *tag);
+
+    // The largest power of two not above the number of tables (it's at least one)
+    let entry_selector = (usize::BITS - 1 - num_tables.leading_zeros()) as u16;
+    let search_range = (1u16 << entry_selector)
+        .checked_mul(16)
+        .ok_or_else(|| format!("Invalid number of tables ({})", num_tables))?;
+    let range_shift = (num_tables as u16)
+        .checked_mul(16)
+        .and_then(|size| size.checked_sub(search_range))
+        .ok_or_else(|| format!("Invalid number of tables ({})", num_tables))?;
+
+    let mut font = vec![];
+    font.extend(sfnt_version.to_be_bytes());
+    font.extend((num_tables as u16).to_be_bytes());
+    font.extend(search_range.to_be_bytes());
+    font.extend(entry_selector.to_be_bytes());
+    font.extend(range_shift.to_be_bytes());
+
+    let mut head_offset = None;
+    let mut table_offset = 12 + num_tables * 16;
+    for (tag, table) in &mut tables {
+        if &tag.to_be_bytes() == b"head" && table.len() >= 12 {
+            // Zero the checksum adjustment, it's recalculated at the end
+            table[8..12].fill(0);
+            head_offset = Some(table_offset);
+        }
+
+        font.extend(tag.to_be_bytes());
+        font.extend(checksum(table).to_be_bytes());
+        let record_offset =
+            u32::try_from(table_offset).map_err(|_| "Face too large".to_string())?;
+        font.extend(record_offset.to_be_bytes());
+        font.extend((table.len() as u32).to_be_bytes());
+        table_offset += (table.len() + 3) & !3;
+    }
+
+    for (_, table) in &tables {
+        font.extend(table);
+        font.resize((font.len() + 3) & !3, 0);
+    }
+
+    if let Some(head_offset) = head_offset {
+        let adjustment = 0xB1B0AFBA_u32.wrapping_sub(checksum(&font));
+        font[head_offset + 8..head_offset + 12].copy_from_slice(&adjustment.to_be_bytes());
+    }
+
+    Ok(font)
+}
+
+/// Splits a TrueType Collection into one font per face
+pub fn split_collection(data: &[u8]) -> Result<Vec<Vec<u8>>, String> {
+    if !is_collection(data) {
+        return Err("Not a TrueType Collection".to_string());
+    }
+
+    let num_fonts = read_u32(data, 8).ok_or("Truncated collection header")? as usize;
+
+    (0..num_fonts)
+        .map(|index| {
+            let offset = read_u32(data, 12 + index * 4).ok_or_else(|| {
+                format!("Failed to extract face {}: Truncated offset table", index)
+            })?;
+            extract_face(data, offset as usize)
+                .map_err(|e| format!("Failed to extract face {}: {}", index, e))
+        })
+        .collect()
+}
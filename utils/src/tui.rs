@@ -0,0 +1,441 @@
+use std::{io, path::Path, time::Duration};
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEventKind},
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    text::Line,
+    widgets::{Block, Gauge, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use uuid::Uuid;
+
+use crate::{
+    client::DownloadManager,
+    install,
+    preflight::{check_space, required_space},
+    store::ArtifactCache,
+    system::PresentFamilies,
+    types::{Compiled, CompiledFont},
+    utils::format_size,
+};
+
+/// Where the installs go and how the files are fetched
+pub struct InstallTarget<'a> {
+    pub prefix: &'a Path,
+    pub cache: &'a ArtifactCache,
+    /// The wine binary used to register the fonts
+    pub wine: &'a Path,
+    pub manager: &'a DownloadManager<'a>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+/// The list the keys move in
+enum Pane {
+    Groups,
+    Fonts,
+}
+
+/// The browsing state
+struct Picker<'a> {
+    compiled: &'a Compiled,
+    present: &'a PresentFamilies,
+    pane: Pane,
+    /// The first entry lists every font
+    groups: ListState,
+    fonts: ListState,
+    /// In the order they were picked
+    selected: Vec<Uuid>,
+    /// Shown at the bottom (help or the last error)
+    status: Option<String>,
+    /// Only list variable fonts
+    variable_only: bool,
+}
+
+impl<'a> Picker<'a> {
+    fn new(compiled: &'a Compiled, present: &'a PresentFamilies) -> Picker<'a> {
+        Picker {
+            compiled,
+            present,
+            pane: Pane::Groups,
+            groups: ListState::default().with_selected(Some(0)),
+            fonts: ListState::default().with_selected(Some(0)),
+            selected: vec![],
+            status: None,
+            variable_only: false,
+        }
+    }
+
+    /// The groups and then the applications (name, fonts), after "All fonts" in the left pane
+    fn lists(&self) -> Vec<(String, &'a [Uuid])> {
+        let groups = self
+            .compiled
+            .groups
+            .iter()
+            .map(|group| (group.name.clone(), group.fonts.as_slice()));
+        let applications = self.compiled.applications.iter().map(|application| {
+            (
+                format!("{} (app)", application.name),
+                application.fonts.as_slice(),
+            )
+        });
+
+        groups.chain(applications).collect()
+    }
+
+    /// The fonts of the highlighted group (or application)
+    fn visible_fonts(&self) -> Vec<&'a CompiledFont> {
+        let fonts: Vec<&'a CompiledFont> = match self.groups.selected() {
+            Some(0) | None => self.compiled.fonts.iter().collect(),
+            Some(index) => match self.lists().get(index - 1) {
+                Some((_, fonts)) => fonts
+                    .iter()
+                    .filter_map(|id| self.compiled.find_font(*id))
+                    .collect(),
+                None => vec![],
+            },
+        };
+
+        match self.variable_only {
+            true => fonts
+                .into_iter()
+                .filter(|font| !self.compiled.font_axes(font.id).is_empty())
+                .collect(),
+            false => fonts,
+        }
+    }
+
+    fn current_font(&self) -> Option<&'a CompiledFont> {
+        let index = self.fonts.selected()?;
+        self.visible_fonts().get(index).copied()
+    }
+
+    fn selected_fonts(&self) -> Vec<&'a CompiledFont> {
+        self.selected
+            .iter()
+            .filter_map(|id| self.compiled.find_font(*id))
+            .collect()
+    }
+
+    fn move_cursor(&mut self, delta: isize) {
+        let len = match self.pane {
+            Pane::Groups => self.lists().len() + 1,
+            Pane::Fonts => self.visible_fonts().len(),
+        };
+        let state = match self.pane {
+            Pane::Groups => &mut self.groups,
+            Pane::Fonts => &mut self.fonts,
+        };
+        if len == 0 {
+            return;
+        }
+
+        let current = state.selected().unwrap_or(0) as isize;
+        state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+
+        // Another group, start at its first font
+        if self.pane == Pane::Groups {
+            self.fonts.select(Some(0));
+        }
+    }
+
+    /// Toggles the highlighted font, or every font of the highlighted group
+    fn toggle(&mut self) {
+        let fonts: Vec<Uuid> = match self.pane {
+            Pane::Groups => self.visible_fonts().iter().map(|font| font.id).collect(),
+            Pane::Fonts => self
+                .current_font()
+                .map(|font| font.id)
+                .into_iter()
+                .collect(),
+        };
+
+        if fonts.iter().all(|id| self.selected.contains(id)) {
+            self.selected.retain(|id| !fonts.contains(id));
+        } else {
+            for id in fonts {
+                if !self.selected.contains(&id) {
+                    self.selected.push(id);
+                }
+            }
+        }
+    }
+
+    fn details(&self, font: &CompiledFont) -> Vec<Line<'static>> {
+        let mut lines = vec![
+            Line::styled(font.name.clone(), Style::new().add_modifier(Modifier::BOLD)),
+            Line::from(format!("Publisher: {}", font.publisher)),
+        ];
+
+        if !font.categories.is_empty() {
+            let categories: Vec<String> = font.categories.iter().map(|c| c.to_string()).collect();
+            lines.push(Line::from(format!("Categories: {}", categories.join(", "))));
+        }
+        if !font.tags.is_empty() {
+            lines.push(Line::from(format!("Tags: {}", font.tags.join(", "))));
+        }
+
+        if let Ok(space) = required_space(self.compiled, &[font]) {
+            lines.push(Line::from(format!(
+                "Download {} / installs {}",
+                format_size(space.download),
+                format_size(space.installed)
+            )));
+        }
+        if self.present.has_font(font) {
+            lines.push(Line::from("Already installed"));
+        }
+
+        let axes = self.compiled.font_axes(font.id);
+        if !axes.is_empty() {
+            let axes: Vec<String> = axes
+                .iter()
+                .map(|axis| format!("{} {}–{}", axis.tag, axis.min, axis.max))
+                .collect();
+            lines.push(Line::from(format!("Variable: {}", axes.join(", "))));
+        }
+
+        lines.push(Line::from(""));
+        // Databases built before families were recorded only have the files
+        let mut families = self.compiled.font_families(font.id).peekable();
+        if families.peek().is_some() {
+            for family in families {
+                let mut styles: Vec<&str> = vec![];
+                for face in family.faces.iter().filter(|face| face.font == font.id) {
+                    if !styles.contains(&face.style_name.as_str()) {
+                        styles.push(&face.style_name);
+                    }
+                }
+                lines.push(Line::from(match styles.len() {
+                    1 => format!("{} ({})", family.name, styles[0]),
+                    count => format!("{} ({} styles: {})", family.name, count, styles.join(", ")),
+                }));
+            }
+            return lines;
+        }
+        for installation in &font.installations {
+            for file in installation.installed_files() {
+                lines.push(Line::from(format!(
+                    "{} ({})",
+                    file.registry_name, file.file
+                )));
+            }
+        }
+
+        lines
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [body, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [groups, fonts, details] = Layout::horizontal([
+            Constraint::Percentage(25),
+            Constraint::Percentage(35),
+            Constraint::Percentage(40),
+        ])
+        .areas(body);
+
+        let focused = |pane: Pane| match self.pane == pane {
+            true => Style::new().add_modifier(Modifier::BOLD),
+            false => Style::new().add_modifier(Modifier::DIM),
+        };
+        let highlight = Style::new().add_modifier(Modifier::REVERSED);
+
+        // Groups
+        let mut items = vec![ListItem::new(format!(
+            "All fonts ({})",
+            self.compiled.fonts.len()
+        ))];
+        items.extend(
+            self.lists()
+                .into_iter()
+                .map(|(name, fonts)| ListItem::new(format!("{} ({})", name, fonts.len()))),
+        );
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title("Groups")
+                    .border_style(focused(Pane::Groups)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, groups, &mut self.groups);
+
+        // Fonts
+        let items: Vec<ListItem> = self
+            .visible_fonts()
+            .iter()
+            .map(|font| {
+                let mark = match self.selected.contains(&font.id) {
+                    true => "[x]",
+                    false => "[ ]",
+                };
+                let installed = match self.present.has_font(font) {
+                    true => " (installed)",
+                    false => "",
+                };
+                let variable = match self.compiled.font_axes(font.id).is_empty() {
+                    true => "",
+                    false => " (variable)",
+                };
+                ListItem::new(format!("{} {}{}{}", mark, font.name, variable, installed))
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::bordered()
+                    .title(match self.variable_only {
+                        true => format!("Variable fonts ({} selected)", self.selected.len()),
+                        false => format!("Fonts ({} selected)", self.selected.len()),
+                    })
+                    .border_style(focused(Pane::Fonts)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(list, fonts, &mut self.fonts);
+
+        // Details
+        let lines = match self.current_font() {
+            Some(font) => self.details(font),
+            None => vec![],
+        };
+        frame.render_widget(
+            Paragraph::new(lines)
+                .block(Block::bordered().title("Details"))
+                .wrap(Wrap { trim: false }),
+            details,
+        );
+
+        let help =
+            "↑/↓ move · tab switch · space select · v variable only · enter install · q quit";
+        frame.render_widget(
+            Paragraph::new(self.status.clone().unwrap_or_else(|| help.to_string())),
+            status,
+        );
+    }
+}
+
+/// The install screen: progress of the current font and a log of the finished ones
+fn draw_progress(frame: &mut Frame, title: &str, done: usize, total: usize, log: &[String]) {
+    let [gauge, log_area] =
+        Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(frame.area());
+
+    frame.render_widget(
+        Gauge::default()
+            .block(Block::bordered().title(title.to_string()))
+            .ratio(match total {
+                0 => 1.0,
+                _ => done as f64 / total as f64,
+            })
+            .label(format!("{}/{}", done, total)),
+        gauge,
+    );
+
+    let items: Vec<ListItem> = log.iter().map(|line| ListItem::new(line.clone())).collect();
+    frame.render_widget(List::new(items).block(Block::bordered()), log_area);
+}
+
+/// Installs and registers the fonts, showing the progress, returns how many were installed
+async fn install_fonts(
+    terminal: &mut DefaultTerminal,
+    compiled: &Compiled,
+    fonts: &[&CompiledFont],
+    target: &InstallTarget<'_>,
+) -> io::Result<usize> {
+    let mut log = vec![];
+    let mut installed = vec![];
+    let mut count = 0;
+
+    for (index, font) in fonts.iter().enumerate() {
+        let title = format!("Installing {}", font.name);
+        terminal.draw(|frame| draw_progress(frame, &title, index, fonts.len(), &log))?;
+
+        match install::install_font(target.prefix, compiled, font, target.manager, target.cache)
+            .await
+        {
+            Ok(entries) => {
+                installed.extend(entries);
+                count += 1;
+                log.push(format!("Installed {}", font.name));
+            }
+            Err(e) => log.push(format!("Failed to install {}: {}", font.name, e)),
+        }
+    }
+
+    if !installed.is_empty() {
+        terminal.draw(|frame| {
+            draw_progress(frame, "Registering fonts", fonts.len(), fonts.len(), &log)
+        })?;
+        if let Err(e) = install::register(target.prefix, target.wine, &installed).await {
+            log.push(e.to_string());
+        }
+    }
+
+    log.push("Press any key to exit".to_string());
+    terminal.draw(|frame| draw_progress(frame, "Done", fonts.len(), fonts.len(), &log))?;
+    loop {
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+/// Lets the user browse the database and pick fonts, then installs them
+///
+/// Returns how many fonts were installed (0 if the user quit).
+pub async fn run(
+    terminal: &mut DefaultTerminal,
+    compiled: &Compiled,
+    present: &PresentFamilies,
+    target: &InstallTarget<'_>,
+) -> io::Result<usize> {
+    let mut picker = Picker::new(compiled, present);
+
+    loop {
+        terminal.draw(|frame| picker.draw(frame))?;
+
+        // Poll so the tokio runtime isn't blocked for long
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let key = match event::read()? {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        picker.status = None;
+
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(0),
+            KeyCode::Tab | KeyCode::Left | KeyCode::Right => {
+                picker.pane = match picker.pane {
+                    Pane::Groups => Pane::Fonts,
+                    Pane::Fonts => Pane::Groups,
+                }
+            }
+            KeyCode::Up | KeyCode::Char('k') => picker.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => picker.move_cursor(1),
+            KeyCode::Char(' ') => picker.toggle(),
+            KeyCode::Char('v') => {
+                picker.variable_only = !picker.variable_only;
+                picker.fonts.select(Some(0));
+            }
+            KeyCode::Enter => {
+                let fonts = picker.selected_fonts();
+                if fonts.is_empty() {
+                    picker.status = Some("Nothing selected".to_string());
+                    continue;
+                }
+
+                let space = required_space(compiled, &fonts)
+                    .and_then(|space| check_space(target.cache.directory(), target.prefix, space));
+                if let Err(e) = space {
+                    picker.status = Some(e.to_string());
+                    continue;
+                }
+
+                return install_fonts(terminal, compiled, &fonts, target).await;
+            }
+            _ => {}
+        }
+    }
+}
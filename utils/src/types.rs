@@ -1,21 +1,77 @@
 use crate::{instalation_options, instalation_struct};
 
-use std::path::PathBuf;
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    path::{Component, Path, PathBuf},
+};
 
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use url::Url;
 use uuid::Uuid;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
-#[serde(untagged)]
-/// A source uuid either a uuid or null for auto generated uuid
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A source uuid, either a uuid or the `<UUID>` placeholder `lint --fix` replaces with a new one
+///
+/// `null` (or a missing id) is read as the placeholder too (older files), anything else that
+/// isn't a uuid is an error so a mistyped placeholder can't slip through.
 pub enum SourceUUID {
     Uuid(Uuid),
-    #[serde(rename = "<UUID>")]
     Null,
 }
 
+impl SourceUUID {
+    /// What the placeholder is written as
+    pub const PLACEHOLDER: &'static str = "<UUID>";
+
+    pub fn parse(text: &str) -> Result<SourceUUID, String> {
+        if text == SourceUUID::PLACEHOLDER {
+            return Ok(SourceUUID::Null);
+        }
+
+        Uuid::parse_str(text).map(SourceUUID::Uuid).map_err(|_| {
+            format!(
+                "\"{}\" isn't a uuid or the \"{}\" placeholder",
+                text,
+                SourceUUID::PLACEHOLDER
+            )
+        })
+    }
+
+    /// The uuid, unless it's still a placeholder
+    pub fn uuid(&self) -> Option<Uuid> {
+        match self {
+            SourceUUID::Uuid(uuid) => Some(*uuid),
+            SourceUUID::Null => None,
+        }
+    }
+}
+
+impl Display for SourceUUID {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SourceUUID::Uuid(uuid) => write!(f, "{}", uuid),
+            SourceUUID::Null => write!(f, "{}", SourceUUID::PLACEHOLDER),
+        }
+    }
+}
+
+impl Serialize for SourceUUID {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for SourceUUID {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => SourceUUID::parse(&text).map_err(serde::de::Error::custom),
+            None => Ok(SourceUUID::Null),
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 #[serde(rename_all = "camelCase")]
 #[serde(untagged)]
@@ -25,6 +81,39 @@ pub enum SourceDownload {
     ExternalResource(Url),
     /// We have the right to distribute the file
     LocalResource(PathBuf),
+    /// An asset of a GitHub release, resolved at build time (tag can be `latest`)
+    GitHubRelease {
+        repo: String,
+        tag: String,
+        asset: String,
+    },
+    /// A file hosted on SourceForge, resolved at build time
+    SourceForge { project: String, file: String },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+/// A known hash (sha256) and size of a source download
+pub struct DownloadPin {
+    pub hash: String,
+    pub file_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+/// Who can fetch a download
+pub enum DownloadAccess {
+    /// Anyone with the url
+    #[default]
+    Public,
+    /// Uploaded privately, the client has to sign the url (e.g. licensed fonts)
+    Signed,
+}
+
+impl DownloadAccess {
+    pub fn is_public(&self) -> bool {
+        *self == DownloadAccess::Public
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
@@ -32,6 +121,121 @@ pub enum SourceDownload {
 pub struct CabextractInstalationExtractData {
     pub file: String,
     pub registry_name: String,
+    /// Split a TrueType Collection into one font per face at build time (in face order)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub split: Option<Vec<CollectionFace>>,
+}
+
+/// Whether a file name is a single plain path component, the only names a client may join onto a
+/// fonts directory (no directories, `..`, roots or drive prefixes)
+pub fn is_plain_file_name(name: &str) -> bool {
+    let mut components = Path::new(name).components();
+    let single = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+
+    // Windows separators and drive prefixes are plain characters on other hosts, control
+    // characters would break out of the registry entries
+    single && !name.contains(['/', '\\', ':']) && !name.chars().any(char::is_control)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+/// A single face of a split TrueType Collection
+pub struct CollectionFace {
+    pub file: String,
+    pub registry_name: String,
+}
+
+impl CabextractInstalationExtractData {
+    /// The files that end up installed (split collections are replaced by their faces)
+    pub fn installed_files(&self) -> Vec<CabextractInstalationExtractData> {
+        match &self.split {
+            Some(faces) => faces
+                .iter()
+                .map(|face| CabextractInstalationExtractData {
+                    file: face.file.clone(),
+                    registry_name: face.registry_name.clone(),
+                    split: None,
+                })
+                .collect(),
+            None => vec![self.clone()],
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+/// Only ship the glyphs for some unicode ranges (e.g. `U+0000-00FF`)
+pub struct SubsetOptions {
+    pub unicode_ranges: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+/// An operating system the client runs on
+pub enum HostOs {
+    Linux,
+    Macos,
+    Freebsd,
+}
+
+impl HostOs {
+    pub const ALL: [HostOs; 3] = [HostOs::Linux, HostOs::Macos, HostOs::Freebsd];
+
+    /// The host the client was built for (`None` on others)
+    pub fn current() -> Option<HostOs> {
+        if cfg!(target_os = "linux") {
+            Some(HostOs::Linux)
+        } else if cfg!(target_os = "macos") {
+            Some(HostOs::Macos)
+        } else if cfg!(target_os = "freebsd") {
+            Some(HostOs::Freebsd)
+        } else {
+            None
+        }
+    }
+}
+
+impl Display for HostOs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HostOs::Linux => write!(f, "linux"),
+            HostOs::Macos => write!(f, "macos"),
+            HostOs::Freebsd => write!(f, "freebsd"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+/// The hosts an installation is for (e.g. a variant with lowercase file names for case-sensitive
+/// file systems)
+pub struct HostCondition {
+    /// Only these hosts (every host if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub only: Vec<HostOs>,
+    /// Not these hosts
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub except: Vec<HostOs>,
+}
+
+impl HostCondition {
+    pub fn is_empty(&self) -> bool {
+        self.only.is_empty() && self.except.is_empty()
+    }
+
+    /// Whether the installation is for the host (unknown hosts only get the installations that
+    /// don't name the hosts they're for)
+    pub fn applies(&self, host: Option<HostOs>) -> bool {
+        match host {
+            Some(host) => {
+                (self.only.is_empty() || self.only.contains(&host)) && !self.except.contains(&host)
+            }
+            None => self.only.is_empty(),
+        }
+    }
 }
 
 instalation_struct! {
@@ -41,6 +245,20 @@ instalation_struct! {
     pub struct CabextractInstalationSource, CabextractInstalationCompiled {
         /// The cabextract file
         pub files: Vec<CabextractInstalationExtractData>,
+        /// Subset the files at build time (the download is then a derived cabinet)
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub subset: Option<SubsetOptions>,
+    }
+}
+
+instalation_struct! {
+    #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+    #[serde(rename_all = "camelCase")]
+    /// WOFF2 instalation type (converted to a TTF at build time)
+    pub struct Woff2InstalationSource, Woff2InstalationCompiled {
+        /// The name of the converted file
+        pub file: String,
+        pub registry_name: String,
     }
 }
 
@@ -51,28 +269,228 @@ instalation_options! {
     /// Installation type
     pub enum {
         Cabextract(CabextractInstalationSource, CabextractInstalationCompiled)
+        Woff2(Woff2InstalationSource, Woff2InstalationCompiled)
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "kebab-case")]
+impl SourceInstalationType {
+    pub fn download(&self) -> &SourceDownload {
+        match self {
+            SourceInstalationType::Cabextract(data) => &data.download,
+            SourceInstalationType::Woff2(data) => &data.download,
+        }
+    }
+
+    pub fn pin(&self) -> Option<&DownloadPin> {
+        match self {
+            SourceInstalationType::Cabextract(data) => data.pin.as_ref(),
+            SourceInstalationType::Woff2(data) => data.pin.as_ref(),
+        }
+    }
+
+    pub fn access(&self) -> DownloadAccess {
+        match self {
+            SourceInstalationType::Cabextract(data) => data.access,
+            SourceInstalationType::Woff2(data) => data.access,
+        }
+    }
+
+    pub fn hosts(&self) -> &HostCondition {
+        match self {
+            SourceInstalationType::Cabextract(data) => &data.hosts,
+            SourceInstalationType::Woff2(data) => &data.hosts,
+        }
+    }
+
+    /// The files (and registry names) the installation puts into the prefix
+    pub fn installed_files(&self) -> Vec<CabextractInstalationExtractData> {
+        match self {
+            SourceInstalationType::Cabextract(data) => data
+                .files
+                .iter()
+                .flat_map(|file| file.installed_files())
+                .collect(),
+            SourceInstalationType::Woff2(data) => vec![CabextractInstalationExtractData {
+                file: data.file.clone(),
+                registry_name: data.registry_name.clone(),
+                split: None,
+            }],
+        }
+    }
+}
+
+impl CompiledInstalationType {
+    pub fn download(&self) -> Uuid {
+        match self {
+            CompiledInstalationType::Cabextract(data) => data.download,
+            CompiledInstalationType::Woff2(data) => data.download,
+        }
+    }
+
+    pub fn set_download(&mut self, download: Uuid) {
+        match self {
+            CompiledInstalationType::Cabextract(data) => data.download = download,
+            CompiledInstalationType::Woff2(data) => data.download = download,
+        }
+    }
+
+    pub fn hosts(&self) -> &HostCondition {
+        match self {
+            CompiledInstalationType::Cabextract(data) => &data.hosts,
+            CompiledInstalationType::Woff2(data) => &data.hosts,
+        }
+    }
+
+    /// The uncompressed size of the installed files (if the database records it)
+    pub fn installed_size(&self) -> Option<u64> {
+        match self {
+            CompiledInstalationType::Cabextract(data) => data.installed_size,
+            CompiledInstalationType::Woff2(data) => data.installed_size,
+        }
+    }
+
+    /// The files (and registry names) the installation puts into the prefix
+    pub fn installed_files(&self) -> Vec<CabextractInstalationExtractData> {
+        match self {
+            CompiledInstalationType::Cabextract(data) => data.files.clone(),
+            CompiledInstalationType::Woff2(data) => vec![CabextractInstalationExtractData {
+                file: data.file.clone(),
+                registry_name: data.registry_name.clone(),
+                split: None,
+            }],
+        }
+    }
+
+    /// The sha256 of every installed file (by name, empty if the database doesn't record them)
+    pub fn file_hashes(&self) -> &BTreeMap<String, String> {
+        match self {
+            CompiledInstalationType::Cabextract(data) => &data.file_hashes,
+            CompiledInstalationType::Woff2(data) => &data.file_hashes,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 /// The font category
 pub enum FontCategory {
-    Serif = 4,
-    SansSerif = 3,
-    Monospace = 2,
-    Cursive = 0,
-    Display = 1,
-    Symbol = 5,
+    Cursive,
+    Display,
+    Monospace,
+    SansSerif,
+    Serif,
+    Symbol,
+    /// A category this version doesn't know about (kept so new categories don't break clients)
+    Other(String),
+}
+
+impl FontCategory {
+    /// The serialized (kebab-case) name
+    pub fn as_str(&self) -> &str {
+        match self {
+            FontCategory::Cursive => "cursive",
+            FontCategory::Display => "display",
+            FontCategory::Monospace => "monospace",
+            FontCategory::SansSerif => "sans-serif",
+            FontCategory::Serif => "serif",
+            FontCategory::Symbol => "symbol",
+            FontCategory::Other(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> FontCategory {
+        match name {
+            "cursive" => FontCategory::Cursive,
+            "display" => FontCategory::Display,
+            "monospace" => FontCategory::Monospace,
+            "sans-serif" => FontCategory::SansSerif,
+            "serif" => FontCategory::Serif,
+            "symbol" => FontCategory::Symbol,
+            other => FontCategory::Other(other.to_string()),
+        }
+    }
+}
+
+impl Display for FontCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for FontCategory {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FontCategory {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(FontCategory::from_name(&String::deserialize(deserializer)?))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// What the license of a font allows, see `policy` for where it can be installed
+pub enum FontLicense {
+    /// Free to use and redistribute anywhere (e.g. OFL or Apache 2.0)
+    Open,
+    /// Can be passed on unmodified, but only to run Windows software (e.g. the core fonts EULA)
+    ProprietaryRedistributable,
+    /// Only for users who have a license of their own (usually signed downloads)
+    Proprietary,
+    /// A license this version doesn't know about (treated like the strictest one)
+    Other(String),
+}
+
+impl FontLicense {
+    /// The serialized (kebab-case) name
+    pub fn as_str(&self) -> &str {
+        match self {
+            FontLicense::Open => "open",
+            FontLicense::ProprietaryRedistributable => "proprietary-redistributable",
+            FontLicense::Proprietary => "proprietary",
+            FontLicense::Other(name) => name,
+        }
+    }
+
+    pub fn from_name(name: &str) -> FontLicense {
+        match name {
+            "open" => FontLicense::Open,
+            "proprietary-redistributable" => FontLicense::ProprietaryRedistributable,
+            "proprietary" => FontLicense::Proprietary,
+            other => FontLicense::Other(other.to_string()),
+        }
+    }
+}
+
+impl Display for FontLicense {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for FontLicense {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FontLicense {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(FontLicense::from_name(&String::deserialize(deserializer)?))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 /// A group of fonts
 pub struct SourceGroup {
     pub id: SourceUUID,
     pub name: String,
-    pub fonts: Vec<String>,
+    /// Fonts by uuid (names still work, `lint --fix` migrates them)
+    pub fonts: Vec<FontReference>,
+    /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+    #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -82,18 +500,175 @@ pub struct CompiledGroup {
     pub id: Uuid,
     pub name: String,
     pub fonts: Vec<Uuid>,
+    /// The overlay the group comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+/// An application and the fonts it needs
+pub struct SourceApplication {
+    pub id: SourceUUID,
+    pub name: String,
+    /// The Steam app id (e.g. `1091500`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steam_app_id: Option<u32>,
+    /// Fonts by uuid (names still work, `lint --fix` migrates them)
+    pub fonts: Vec<FontReference>,
+    /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+    #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
+/// An application and the fonts it needs
+pub struct CompiledApplication {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub steam_app_id: Option<u32>,
+    pub fonts: Vec<Uuid>,
+    /// The overlay the application comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+#[serde(untagged)]
+/// A reference to another font (by uuid or name)
+pub enum FontReference {
+    Uuid(Uuid),
+    Name(String),
+}
+
+impl FontReference {
+    /// Whether the reference points to the font
+    pub fn matches(&self, font: &SourceFont) -> bool {
+        match self {
+            FontReference::Uuid(uuid) => font.id == SourceUUID::Uuid(*uuid),
+            FontReference::Name(name) => &font.name == name || font.previous_names.contains(name),
+        }
+    }
+}
+
+impl Display for FontReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FontReference::Uuid(uuid) => write!(f, "{}", uuid),
+            FontReference::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+/// A publisher of fonts
+pub struct SourcePublisher {
+    pub id: SourceUUID,
+    /// The canonical name
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// Other spellings of the name (migrated to the id by `lint --fix`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+    #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A publisher of fonts
+pub struct CompiledPublisher {
+    pub id: Uuid,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(untagged)]
+/// A reference to a publisher (names are only accepted to migrate them)
+pub enum PublisherReference {
+    Id(Uuid),
+    Name(String),
+}
+
+impl PublisherReference {
+    /// Whether the reference points to the publisher (names match the canonical name or an alias)
+    pub fn matches(&self, publisher: &SourcePublisher) -> bool {
+        match self {
+            PublisherReference::Id(uuid) => publisher.id == SourceUUID::Uuid(*uuid),
+            PublisherReference::Name(name) => {
+                publisher.name.eq_ignore_ascii_case(name)
+                    || publisher
+                        .aliases
+                        .iter()
+                        .any(|alias| alias.eq_ignore_ascii_case(name))
+            }
+        }
+    }
+}
+
+impl Display for PublisherReference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublisherReference::Id(uuid) => write!(f, "{}", uuid),
+            PublisherReference::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 /// A font
 pub struct SourceFont {
     pub id: SourceUUID,
     pub name: String,
+    /// Names the font was published under before, so old references keep resolving
+    /// (`lint --fix --published` records renames)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_names: Vec<String>,
+    /// Lowercase kebab-case key of the font, `lint --fix` creates it from the name if missing
+    #[serde(default)]
     pub short_name: String,
-    pub publisher: String,
+    pub publisher: PublisherReference,
     pub categories: Vec<FontCategory>,
+    /// Free-form tags (lowercase, e.g. `cjk`) for filtering
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// What the license allows (fonts without one are only installed into prefixes)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<FontLicense>,
     pub installations: Vec<SourceInstalationType>,
+    /// Fonts that can't be installed alongside this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts_with: Vec<FontReference>,
+    /// The wine versions the font works with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wine: Option<WineCompatibility>,
+    /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+    #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+/// Known-good and known-broken wine versions of a font, as semver requirements (e.g. `>=8.0`,
+/// a bare `8.0` means `^8.0`)
+pub struct WineCompatibility {
+    /// The versions the font needs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires: Option<String>,
+    /// Versions the font is broken on, even if they meet `requires`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub broken: Vec<String>,
+    /// Why, for humans (e.g. "COLRv1 needs wine 8")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -102,10 +677,39 @@ pub struct SourceFont {
 pub struct CompiledFont {
     pub id: Uuid,
     pub name: String,
+    /// Names the font was published under before
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_names: Vec<String>,
     pub short_name: String,
+    /// The canonical name of the publisher
     pub publisher: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub publisher_id: Option<Uuid>,
     pub categories: Vec<FontCategory>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<FontLicense>,
     pub installations: Vec<CompiledInstalationType>,
+    /// The overlay the font comes from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    /// Fonts that can't be installed alongside this one
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub conflicts_with: Vec<Uuid>,
+    /// The wine versions the font works with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub wine: Option<WineCompatibility>,
+}
+
+impl CompiledFont {
+    /// The installations for the host the client runs on
+    pub fn host_installations(&self) -> impl Iterator<Item = &CompiledInstalationType> {
+        let host = HostOs::current();
+        self.installations
+            .iter()
+            .filter(move |installation| installation.hosts().applies(host))
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -116,22 +720,331 @@ pub struct CompiledDownloads {
     pub file_size: u64,
     pub hash: String,
     pub download_url: Url,
+    /// The download this one was generated from (e.g. a subset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub derived_from: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "DownloadAccess::is_public")]
+    pub access: DownloadAccess,
+    /// Other places the same file can be fetched from (e.g. an IPFS gateway)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
+    /// A metalink listing the url and mirrors, for multi-source downloads of large files
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub metalink: Option<Url>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+/// Adjustments of the lint checks for a catalog (added to the built-in ones)
+pub struct SourceLintSettings {
+    /// Characters that look like latin letters, names mixing them with latin letters are errors
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lookalikes: Vec<char>,
+    /// Characters the built-in lookalikes flag that the catalog's names really use
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_lookalikes: Vec<char>,
+}
+
+impl SourceLintSettings {
+    pub fn is_default(&self) -> bool {
+        self == &SourceLintSettings::default()
+    }
+
+    /// Adds the settings of another catalog (merged catalogs are linted with all of them)
+    pub fn extend(&mut self, other: &SourceLintSettings) {
+        fn extend<T: PartialEq + Clone>(list: &mut Vec<T>, other: &[T]) {
+            for item in other {
+                if !list.contains(item) {
+                    list.push(item.clone());
+                }
+            }
+        }
+
+        extend(&mut self.lookalikes, &other.lookalikes);
+        extend(&mut self.allowed_lookalikes, &other.allowed_lookalikes);
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
 /// The file format (of the source)
 pub struct Source {
+    /// Set by overlay catalogs (merged into an upstream database), exported on their fonts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publishers: Vec<SourcePublisher>,
     pub groups: Vec<SourceGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applications: Vec<SourceApplication>,
     pub fonts: Vec<SourceFont>,
+    #[serde(default, skip_serializing_if = "SourceLintSettings::is_default")]
+    pub lint: SourceLintSettings,
+    /// Context for humans (`x-comment` is accepted too), kept by `lint --fix` and not built
+    #[serde(default, alias = "x-comment", skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+impl Source {
+    /// Finds the font a reference points to
+    pub fn find_font(&self, reference: &FontReference) -> Option<&SourceFont> {
+        self.fonts.iter().find(|font| reference.matches(font))
+    }
+
+    /// Finds the publisher a reference points to
+    pub fn find_publisher(&self, reference: &PublisherReference) -> Option<&SourcePublisher> {
+        self.publishers
+            .iter()
+            .find(|publisher| reference.matches(publisher))
+    }
+
+    /// Whether either font declared the other as conflicting
+    pub fn conflicting(&self, a: &SourceFont, b: &SourceFont) -> bool {
+        let declares = |font: &SourceFont, other: &SourceFont| {
+            font.conflicts_with
+                .iter()
+                .filter_map(|reference| self.find_font(reference))
+                .any(|found| found.name == other.name)
+        };
+
+        declares(a, b) || declares(b, a)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+/// Lookups of font ids so clients don't have to scan every font
+pub struct CompiledIndexes {
+    /// Publisher id -> fonts
+    pub publishers: BTreeMap<Uuid, Vec<Uuid>>,
+    /// Category -> fonts
+    pub categories: BTreeMap<FontCategory, Vec<Uuid>>,
+    /// Tag -> fonts
+    pub tags: BTreeMap<String, Vec<Uuid>>,
+}
+
+impl CompiledIndexes {
+    /// Builds the indexes for the fonts
+    pub fn from_fonts(fonts: &[CompiledFont]) -> CompiledIndexes {
+        let mut indexes = CompiledIndexes::default();
+
+        for font in fonts {
+            if let Some(publisher) = font.publisher_id {
+                indexes
+                    .publishers
+                    .entry(publisher)
+                    .or_default()
+                    .push(font.id);
+            }
+
+            for category in &font.categories {
+                indexes
+                    .categories
+                    .entry(category.clone())
+                    .or_default()
+                    .push(font.id);
+            }
+
+            for tag in &font.tags {
+                indexes.tags.entry(tag.clone()).or_default().push(font.id);
+            }
+        }
+
+        indexes
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+/// The slant of a face
+pub enum FaceStyle {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A design axis of a variable font (`fvar`)
+pub struct VariationAxis {
+    /// The axis tag (e.g. `wght`, `wdth` or `opsz`)
+    pub tag: String,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A face read from a font file's metadata
+pub struct FontFace {
+    /// The typographic family (e.g. `Arial`)
+    pub family: String,
+    /// The typographic subfamily (e.g. `Bold Italic`)
+    pub style_name: String,
+    /// The OS/2 weight class (400 is regular, 700 is bold)
+    pub weight: u16,
+    pub style: FaceStyle,
+    /// The axes the face varies along, empty if it isn't variable
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub axes: Vec<VariationAxis>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// A face of a family and the font that installs it
+pub struct CompiledFace {
+    pub font: Uuid,
+    /// The installed file
+    pub file: String,
+    pub style_name: String,
+    pub weight: u16,
+    pub style: FaceStyle,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub axes: Vec<VariationAxis>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+/// The faces of a family, from lightest to boldest
+pub struct CompiledFamily {
+    pub name: String,
+    pub faces: Vec<CompiledFace>,
+}
+
+impl CompiledFamily {
+    /// Groups faces (font, installed file, face) into families, sorted by name
+    pub fn from_faces(faces: Vec<(Uuid, String, FontFace)>) -> Vec<CompiledFamily> {
+        let mut families: BTreeMap<String, CompiledFamily> = BTreeMap::new();
+
+        for (font, file, face) in faces {
+            let family = families
+                .entry(face.family.to_lowercase())
+                .or_insert_with(|| CompiledFamily {
+                    name: face.family.clone(),
+                    faces: vec![],
+                });
+            let compiled = CompiledFace {
+                font,
+                file,
+                style_name: face.style_name,
+                weight: face.weight,
+                style: face.style,
+                axes: face.axes,
+            };
+            if !family.faces.contains(&compiled) {
+                family.faces.push(compiled);
+            }
+        }
+
+        families
+            .into_values()
+            .map(|mut family| {
+                family.faces.sort_by(|a, b| {
+                    (a.weight, a.style, &a.style_name).cmp(&(b.weight, b.style, &b.style_name))
+                });
+                family
+            })
+            .collect()
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 /// The file format (of the compiled)
 pub struct Compiled {
     pub version: Version,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub publishers: Vec<CompiledPublisher>,
     pub downloads: Vec<CompiledDownloads>,
     pub groups: Vec<CompiledGroup>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub applications: Vec<CompiledApplication>,
     pub fonts: Vec<CompiledFont>,
+    /// Families of the installed files, missing in databases built before they were recorded
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub families: Vec<CompiledFamily>,
+    #[serde(default)]
+    pub indexes: CompiledIndexes,
+}
+
+impl Compiled {
+    pub fn find_font(&self, id: Uuid) -> Option<&CompiledFont> {
+        self.fonts.iter().find(|font| font.id == id)
+    }
+
+    /// The families a font installs faces of
+    pub fn font_families(&self, font: Uuid) -> impl Iterator<Item = &CompiledFamily> {
+        self.families
+            .iter()
+            .filter(move |family| family.faces.iter().any(|face| face.font == font))
+    }
+
+    /// The axes of a font's variable faces (each tag once, with the widest range)
+    pub fn font_axes(&self, font: Uuid) -> Vec<VariationAxis> {
+        let mut axes: Vec<VariationAxis> = vec![];
+        let faces = self
+            .families
+            .iter()
+            .flat_map(|family| &family.faces)
+            .filter(|face| face.font == font);
+
+        for axis in faces.flat_map(|face| &face.axes) {
+            match axes.iter_mut().find(|other| other.tag == axis.tag) {
+                Some(other) => {
+                    other.min = other.min.min(axis.min);
+                    other.max = other.max.max(axis.max);
+                }
+                None => axes.push(axis.clone()),
+            }
+        }
+
+        axes
+    }
+
+    /// The fonts a reference (font name, short name or uuid, group name or uuid, or application)
+    /// points to
+    pub fn select(&self, reference: &str) -> Option<Vec<&CompiledFont>> {
+        let uuid = Uuid::parse_str(reference).ok();
+
+        let font = self.fonts.iter().find(|font| {
+            Some(font.id) == uuid
+                || font.name.eq_ignore_ascii_case(reference)
+                || font.short_name.eq_ignore_ascii_case(reference)
+                || font
+                    .previous_names
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(reference))
+        });
+        if let Some(font) = font {
+            return Some(vec![font]);
+        }
+
+        let group = self
+            .groups
+            .iter()
+            .find(|group| Some(group.id) == uuid || group.name.eq_ignore_ascii_case(reference))
+            .map(|group| &group.fonts);
+        let fonts = match group {
+            Some(fonts) => fonts,
+            None => &self.find_application(reference)?.fonts,
+        };
+
+        Some(fonts.iter().filter_map(|id| self.find_font(*id)).collect())
+    }
+
+    /// The application a reference (name, uuid or `steam:<app id>`) points to
+    pub fn find_application(&self, reference: &str) -> Option<&CompiledApplication> {
+        let uuid = Uuid::parse_str(reference).ok();
+        let steam_app_id = reference
+            .strip_prefix("steam:")
+            .and_then(|id| id.parse::<u32>().ok());
+
+        self.applications.iter().find(|application| {
+            Some(application.id) == uuid
+                || application.name.eq_ignore_ascii_case(reference)
+                || (steam_app_id.is_some() && application.steam_app_id == steam_app_id)
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -1,11 +1,32 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use s3::Bucket;
+#[cfg(feature = "publish")]
+use reqwest::header::{HeaderMap, HeaderValue};
+#[cfg(feature = "publish")]
+use s3::{error::S3Error, serde_types::Object, Bucket};
 use semver::Version;
 use serde::{Deserialize, Serialize};
 use url::Url;
 use uuid::Uuid;
 
+use crate::types::DownloadAccess;
+#[cfg(feature = "publish")]
+use crate::{
+    badge::Badge,
+    build::replace_download,
+    feed::{self, Release},
+    types::Compiled,
+};
+
+/// The current unix time (in seconds)
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Downloadable {
@@ -13,43 +34,180 @@ pub struct Downloadable {
     pub file_size: u64,
     pub hash: String,
     pub download_url: Url,
+    #[serde(default, skip_serializing_if = "DownloadAccess::is_public")]
+    pub access: DownloadAccess,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
 }
 
 pub type DownloadsList = Vec<Downloadable>;
 
-pub async fn grab_downloadables_from_s3(s3: &Bucket) -> DownloadsList {
+/// A byte count for humans (e.g. `5.2 MB`)
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit < UNITS.len() - 1 {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    match unit {
+        0 => format!("{} B", bytes),
+        _ => format!("{:.1} {}", size, UNITS[unit]),
+    }
+}
+
+#[cfg(feature = "publish")]
+/// Keeps a copy of a corrupt index next to it so it can be inspected later
+async fn backup_corrupt_index(s3: &Bucket, name: &str, data: &[u8]) {
+    let backup = format!("{}.corrupt-{}", name, now());
+
+    match s3.put_object(&backup, data).await {
+        Ok(_) => warn!("Backed up the corrupt {} to {}", name, backup),
+        Err(e) => warn!("Failed to back up the corrupt {}: {}", name, e),
+    }
+}
+
+#[cfg(feature = "publish")]
+pub async fn grab_downloadables_from_s3(
+    s3: &Bucket,
+    base_url: &Url,
+    rebuild: bool,
+) -> DownloadsList {
     match s3.get_object("/downloadables.json").await {
         Ok(data) => {
-            let data = serde_json::from_slice(data.as_slice());
+            let parsed = serde_json::from_slice(data.as_slice());
 
-            match data {
-                Ok(data) => data,
+            match parsed {
+                Ok(parsed) => parsed,
                 Err(e) => {
                     // File is corrupted
                     error!("Failed to parse downloadables.json: {}", e);
-                    std::process::exit(1);
+                    backup_corrupt_index(s3, "downloadables.json", data.as_slice()).await;
+
+                    if !rebuild {
+                        error!("Rerun with --rebuild-index to rebuild it from the bucket");
+                        std::process::exit(1);
+                    }
+
+                    rebuild_downloadables(s3, base_url).await
                 }
             }
         }
+        Err(e) if rebuild => {
+            warn!("Failed to get downloadables.json: {}... Rebuilding it", e);
+            rebuild_downloadables(s3, base_url).await
+        }
+        // Starting from an empty list would upload everything again under new ids
+        Err(S3Error::Http(404, _)) => {
+            warn!("downloadables.json is missing... Rebuilding it from the bucket");
+            rebuild_downloadables(s3, base_url).await
+        }
+        // Anything else could be transient, an empty list would drop every download from it
         Err(e) => {
-            // File probably doesn't exist
-            warn!(
-                "Failed to get downloadables.json: {}... Using empty list",
-                e
-            );
-            vec![]
+            error!("Failed to get downloadables.json: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(feature = "publish")]
+/// The metadata uploads carry their sha256 in
+const HASH_METADATA: &str = "sha256";
+#[cfg(feature = "publish")]
+const HASH_HEADER: &str = "x-amz-meta-sha256";
+#[cfg(feature = "publish")]
+/// The metadata uploads record their resource in
+const SOURCE_METADATA: &str = "source";
+#[cfg(feature = "publish")]
+/// The resource a download was uploaded from (percent-encoded, relative to the base path)
+const SOURCE_HEADER: &str = "x-amz-meta-source";
+
+#[cfg(feature = "publish")]
+/// The resource a download was uploaded from (relative to the base path), none for generated
+/// downloads and ones uploaded without the metadata
+pub async fn object_source(s3: &Bucket, key: &str) -> Result<Option<PathBuf>, S3Error> {
+    let (head, _) = s3.head_object(key).await?;
+
+    Ok(head
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get(SOURCE_METADATA))
+        .and_then(|source| url::form_urlencoded::parse(source.as_bytes()).next())
+        .map(|(source, _)| PathBuf::from(source.into_owned())))
+}
+
+#[cfg(feature = "publish")]
+/// The size and hash of an uploaded download, from its metadata or, for downloads uploaded
+/// without it, by downloading and hashing it
+pub async fn object_hash(s3: &Bucket, key: &str) -> Result<(u64, String), S3Error> {
+    let (head, _) = s3.head_object(key).await?;
+    let hash = head
+        .metadata
+        .as_ref()
+        .and_then(|metadata| metadata.get(HASH_METADATA));
+    if let (Some(hash), Some(size)) = (hash, head.content_length) {
+        return Ok((size as u64, hash.clone()));
+    }
+
+    let data = s3.get_object(key).await?;
+    Ok((
+        data.as_slice().len() as u64,
+        sha256::digest(data.as_slice()),
+    ))
+}
+
+#[cfg(feature = "publish")]
+/// Reconstructs downloadables.json from the uploaded downloads
+pub async fn rebuild_downloadables(s3: &Bucket, base_url: &Url) -> DownloadsList {
+    let mut downloads = vec![];
+
+    for access in [DownloadAccess::Public, DownloadAccess::Signed] {
+        for object in list_objects(s3, download_directory(access)).await {
+            let (uuid, extension) = match object_uuid(&object.key) {
+                Some(found) => found,
+                None => {
+                    warn!("Skipping unknown object {}", object.key);
+                    continue;
+                }
+            };
+
+            let (file_size, hash) = match object_hash(s3, &object.key).await {
+                Ok(found) => found,
+                Err(e) => {
+                    error!("Failed to get {}: {}", object.key, e);
+                    std::process::exit(1);
+                }
+            };
+
+            downloads.push(Downloadable {
+                id: uuid,
+                file_size,
+                hash,
+                download_url: generate_url(base_url, extension.as_deref(), &uuid, access),
+                access,
+                mirrors: vec![],
+            });
         }
     }
+
+    info!("Rebuilt downloadables.json ({} downloads)", downloads.len());
+
+    downloads
 }
 
 pub const DOWNLOAD_FILE_PATH: &str = "downloads";
+/// Signed downloads live here, the bucket policy should keep it private
+pub const PRIVATE_DOWNLOAD_FILE_PATH: &str = "private-downloads";
 pub const VERSIONS_FILE_PATH: &str = "versions";
 
 pub fn generate_versions_url(base_url: &Url, id: &Uuid) -> Url {
     let mut url = base_url.clone();
     let mut url_path = base_url.path_segments().unwrap().collect::<Vec<_>>();
 
-    let data = urlencoding::encode(&VERSIONS_FILE_PATH).into_owned();
+    let data = urlencoding::encode(VERSIONS_FILE_PATH).into_owned();
     url_path.push(data.as_str());
 
     let data = format!("{}.json", id);
@@ -61,19 +219,148 @@ pub fn generate_versions_url(base_url: &Url, id: &Uuid) -> Url {
     url
 }
 
-pub async fn upload_version_to_s3(s3: &Bucket, id: Uuid, built: &Vec<u8>) {
-    let mut path: PathBuf = [VERSIONS_FILE_PATH, &id.to_string()].iter().collect();
-    path.set_extension("json");
+pub const METALINKS_FILE_PATH: &str = "metalinks";
+/// The shields.io badges of the latest version
+pub const BADGES_FILE_PATH: &str = "badges";
+/// The Atom feed of the releases
+pub const FEED_FILE_PATH: &str = "releases.atom";
+/// The releases the feed is generated from
+pub const RELEASES_FILE_PATH: &str = "releases.json";
+
+pub fn generate_feed_url(base_url: &Url) -> Url {
+    let mut url = base_url.clone();
+    let mut url_path = base_url
+        .path_segments()
+        .unwrap()
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>();
+    url_path.push(FEED_FILE_PATH);
+
+    url.set_path(&url_path.join("/"));
+
+    url
+}
+
+pub fn generate_metalink_url(base_url: &Url, id: &Uuid) -> Url {
+    let mut url = base_url.clone();
+    let mut url_path = base_url.path_segments().unwrap().collect::<Vec<_>>();
+
+    let data = urlencoding::encode(METALINKS_FILE_PATH).into_owned();
+    url_path.push(data.as_str());
+
+    let data = format!("{}.meta4", id);
+    let data = urlencoding::encode(&data).into_owned();
+    url_path.push(data.as_str());
+
+    url.set_path(&url_path.join("/"));
+
+    url
+}
+
+#[cfg(feature = "publish")]
+/// Where the metalink of a download is kept in the bucket
+pub fn metalink_key(id: &Uuid) -> String {
+    format!("{}/{}.meta4", METALINKS_FILE_PATH, id)
+}
+
+#[cfg(feature = "publish")]
+/// Uploads the metalink of a download
+pub async fn put_metalink(s3: &Bucket, id: Uuid, metalink: &str) -> Result<(), S3Error> {
+    s3.put_object_with_content_type(
+        metalink_key(&id),
+        metalink.as_bytes(),
+        "application/metalink4+xml",
+    )
+    .await?;
+    info!("Uploaded metalink {}.meta4", id);
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+/// Where a badge is kept in the bucket
+pub fn badge_key(name: &str) -> String {
+    format!("{}/{}.json", BADGES_FILE_PATH, name)
+}
+
+#[cfg(feature = "publish")]
+/// Uploads a badge
+pub async fn put_badge(s3: &Bucket, name: &str, badge: &Badge) -> Result<(), String> {
+    let data = serde_json::to_vec(badge).map_err(|e| e.to_string())?;
+
+    s3.put_object_with_content_type(badge_key(name), &data, "application/json")
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Uploaded badge {}.json", name);
+    Ok(())
+}
 
-    match s3.put_object_with_content_type(path.to_str().unwrap(), &built, "application/json").await {
-        Ok(_) => info!("Uploaded version {}.json", id),
+#[cfg(feature = "publish")]
+/// The releases the feed lists, a corrupt list is backed up and started over (the feed only
+/// loses its history)
+pub async fn grab_releases_from_s3(s3: &Bucket) -> Result<Vec<Release>, String> {
+    let data = match s3.get_object(RELEASES_FILE_PATH).await {
+        Ok(data) => data,
+        Err(S3Error::Http(404, _)) => return Ok(vec![]),
+        Err(e) => return Err(e.to_string()),
+    };
+
+    match serde_json::from_slice(data.as_slice()) {
+        Ok(releases) => Ok(releases),
         Err(e) => {
-            error!("Failed to upload version {}.json: {}", id, e);
-            std::process::exit(1);
+            error!("Failed to parse {}: {}", RELEASES_FILE_PATH, e);
+            backup_corrupt_index(s3, RELEASES_FILE_PATH, data.as_slice()).await;
+            Ok(vec![])
         }
     }
 }
 
+#[cfg(feature = "publish")]
+/// Uploads the releases and the feed generated from them
+pub async fn put_releases(s3: &Bucket, base_url: &Url, releases: &[Release]) -> Result<(), String> {
+    let data = serde_json::to_vec(releases).map_err(|e| e.to_string())?;
+    s3.put_object_with_content_type(RELEASES_FILE_PATH, &data, "application/json")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let xml = feed::feed(&generate_feed_url(base_url), releases);
+    s3.put_object_with_content_type(FEED_FILE_PATH, xml.as_bytes(), "application/atom+xml")
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Uploaded {}", FEED_FILE_PATH);
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+pub async fn upload_metalink_to_s3(s3: &Bucket, id: Uuid, metalink: &str) {
+    if let Err(e) = put_metalink(s3, id, metalink).await {
+        error!("Failed to upload metalink {}.meta4: {}", id, e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "publish")]
+/// Where a version file is kept in the bucket
+pub fn version_key(id: &Uuid) -> String {
+    format!("{}/{}.json", VERSIONS_FILE_PATH, id)
+}
+
+#[cfg(feature = "publish")]
+/// Uploads a version file
+pub async fn put_version(s3: &Bucket, id: Uuid, built: &[u8]) -> Result<(), S3Error> {
+    s3.put_object_with_content_type(version_key(&id), built, "application/json")
+        .await?;
+    info!("Uploaded version {}.json", id);
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+pub async fn upload_version_to_s3(s3: &Bucket, id: Uuid, built: &[u8]) {
+    if let Err(e) = put_version(s3, id, built).await {
+        error!("Failed to upload version {}.json: {}", id, e);
+        std::process::exit(1);
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct VersionInfo {
@@ -82,60 +369,168 @@ pub struct VersionInfo {
     pub download_url: Url,
     pub hash: String,
     pub file_size: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<Url>,
+    /// When a pre-release expires (unix time in seconds), `gc` removes it after that
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
 }
 
-pub async fn grab_versions_from_s3(s3: &Bucket) -> Vec<VersionInfo> {
+#[cfg(feature = "publish")]
+pub async fn grab_versions_from_s3(s3: &Bucket, base_url: &Url, rebuild: bool) -> Vec<VersionInfo> {
     match s3.get_object("/versions.json").await {
         Ok(data) => {
-            let data = serde_json::from_slice(data.as_slice());
+            let parsed = serde_json::from_slice(data.as_slice());
 
-            match data {
-                Ok(data) => data,
+            match parsed {
+                Ok(parsed) => parsed,
                 Err(e) => {
                     // File is corrupted
                     error!("Failed to parse versions.json: {}", e);
-                    std::process::exit(1);
+                    backup_corrupt_index(s3, "versions.json", data.as_slice()).await;
+
+                    if !rebuild {
+                        error!("Rerun with --rebuild-index to rebuild it from the bucket");
+                        std::process::exit(1);
+                    }
+
+                    rebuild_versions(s3, base_url).await
                 }
             }
         }
+        Err(e) if rebuild => {
+            warn!("Failed to get versions.json: {}... Rebuilding it", e);
+            rebuild_versions(s3, base_url).await
+        }
+        // Starting from an empty list would drop every earlier release
+        Err(S3Error::Http(404, _)) => {
+            warn!("versions.json is missing... Rebuilding it from the bucket");
+            rebuild_versions(s3, base_url).await
+        }
+        // Anything else could be transient, an empty list would drop every version from it
         Err(e) => {
-            // File probably doesn't exist
-            warn!("Failed to get versions.json: {}... Using empty list", e);
-            vec![]
+            error!("Failed to get versions.json: {}", e);
+            std::process::exit(1);
         }
     }
 }
 
-pub async fn upload_versions_to_s3(s3: &Bucket, versions: Vec<VersionInfo>) {
-    let data = match serde_json::to_vec(&versions) {
+#[cfg(feature = "publish")]
+/// Reconstructs versions.json from the uploaded version files
+pub async fn rebuild_versions(s3: &Bucket, base_url: &Url) -> Vec<VersionInfo> {
+    let mut versions = vec![];
+
+    for object in list_objects(s3, VERSIONS_FILE_PATH).await {
+        let uuid = match object_uuid(&object.key) {
+            Some((uuid, _)) => uuid,
+            None => {
+                warn!("Skipping unknown object {}", object.key);
+                continue;
+            }
+        };
+
+        let data = match s3.get_object(&object.key).await {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to get {}: {}", object.key, e);
+                std::process::exit(1);
+            }
+        };
+
+        // The version number only lives in the file itself
+        let compiled: Compiled = match serde_json::from_slice(data.as_slice()) {
+            Ok(compiled) => compiled,
+            Err(e) => {
+                warn!("Skipping unreadable version {}: {}", object.key, e);
+                continue;
+            }
+        };
+
+        versions.push(VersionInfo {
+            id: uuid,
+            version: compiled.version,
+            download_url: generate_versions_url(base_url, &uuid),
+            hash: sha256::digest(data.as_slice()),
+            file_size: data.as_slice().len() as u64,
+            mirrors: vec![],
+            expires_at: None,
+        });
+    }
+
+    versions.sort_by(|a, b| a.version.cmp(&b.version));
+
+    info!("Rebuilt versions.json ({} versions)", versions.len());
+
+    versions
+}
+
+#[cfg(feature = "publish")]
+/// Reads a published version, `None` if it can't be read
+pub async fn grab_version_from_s3(s3: &Bucket, id: &Uuid) -> Option<Compiled> {
+    let path = format!("{}/{}.json", VERSIONS_FILE_PATH, id);
+
+    let data = match s3.get_object(&path).await {
         Ok(data) => data,
         Err(e) => {
-            error!("Failed to serialize versions.json: {}", e);
-            std::process::exit(1);
+            warn!("Failed to get version {}: {}", id, e);
+            return None;
         }
     };
 
-    match s3.put_object_with_content_type("/versions.json", &data, "application/json").await {
-        Ok(_) => info!("Uploaded versions.json"),
+    match serde_json::from_slice(data.as_slice()) {
+        Ok(compiled) => Some(compiled),
         Err(e) => {
-            error!("Failed to upload versions.json: {}", e);
-            std::process::exit(1);
+            warn!("Failed to parse version {}: {}", id, e);
+            None
         }
     }
 }
 
-pub fn generate_url(base_url: &Url, path: &PathBuf, uuid: &Uuid) -> Url {
+#[cfg(feature = "publish")]
+/// Uploads versions.json
+pub async fn put_versions(s3: &Bucket, versions: &[VersionInfo]) -> Result<(), String> {
+    let data = serde_json::to_vec(versions).map_err(|e| e.to_string())?;
+
+    s3.put_object_with_content_type("/versions.json", &data, "application/json")
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Uploaded versions.json");
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+pub async fn upload_versions_to_s3(s3: &Bucket, versions: Vec<VersionInfo>) {
+    if let Err(e) = put_versions(s3, &versions).await {
+        error!("Failed to upload versions.json: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// The directory (in the bucket) a download is uploaded to
+pub fn download_directory(access: DownloadAccess) -> &'static str {
+    match access {
+        DownloadAccess::Public => DOWNLOAD_FILE_PATH,
+        DownloadAccess::Signed => PRIVATE_DOWNLOAD_FILE_PATH,
+    }
+}
+
+pub fn generate_url(
+    base_url: &Url,
+    extension: Option<&str>,
+    uuid: &Uuid,
+    access: DownloadAccess,
+) -> Url {
     let mut url = base_url.clone();
     let mut url_path = base_url.path_segments().unwrap().collect::<Vec<_>>();
 
-    let file_extension = match path.extension() {
-        Some(extension) => ".".to_string() + extension.to_str().unwrap(),
+    let file_extension = match extension {
+        Some(extension) => ".".to_string() + extension,
         None => "".to_string(),
     };
 
-    let file_name = format!("{}{}", uuid.to_string(), file_extension);
+    let file_name = format!("{}{}", uuid, file_extension);
 
-    let data = urlencoding::encode(&DOWNLOAD_FILE_PATH).into_owned();
+    let data = urlencoding::encode(download_directory(access)).into_owned();
     url_path.push(data.as_str());
 
     let data = urlencoding::encode(&file_name).into_owned();
@@ -146,75 +541,402 @@ pub fn generate_url(base_url: &Url, path: &PathBuf, uuid: &Uuid) -> Url {
     url
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub enum UploadableData {
+    /// A local resource (relative to the base path)
+    File(PathBuf),
+    /// Bytes generated during the build (data, extension)
+    Generated(Vec<u8>, String),
+}
+
+impl UploadableData {
+    pub fn extension(&self) -> Option<&str> {
+        match self {
+            UploadableData::File(path) => path.extension().and_then(|e| e.to_str()),
+            UploadableData::Generated(_, extension) => Some(extension),
+        }
+    }
+}
+
+/// Reads the data to upload, exits on failure
+fn read_uploadable(base_path: &Path, uploadable: &UploadableData) -> Vec<u8> {
+    match uploadable {
+        UploadableData::File(file_path) => match std::fs::read(base_path.join(file_path)) {
+            Ok(data) => data,
+            Err(e) => {
+                error!("Failed to read file: {}", e);
+                std::process::exit(1);
+            }
+        },
+        UploadableData::Generated(data, _) => data.clone(),
+    }
+}
+
+#[cfg(feature = "publish")]
+/// Local resources larger than this are uploaded in parts, one part in memory at a time (S3's
+/// smallest part is 5 MiB)
+pub const UPLOAD_PART_SIZE: usize = 8 * 1024 * 1024;
+
+#[cfg(feature = "publish")]
+/// Reads the next part of a file (a short part is the last one)
+fn read_part(file: &mut impl std::io::Read) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+
+    let mut part = Vec::with_capacity(UPLOAD_PART_SIZE);
+    file.take(UPLOAD_PART_SIZE as u64).read_to_end(&mut part)?;
+    Ok(part)
+}
+
+#[cfg(feature = "publish")]
+/// The ETag S3 gives an upload of the data, the md5 of the data or, for uploads in parts, the md5
+/// of the parts' md5s followed by the number of parts
+fn upload_etag(base_path: &Path, uploadable: &UploadableData) -> std::io::Result<String> {
+    let path = match uploadable {
+        UploadableData::File(path) => base_path.join(path),
+        UploadableData::Generated(data, _) => return Ok(format!("\"{:x}\"", md5::compute(data))),
+    };
+
+    let mut file = std::fs::File::open(path)?;
+    let mut digests = vec![];
+    loop {
+        let part = read_part(&mut file)?;
+        if part.len() < UPLOAD_PART_SIZE && digests.is_empty() {
+            return Ok(format!("\"{:x}\"", md5::compute(&part)));
+        }
+        if !part.is_empty() {
+            digests.extend(md5::compute(&part).0);
+        }
+        if part.len() < UPLOAD_PART_SIZE {
+            break;
+        }
+    }
+
+    Ok(format!(
+        "\"{:x}-{}\"",
+        md5::compute(&digests),
+        digests.len() / 16
+    ))
+}
+
+#[cfg(feature = "publish")]
+/// Uploads the data, local resources larger than a part are read and uploaded a part at a time
+async fn upload_data(
+    s3: &Bucket,
+    key: &str,
+    base_path: &Path,
+    uploadable: &UploadableData,
+) -> Result<(), String> {
+    let path = match uploadable {
+        UploadableData::File(path) => base_path.join(path),
+        UploadableData::Generated(data, _) => {
+            return s3
+                .put_object(key, data)
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+    };
+
+    let mut file = std::fs::File::open(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let read = |file: &mut std::fs::File| {
+        read_part(file).map_err(|e| format!("{}: {}", path.display(), e))
+    };
+
+    let mut part = read(&mut file)?;
+    if part.len() < UPLOAD_PART_SIZE {
+        return s3
+            .put_object(key, &part)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+    }
+
+    let upload = s3
+        .initiate_multipart_upload(key, "application/octet-stream")
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut parts = vec![];
+    let uploaded: Result<(), String> = async {
+        loop {
+            let last = part.len() < UPLOAD_PART_SIZE;
+            if !part.is_empty() {
+                let number = parts.len() as u32 + 1;
+                parts.push(
+                    s3.put_multipart_chunk(
+                        part,
+                        &upload.key,
+                        number,
+                        &upload.upload_id,
+                        "application/octet-stream",
+                    )
+                    .await
+                    .map_err(|e| e.to_string())?,
+                );
+            }
+            if last {
+                return Ok(());
+            }
+            part = read(&mut file)?;
+        }
+    }
+    .await;
+
+    let completed = match uploaded {
+        Ok(()) => s3
+            .complete_multipart_upload(&upload.key, &upload.upload_id, parts)
+            .await
+            .map_err(|e| e.to_string())
+            .and_then(|response| match response.status_code() {
+                200..=299 => Ok(()),
+                code => Err(format!("Status code: {}", code)),
+            }),
+        Err(e) => Err(e),
+    };
+
+    // Don't leave the parts behind
+    if completed.is_err() {
+        if let Err(e) = s3.abort_upload(&upload.key, &upload.upload_id).await {
+            warn!("Failed to abort the upload of {}: {}", key, e);
+        }
+    }
+
+    completed
+}
+
+/// The file name and data of an upload (as it is named in the bucket)
+pub fn read_artifact(base_path: &Path, download: &UploadableDownloadInfo) -> (String, Vec<u8>) {
+    (
+        download.file_name(),
+        read_uploadable(base_path, &download.data),
+    )
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
 pub struct UploadableDownloadInfo {
     pub uuid: Uuid,
-    pub file_path: PathBuf,
+    pub data: UploadableData,
     pub hash: String,
     pub file_size: u64,
+    pub access: DownloadAccess,
+    pub mirrors: Vec<Url>,
+}
+
+impl UploadableDownloadInfo {
+    /// The name of the upload in the bucket (`<uuid>.<ext>`)
+    pub fn file_name(&self) -> String {
+        match self.data.extension() {
+            Some(extension) => format!("{}.{}", self.uuid, extension),
+            None => self.uuid.to_string(),
+        }
+    }
+
+    /// The key of the upload in the bucket
+    pub fn key(&self) -> String {
+        format!("{}/{}", download_directory(self.access), self.file_name())
+    }
+
+    /// The downloadables.json entry of the upload
+    pub fn downloadable(&self, base_url: &Url) -> Downloadable {
+        Downloadable {
+            id: self.uuid,
+            file_size: self.file_size,
+            hash: self.hash.clone(),
+            download_url: generate_url(base_url, self.data.extension(), &self.uuid, self.access),
+            access: self.access,
+            mirrors: self.mirrors.clone(),
+        }
+    }
+}
+
+#[cfg(feature = "publish")]
+/// Uploads a download to its key
+pub async fn put_download(
+    s3: &Bucket,
+    base_path: &Path,
+    download: &UploadableDownloadInfo,
+) -> Result<(), String> {
+    let key = download.key();
+
+    put_download_object(s3, &key, base_path, &download.data, &download.hash).await?;
+    info!("Uploaded file: {}", key);
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+/// Uploads the data of a download (with its hash) to the key
+pub async fn put_download_object(
+    s3: &Bucket,
+    key: &str,
+    base_path: &Path,
+    data: &UploadableData,
+    hash: &str,
+) -> Result<(), String> {
+    // Lets the index be rebuilt (and the bucket checked) without downloading everything
+    let mut headers = HeaderMap::new();
+    let hash = HeaderValue::from_str(hash).map_err(|e| e.to_string())?;
+    headers.insert(HASH_HEADER, hash);
+    // Staged (generated) downloads have no resource to point at
+    if let UploadableData::File(path) = data {
+        if path.is_relative() {
+            let source: String =
+                url::form_urlencoded::byte_serialize(path.to_string_lossy().as_bytes()).collect();
+            let source = HeaderValue::from_str(&source).map_err(|e| e.to_string())?;
+            headers.insert(SOURCE_HEADER, source);
+        }
+    }
+    let s3 = s3.with_extra_headers(headers);
+
+    upload_data(&s3, key, base_path, data).await
+}
+
+#[cfg(feature = "publish")]
+/// Whether the download is in the bucket as we'd upload it (same size and ETag), so a resumed
+/// update neither trusts a half-written object nor uploads a finished one again
+pub async fn is_uploaded(
+    s3: &Bucket,
+    base_path: &Path,
+    download: &UploadableDownloadInfo,
+) -> Result<bool, String> {
+    let head = match s3.head_object(download.key()).await {
+        Ok((head, _)) => head,
+        Err(S3Error::Http(404, _)) => return Ok(false),
+        Err(e) => return Err(e.to_string()),
+    };
+    if head.content_length != Some(download.file_size as i64) {
+        return Ok(false);
+    }
+
+    let etag = upload_etag(base_path, &download.data).map_err(|e| e.to_string())?;
+    Ok(head.e_tag.as_deref() == Some(etag.as_str()))
+}
+
+#[cfg(feature = "publish")]
+/// Uploads downloadables.json
+pub async fn put_downloadables(s3: &Bucket, downloads: &DownloadsList) -> Result<(), String> {
+    let data = serde_json::to_vec(downloads).map_err(|e| e.to_string())?;
+
+    s3.put_object_with_content_type("downloadables.json", &data, "application/json")
+        .await
+        .map_err(|e| e.to_string())?;
+    info!("Uploaded downloadables.json");
+    Ok(())
+}
+
+#[cfg(feature = "publish")]
+pub async fn upload_downloadables_to_s3(s3: &Bucket, downloads: &DownloadsList) {
+    if let Err(e) = put_downloadables(s3, downloads).await {
+        error!("Failed to upload downloadables.json: {}", e);
+        std::process::exit(1);
+    }
 }
 
-pub async fn upload_files_to_s3(
+#[cfg(feature = "publish")]
+/// Drops the uploads whose content is already in the bucket (e.g. left by an interrupted run)
+///
+/// Objects are matched by directory, size and ETag (the one the upload would get, large files
+/// are uploaded in parts). The database is pointed at the existing objects, which are returned
+/// so they can be added to downloadables.json.
+pub async fn skip_unchanged_uploads(
     s3: &Bucket,
     base_url: &Url,
-    base_path: PathBuf,
-    original_downloads: DownloadsList,
+    base_path: &Path,
+    file: &mut Compiled,
     downloads: Vec<UploadableDownloadInfo>,
-) {
-    let mut new_downloads = original_downloads.clone();
+) -> (Vec<UploadableDownloadInfo>, DownloadsList) {
+    let mut objects = vec![];
+    for directory in [DOWNLOAD_FILE_PATH, PRIVATE_DOWNLOAD_FILE_PATH] {
+        objects.extend(list_objects(s3, directory).await);
+    }
+
+    let mut uploads = vec![];
+    let mut existing = vec![];
 
-    // Loop through the downloads
     for download in downloads {
-        let UploadableDownloadInfo {
-            uuid,
-            file_path,
-            hash,
-            file_size,
-        } = download;
-
-        // Upload the file
-        let data = match std::fs::read(base_path.join(&file_path)) {
-            Ok(data) => data,
+        let directory = download_directory(download.access);
+        let etag = match upload_etag(base_path, &download.data) {
+            Ok(etag) => etag,
             Err(e) => {
                 error!("Failed to read file: {}", e);
                 std::process::exit(1);
             }
         };
 
-        let mut path: PathBuf = [DOWNLOAD_FILE_PATH, &uuid.to_string()].iter().collect();
+        let object = objects.iter().find(|object| {
+            object.size == download.file_size
+                && object.e_tag.as_deref() == Some(etag.as_str())
+                && object.key.starts_with(&format!("{}/", directory))
+        });
 
-        path.set_extension(file_path.extension().unwrap());
+        // The object has to be named like one of ours
+        let found = object.and_then(|object| object_uuid(&object.key));
 
-        match s3.put_object(path.to_str().unwrap(), &data).await {
-            Ok(_) => info!("Uploaded file: {}", path.to_str().unwrap()),
-            Err(e) => {
-                error!("Failed to upload file: {}", e);
-                std::process::exit(1);
+        let (uuid, extension) = match found {
+            Some(found) => found,
+            None => {
+                uploads.push(download);
+                continue;
+            }
+        };
+
+        info!(
+            "Skipping upload of {} (already uploaded as {})",
+            download.uuid, uuid
+        );
+
+        let download_url = generate_url(base_url, extension.as_deref(), &uuid, download.access);
+
+        // Point the database at the existing object
+        for compiled in &mut file.downloads {
+            if compiled.id == download.uuid {
+                compiled.id = uuid;
+                compiled.download_url = download_url.clone();
+            }
+            if compiled.derived_from == Some(download.uuid) {
+                compiled.derived_from = Some(uuid);
             }
         }
+        replace_download(file, download.uuid, uuid);
 
-        // Add the download to the list
-        new_downloads.push(Downloadable {
+        existing.push(Downloadable {
             id: uuid,
-            file_size,
-            hash,
-            download_url: generate_url(&base_url, &file_path, &uuid),
+            file_size: download.file_size,
+            hash: download.hash,
+            download_url,
+            access: download.access,
+            mirrors: download.mirrors,
         });
     }
 
-    // Upload the downloadables.json file
-    let data = match serde_json::to_vec(&new_downloads) {
-        Ok(data) => data,
-        Err(e) => {
-            error!("Failed to serialize downloadables.json: {}", e);
-            std::process::exit(1);
-        }
-    };
+    (uploads, existing)
+}
 
-    match s3.put_object_with_content_type("downloadables.json", &data, "application/json").await {
-        Ok(_) => info!("Uploaded downloadables.json"),
+#[cfg(feature = "publish")]
+/// Lists the objects in a directory of the bucket, exits on failure
+pub async fn list_objects(s3: &Bucket, directory: &str) -> Vec<Object> {
+    match s3.list(format!("{}/", directory), None).await {
+        Ok(results) => results.into_iter().flat_map(|r| r.contents).collect(),
         Err(e) => {
-            error!("Failed to upload downloadables.json: {}", e);
+            error!("Failed to list {}: {}", directory, e);
             std::process::exit(1);
         }
     }
 }
+
+/// The uuid and extension of an object named like one of ours (`<directory>/<uuid>.<ext>`)
+pub fn object_uuid(key: &str) -> Option<(Uuid, Option<String>)> {
+    let path = Path::new(key);
+    let uuid = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .and_then(|stem| Uuid::parse_str(stem).ok())?;
+
+    Some((
+        uuid,
+        path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_string()),
+    ))
+}
@@ -0,0 +1,417 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+
+use semver::Version;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    approval::DEFAULT_APPROVAL_KEYS_FILE,
+    catalog::Catalog,
+    lock::DEFAULT_LOCK_FILE,
+    types::{DownloadAccess, Source, SourceDownload},
+};
+
+/// The manifest is the first entry of a bundle
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The bundle format we write (and the newest we can read)
+pub const VENDOR_VERSION: u32 = 1;
+
+/// The http cache in a bundle (the external resources themselves are in `EXTERNALS_DIRECTORY`)
+pub const CACHE_FILE: &str = "http-cache.json";
+
+/// Where the catalogs are kept in a bundle (`catalogs/<index>/<file name>`)
+const CATALOGS_DIRECTORY: &str = "catalogs";
+
+/// Where the local resources are kept in a bundle (by their path relative to the base path)
+const RESOURCES_DIRECTORY: &str = "resources";
+
+/// Where the locked external resources are kept in a bundle (by their hash)
+const EXTERNALS_DIRECTORY: &str = "externals";
+
+pub enum VendorError {
+    /// A file couldn't be read (Path, Error)
+    Read(PathBuf, String),
+    /// The bundle couldn't be written (Path, Error)
+    Write(PathBuf, String),
+    /// A local resource points outside of the base path (Path)
+    OutsideBasePath(PathBuf),
+    /// The bundle isn't a valid bundle (Path, Error)
+    Invalid(PathBuf, String),
+    /// A file of the bundle doesn't match the manifest (Path in the bundle)
+    HashMismatch(String),
+}
+
+impl Display for VendorError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VendorError::Read(path, error) => {
+                write!(f, "Failed to read {}: {}", path.display(), error)
+            }
+            VendorError::Write(path, error) => {
+                write!(f, "Failed to write {}: {}", path.display(), error)
+            }
+            VendorError::OutsideBasePath(path) => write!(
+                f,
+                "The local resource {} is outside of the base path",
+                path.display()
+            ),
+            VendorError::Invalid(path, error) => {
+                write!(f, "{} isn't a valid bundle: {}", path.display(), error)
+            }
+            VendorError::HashMismatch(path) => {
+                write!(f, "{} doesn't match the hash in the manifest", path)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// A file of a bundle
+pub struct VendoredFile {
+    /// Path in the bundle
+    pub path: String,
+    pub hash: String,
+    pub file_size: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+/// What a vendored bundle holds, everything needed to build the catalog without the network
+/// (as long as the lockfile covers the external resources)
+pub struct VendorManifest {
+    pub version: u32,
+    /// The published version the bundle was made for
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub built_version: Option<Version>,
+    /// The git commit of the catalogs, approvals of the bundle are for it (none if they weren't
+    /// committed)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// The catalogs in the order they're merged (paths in the bundle)
+    pub catalogs: Vec<String>,
+    /// The lockfile (path in the bundle)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lock: Option<String>,
+    /// The http cache (path in the bundle)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache: Option<String>,
+    /// Every file of the bundle but the manifest
+    pub files: Vec<VendoredFile>,
+    /// Signed local resources that were left out (paths relative to the base path)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub excluded: Vec<String>,
+}
+
+/// What goes into a bundle
+pub struct VendorOptions<'a> {
+    pub catalogs: &'a [Catalog],
+    /// The merged catalogs (the local resources come from it)
+    pub source: &'a Source,
+    pub base_path: &'a Path,
+    /// The lockfile (if there is one)
+    pub lock_file: Option<&'a Path>,
+    /// The http cache (if there is one)
+    pub cache_file: Option<&'a Path>,
+    /// Signed resources aren't redistributable, they're only bundled when asked for
+    pub include_signed: bool,
+    pub built_version: Option<Version>,
+    pub commit: Option<String>,
+    /// The locked external resources (already checked against the lockfile), builds from the
+    /// bundle hash them instead of trusting the cache
+    pub externals: &'a [Vec<u8>],
+}
+
+/// A local resource path relative to the base path (`./a/../b` isn't allowed)
+fn relative_resource_path(path: &Path) -> Result<PathBuf, VendorError> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => relative.push(part),
+            Component::CurDir => {}
+            _ => return Err(VendorError::OutsideBasePath(path.to_owned())),
+        }
+    }
+
+    Ok(relative)
+}
+
+/// Bundle paths always use forward slashes
+fn bundle_path(parts: &[&str], path: &Path) -> String {
+    parts
+        .iter()
+        .map(|part| part.to_string())
+        .chain(
+            path.components()
+                .map(|component| component.as_os_str().to_string_lossy().to_string()),
+        )
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Appends a file to the tarball (with fixed metadata, bundles of the same files are identical)
+fn append<W: std::io::Write>(
+    builder: &mut tar::Builder<W>,
+    output: &Path,
+    name: &str,
+    data: &[u8],
+) -> Result<(), VendorError> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(0);
+    header.set_entry_type(tar::EntryType::Regular);
+
+    builder
+        .append_data(&mut header, name, data)
+        .map_err(|e| VendorError::Write(output.to_owned(), e.to_string()))
+}
+
+/// Bundles the catalogs, the lockfile and the local resources into a tarball
+pub fn vendor(options: &VendorOptions, output: &Path) -> Result<VendorManifest, VendorError> {
+    // Bundle path -> path on disk
+    let mut entries: Vec<(String, PathBuf)> = vec![];
+    let mut manifest = VendorManifest {
+        version: VENDOR_VERSION,
+        built_version: options.built_version.clone(),
+        commit: options.commit.clone(),
+        catalogs: vec![],
+        lock: None,
+        cache: None,
+        files: vec![],
+        excluded: vec![],
+    };
+
+    for (index, catalog) in options.catalogs.iter().enumerate() {
+        let name = catalog
+            .path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "fonts.json".to_string());
+        let path = format!("{}/{}/{}", CATALOGS_DIRECTORY, index, name);
+
+        manifest.catalogs.push(path.clone());
+        entries.push((path, catalog.path.clone()));
+    }
+
+    // The repository's trusted keys stay next to the first catalog, building from the bundle
+    // still needs an approval
+    if let Some(keys) = options
+        .catalogs
+        .first()
+        .map(|catalog| catalog.path.with_file_name(DEFAULT_APPROVAL_KEYS_FILE))
+        .filter(|keys| keys.is_file())
+    {
+        entries.push((
+            format!("{}/0/{}", CATALOGS_DIRECTORY, DEFAULT_APPROVAL_KEYS_FILE),
+            keys,
+        ));
+    }
+
+    if let Some(lock_file) = options.lock_file {
+        manifest.lock = Some(DEFAULT_LOCK_FILE.to_string());
+        entries.push((DEFAULT_LOCK_FILE.to_string(), lock_file.to_owned()));
+    }
+
+    if let Some(cache_file) = options.cache_file {
+        manifest.cache = Some(CACHE_FILE.to_string());
+        entries.push((CACHE_FILE.to_string(), cache_file.to_owned()));
+    }
+
+    // Every local resource once (signed ones only when asked for)
+    let mut signed: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut resources: BTreeSet<PathBuf> = BTreeSet::new();
+    for installation in options
+        .source
+        .fonts
+        .iter()
+        .flat_map(|font| &font.installations)
+    {
+        if let SourceDownload::LocalResource(path) = installation.download() {
+            let relative = relative_resource_path(path)?;
+            if installation.access() == DownloadAccess::Signed {
+                signed.insert(relative.clone());
+            }
+            resources.insert(relative);
+        }
+    }
+    for relative in resources {
+        if signed.contains(&relative) && !options.include_signed {
+            manifest.excluded.push(bundle_path(&[], &relative));
+            continue;
+        }
+
+        entries.push((
+            bundle_path(&[RESOURCES_DIRECTORY], &relative),
+            options.base_path.join(&relative),
+        ));
+    }
+
+    // Hash everything first, the manifest goes in front
+    for (name, path) in &entries {
+        let data =
+            std::fs::read(path).map_err(|e| VendorError::Read(path.clone(), e.to_string()))?;
+        manifest.files.push(VendoredFile {
+            path: name.clone(),
+            hash: sha256::digest(&data[..]),
+            file_size: data.len() as u64,
+        });
+    }
+
+    // Every external resource once
+    let mut externals: Vec<(String, &[u8])> = vec![];
+    for data in options.externals {
+        let hash = sha256::digest(&data[..]);
+        let name = format!("{}/{}", EXTERNALS_DIRECTORY, hash);
+        if externals.iter().any(|(existing, _)| existing == &name) {
+            continue;
+        }
+
+        manifest.files.push(VendoredFile {
+            path: name.clone(),
+            hash,
+            file_size: data.len() as u64,
+        });
+        externals.push((name, data));
+    }
+
+    let file =
+        File::create(output).map_err(|e| VendorError::Write(output.to_owned(), e.to_string()))?;
+    let mut builder = tar::Builder::new(file);
+
+    let data = serde_json::to_vec_pretty(&manifest)
+        .map_err(|e| VendorError::Write(output.to_owned(), e.to_string()))?;
+    append(&mut builder, output, MANIFEST_FILE, &data)?;
+
+    for (name, path) in &entries {
+        let data =
+            std::fs::read(path).map_err(|e| VendorError::Read(path.clone(), e.to_string()))?;
+        append(&mut builder, output, name, &data)?;
+    }
+    for (name, data) in externals {
+        append(&mut builder, output, &name, data)?;
+    }
+
+    builder
+        .into_inner()
+        .map_err(|e| VendorError::Write(output.to_owned(), e.to_string()))?;
+
+    Ok(manifest)
+}
+
+impl VendorManifest {
+    /// Where the local resources of a bundle unpacked into the directory are (the base path)
+    pub fn resources(directory: &Path) -> PathBuf {
+        directory.join(RESOURCES_DIRECTORY)
+    }
+
+    /// Where the external resources of a bundle unpacked into the directory are (named by hash)
+    pub fn externals(directory: &Path) -> PathBuf {
+        directory.join(EXTERNALS_DIRECTORY)
+    }
+}
+
+/// The hash of a bundle, what approvals of it are for
+pub fn bundle_hash(bundle: &Path) -> Result<String, VendorError> {
+    sha256::try_digest(bundle).map_err(|e| VendorError::Read(bundle.to_owned(), e.to_string()))
+}
+
+/// Reads the manifest, the first entry of a bundle
+fn read_manifest(
+    bundle: &Path,
+    entries: &mut tar::Entries<File>,
+) -> Result<VendorManifest, VendorError> {
+    let invalid = |error: String| VendorError::Invalid(bundle.to_owned(), error);
+
+    let manifest: VendorManifest = match entries.next() {
+        Some(Ok(entry))
+            if entry
+                .path()
+                .is_ok_and(|path| path == Path::new(MANIFEST_FILE)) =>
+        {
+            serde_json::from_reader(entry).map_err(|e| invalid(e.to_string()))?
+        }
+        Some(Err(error)) => return Err(invalid(error.to_string())),
+        _ => return Err(invalid(format!("{} isn't the first entry", MANIFEST_FILE))),
+    };
+    if manifest.version > VENDOR_VERSION {
+        return Err(invalid(format!(
+            "version {}, this build only knows version {}",
+            manifest.version, VENDOR_VERSION
+        )));
+    }
+
+    Ok(manifest)
+}
+
+/// The manifest of a bundle (without unpacking it)
+pub fn manifest(bundle: &Path) -> Result<VendorManifest, VendorError> {
+    let file =
+        File::open(bundle).map_err(|e| VendorError::Read(bundle.to_owned(), e.to_string()))?;
+    let mut archive = tar::Archive::new(file);
+    let mut entries = archive
+        .entries()
+        .map_err(|e| VendorError::Invalid(bundle.to_owned(), e.to_string()))?;
+
+    read_manifest(bundle, &mut entries)
+}
+
+/// Unpacks a bundle into the directory, every file is checked against the manifest
+pub fn unpack(bundle: &Path, directory: &Path) -> Result<VendorManifest, VendorError> {
+    let invalid = |error: String| VendorError::Invalid(bundle.to_owned(), error);
+
+    let file =
+        File::open(bundle).map_err(|e| VendorError::Read(bundle.to_owned(), e.to_string()))?;
+    let mut archive = tar::Archive::new(file);
+    let mut entries = archive.entries().map_err(|e| invalid(e.to_string()))?;
+
+    // The manifest comes first
+    let manifest = read_manifest(bundle, &mut entries)?;
+
+    let mut unpacked: BTreeSet<String> = BTreeSet::new();
+    for entry in entries {
+        let mut entry = entry.map_err(|e| invalid(e.to_string()))?;
+        let name = entry
+            .path()
+            .map_err(|e| invalid(e.to_string()))?
+            .to_string_lossy()
+            .to_string();
+        let expected = manifest
+            .files
+            .iter()
+            .find(|file| file.path == name)
+            .ok_or_else(|| invalid(format!("{} isn't in the manifest", name)))?;
+
+        let mut data = vec![];
+        std::io::Read::read_to_end(&mut entry, &mut data).map_err(|e| invalid(e.to_string()))?;
+        if data.len() as u64 != expected.file_size || sha256::digest(&data[..]) != expected.hash {
+            return Err(VendorError::HashMismatch(name));
+        }
+
+        let target = directory.join(
+            relative_resource_path(Path::new(&name))
+                .map_err(|_| invalid(format!("{} is outside of the bundle", name)))?,
+        );
+        if let Some(parent) = target.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| VendorError::Write(parent.to_owned(), e.to_string()))?;
+        }
+        std::fs::write(&target, data).map_err(|e| VendorError::Write(target, e.to_string()))?;
+        unpacked.insert(name);
+    }
+
+    if let Some(missing) = manifest
+        .files
+        .iter()
+        .find(|file| !unpacked.contains(&file.path))
+    {
+        return Err(invalid(format!("{} is missing", missing.path)));
+    }
+
+    Ok(manifest)
+}
@@ -0,0 +1,333 @@
+use std::{collections::HashMap, fmt::Display};
+
+use uuid::Uuid;
+
+use crate::{
+    types::{Compiled, CompiledIndexes, Source},
+    utils::DownloadsList,
+};
+
+pub enum ConsistencyError {
+    /// An installation points to a download that isn't in the output (Font, Download)
+    MissingDownload(String, Uuid),
+    /// The built download doesn't match the source pin (Font, Download, Expected, Actual)
+    PinMismatch(String, Uuid, String, String),
+    /// The built download doesn't match the already published one (Download, Field)
+    PublishedMismatch(Uuid, &'static str),
+}
+
+impl Display for ConsistencyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConsistencyError::MissingDownload(font, download) => write!(
+                f,
+                "Missing download (font: {}, download: {})",
+                font, download
+            ),
+            ConsistencyError::PinMismatch(font, download, expected, actual) => write!(
+                f,
+                "Download doesn't match the pin (font: {}, download: {}, expected: {}, actual: {})",
+                font, download, expected, actual
+            ),
+            ConsistencyError::PublishedMismatch(download, field) => write!(
+                f,
+                "Download doesn't match downloadables.json (download: {}, field: {})",
+                download, field
+            ),
+        }
+    }
+}
+
+/// Cross-checks the built downloads against the source pins and the published downloads
+pub fn verify(
+    source: &Source,
+    built: &Compiled,
+    downloadables: &DownloadsList,
+) -> Vec<ConsistencyError> {
+    let mut errors = vec![];
+
+    for font in &source.fonts {
+        let compiled = font
+            .id
+            .uuid()
+            .and_then(|id| built.fonts.iter().find(|f| f.id == id));
+        let compiled = match compiled {
+            Some(compiled) => compiled,
+            None => continue,
+        };
+
+        // The installations are built in order
+        for (installation, compiled) in font.installations.iter().zip(&compiled.installations) {
+            let download = match built.downloads.iter().find(|d| d.id == compiled.download()) {
+                Some(download) => download,
+                None => {
+                    errors.push(ConsistencyError::MissingDownload(
+                        font.name.clone(),
+                        compiled.download(),
+                    ));
+                    continue;
+                }
+            };
+
+            let pin = match installation.pin() {
+                Some(pin) => pin,
+                None => continue,
+            };
+
+            // Pins are for the source download, not the derived one
+            let original = match download.derived_from {
+                Some(parent) => match built.downloads.iter().find(|d| d.id == parent) {
+                    Some(original) => original,
+                    None => {
+                        errors.push(ConsistencyError::MissingDownload(font.name.clone(), parent));
+                        continue;
+                    }
+                },
+                None => download,
+            };
+
+            if original.hash != pin.hash.to_lowercase() {
+                errors.push(ConsistencyError::PinMismatch(
+                    font.name.clone(),
+                    original.id,
+                    pin.hash.clone(),
+                    original.hash.clone(),
+                ));
+            }
+            if original.file_size != pin.file_size {
+                errors.push(ConsistencyError::PinMismatch(
+                    font.name.clone(),
+                    original.id,
+                    pin.file_size.to_string(),
+                    original.file_size.to_string(),
+                ));
+            }
+        }
+    }
+
+    for download in &built.downloads {
+        let published = match downloadables.iter().find(|d| d.id == download.id) {
+            Some(published) => published,
+            None => continue,
+        };
+
+        if published.hash != download.hash {
+            errors.push(ConsistencyError::PublishedMismatch(download.id, "hash"));
+        }
+        if published.file_size != download.file_size {
+            errors.push(ConsistencyError::PublishedMismatch(download.id, "fileSize"));
+        }
+        if published.download_url != download.download_url {
+            errors.push(ConsistencyError::PublishedMismatch(
+                download.id,
+                "downloadUrl",
+            ));
+        }
+    }
+
+    errors
+}
+
+pub enum CompiledError {
+    /// An id is used more than once (Kind, Other kind, Id)
+    DuplicateUuid(&'static str, &'static str, Uuid),
+    /// A group lists a font that doesn't exist (Group, Font)
+    MissingGroupFont(String, Uuid),
+    /// An application needs a font that doesn't exist (Application, Font)
+    MissingApplicationFont(String, Uuid),
+    /// An installation points to a download that doesn't exist (Font, Download)
+    MissingDownload(String, Uuid),
+    /// A download is derived from one that doesn't exist (Download, Parent)
+    MissingParent(Uuid, Uuid),
+    /// A font points to a publisher that doesn't exist (Font, Publisher)
+    MissingPublisher(String, Uuid),
+    /// A font conflicts with a font that doesn't exist (Font, Other font)
+    MissingConflict(String, Uuid),
+    /// A hash isn't a sha256 hex digest (Where, Hash)
+    InvalidHash(String, String),
+    /// A download is empty (Download)
+    EmptyDownload(Uuid),
+    /// A family face is of a font that doesn't exist or a file it doesn't install (Family, Font, File)
+    MissingFace(String, Uuid, String),
+    /// The indexes don't match the fonts
+    StaleIndexes,
+}
+
+impl Display for CompiledError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompiledError::DuplicateUuid(kind, other, id) => {
+                write!(f, "The {} id {} is also used by a {}", kind, id, other)
+            }
+            CompiledError::MissingGroupFont(group, font) => {
+                write!(f, "Missing font (group: {}, font: {})", group, font)
+            }
+            CompiledError::MissingApplicationFont(application, font) => write!(
+                f,
+                "Missing font (application: {}, font: {})",
+                application, font
+            ),
+            CompiledError::MissingDownload(font, download) => write!(
+                f,
+                "Missing download (font: {}, download: {})",
+                font, download
+            ),
+            CompiledError::MissingParent(download, parent) => write!(
+                f,
+                "Missing parent download (download: {}, parent: {})",
+                download, parent
+            ),
+            CompiledError::MissingPublisher(font, publisher) => write!(
+                f,
+                "Missing publisher (font: {}, publisher: {})",
+                font, publisher
+            ),
+            CompiledError::MissingConflict(font, other) => write!(
+                f,
+                "Missing conflicting font (font: {}, conflict: {})",
+                font, other
+            ),
+            CompiledError::InvalidHash(at, hash) => {
+                write!(f, "Invalid sha256 (at: {}, hash: {})", at, hash)
+            }
+            CompiledError::EmptyDownload(download) => {
+                write!(f, "Empty download (download: {})", download)
+            }
+            CompiledError::MissingFace(family, font, file) => write!(
+                f,
+                "Missing face (family: {}, font: {}, file: {})",
+                family, font, file
+            ),
+            CompiledError::StaleIndexes => write!(f, "The indexes don't match the fonts"),
+        }
+    }
+}
+
+/// Whether the text is a sha256 hex digest
+fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Checks a compiled database on its own (for mirrors and client developers): every reference
+/// resolves, ids are unique and hashes are well formed
+pub fn validate_compiled(compiled: &Compiled) -> Vec<CompiledError> {
+    let mut errors = vec![];
+
+    // Ids
+    let ids = compiled
+        .publishers
+        .iter()
+        .map(|p| ("publisher", p.id))
+        .chain(compiled.downloads.iter().map(|d| ("download", d.id)))
+        .chain(compiled.groups.iter().map(|g| ("group", g.id)))
+        .chain(compiled.applications.iter().map(|a| ("application", a.id)))
+        .chain(compiled.fonts.iter().map(|f| ("font", f.id)));
+    let mut seen = HashMap::<Uuid, &'static str>::new();
+    for (kind, id) in ids {
+        if let Some(other) = seen.insert(id, kind) {
+            errors.push(CompiledError::DuplicateUuid(kind, other, id));
+        }
+    }
+
+    let font_exists = |id: Uuid| compiled.fonts.iter().any(|f| f.id == id);
+    let download_exists = |id: Uuid| compiled.downloads.iter().any(|d| d.id == id);
+
+    for group in &compiled.groups {
+        for font in &group.fonts {
+            if !font_exists(*font) {
+                errors.push(CompiledError::MissingGroupFont(group.name.clone(), *font));
+            }
+        }
+    }
+
+    for application in &compiled.applications {
+        for font in &application.fonts {
+            if !font_exists(*font) {
+                errors.push(CompiledError::MissingApplicationFont(
+                    application.name.clone(),
+                    *font,
+                ));
+            }
+        }
+    }
+
+    for font in &compiled.fonts {
+        for installation in &font.installations {
+            if !download_exists(installation.download()) {
+                errors.push(CompiledError::MissingDownload(
+                    font.name.clone(),
+                    installation.download(),
+                ));
+            }
+
+            for (file, hash) in installation.file_hashes() {
+                if !is_sha256(hash) {
+                    errors.push(CompiledError::InvalidHash(
+                        format!("{} -> {}", font.name, file),
+                        hash.clone(),
+                    ));
+                }
+            }
+        }
+
+        if let Some(publisher) = font.publisher_id {
+            if !compiled.publishers.iter().any(|p| p.id == publisher) {
+                errors.push(CompiledError::MissingPublisher(
+                    font.name.clone(),
+                    publisher,
+                ));
+            }
+        }
+
+        for other in &font.conflicts_with {
+            if !font_exists(*other) {
+                errors.push(CompiledError::MissingConflict(font.name.clone(), *other));
+            }
+        }
+    }
+
+    for download in &compiled.downloads {
+        if let Some(parent) = download.derived_from {
+            if !download_exists(parent) {
+                errors.push(CompiledError::MissingParent(download.id, parent));
+            }
+        }
+        if !is_sha256(&download.hash) {
+            errors.push(CompiledError::InvalidHash(
+                format!("download {}", download.id),
+                download.hash.clone(),
+            ));
+        }
+        if download.file_size == 0 {
+            errors.push(CompiledError::EmptyDownload(download.id));
+        }
+    }
+
+    for family in &compiled.families {
+        for face in &family.faces {
+            let installed = compiled.find_font(face.font).is_some_and(|font| {
+                font.installations.iter().any(|installation| {
+                    installation
+                        .installed_files()
+                        .iter()
+                        .any(|file| file.file.eq_ignore_ascii_case(&face.file))
+                })
+            });
+            if !installed {
+                errors.push(CompiledError::MissingFace(
+                    family.name.clone(),
+                    face.font,
+                    face.file.clone(),
+                ));
+            }
+        }
+    }
+
+    // Older databases don't have indexes
+    let has_indexes = compiled.indexes != CompiledIndexes::default();
+    if has_indexes && compiled.indexes != CompiledIndexes::from_fonts(&compiled.fonts) {
+        errors.push(CompiledError::StaleIndexes);
+    }
+
+    errors
+}
@@ -0,0 +1,66 @@
+#[cfg(feature = "client")]
+use std::path::Path;
+
+use semver::{Version, VersionReq};
+
+use crate::types::WineCompatibility;
+
+/// Parses a wine version (`9.0`, `8.0.2`, `9.0-rc3` or what `wine --version` prints, e.g.
+/// `wine-9.0 (Staging)`)
+///
+/// Missing parts are zero and release candidates count as the release they lead to.
+pub fn parse_version(version: &str) -> Option<Version> {
+    let version = version.split_whitespace().next()?;
+    let version = version.strip_prefix("wine-").unwrap_or(version);
+    let numbers = version.split('-').next()?;
+
+    let mut parts = numbers.split('.').map(|part| part.parse::<u64>().ok());
+    let major = parts.next()??;
+    let minor = parts.next().unwrap_or(Some(0))?;
+    let patch = parts.next().unwrap_or(Some(0))?;
+    if parts.next().is_some() {
+        return None;
+    }
+
+    Some(Version::new(major, minor, patch))
+}
+
+/// Parses a requirement of a font's wine compatibility
+pub fn parse_requirement(requirement: &str) -> Result<VersionReq, String> {
+    VersionReq::parse(requirement).map_err(|e| e.to_string())
+}
+
+impl WineCompatibility {
+    /// Every requirement (`requires` and `broken`)
+    pub fn requirements(&self) -> impl Iterator<Item = &String> {
+        self.requires.iter().chain(&self.broken)
+    }
+
+    /// Whether the font works with the wine version (malformed requirements are ignored, lint
+    /// rejects them)
+    pub fn supports(&self, version: &Version) -> bool {
+        let matches = |requirement: &String| {
+            parse_requirement(requirement).map(|requirement| requirement.matches(version))
+        };
+
+        if self.requires.as_ref().map(matches) == Some(Ok(false)) {
+            return false;
+        }
+        !self.broken.iter().any(|broken| matches(broken) == Ok(true))
+    }
+}
+
+#[cfg(feature = "client")]
+/// The version of a wine binary (`wine --version`)
+pub async fn detect(wine: &Path) -> Option<Version> {
+    let output = tokio::process::Command::new(wine)
+        .arg("--version")
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_version(&String::from_utf8_lossy(&output.stdout))
+}
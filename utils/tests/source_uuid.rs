@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use utils::types::SourceUUID;
+use uuid::Uuid;
+
+const UUID: &str = "7c9dcf60-3fd9-4464-859f-69ffb75593f3";
+
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Entry {
+    id: SourceUUID,
+}
+
+fn parse(json: &str) -> Result<SourceUUID, serde_json::Error> {
+    serde_json::from_str::<Entry>(json).map(|entry| entry.id)
+}
+
+fn write(id: SourceUUID) -> String {
+    serde_json::to_string(&Entry { id }).unwrap()
+}
+
+#[test]
+fn uuid_round_trips() {
+    let id = parse(&format!(r#"{{"id": "{}"}}"#, UUID)).unwrap();
+
+    assert_eq!(id, SourceUUID::Uuid(Uuid::parse_str(UUID).unwrap()));
+    assert_eq!(write(id), format!(r#"{{"id":"{}"}}"#, UUID));
+    assert_eq!(parse(&write(id)).unwrap(), id);
+}
+
+#[test]
+fn placeholder_round_trips() {
+    let id = parse(r#"{"id": "<UUID>"}"#).unwrap();
+
+    assert_eq!(id, SourceUUID::Null);
+    assert_eq!(write(id), r#"{"id":"<UUID>"}"#);
+    assert_eq!(parse(&write(id)).unwrap(), id);
+}
+
+#[test]
+fn null_is_written_as_the_placeholder() {
+    let id = parse(r#"{"id": null}"#).unwrap();
+
+    assert_eq!(id, SourceUUID::Null);
+    assert_eq!(write(id), r#"{"id":"<UUID>"}"#);
+}
+
+#[test]
+fn other_uuid_forms_are_written_hyphenated() {
+    let expected = SourceUUID::Uuid(Uuid::parse_str(UUID).unwrap());
+    let forms = [
+        UUID.to_uppercase(),
+        UUID.replace('-', ""),
+        format!("{{{}}}", UUID),
+        format!("urn:uuid:{}", UUID),
+    ];
+
+    for form in forms {
+        let id = parse(&format!(r#"{{"id": "{}"}}"#, form)).unwrap();
+
+        assert_eq!(id, expected, "{}", form);
+        assert_eq!(write(id), format!(r#"{{"id":"{}"}}"#, UUID), "{}", form);
+    }
+}
+
+#[test]
+fn invalid_ids_are_rejected() {
+    let invalid = [
+        r#""""#,
+        r#""<uuid>""#,
+        r#""UUID""#,
+        r#"" <UUID>""#,
+        r#""not-a-uuid""#,
+        r#""7c9dcf60-3fd9-4464-859f-69ffb75593f""#,
+        r#"" 7c9dcf60-3fd9-4464-859f-69ffb75593f3""#,
+        "5",
+        "true",
+        "{}",
+        "[]",
+    ];
+
+    for id in invalid {
+        assert!(parse(&format!(r#"{{"id": {}}}"#, id)).is_err(), "{}", id);
+    }
+}
+
+#[test]
+fn rejections_explain_the_placeholder() {
+    let error = parse(r#"{"id": "<uuid>"}"#).unwrap_err().to_string();
+
+    assert!(error.contains("\"<uuid>\" isn't a uuid"), "{}", error);
+    assert!(error.contains(SourceUUID::PLACEHOLDER), "{}", error);
+}
+
+#[test]
+fn missing_ids_are_read_as_the_placeholder() {
+    assert_eq!(parse("{}").unwrap(), SourceUUID::Null);
+}